@@ -1,4 +1,4 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use monero::cryptonote::hash::Hash;
 use monero::util::ringct;
 use monero::PublicKey;
@@ -12,6 +12,16 @@ pub trait MonerodRpc {
     async fn get_block_header_by_height(&self, height: u32) -> BlockHeader;
     async fn get_block_count(&self) -> BlockCount;
     async fn get_block(&self, height: u32) -> GetBlockResponse;
+    async fn get_info(&self) -> GetInfo;
+    async fn get_fee_estimate(&self, grace_blocks: u64) -> FeeEstimate;
+    async fn get_output_distribution(
+        &self,
+        amounts: Vec<u64>,
+        cumulative: bool,
+        from_height: u64,
+        to_height: u64,
+        binary: bool,
+    ) -> GetOutputDistribution;
 }
 
 #[jsonrpc_client::implement(MonerodRpc)]
@@ -21,6 +31,8 @@ pub struct Client {
     base_url: reqwest::Url,
     get_o_indexes_bin_url: reqwest::Url,
     get_outs_bin_url: reqwest::Url,
+    get_transactions_url: reqwest::Url,
+    send_raw_transaction_url: reqwest::Url,
 }
 
 impl Client {
@@ -29,36 +41,137 @@ impl Client {
         Self::new("127.0.0.1".to_owned(), port)
     }
 
-    fn new(host: String, port: u16) -> Result<Self> {
+    /// New monerod RPC client for the daemon at `host` and `port`.
+    pub fn new(host: String, port: u16) -> Result<Self> {
+        let url = format!("http://{}:{}", host, port)
+            .parse()
+            .context("url is well formed")?;
+
+        Self::from_url(url)
+    }
+
+    /// New monerod RPC client for the daemon at `url`.
+    ///
+    /// The scheme must be `http` or `https`. The endpoints of the daemon are
+    /// resolved relative to the path of `url`, so a daemon behind a reverse
+    /// proxy can be reached at e.g. `https://example.com/monerod/`.
+    pub fn from_url(mut url: reqwest::Url) -> Result<Self> {
+        if !matches!(url.scheme(), "http" | "https") {
+            bail!(
+                "Monero daemon URL {} must use http or https but uses {}",
+                url,
+                url.scheme()
+            )
+        }
+        if url.cannot_be_a_base() {
+            bail!("Monero daemon URL {} cannot be a base URL", url)
+        }
+        if !url.path().ends_with('/') {
+            let path = format!("{}/", url.path());
+            url.set_path(&path);
+        }
+
+        let endpoint = |name: &str| {
+            url.join(name)
+                .with_context(|| format!("Failed to build URL of {} endpoint", name))
+        };
+
         Ok(Self {
             inner: reqwest::ClientBuilder::new()
                 .connection_verbose(true)
                 .build()?,
-            base_url: format!("http://{}:{}/json_rpc", host, port)
-                .parse()
-                .context("url is well formed")?,
-            get_o_indexes_bin_url: format!("http://{}:{}/get_o_indexes.bin", host, port)
-                .parse()
-                .context("url is well formed")?,
-            get_outs_bin_url: format!("http://{}:{}/get_outs.bin", host, port)
-                .parse()
-                .context("url is well formed")?,
+            base_url: endpoint("json_rpc")?,
+            get_o_indexes_bin_url: endpoint("get_o_indexes.bin")?,
+            get_outs_bin_url: endpoint("get_outs.bin")?,
+            get_transactions_url: endpoint("get_transactions")?,
+            send_raw_transaction_url: endpoint("send_raw_transaction")?,
         })
     }
 
-    pub async fn get_o_indexes(&self, txid: Hash) -> Result<GetOIndexesResponse> {
+    /// Sends all requests through `proxy`, e.g. the socks5 port of Tor.
+    pub fn with_proxy(self, proxy: reqwest::Proxy) -> Result<Self> {
+        Ok(Self {
+            inner: reqwest::ClientBuilder::new()
+                .connection_verbose(true)
+                .proxy(proxy)
+                .build()?,
+            ..self
+        })
+    }
+
+    pub async fn get_o_indexes(&self, txid: Hash) -> Result<GetOIndexesResponse, Error> {
         self.binary_request(self.get_o_indexes_bin_url.clone(), GetOIndexesPayload {
             txid,
         })
         .await
     }
 
-    pub async fn get_outs(&self, outputs: Vec<GetOutputsOut>) -> Result<GetOutsResponse> {
+    pub async fn get_outs(&self, outputs: Vec<GetOutputsOut>) -> Result<GetOutsResponse, Error> {
         self.binary_request(self.get_outs_bin_url.clone(), GetOutsPayload { outputs })
             .await
     }
 
-    async fn binary_request<Req, Res>(&self, url: reqwest::Url, request: Req) -> Result<Res>
+    /// Fetch the given transactions from the blockchain or the transaction
+    /// pool.
+    pub async fn get_transactions(
+        &self,
+        txs_hashes: Vec<Hash>,
+    ) -> Result<GetTransactionsResponse, Error> {
+        let response = self
+            .inner
+            .post(self.get_transactions_url.clone())
+            .json(&GetTransactionsPayload {
+                txs_hashes: txs_hashes.iter().map(|hash| hex::encode(hash.0)).collect(),
+                decode_as_json: false,
+            })
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Error::Http(response.status()));
+        }
+
+        let response = response.json::<GetTransactionsResponse>().await?;
+
+        if response.status != Status::Ok {
+            return Err(Error::Status(response.status));
+        }
+
+        Ok(response)
+    }
+
+    /// Broadcast the transaction to the network.
+    ///
+    /// Fails with [`Error::Rejected`] if monerod does not accept the
+    /// transaction.
+    pub async fn send_raw_transaction(
+        &self,
+        transaction: &monero::Transaction,
+    ) -> Result<SendRawTransaction, Error> {
+        let response = self
+            .inner
+            .post(self.send_raw_transaction_url.clone())
+            .json(&SendRawTransactionPayload {
+                tx_as_hex: hex::encode(monero::consensus::serialize(transaction)),
+                do_not_relay: false,
+            })
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Error::Http(response.status()));
+        }
+
+        let response = response.json::<SendRawTransaction>().await?;
+
+        if response.status != Status::Ok {
+            return Err(Error::Rejected(TransactionRejected::from(response)));
+        }
+
+        Ok(response)
+    }
+
+    async fn binary_request<Req, Res>(&self, url: reqwest::Url, request: Req) -> Result<Res, Error>
     where
         Req: Serialize,
         Res: DeserializeOwned,
@@ -71,7 +184,7 @@ impl Client {
             .await?;
 
         if !response.status().is_success() {
-            anyhow::bail!("Request failed with status code {}", response.status())
+            return Err(Error::Http(response.status()));
         }
 
         let body = response.bytes().await?;
@@ -116,6 +229,122 @@ pub struct GetBlockResponse {
     pub blob: monero::Block,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct GetOutputDistribution {
+    pub distributions: Vec<OutputDistribution>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct OutputDistribution {
+    pub amount: u64,
+    /// The height of the first block in `distribution`.
+    pub start_height: u64,
+    /// The number of outputs per block, or up to and including each block if
+    /// requested cumulative.
+    pub distribution: Vec<u64>,
+    pub base: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct GetInfo {
+    pub height: u64,
+    pub target_height: u64,
+    pub difficulty: u64,
+    pub tx_pool_size: u64,
+    pub nettype: String,
+    pub synchronized: bool,
+    pub offline: bool,
+    pub untrusted: bool,
+    pub version: String,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct FeeEstimate {
+    /// The fee per byte of transaction weight, in piconero.
+    pub fee: u64,
+    /// Fees are rounded up to a multiple of this.
+    pub quantization_mask: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct SendRawTransactionPayload {
+    tx_as_hex: String,
+    do_not_relay: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SendRawTransaction {
+    pub status: Status,
+    #[serde(default)]
+    pub reason: String,
+    #[serde(default)]
+    pub double_spend: bool,
+    #[serde(default)]
+    pub fee_too_low: bool,
+    #[serde(default)]
+    pub invalid_input: bool,
+    #[serde(default)]
+    pub invalid_output: bool,
+    #[serde(default)]
+    pub low_mixin: bool,
+    #[serde(default)]
+    pub not_relayed: bool,
+    #[serde(default)]
+    pub overspend: bool,
+    #[serde(default)]
+    pub too_big: bool,
+}
+
+/// The errors of the monerod requests that do not go through JSON-RPC.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Failed to send request to monerod")]
+    Request(#[from] reqwest::Error),
+    #[error("Request failed with status code {0}")]
+    Http(reqwest::StatusCode),
+    #[error("Failed to encode or decode binary message")]
+    Binary(#[from] monero_epee_bin_serde::Error),
+    #[error("Monerod returned status {0:?}")]
+    Status(Status),
+    #[error(transparent)]
+    Rejected(#[from] TransactionRejected),
+}
+
+/// Monerod did not accept a transaction, with the checks it failed.
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+#[error("Monerod rejected the transaction: {}", .reasons.join(", "))]
+pub struct TransactionRejected {
+    pub reasons: Vec<String>,
+}
+
+impl From<SendRawTransaction> for TransactionRejected {
+    fn from(response: SendRawTransaction) -> Self {
+        let checks = [
+            (response.double_spend, "double spend"),
+            (response.fee_too_low, "fee too low"),
+            (response.invalid_input, "invalid input"),
+            (response.invalid_output, "invalid output"),
+            (response.low_mixin, "ring size too small"),
+            (response.overspend, "overspend"),
+            (response.too_big, "too big"),
+        ];
+
+        let mut reasons = checks
+            .iter()
+            .filter(|(failed, _)| *failed)
+            .map(|(_, reason)| reason.to_string())
+            .collect::<Vec<_>>();
+        if !response.reason.is_empty() {
+            reasons.push(response.reason);
+        }
+        if reasons.is_empty() {
+            reasons.push("unknown reason".to_owned());
+        }
+
+        Self { reasons }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct GetIndexesResponse {
     pub o_indexes: Vec<u32>,
@@ -132,6 +361,33 @@ struct GetOutsPayload {
     outputs: Vec<GetOutputsOut>,
 }
 
+#[derive(Clone, Debug, Serialize)]
+struct GetTransactionsPayload {
+    txs_hashes: Vec<String>,
+    decode_as_json: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetTransactionsResponse {
+    pub status: Status,
+    #[serde(default)]
+    pub txs: Vec<TransactionEntry>,
+    /// The hashes of the requested transactions monerod does not know.
+    #[serde(default)]
+    pub missed_tx: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransactionEntry {
+    pub tx_hash: String,
+    #[serde(rename = "as_hex", with = "monero_serde_hex_transaction")]
+    pub transaction: monero::Transaction,
+    pub in_pool: bool,
+    /// Not set for transactions in the pool.
+    #[serde(default)]
+    pub block_height: u64,
+}
+
 #[derive(Clone, Copy, Debug, Serialize)]
 pub struct GetOutputsOut {
     pub amount: u64,
@@ -203,6 +459,29 @@ mod monero_serde_hex_block {
     }
 }
 
+mod monero_serde_hex_transaction {
+    use super::*;
+    use monero::consensus::Decodable;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer};
+    use std::io::Cursor;
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<monero::Transaction, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let hex = String::deserialize(deserializer)?;
+
+        let bytes = hex::decode(&hex).map_err(D::Error::custom)?;
+        let mut cursor = Cursor::new(bytes);
+
+        let transaction =
+            monero::Transaction::consensus_decode(&mut cursor).map_err(D::Error::custom)?;
+
+        Ok(transaction)
+    }
+}
+
 mod byte_array {
     use super::*;
     use serde::de::Error;
@@ -257,3 +536,147 @@ mod byte_array {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_scheme_and_path_of_daemon_url() {
+        let client =
+            Client::from_url("https://example.com:18089/monerod".parse().unwrap()).unwrap();
+
+        assert_eq!(
+            client.base_url.as_str(),
+            "https://example.com:18089/monerod/json_rpc"
+        );
+        assert_eq!(
+            client.get_outs_bin_url.as_str(),
+            "https://example.com:18089/monerod/get_outs.bin"
+        );
+    }
+
+    #[test]
+    fn rejects_daemon_url_that_is_not_http() {
+        assert!(Client::from_url("ws://127.0.0.1:18081".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn can_deserialize_get_output_distribution() {
+        let response = r#"{
+          "credits": 0,
+          "distributions": [{
+            "amount": 0,
+            "base": 0,
+            "distribution": [1, 2, 4, 7],
+            "start_height": 0
+          }],
+          "status": "OK",
+          "top_hash": "",
+          "untrusted": false
+        }"#;
+
+        let response = serde_json::from_str::<GetOutputDistribution>(response).unwrap();
+
+        assert_eq!(response.distributions[0].distribution, vec![1, 2, 4, 7]);
+    }
+
+    #[test]
+    fn can_deserialize_get_transactions_response_with_missed_tx() {
+        let response = r#"{
+          "credits": 0,
+          "missed_tx": ["d6e48158472848e6687173a91ae6eebfa3e1d778e65252ee99d7515d63090408"],
+          "status": "OK",
+          "top_hash": "",
+          "untrusted": false
+        }"#;
+
+        let response = serde_json::from_str::<GetTransactionsResponse>(response).unwrap();
+
+        assert!(response.txs.is_empty());
+        assert_eq!(response.missed_tx, vec![
+            "d6e48158472848e6687173a91ae6eebfa3e1d778e65252ee99d7515d63090408".to_owned()
+        ]);
+    }
+
+    #[test]
+    fn can_deserialize_fee_estimate() {
+        let response = r#"{
+          "id": "0",
+          "jsonrpc": "2.0",
+          "result": {
+            "credits": 0,
+            "fee": 7874,
+            "fees": [20000, 80000, 320000, 4000000],
+            "quantization_mask": 10000,
+            "status": "OK",
+            "top_hash": "",
+            "untrusted": false
+          }
+        }"#;
+
+        let response: jsonrpc_client::Response<FeeEstimate> =
+            serde_json::from_str(response).unwrap();
+        let estimate = response.payload.unwrap();
+
+        assert_eq!(estimate.fee, 7874);
+        assert_eq!(estimate.quantization_mask, 10000);
+    }
+
+    #[test]
+    fn can_deserialize_get_info() {
+        let response = r#"{
+          "id": "0",
+          "jsonrpc": "2.0",
+          "result": {
+            "alt_blocks_count": 0,
+            "block_size_limit": 600000,
+            "credits": 0,
+            "difficulty": 1,
+            "height": 150,
+            "nettype": "fakechain",
+            "offline": false,
+            "status": "OK",
+            "synchronized": true,
+            "target_height": 0,
+            "top_block_hash": "",
+            "tx_pool_size": 2,
+            "untrusted": false,
+            "version": "0.17.3.0-release"
+          }
+        }"#;
+
+        let response: jsonrpc_client::Response<GetInfo> = serde_json::from_str(response).unwrap();
+        let info = response.payload.unwrap();
+
+        assert_eq!(info.height, 150);
+        assert!(info.synchronized);
+    }
+
+    #[test]
+    fn collects_reasons_of_rejected_transaction() {
+        let response = r#"{
+          "credits": 0,
+          "double_spend": true,
+          "fee_too_low": false,
+          "invalid_input": false,
+          "invalid_output": false,
+          "low_mixin": false,
+          "not_relayed": false,
+          "overspend": false,
+          "reason": "",
+          "sanity_check_failed": false,
+          "status": "Failed",
+          "too_big": false,
+          "too_few_outputs": false,
+          "top_hash": "",
+          "untrusted": false
+        }"#;
+
+        let response = serde_json::from_str::<SendRawTransaction>(response).unwrap();
+
+        assert_eq!(TransactionRejected::from(response), TransactionRejected {
+            reasons: vec!["double spend".to_owned()]
+        });
+    }
+}
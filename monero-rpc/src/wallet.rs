@@ -1,3 +1,4 @@
+use crate::jsonrpc;
 use anyhow::{Context, Result};
 use serde::de::Error;
 use serde::{Deserialize, Deserializer, Serialize};
@@ -32,7 +33,44 @@ pub trait MoneroWalletRpc {
     ) -> GenerateFromKeys;
     async fn refresh(&self) -> Refreshed;
     async fn sweep_all(&self, address: String) -> SweepAll;
+    async fn sweep_single(
+        &self,
+        key_image: String,
+        address: String,
+        get_tx_key: bool,
+    ) -> SweepSingle;
     async fn get_version(&self) -> Version;
+    /// Lists the transfers of the account, `in`, `out`, `pending`, `failed`
+    /// and `pool` select which kinds are included.
+    async fn get_transfers(
+        &self,
+        r#in: bool,
+        out: bool,
+        pending: bool,
+        failed: bool,
+        pool: bool,
+        account_index: u32,
+    ) -> GetTransfers;
+    async fn get_transfer_by_txid(&self, txid: String, account_index: u32) -> GetTransferByTxid;
+    async fn incoming_transfers(
+        &self,
+        transfer_type: IncomingTransferType,
+        account_index: u32,
+    ) -> IncomingTransfers;
+    async fn export_key_images(&self, all: bool) -> ExportKeyImages;
+    async fn import_key_images(
+        &self,
+        signed_key_images: Vec<SignedKeyImage>,
+        offset: u32,
+    ) -> ImportKeyImages;
+    async fn get_tx_proof(&self, txid: String, address: String, message: String) -> TxProof;
+    async fn check_tx_proof(
+        &self,
+        txid: String,
+        address: String,
+        message: String,
+        signature: String,
+    ) -> CheckTxProof;
 }
 
 #[jsonrpc_client::implement(MoneroWalletRpc)]
@@ -62,19 +100,92 @@ impl Client {
         })
     }
 
+    /// Sends all requests through `proxy`, e.g. the socks5 port of Tor.
+    pub fn with_proxy(self, proxy: reqwest::Proxy) -> Result<Self> {
+        Ok(Self {
+            inner: reqwest::ClientBuilder::new()
+                .connection_verbose(true)
+                .proxy(proxy)
+                .build()?,
+            ..self
+        })
+    }
+
     /// Transfers `amount` monero from `account_index` to `address`.
     pub async fn transfer_single(
         &self,
         account_index: u32,
         amount: u64,
         address: &str,
-    ) -> Result<Transfer> {
+    ) -> Result<Transfer, jsonrpc::Error<reqwest::Error>> {
         let dest = vec![Destination {
             amount,
             address: address.to_owned(),
         }];
 
-        Ok(self.transfer(account_index, dest, true).await?)
+        self.transfer(account_index, dest, true).await
+    }
+}
+
+/// The error codes of `monero-wallet-rpc`, see
+/// `wallet_rpc_server_error_codes.h`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCode {
+    Unknown,
+    WrongAddress,
+    DaemonIsBusy,
+    GenericTransferError,
+    WrongTxId,
+    WrongKeyImage,
+    NotOpen,
+    TxNotPossible,
+    NotEnoughMoney,
+    TxTooLarge,
+    NotEnoughOutsToMix,
+    ZeroDestination,
+    WalletAlreadyExists,
+    InvalidPassword,
+    NoTxKey,
+    WrongKey,
+    BadHex,
+    NoDaemonConnection,
+    Other(i64),
+}
+
+impl ErrorCode {
+    /// The code of a JSON-RPC error returned by the wallet, `None` for
+    /// transport errors.
+    pub fn of<C>(error: &jsonrpc::Error<C>) -> Option<Self> {
+        match error {
+            jsonrpc::Error::JsonRpc(error) => Some(Self::from(error.code)),
+            _ => None,
+        }
+    }
+}
+
+impl From<i64> for ErrorCode {
+    fn from(code: i64) -> Self {
+        match code {
+            -1 => Self::Unknown,
+            -2 => Self::WrongAddress,
+            -3 => Self::DaemonIsBusy,
+            -4 => Self::GenericTransferError,
+            -8 => Self::WrongTxId,
+            -10 => Self::WrongKeyImage,
+            -13 => Self::NotOpen,
+            -16 => Self::TxNotPossible,
+            -17 => Self::NotEnoughMoney,
+            -18 => Self::TxTooLarge,
+            -19 => Self::NotEnoughOutsToMix,
+            -20 => Self::ZeroDestination,
+            -21 => Self::WalletAlreadyExists,
+            -22 => Self::InvalidPassword,
+            -24 => Self::NoTxKey,
+            -25 => Self::WrongKey,
+            -26 => Self::BadHex,
+            -38 => Self::NoDaemonConnection,
+            other => Self::Other(other),
+        }
     }
 }
 
@@ -108,7 +219,7 @@ pub struct GetAccounts {
 #[derive(Deserialize, Debug, Clone)]
 pub struct SubAddressAccount {
     pub account_index: u32,
-    pub balance: u32,
+    pub balance: u64,
     pub base_address: String,
     pub label: String,
     pub tag: String,
@@ -192,6 +303,136 @@ pub struct Version {
     pub version: u32,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct SweepSingle {
+    pub amount: u64,
+    pub fee: u64,
+    pub tx_hash: String,
+    #[serde(deserialize_with = "opt_key_from_blank")]
+    pub tx_key: Option<monero::PrivateKey>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct GetTransfers {
+    #[serde(rename = "in", default)]
+    pub incoming: Vec<TransferEntry>,
+    #[serde(rename = "out", default)]
+    pub outgoing: Vec<TransferEntry>,
+    #[serde(default)]
+    pub pending: Vec<TransferEntry>,
+    #[serde(default)]
+    pub failed: Vec<TransferEntry>,
+    #[serde(default)]
+    pub pool: Vec<TransferEntry>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct GetTransferByTxid {
+    pub transfer: TransferEntry,
+    /// All transfers of the transaction if it has several, e.g. to different
+    /// subaddresses.
+    #[serde(default)]
+    pub transfers: Vec<TransferEntry>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TransferEntry {
+    pub address: String,
+    pub amount: u64,
+    /// Not set for transfers that are not in a block yet.
+    #[serde(default)]
+    pub confirmations: u64,
+    pub double_spend_seen: bool,
+    pub fee: u64,
+    pub height: u64,
+    pub note: String,
+    pub payment_id: String,
+    pub subaddr_index: SubaddressIndex,
+    pub timestamp: u64,
+    pub txid: String,
+    #[serde(rename = "type")]
+    pub kind: TransferKind,
+    pub unlock_time: u64,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferKind {
+    In,
+    Out,
+    Pending,
+    Failed,
+    Pool,
+    Block,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub struct SubaddressIndex {
+    pub major: u32,
+    pub minor: u32,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IncomingTransferType {
+    All,
+    Available,
+    Unavailable,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct IncomingTransfers {
+    /// Left out by the wallet if there are none.
+    #[serde(default)]
+    pub transfers: Vec<IncomingTransfer>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct IncomingTransfer {
+    pub amount: u64,
+    pub global_index: u64,
+    pub key_image: String,
+    pub spent: bool,
+    pub subaddr_index: SubaddressIndex,
+    pub tx_hash: String,
+    #[serde(default)]
+    pub unlocked: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ExportKeyImages {
+    #[serde(default)]
+    pub offset: u32,
+    #[serde(default)]
+    pub signed_key_images: Vec<SignedKeyImage>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SignedKeyImage {
+    pub key_image: String,
+    pub signature: String,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct ImportKeyImages {
+    pub height: u64,
+    pub spent: u64,
+    pub unspent: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TxProof {
+    pub signature: String,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct CheckTxProof {
+    pub confirmations: u64,
+    pub good: bool,
+    pub in_pool: bool,
+    pub received: u64,
+}
+
 pub type WalletCreated = Empty;
 pub type WalletClosed = Empty;
 pub type WalletOpened = Empty;
@@ -253,4 +494,157 @@ mod tests {
 
         let _: Response<WalletCreated> = serde_json::from_str(response).unwrap();
     }
+
+    #[test]
+    fn can_deserialize_get_transfers_without_empty_lists() {
+        let response = r#"{
+          "id": 0,
+          "jsonrpc": "2.0",
+          "result": {
+            "in": [{
+              "address": "55LTR8KniP4LQGJSPtbYDacR7dz8RBFnsfAKMaMuwUNYX6aQbBcovzDPyrQF9KXF9tVU6Xk3K8no1BywnJX6GvZX8yJsXvt",
+              "amount": 200000000000,
+              "amounts": [200000000000],
+              "confirmations": 1,
+              "double_spend_seen": false,
+              "fee": 21650200000,
+              "height": 153624,
+              "locked": false,
+              "note": "",
+              "payment_id": "0000000000000000",
+              "subaddr_index": {"major": 0, "minor": 0},
+              "subaddr_indices": [{"major": 0, "minor": 0}],
+              "suggested_confirmations_threshold": 1,
+              "timestamp": 1535918400,
+              "txid": "c36258a276018c3a4bc1f195a7fb530f50cd63a4fa765fb7c6f7f49fc051762a",
+              "type": "in",
+              "unlock_time": 0
+            }]
+          }
+        }"#;
+
+        let response: Response<GetTransfers> = serde_json::from_str(response).unwrap();
+        let transfers = response.payload.unwrap();
+
+        assert_eq!(transfers.incoming.len(), 1);
+        assert_eq!(transfers.incoming[0].kind, TransferKind::In);
+        assert!(transfers.outgoing.is_empty());
+        assert!(transfers.pool.is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_transfers_names_the_params_like_monero() {
+        #[derive(Default)]
+        struct RecordingClient {
+            request: std::sync::Mutex<Option<String>>,
+        }
+
+        #[async_trait::async_trait]
+        impl MoneroWalletRpc<reqwest::Client> for RecordingClient {
+            async fn send_request<P>(&self, request: String) -> Result<Response<P>, reqwest::Error>
+            where
+                P: serde::de::DeserializeOwned,
+            {
+                *self.request.lock().unwrap() = Some(request);
+
+                Ok(serde_json::from_str(r#"{"id": "0", "jsonrpc": "2.0", "result": {}}"#).unwrap())
+            }
+        }
+
+        let client = RecordingClient::default();
+        client
+            .get_transfers(true, false, false, false, true, 1)
+            .await
+            .unwrap();
+
+        let request: serde_json::Value =
+            serde_json::from_str(client.request.lock().unwrap().as_ref().unwrap()).unwrap();
+        assert_eq!(request["method"], "get_transfers");
+        assert_eq!(
+            request["params"],
+            serde_json::json!({
+                "in": true,
+                "out": false,
+                "pending": false,
+                "failed": false,
+                "pool": true,
+                "account_index": 1
+            })
+        );
+    }
+
+    #[test]
+    fn can_deserialize_incoming_transfers_without_transfers() {
+        let response = r#"{
+          "id": "0",
+          "jsonrpc": "2.0",
+          "result": {
+          }
+        }"#;
+
+        let response: Response<IncomingTransfers> = serde_json::from_str(response).unwrap();
+
+        assert!(response.payload.unwrap().transfers.is_empty());
+    }
+
+    #[test]
+    fn can_deserialize_sweep_single_response() {
+        let response = r#"{
+          "id": "0",
+          "jsonrpc": "2.0",
+          "result": {
+            "amount": 27126892247503,
+            "fee": 14111630000,
+            "multisig_txset": "",
+            "tx_blob": "",
+            "tx_hash": "106d4391a031e5b735ded555862fec63233e34e5fa4fc7edcfdbe461c275ae5b",
+            "tx_key": "",
+            "tx_metadata": "",
+            "unsigned_txset": "",
+            "weight": 1528
+          }
+        }"#;
+
+        let response: Response<SweepSingle> = serde_json::from_str(response).unwrap();
+        let sweep = response.payload.unwrap();
+
+        assert_eq!(sweep.amount, 27126892247503);
+        assert!(sweep.tx_key.is_none());
+    }
+
+    #[test]
+    fn can_deserialize_check_tx_proof_response() {
+        let response = r#"{
+          "id": "0",
+          "jsonrpc": "2.0",
+          "result": {
+            "confirmations": 482,
+            "good": true,
+            "in_pool": false,
+            "received": 1000000000000
+          }
+        }"#;
+
+        let response: Response<CheckTxProof> = serde_json::from_str(response).unwrap();
+
+        assert!(response.payload.unwrap().good);
+    }
+
+    #[test]
+    fn maps_error_codes_of_failed_requests() {
+        let response = r#"{
+          "id": "0",
+          "jsonrpc": "2.0",
+          "error": {
+            "code": -13,
+            "message": "No wallet file"
+          }
+        }"#;
+
+        let response: Response<WalletOpened> = serde_json::from_str(response).unwrap();
+        let error = jsonrpc::Error::<reqwest::Error>::JsonRpc(response.payload.unwrap_err());
+
+        assert_eq!(ErrorCode::of(&error), Some(ErrorCode::NotOpen));
+        assert_eq!(ErrorCode::from(-99), ErrorCode::Other(-99));
+    }
 }
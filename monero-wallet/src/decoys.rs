@@ -0,0 +1,167 @@
+//! Selecting the decoys of a ring the way `wallet2` does.
+//!
+//! The age of real spends roughly follows a gamma distribution, so decoys are
+//! picked by sampling an age from that distribution and taking an output of
+//! the block that age ago. Ages are converted to outputs with the average time
+//! between outputs over the last year.
+
+use anyhow::{bail, Result};
+use monero::consensus::encode::VarInt;
+use rand::{CryptoRng, Rng};
+use rand_distr::{Distribution, Gamma};
+
+/// The number of members in a ring, including the real output.
+pub const RING_SIZE: usize = 11;
+
+const GAMMA_SHAPE: f64 = 19.28;
+const GAMMA_SCALE: f64 = 1.0 / 1.61;
+/// Seconds per block.
+const DIFFICULTY_TARGET: u64 = 120;
+/// Outputs can only be spent after this many blocks.
+const SPENDABLE_AGE: usize = 10;
+const DEFAULT_UNLOCK_TIME: u64 = SPENDABLE_AGE as u64 * DIFFICULTY_TARGET;
+const BLOCKS_IN_A_YEAR: usize = 86400 * 365 / DIFFICULTY_TARGET as usize;
+
+#[derive(Debug)]
+pub struct DecoySelector {
+    /// The cumulative number of RingCT outputs up to and including each
+    /// block, starting at the genesis block.
+    rct_offsets: Vec<u64>,
+    /// The outputs that are old enough to be spent.
+    num_spendable_outputs: u64,
+    average_output_time: f64,
+    gamma: Gamma<f64>,
+}
+
+impl DecoySelector {
+    /// Create a selector from the cumulative RingCT output distribution.
+    pub fn new(rct_offsets: Vec<u64>) -> Result<Self> {
+        if rct_offsets.len() <= SPENDABLE_AGE {
+            bail!(
+                "Need more than {} blocks to select decoys, got {}",
+                SPENDABLE_AGE,
+                rct_offsets.len()
+            )
+        }
+
+        let blocks_to_consider = rct_offsets.len().min(BLOCKS_IN_A_YEAR);
+        let outputs_before = if blocks_to_consider < rct_offsets.len() {
+            rct_offsets[rct_offsets.len() - blocks_to_consider - 1]
+        } else {
+            0
+        };
+        let outputs_to_consider = rct_offsets[rct_offsets.len() - 1] - outputs_before;
+        if outputs_to_consider == 0 {
+            bail!("No RingCT outputs to select decoys from")
+        }
+
+        let num_spendable_outputs = rct_offsets[rct_offsets.len() - SPENDABLE_AGE - 1];
+        let average_output_time =
+            (DIFFICULTY_TARGET * blocks_to_consider as u64) as f64 / outputs_to_consider as f64;
+
+        Ok(Self {
+            rct_offsets,
+            num_spendable_outputs,
+            average_output_time,
+            gamma: Gamma::new(GAMMA_SHAPE, GAMMA_SCALE).expect("valid gamma parameters"),
+        })
+    }
+
+    /// The global index of a random decoy, or `None` if the sampled age does
+    /// not map to a spendable output and another one has to be sampled.
+    pub fn pick<R: Rng + CryptoRng>(&self, rng: &mut R) -> Option<u64> {
+        let mut age = self.gamma.sample(rng).exp();
+
+        if age > DEFAULT_UNLOCK_TIME as f64 {
+            age -= DEFAULT_UNLOCK_TIME as f64;
+        } else {
+            // Spends of outputs that just became spendable are spread over the first block
+            let outputs_per_block = (DIFFICULTY_TARGET as f64 / self.average_output_time).ceil();
+            age = rng.gen_range(0..outputs_per_block as u64) as f64;
+        }
+
+        let outputs_ago = (age / self.average_output_time) as u64;
+        if outputs_ago >= self.num_spendable_outputs {
+            return None;
+        }
+        let output = self.num_spendable_outputs - 1 - outputs_ago;
+
+        // The block containing the output, to pick uniformly among its outputs
+        let spendable_blocks = &self.rct_offsets[..self.rct_offsets.len() - SPENDABLE_AGE];
+        let block = spendable_blocks.partition_point(|&offset| offset <= output);
+        let first_output = if block == 0 {
+            0
+        } else {
+            spendable_blocks[block - 1]
+        };
+        let outputs_in_block = spendable_blocks[block] - first_output;
+        if outputs_in_block == 0 {
+            return None;
+        }
+
+        Some(first_output + rng.gen_range(0..outputs_in_block))
+    }
+}
+
+/// Convert ascending global output indices to the offsets stored in a
+/// transaction input, each relative to the previous one.
+pub fn relative_offsets(indices: &[u64]) -> Vec<VarInt> {
+    indices
+        .iter()
+        .scan(0, |previous, &index| {
+            let offset = index - *previous;
+            *previous = index;
+
+            Some(VarInt(offset))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_only_spendable_outputs() {
+        // 10 outputs per block for 1000 blocks
+        let rct_offsets = (1..=1000).map(|block| block * 10).collect::<Vec<u64>>();
+        let selector = DecoySelector::new(rct_offsets).unwrap();
+        let mut rng = rand::thread_rng();
+
+        let picks = (0..1000)
+            .filter_map(|_| selector.pick(&mut rng))
+            .collect::<Vec<_>>();
+
+        assert!(!picks.is_empty());
+        assert!(picks.iter().all(|&pick| pick < 9900));
+    }
+
+    #[test]
+    fn prefers_recent_outputs() {
+        let rct_offsets = (1..=100_000).map(|block| block * 10).collect::<Vec<u64>>();
+        let selector = DecoySelector::new(rct_offsets).unwrap();
+        let mut rng = rand::thread_rng();
+
+        let picks = (0..1000)
+            .filter_map(|_| selector.pick(&mut rng))
+            .collect::<Vec<_>>();
+        let recent = picks.iter().filter(|&&pick| pick > 500_000).count();
+
+        assert!(recent > picks.len() / 2);
+    }
+
+    #[test]
+    fn fails_without_enough_blocks() {
+        assert!(DecoySelector::new(vec![1; SPENDABLE_AGE]).is_err());
+    }
+
+    #[test]
+    fn converts_to_relative_offsets() {
+        assert_eq!(relative_offsets(&[3, 10, 11, 50]), vec![
+            VarInt(3),
+            VarInt(7),
+            VarInt(1),
+            VarInt(39)
+        ]);
+    }
+}
@@ -1,93 +1,455 @@
-use anyhow::{Context, Result};
+//! A Monero wallet working directly against monerod.
+//!
+//! The wallet finds outputs sent to a view pair, checks transfers with their
+//! transaction key, estimates fees and picks the rings for spending outputs.
+//! Building transactions is not implemented yet: signing inputs with CLSAG and
+//! proving outputs with Bulletproofs are missing, so sending Monero still goes
+//! through `monero-wallet-rpc`.
+
+pub mod decoys;
+pub mod scan;
+
+use crate::decoys::{DecoySelector, RING_SIZE};
+use crate::scan::{KeyDerivation, OwnedOutput};
+use anyhow::{bail, Context, Result};
 use monero::consensus::encode::VarInt;
-use monero::cryptonote::hash::Hashable;
+use monero::cryptonote::hash::{Hash, Hashable};
+use monero::{PrivateKey, PublicKey, Transaction, ViewPair};
 use monero_rpc::monerod;
-use monero_rpc::monerod::{GetBlockResponse, MonerodRpc as _};
-use rand::Rng;
+use monero_rpc::monerod::{FeeEstimate, GetBlockResponse, GetOutputsOut, MonerodRpc as _, OutKey};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+/// How often to sample the decoy distribution before giving up on filling a
+/// ring.
+const MAX_DECOY_PICKS: usize = 100 * RING_SIZE;
+/// The number of blocks a transaction paying the estimated fee may take to be
+/// mined, the same `wallet2` uses.
+const FEE_ESTIMATE_GRACE_BLOCKS: u64 = 10;
+/// The fee multiplier of the default priority `wallet2` picks when the pool is
+/// busy, the highest fee `monero-wallet-rpc` pays unless told otherwise.
+const DEFAULT_PRIORITY_MULTIPLIER: u64 = 5;
 
+/// A wallet working directly against monerod, without `monero-wallet-rpc`.
+#[derive(Debug)]
 pub struct Wallet {
     client: monerod::Client,
 }
 
+/// An output sent to the scanned wallet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReceivedOutput {
+    pub tx_hash: Hash,
+    pub height: u32,
+    pub output: OwnedOutput,
+}
+
 impl Wallet {
-    /// Chooses 10 random key offsets for use within a new confidential
-    /// transactions.
+    pub fn new(client: monerod::Client) -> Self {
+        Self { client }
+    }
+
+    /// The height of the next block, i.e. the number of blocks in the chain.
+    pub async fn block_height(&self) -> Result<u32> {
+        Ok(self.client.get_block_count().await?.count)
+    }
+
+    /// Scan the blocks at the given heights for outputs sent to the main
+    /// address of the view pair.
+    ///
+    /// Only finds outputs, spending them is not detected because that needs
+    /// the private spend key.
+    pub async fn scan(
+        &self,
+        view_pair: &ViewPair,
+        heights: Range<u32>,
+    ) -> Result<Vec<ReceivedOutput>> {
+        let mut received = Vec::new();
+
+        for height in heights {
+            let block: GetBlockResponse = self
+                .client
+                .get_block(height)
+                .await
+                .with_context(|| format!("Failed to get block at height {}", height))?;
+
+            let miner_tx = block.blob.miner_tx;
+            received.extend(
+                scan_transaction(view_pair, &miner_tx)?
+                    .into_iter()
+                    .map(|output| ReceivedOutput {
+                        tx_hash: miner_tx.hash(),
+                        height,
+                        output,
+                    }),
+            );
+
+            if block.blob.tx_hashes.is_empty() {
+                continue;
+            }
+
+            let transactions = self
+                .client
+                .get_transactions(block.blob.tx_hashes.clone())
+                .await?;
+
+            if !transactions.missed_tx.is_empty() {
+                bail!(
+                    "Monerod does not know the transactions {:?} of block {}",
+                    transactions.missed_tx,
+                    height
+                )
+            }
+
+            for (tx_hash, entry) in block.blob.tx_hashes.iter().zip(transactions.txs) {
+                received.extend(
+                    scan_transaction(view_pair, &entry.transaction)?
+                        .into_iter()
+                        .map(|output| ReceivedOutput {
+                            tx_hash: *tx_hash,
+                            height,
+                            output,
+                        }),
+                );
+            }
+        }
+
+        Ok(received)
+    }
+
+    /// Check how much the transaction sent to the address with the given
+    /// public keys and how many confirmations it has, from the transaction
+    /// key of the sender.
     ///
-    /// Choosing these offsets randomly is not ideal for privacy, instead they
-    /// should be chosen in a way that mimics a real spending pattern as much as
-    /// possible.
-    pub async fn choose_ten_random_key_offsets(&self) -> Result<[VarInt; 10]> {
-        let latest_block = self.client.get_block_count().await?;
-        let latest_spendable_block = latest_block.count - 10;
-
-        let block: GetBlockResponse = self.client.get_block(latest_spendable_block).await?;
-
-        let tx_hash = block
-            .blob
-            .tx_hashes
-            .first()
-            .copied()
-            .unwrap_or_else(|| block.blob.miner_tx.hash());
-
-        let indices = self.client.get_o_indexes(tx_hash).await?;
-
-        let last_index = indices
-            .o_indexes
+    /// Works like `check_tx_key` of `monero-wallet-rpc` but only trusts
+    /// monerod to serve the transaction, the amount is checked against the
+    /// commitments.
+    pub async fn check_transfer(
+        &self,
+        tx_hash: Hash,
+        tx_key: &PrivateKey,
+        public_spend_key: &PublicKey,
+        public_view_key: &PublicKey,
+    ) -> Result<Transfer> {
+        let response = self.client.get_transactions(vec![tx_hash]).await?;
+        let entry = response
+            .txs
+            .into_iter()
+            .next()
+            .with_context(|| format!("Monerod does not know the transaction {}", tx_hash))?;
+
+        let derivation = KeyDerivation::from_tx_key(tx_key, public_view_key)?;
+        let received = scan::owned_outputs(&entry.transaction, &derivation, public_spend_key)?
+            .iter()
+            .map(|output| output.amount)
+            .sum();
+
+        let confirmations = if entry.in_pool {
+            0
+        } else {
+            let height = self.block_height().await?;
+            u64::from(height).saturating_sub(entry.block_height)
+        };
+
+        Ok(Transfer {
+            confirmations,
+            received,
+        })
+    }
+
+    /// Estimate the fee `monero-wallet-rpc` pays for a transaction of the
+    /// given weight with the default priority, from the fee per byte monerod
+    /// estimates.
+    pub async fn estimate_fee(&self, weight: u64) -> Result<u64> {
+        let estimate = self
+            .client
+            .get_fee_estimate(FEE_ESTIMATE_GRACE_BLOCKS)
+            .await
+            .context("Failed to get fee estimate")?;
+
+        Ok(fee_for_weight(weight, &estimate))
+    }
+
+    /// Select decoys from the RingCT outputs on the chain.
+    pub async fn decoy_selector(&self) -> Result<DecoySelector> {
+        let response = self
+            .client
+            .get_output_distribution(vec![0], true, 0, 0, false)
+            .await
+            .context("Failed to get RingCT output distribution")?;
+        let distribution = response
+            .distributions
             .into_iter()
-            .max()
-            .context("Expected at least one output index")?;
-        let oldest_index = last_index - (last_index / 100) * 40; // oldest index must be within last 40% TODO: CONFIRM THIS
+            .find(|distribution| distribution.amount == 0)
+            .context("Monerod did not return the RingCT output distribution")?;
+
+        if distribution.start_height != 0 {
+            bail!(
+                "Expected output distribution from genesis block, got it from height {}",
+                distribution.start_height
+            )
+        }
 
+        DecoySelector::new(distribution.distribution)
+    }
+
+    /// Fetch a ring for spending the RingCT output with the given global
+    /// index, with decoys picked by the selector.
+    ///
+    /// Decoys that turn out to be locked, such as coinbase outputs in their
+    /// unlock window, are replaced by new picks.
+    pub async fn fetch_ring(&self, selector: &DecoySelector, real_output: u64) -> Result<Ring> {
         let mut rng = rand::thread_rng();
+        let mut members = BTreeMap::new();
+        let mut locked = BTreeSet::new();
+        let mut candidates = vec![real_output];
+
+        let mut attempts = 0;
+        loop {
+            let response = self
+                .client
+                .get_outs(
+                    candidates
+                        .iter()
+                        .map(|index| GetOutputsOut {
+                            amount: 0,
+                            index: *index,
+                        })
+                        .collect(),
+                )
+                .await
+                .context("Failed to get ring members")?;
+
+            if response.outs.len() != candidates.len() {
+                bail!(
+                    "Expected {} ring members, got {}",
+                    candidates.len(),
+                    response.outs.len()
+                )
+            }
+
+            for (index, member) in candidates.drain(..).zip(response.outs) {
+                if member.unlocked {
+                    members.insert(index, member);
+                } else if index == real_output {
+                    bail!("Output {} is still locked", real_output)
+                } else {
+                    locked.insert(index);
+                }
+            }
+
+            if members.len() == RING_SIZE {
+                break;
+            }
+
+            while members.len() + candidates.len() < RING_SIZE {
+                if attempts == MAX_DECOY_PICKS {
+                    bail!("Not enough spendable outputs on the chain to pick decoys from")
+                }
+                attempts += 1;
+
+                if let Some(decoy) = selector.pick(&mut rng) {
+                    if !members.contains_key(&decoy)
+                        && !locked.contains(&decoy)
+                        && !candidates.contains(&decoy)
+                    {
+                        candidates.push(decoy);
+                    }
+                }
+            }
+        }
+
+        let (indices, members): (Vec<_>, Vec<_>) = members.into_iter().unzip();
+        let real_position = indices
+            .iter()
+            .position(|index| *index == real_output)
+            .expect("real output to be in the ring");
+
+        Ok(Ring {
+            indices,
+            members,
+            real_position,
+        })
+    }
+}
+
+/// The result of checking a transfer with its transaction key.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transfer {
+    pub confirmations: u64,
+    /// The amount in piconero.
+    pub received: u64,
+}
+
+/// The outputs referenced by an input, sorted by their global index.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ring {
+    pub indices: Vec<u64>,
+    pub members: Vec<OutKey>,
+    /// The position of the output that is actually spent.
+    pub real_position: usize,
+}
 
-        Ok([
-            VarInt(rng.gen_range(oldest_index, last_index)),
-            VarInt(rng.gen_range(oldest_index, last_index)),
-            VarInt(rng.gen_range(oldest_index, last_index)),
-            VarInt(rng.gen_range(oldest_index, last_index)),
-            VarInt(rng.gen_range(oldest_index, last_index)),
-            VarInt(rng.gen_range(oldest_index, last_index)),
-            VarInt(rng.gen_range(oldest_index, last_index)),
-            VarInt(rng.gen_range(oldest_index, last_index)),
-            VarInt(rng.gen_range(oldest_index, last_index)),
-            VarInt(rng.gen_range(oldest_index, last_index)),
-        ])
+impl Ring {
+    /// The key offsets of a `TxIn::ToKey` spending from this ring.
+    pub fn key_offsets(&self) -> Vec<VarInt> {
+        decoys::relative_offsets(&self.indices)
     }
 }
 
+/// The fee for the weight, rounded up like monerod expects.
+fn fee_for_weight(weight: u64, estimate: &FeeEstimate) -> u64 {
+    let fee = weight * estimate.fee * DEFAULT_PRIORITY_MULTIPLIER;
+    let quantization_mask = estimate.quantization_mask.max(1);
+
+    (fee + quantization_mask - 1) / quantization_mask * quantization_mask
+}
+
+fn scan_transaction(view_pair: &ViewPair, transaction: &Transaction) -> Result<Vec<OwnedOutput>> {
+    let tx_pubkey = match scan::tx_pubkey(transaction) {
+        Some(tx_pubkey) => tx_pubkey,
+        None => return Ok(Vec::new()),
+    };
+    let derivation = KeyDerivation::from_view_key(&view_pair.view, &tx_pubkey)?;
+
+    scan::owned_outputs(transaction, &derivation, &view_pair.spend)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use curve25519_dalek::scalar::Scalar;
+    use monero::{Address, Network};
     use monero_harness::image::Monerod;
-    use monero_rpc::monerod::{Client, GetOutputsOut};
+    use monero_harness::Monero;
+    use monero_rpc::monerod::Client;
+    use std::str::FromStr;
     use testcontainers::clients::Cli;
     use testcontainers::Docker;
 
+    #[test]
+    fn rounds_fee_up_to_quantization_mask() {
+        let estimate = FeeEstimate {
+            fee: 7_874,
+            quantization_mask: 10_000,
+        };
+
+        assert_eq!(fee_for_weight(1_500, &estimate), 59_060_000);
+        assert_eq!(fee_for_weight(0, &estimate), 0);
+    }
+
     #[tokio::test]
-    async fn get_outs_for_key_offsets() {
+    async fn fetches_ring_with_decoys() {
         let cli = Cli::default();
         let container = cli.run(Monerod::default());
         let rpc_client = Client::localhost(container.get_host_port(18081).unwrap()).unwrap();
         rpc_client.generateblocks(150, "498AVruCDWgP9Az9LjMm89VWjrBrSZ2W2K3HFBiyzzrRjUJWUcCVxvY1iitfuKoek2FdX6MKGAD9Qb1G1P8QgR5jPmmt3Vj".to_owned()).await.unwrap();
-        let wallet = Wallet {
-            client: rpc_client.clone(),
+        let wallet = Wallet::new(rpc_client);
+
+        let selector = wallet.decoy_selector().await.unwrap();
+        let ring = wallet.fetch_ring(&selector, 42).await.unwrap();
+
+        assert_eq!(ring.members.len(), RING_SIZE);
+        // the coinbase outputs of the last 60 blocks are still locked
+        assert!(ring.members.iter().all(|member| member.unlocked));
+        assert_eq!(ring.indices[ring.real_position], 42);
+        assert!(ring.indices.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(
+            ring.key_offsets()
+                .iter()
+                .map(|offset| offset.0)
+                .sum::<u64>(),
+            *ring.indices.last().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn finds_coinbase_outputs_of_wallet() {
+        let cli = Cli::default();
+        let container = cli.run(Monerod::default());
+        let rpc_client = Client::localhost(container.get_host_port(18081).unwrap()).unwrap();
+
+        let mut rng = rand::thread_rng();
+        let view_pair = ViewPair {
+            view: PrivateKey {
+                scalar: Scalar::random(&mut rng),
+            },
+            spend: PublicKey::from_private_key(&PrivateKey {
+                scalar: Scalar::random(&mut rng),
+            }),
         };
+        let address = Address::standard(
+            Network::Mainnet,
+            view_pair.spend,
+            PublicKey::from_private_key(&view_pair.view),
+        );
+        rpc_client
+            .generateblocks(5, address.to_string())
+            .await
+            .unwrap();
+
+        let wallet = Wallet::new(rpc_client);
+        let height = wallet.block_height().await.unwrap();
+        let received = wallet.scan(&view_pair, 1..height).await.unwrap();
+
+        assert_eq!(received.len(), 5);
+        assert!(received.iter().all(|received| received.output.amount > 0));
+    }
+
+    #[tokio::test]
+    async fn checks_transfer_with_tx_key() {
+        let cli = Cli::default();
+        let (monero, _monerod_container, _wallet_containers) =
+            Monero::new(&cli, vec!["alice", "bob"]).await.unwrap();
+        monero.init_miner().await.unwrap();
+        monero
+            .init_wallet("alice", vec![1_000_000_000_000])
+            .await
+            .unwrap();
 
-        let key_offsets = wallet.choose_ten_random_key_offsets().await.unwrap();
-        let result = rpc_client
-            .get_outs(
-                key_offsets
-                    .iter()
-                    .cloned()
-                    .map(|varint| GetOutputsOut {
-                        amount: 0,
-                        index: varint.0,
-                    })
-                    .collect(),
+        let bob_address = monero
+            .wallet("bob")
+            .unwrap()
+            .address()
+            .await
+            .unwrap()
+            .address;
+        let transfer = monero
+            .wallet("alice")
+            .unwrap()
+            .transfer(&bob_address, 5_000_000_000)
+            .await
+            .unwrap();
+        let bob_address = Address::from_str(&bob_address).unwrap();
+
+        let wallet = Wallet::new(monero.monerod().client().clone());
+        let check = |tx_key| {
+            wallet.check_transfer(
+                transfer.tx_hash.parse().unwrap(),
+                tx_key,
+                &bob_address.public_spend,
+                &bob_address.public_view,
             )
+        };
+
+        let pending = check(transfer.tx_key.as_ref().unwrap()).await.unwrap();
+        assert_eq!(pending, Transfer {
+            confirmations: 0,
+            received: 5_000_000_000
+        });
+
+        monero
+            .monerod()
+            .client()
+            .generateblocks(3, bob_address.to_string())
             .await
             .unwrap();
+        let confirmed = check(transfer.tx_key.as_ref().unwrap()).await.unwrap();
+        assert_eq!(confirmed.confirmations, 3);
 
-        assert_eq!(result.outs.len(), 10);
+        let wrong_key = PrivateKey {
+            scalar: Scalar::random(&mut rand::thread_rng()),
+        };
+        assert_eq!(check(&wrong_key).await.unwrap().received, 0);
     }
 }
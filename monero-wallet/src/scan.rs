@@ -0,0 +1,294 @@
+//! Recognising the outputs of a transaction that were sent to a wallet and
+//! decoding their amounts.
+//!
+//! The sender and the receiver of an output share the key derivation
+//! `8 * r * A = 8 * a * R`, where `r` is the transaction key with the public
+//! key `R` and `a` is the private view key of the receiver with the public key
+//! `A`. Knowing either side is enough to find the outputs and amounts.
+
+use anyhow::{bail, Context, Result};
+use curve25519_dalek::constants::ED25519_BASEPOINT_TABLE;
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::Scalar;
+use hex_literal::hex;
+use monero::blockdata::transaction::{SubField, TxOutTarget};
+use monero::util::ringct::EcdhInfo;
+use monero::{PrivateKey, PublicKey, Transaction};
+use tiny_keccak::{Hasher, Keccak};
+
+/// The generator for the amounts in Pedersen commitments, `H = 8 *
+/// to_point(keccak(G))`.
+const H: [u8; 32] = hex!("8b655970153799af2aeadc9ff1add0ea6c7251d54154cfa92c173a0dd39c1f94");
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyDerivation(CompressedEdwardsY);
+
+impl KeyDerivation {
+    /// The derivation of the receiver, from its private view key and the
+    /// public key of the transaction.
+    pub fn from_view_key(view_key: &PrivateKey, tx_pubkey: &PublicKey) -> Result<Self> {
+        Self::new(view_key, tx_pubkey)
+    }
+
+    /// The derivation of the sender, from the transaction key and the public
+    /// view key of the receiver.
+    pub fn from_tx_key(tx_key: &PrivateKey, public_view_key: &PublicKey) -> Result<Self> {
+        Self::new(tx_key, public_view_key)
+    }
+
+    fn new(private_key: &PrivateKey, public_key: &PublicKey) -> Result<Self> {
+        let point = public_key
+            .point
+            .decompress()
+            .context("Public key is not a valid point")?;
+
+        Ok(Self(
+            (private_key.scalar * point).mul_by_cofactor().compress(),
+        ))
+    }
+
+    /// `Hs(derivation || varint(index))`, the secret shared for the output at
+    /// `index`.
+    fn shared_secret(&self, index: usize) -> Scalar {
+        let mut buffer = self.0.as_bytes().to_vec();
+        write_varint(&mut buffer, index as u64);
+
+        hash_to_scalar(&[buffer.as_slice()])
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OwnedOutput {
+    /// The index of the output within the transaction.
+    pub index: usize,
+    pub key: PublicKey,
+    /// The amount in piconero.
+    pub amount: u64,
+}
+
+/// The public key of the transaction, from its extra field.
+pub fn tx_pubkey(transaction: &Transaction) -> Option<PublicKey> {
+    transaction
+        .prefix
+        .extra
+        .0
+        .iter()
+        .find_map(|field| match field {
+            SubField::TxPublicKey(key) => Some(*key),
+            _ => None,
+        })
+}
+
+/// The outputs of the transaction that were sent to the address with the
+/// public spend key `spend_key`.
+///
+/// Fails if an output belongs to the address but its amount does not match
+/// its commitment, i.e. the sender lied about the amount.
+pub fn owned_outputs(
+    transaction: &Transaction,
+    derivation: &KeyDerivation,
+    spend_key: &PublicKey,
+) -> Result<Vec<OwnedOutput>> {
+    let spend_point = spend_key
+        .point
+        .decompress()
+        .context("Public spend key is not a valid point")?;
+
+    let mut owned = Vec::new();
+
+    for (index, output) in transaction.prefix.outputs.iter().enumerate() {
+        let key = match output.target {
+            TxOutTarget::ToKey { key } => key,
+            _ => continue,
+        };
+
+        let shared_secret = derivation.shared_secret(index);
+        let expected_key = &shared_secret * &ED25519_BASEPOINT_TABLE + spend_point;
+
+        if expected_key.compress() != key.point {
+            continue;
+        }
+
+        // Only the amounts of RingCT outputs are hidden, coinbase outputs still show
+        // them in the clear
+        let amount = match output.amount.0 {
+            0 => decode_ringct_amount(transaction, index, &shared_secret)?,
+            amount => amount,
+        };
+
+        owned.push(OwnedOutput { index, key, amount });
+    }
+
+    Ok(owned)
+}
+
+fn decode_ringct_amount(
+    transaction: &Transaction,
+    index: usize,
+    shared_secret: &Scalar,
+) -> Result<u64> {
+    let base = transaction
+        .rct_signatures
+        .sig
+        .as_ref()
+        .context("RingCT transaction without signature")?;
+    let ecdh_info = base
+        .ecdh_info
+        .get(index)
+        .context("No encrypted amount for output")?;
+    let commitment = base.out_pk.get(index).context("No commitment for output")?;
+
+    let (amount, mask) = decode_amount(ecdh_info, shared_secret);
+
+    if commitment_to(&mask, amount).compress().to_bytes() != commitment.mask.key {
+        bail!("Amount of output {} does not match its commitment", index)
+    }
+
+    Ok(amount)
+}
+
+/// Decrypt the amount of an output and the mask of its commitment.
+fn decode_amount(ecdh_info: &EcdhInfo, shared_secret: &Scalar) -> (u64, Scalar) {
+    match ecdh_info {
+        EcdhInfo::Standard { mask, amount } => {
+            let mask_secret = hash_to_scalar(&[&shared_secret.as_bytes()[..]]);
+            let amount_secret = hash_to_scalar(&[&mask_secret.as_bytes()[..]]);
+
+            let mask = Scalar::from_bytes_mod_order(mask.key) - mask_secret;
+            let amount = Scalar::from_bytes_mod_order(amount.key) - amount_secret;
+
+            (u64_from_le_prefix(amount.as_bytes()), mask)
+        }
+        EcdhInfo::Bulletproof { amount } => {
+            let key = keccak256(&[b"amount".as_ref(), shared_secret.as_bytes()]);
+
+            let mut decrypted = [0u8; 8];
+            for (i, byte) in decrypted.iter_mut().enumerate() {
+                *byte = amount.0[i] ^ key[i];
+            }
+
+            let mask = hash_to_scalar(&[b"commitment_mask".as_ref(), shared_secret.as_bytes()]);
+
+            (u64::from_le_bytes(decrypted), mask)
+        }
+    }
+}
+
+/// The Pedersen commitment `mask * G + amount * H`.
+fn commitment_to(mask: &Scalar, amount: u64) -> EdwardsPoint {
+    let h = CompressedEdwardsY(H)
+        .decompress()
+        .expect("H to be a valid point");
+
+    mask * &ED25519_BASEPOINT_TABLE + Scalar::from(amount) * h
+}
+
+fn u64_from_le_prefix(bytes: &[u8; 32]) -> u64 {
+    let mut prefix = [0u8; 8];
+    prefix.copy_from_slice(&bytes[..8]);
+
+    u64::from_le_bytes(prefix)
+}
+
+fn keccak256(inputs: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    for input in inputs {
+        hasher.update(input);
+    }
+
+    let mut output = [0u8; 32];
+    hasher.finalize(&mut output);
+
+    output
+}
+
+fn hash_to_scalar(inputs: &[&[u8]]) -> Scalar {
+    Scalar::from_bytes_mod_order(keccak256(inputs))
+}
+
+fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use monero::cryptonote::hash::Hash8;
+    use monero::util::ringct::Key;
+
+    #[test]
+    fn sender_and_receiver_share_the_derivation() {
+        let mut rng = rand::thread_rng();
+        let view_key = PrivateKey {
+            scalar: Scalar::random(&mut rng),
+        };
+        let tx_key = PrivateKey {
+            scalar: Scalar::random(&mut rng),
+        };
+
+        let receiver =
+            KeyDerivation::from_view_key(&view_key, &PublicKey::from_private_key(&tx_key));
+        let sender = KeyDerivation::from_tx_key(&tx_key, &PublicKey::from_private_key(&view_key));
+
+        assert_eq!(receiver.unwrap(), sender.unwrap());
+    }
+
+    #[test]
+    fn decodes_compact_amount() {
+        let shared_secret = Scalar::random(&mut rand::thread_rng());
+        let key = keccak256(&[b"amount".as_ref(), shared_secret.as_bytes()]);
+
+        let mut encrypted = 1_234_567_890u64.to_le_bytes();
+        for (i, byte) in encrypted.iter_mut().enumerate() {
+            *byte ^= key[i];
+        }
+
+        let (amount, mask) = decode_amount(
+            &EcdhInfo::Bulletproof {
+                amount: Hash8(encrypted),
+            },
+            &shared_secret,
+        );
+
+        assert_eq!(amount, 1_234_567_890);
+        assert_eq!(
+            mask,
+            hash_to_scalar(&[b"commitment_mask".as_ref(), shared_secret.as_bytes()])
+        );
+    }
+
+    #[test]
+    fn decodes_standard_amount() {
+        let shared_secret = Scalar::random(&mut rand::thread_rng());
+        let mask = Scalar::random(&mut rand::thread_rng());
+        let mask_secret = hash_to_scalar(&[&shared_secret.as_bytes()[..]]);
+        let amount_secret = hash_to_scalar(&[&mask_secret.as_bytes()[..]]);
+
+        let (amount, decoded_mask) = decode_amount(
+            &EcdhInfo::Standard {
+                mask: Key {
+                    key: (mask + mask_secret).to_bytes(),
+                },
+                amount: Key {
+                    key: (Scalar::from(42u64) + amount_secret).to_bytes(),
+                },
+            },
+            &shared_secret,
+        );
+
+        assert_eq!(amount, 42);
+        assert_eq!(decoded_mask, mask);
+    }
+
+    #[test]
+    fn encodes_varints_like_monero() {
+        let mut buffer = Vec::new();
+        write_varint(&mut buffer, 300);
+
+        assert_eq!(buffer, vec![0xac, 0x02]);
+    }
+}
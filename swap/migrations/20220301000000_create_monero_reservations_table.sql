@@ -0,0 +1,5 @@
+CREATE TABLE if NOT EXISTS monero_reservations
+(
+    swap_id     TEXT    PRIMARY KEY NOT NULL,
+    amount      TEXT                NOT NULL
+);
@@ -0,0 +1,10 @@
+CREATE TABLE if NOT EXISTS seller_observations
+(
+    id              INTEGER PRIMARY KEY autoincrement NOT NULL,
+    peer_id         TEXT                NOT NULL,
+    address         TEXT                NOT NULL,
+    observed_at     TEXT                NOT NULL,
+    price           TEXT,
+    min_quantity    TEXT,
+    max_quantity    TEXT
+);
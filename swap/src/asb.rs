@@ -4,12 +4,18 @@ mod event_loop;
 mod network;
 mod rate;
 mod recovery;
+mod reservations;
+pub mod rpc;
 pub mod tracing;
+pub mod webhook;
 
-pub use event_loop::{EventLoop, EventLoopHandle, FixedRate, KrakenRate, LatestRate};
+pub use event_loop::{
+    EventLoop, EventLoopError, EventLoopHandle, EventLoopService, FixedRate, LatestRate, NoRate,
+    PriceFeedRate,
+};
 pub use network::behaviour::{Behaviour, OutEvent};
 pub use network::transport;
-pub use rate::Rate;
+pub use rate::{Rate, SpreadCurve};
 pub use recovery::cancel::cancel;
 pub use recovery::punish::punish;
 pub use recovery::redeem::{redeem, Finality};
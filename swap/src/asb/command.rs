@@ -1,9 +1,9 @@
 use crate::asb::config::GetDefaults;
-use crate::bitcoin::Amount;
+use crate::bitcoin::{parse_fee_rate, Amount, CoinControl, Recipient};
 use crate::env;
 use crate::env::GetConfig;
 use anyhow::{bail, Result};
-use bitcoin::Address;
+use bitcoin::{Address, OutPoint};
 use serde::Serialize;
 use std::ffi::OsString;
 use std::path::PathBuf;
@@ -41,17 +41,59 @@ where
             env_config: env_config(testnet),
             cmd: Command::History,
         },
-        RawCommand::WithdrawBtc { amount, address } => Arguments {
-            testnet,
-            json,
-            disable_timestamp,
-            config_path: config_path(config, testnet)?,
-            env_config: env_config(testnet),
-            cmd: Command::WithdrawBtc {
-                amount,
-                address: bitcoin_address(address, testnet)?,
-            },
-        },
+        RawCommand::WithdrawBtc {
+            amount,
+            address,
+            recipients,
+            utxos,
+            avoid_utxos,
+            fee_rate,
+            dry_run,
+        } => {
+            let mut recipients = recipients
+                .into_iter()
+                .map(|recipient| {
+                    Ok(Recipient {
+                        address: bitcoin_address(recipient.address, testnet)?,
+                        amount: recipient.amount,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let address = address
+                .map(|address| bitcoin_address(address, testnet))
+                .transpose()?;
+
+            let drain_to = match (address, amount) {
+                (Some(address), Some(amount)) => {
+                    recipients.insert(0, Recipient { address, amount });
+                    None
+                }
+                (Some(address), None) => Some(address),
+                (None, Some(_)) => bail!("An amount can only be withdrawn to an --address"),
+                (None, None) if recipients.is_empty() => {
+                    bail!("Give an --address or --recipient to withdraw to")
+                }
+                (None, None) => None,
+            };
+
+            Arguments {
+                testnet,
+                json,
+                disable_timestamp,
+                config_path: config_path(config, testnet)?,
+                env_config: env_config(testnet),
+                cmd: Command::WithdrawBtc {
+                    recipients,
+                    drain_to,
+                    coin_control: CoinControl {
+                        spend: utxos,
+                        avoid: avoid_utxos,
+                        fee_rate,
+                    },
+                    dry_run,
+                },
+            }
+        }
         RawCommand::Balance => Arguments {
             testnet,
             json,
@@ -198,8 +240,10 @@ pub enum Command {
     History,
     Config,
     WithdrawBtc {
-        amount: Option<Amount>,
-        address: Address,
+        recipients: Vec<Recipient>,
+        drain_to: Option<Address>,
+        coin_control: CoinControl,
+        dry_run: bool,
     },
     Balance,
     Redeem {
@@ -279,8 +323,37 @@ pub enum RawCommand {
             help = "Optionally specify the amount of Bitcoin to be withdrawn. If not specified the wallet will be drained."
         )]
         amount: Option<Amount>,
-        #[structopt(long = "address", help = "The address to receive the Bitcoin.")]
-        address: Address,
+        #[structopt(
+            long = "address",
+            help = "The address to receive the Bitcoin. Without an amount, it receives everything left after paying the recipients and the fee."
+        )]
+        address: Option<Address>,
+        #[structopt(
+            long = "recipient",
+            help = "Pay an amount to an address in the same transaction, given as <address>=<amount>, e.g. \"bc1q...=0.1 BTC\". Can be given multiple times."
+        )]
+        recipients: Vec<Recipient>,
+        #[structopt(
+            long = "utxo",
+            help = "Spend only this output of the wallet, given as <txid>:<vout>. Can be given multiple times."
+        )]
+        utxos: Vec<OutPoint>,
+        #[structopt(
+            long = "avoid-utxo",
+            help = "Never spend this output of the wallet, given as <txid>:<vout>. Can be given multiple times."
+        )]
+        avoid_utxos: Vec<OutPoint>,
+        #[structopt(
+            long = "fee-rate",
+            help = "Pay this fee rate in sat/vB instead of the estimated one.",
+            parse(try_from_str = parse_fee_rate)
+        )]
+        fee_rate: Option<f32>,
+        #[structopt(
+            long = "dry-run",
+            help = "Print the unsigned transaction as a base64 encoded PSBT instead of publishing it."
+        )]
+        dry_run: bool,
     },
     #[structopt(
         about = "Prints the Bitcoin and Monero balance. Requires the monero-wallet-rpc to be running."
@@ -356,6 +429,7 @@ mod tests {
     const BITCOIN_MAINNET_ADDRESS: &str = "1KFHE7w8BhaENAswwryaoccDb6qcT6DbYY";
     const BITCOIN_TESTNET_ADDRESS: &str = "tb1qyccwk4yun26708qg5h6g6we8kxln232wclxf5a";
     const SWAP_ID: &str = "ea030832-3be9-454f-bb98-5ea9a788406b";
+    const UTXO: &str = "c5c1b9e4a3e2ee3a8d2b6a9de5d1b4f2c7c7e0c4f4c2b1a8c7d6e5f4a3b2c1d0:1";
 
     #[test]
     fn ensure_start_command_mapping_mainnet() {
@@ -428,8 +502,10 @@ mod tests {
             config_path: default_mainnet_conf_path,
             env_config: mainnet_env_config,
             cmd: Command::WithdrawBtc {
-                amount: None,
-                address: Address::from_str(BITCOIN_MAINNET_ADDRESS).unwrap(),
+                recipients: vec![],
+                drain_to: Some(Address::from_str(BITCOIN_MAINNET_ADDRESS).unwrap()),
+                coin_control: CoinControl::default(),
+                dry_run: false,
             },
         };
         let args = parse_args(raw_ars).unwrap();
@@ -613,13 +689,92 @@ mod tests {
             config_path: default_testnet_conf_path,
             env_config: testnet_env_config,
             cmd: Command::WithdrawBtc {
-                amount: None,
-                address: Address::from_str(BITCOIN_TESTNET_ADDRESS).unwrap(),
+                recipients: vec![],
+                drain_to: Some(Address::from_str(BITCOIN_TESTNET_ADDRESS).unwrap()),
+                coin_control: CoinControl::default(),
+                dry_run: false,
+            },
+        };
+        let args = parse_args(raw_ars).unwrap();
+        assert_eq!(expected_args, args);
+    }
+
+    #[test]
+    fn ensure_batched_withdraw_command_mapping_testnet() {
+        let default_testnet_conf_path = env::Testnet::getConfigFileDefaults().unwrap().config_path;
+        let testnet_env_config = env::Testnet::get_config();
+        let recipient = format!("{}=0.2 BTC", BITCOIN_TESTNET_ADDRESS);
+
+        let raw_ars = vec![
+            BINARY_NAME,
+            "--testnet",
+            "withdraw-btc",
+            "--address",
+            BITCOIN_TESTNET_ADDRESS,
+            "--amount",
+            "0.1 BTC",
+            "--recipient",
+            &recipient,
+            "--utxo",
+            UTXO,
+            "--fee-rate",
+            "5",
+            "--dry-run",
+        ];
+        let expected_args = Arguments {
+            testnet: true,
+            json: false,
+            disable_timestamp: false,
+            config_path: default_testnet_conf_path,
+            env_config: testnet_env_config,
+            cmd: Command::WithdrawBtc {
+                recipients: vec![
+                    Recipient {
+                        address: Address::from_str(BITCOIN_TESTNET_ADDRESS).unwrap(),
+                        amount: Amount::from_sat(10_000_000),
+                    },
+                    Recipient {
+                        address: Address::from_str(BITCOIN_TESTNET_ADDRESS).unwrap(),
+                        amount: Amount::from_sat(20_000_000),
+                    },
+                ],
+                drain_to: None,
+                coin_control: CoinControl {
+                    spend: vec![OutPoint::from_str(UTXO).unwrap()],
+                    avoid: vec![],
+                    fee_rate: Some(5.0),
+                },
+                dry_run: true,
             },
         };
         let args = parse_args(raw_ars).unwrap();
         assert_eq!(expected_args, args);
     }
+
+    #[test]
+    fn given_no_address_or_recipient_then_withdraw_fails() {
+        let raw_ars = vec![BINARY_NAME, "--testnet", "withdraw-btc"];
+
+        assert!(parse_args(raw_ars).is_err());
+    }
+
+    #[test]
+    fn given_non_positive_fee_rate_then_withdraw_fails() {
+        for fee_rate in ["0", "-1", "NaN"] {
+            let raw_ars = vec![
+                BINARY_NAME,
+                "--testnet",
+                "withdraw-btc",
+                "--address",
+                BITCOIN_TESTNET_ADDRESS,
+                "--fee-rate",
+                fee_rate,
+            ];
+
+            assert!(parse_args(raw_ars).is_err(), "{}", fee_rate);
+        }
+    }
+
     #[test]
     fn ensure_cancel_command_mapping_testnet() {
         let default_testnet_conf_path = env::Testnet::getConfigFileDefaults().unwrap().config_path;
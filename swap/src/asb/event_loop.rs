@@ -1,11 +1,18 @@
+use crate::asb::config::BuyXmr;
+use crate::asb::rate::{PriceHistory, SpreadCurve};
+use crate::asb::reservations::Reservations;
 use crate::asb::{Behaviour, OutEvent, Rate};
+use crate::network::ask_quote::AskQuote;
 use crate::network::quote::BidQuote;
 use crate::network::swap_setup::alice::WalletSnapshot;
-use crate::network::transfer_proof;
+use crate::network::swap_setup::reverse::bob::BtcReservation;
+use crate::network::{encrypted_signature, transfer_proof};
 use crate::protocol::alice::{AliceState, State3, Swap};
-use crate::protocol::{Database, State};
-use crate::{bitcoin, env, kraken, monero};
-use anyhow::{Context, Result};
+use crate::protocol::bob::BobState;
+use crate::protocol::event::{SwapEvent, SwapEvents};
+use crate::protocol::{bob, Database, State};
+use crate::{bitcoin, cli, env, monero, price_feed};
+use anyhow::{anyhow, Context, Result};
 use futures::future;
 use futures::future::{BoxFuture, FutureExt};
 use futures::stream::{FuturesUnordered, StreamExt};
@@ -13,11 +20,15 @@ use libp2p::request_response::{RequestId, ResponseChannel};
 use libp2p::swarm::SwarmEvent;
 use libp2p::{PeerId, Swarm};
 use rust_decimal::Decimal;
-use std::collections::HashMap;
-use std::convert::{Infallible, TryInto};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
 use std::fmt::Debug;
-use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, oneshot};
 use uuid::Uuid;
 
 /// A future that resolves to a tuple of `PeerId`, `transfer_proof::Request` and
@@ -30,6 +41,26 @@ use uuid::Uuid;
 type OutgoingTransferProof =
     BoxFuture<'static, Result<(PeerId, transfer_proof::Request, bmrng::Responder<()>)>>;
 
+/// The same as [`OutgoingTransferProof`] for the encrypted signatures we send
+/// as Bob.
+type OutgoingEncryptedSignature =
+    BoxFuture<'static, Result<(PeerId, encrypted_signature::Request, bmrng::Responder<()>)>>;
+
+/// How many errors are buffered for slow subscribers.
+const ERRORS_CHANNEL_CAPACITY: usize = 100;
+
+/// An error the event loop ran into that did not stop it, but that the
+/// operator should know about.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename = "event_loop_error")]
+pub struct EventLoopError {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swap_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peer: Option<String>,
+    pub error: String,
+}
+
 #[allow(missing_debug_implementations)]
 pub struct EventLoop<LR>
 where
@@ -43,8 +74,11 @@ where
     latest_rate: LR,
     min_buy: bitcoin::Amount,
     max_buy: bitcoin::Amount,
+    buy_xmr: Option<BuyXmr>,
 
     swap_sender: mpsc::Sender<Swap>,
+    /// Receives the swaps in which we buy XMR, i.e. are in the role of Bob.
+    bob_swap_sender: mpsc::Sender<bob::Swap>,
 
     /// Stores incoming [`EncryptedSignature`]s per swap.
     recv_encrypted_signature: HashMap<Uuid, bmrng::RequestSender<bitcoin::EncryptedSignature, ()>>,
@@ -59,6 +93,42 @@ where
     /// Tracks [`transfer_proof::Request`]s which are currently inflight and
     /// awaiting an acknowledgement.
     inflight_transfer_proofs: HashMap<RequestId, bmrng::Responder<()>>,
+
+    /// Stores incoming [`monero::TransferProof`]s per swap in which we are Bob.
+    recv_transfer_proof: HashMap<Uuid, bmrng::RequestSender<monero::TransferProof, ()>>,
+    inflight_transfer_proof_acks: FuturesUnordered<BoxFuture<'static, ResponseChannel<()>>>,
+
+    send_encrypted_signature: FuturesUnordered<OutgoingEncryptedSignature>,
+
+    /// Tracks [`encrypted_signature::Request`]s which could not yet be sent
+    /// because we are currently disconnected from the peer.
+    buffered_encrypted_signatures:
+        HashMap<PeerId, Vec<(encrypted_signature::Request, bmrng::Responder<()>)>>,
+
+    /// Tracks [`encrypted_signature::Request`]s which are currently inflight
+    /// and awaiting an acknowledgement.
+    inflight_encrypted_signature_requests: HashMap<RequestId, bmrng::Responder<()>>,
+
+    /// The peers we currently have at least one connection to.
+    connected_peers: HashSet<PeerId>,
+
+    /// Requests of the [`EventLoopService`]s handed out by this event loop.
+    service_requests: MpscChannels<ServiceRequest>,
+
+    /// Shared by all swaps started by this event loop.
+    swap_events: SwapEvents,
+    errors: broadcast::Sender<EventLoopError>,
+
+    /// Monero held back for swaps that did not lock them yet, the ones of
+    /// started swaps are persisted in the database.
+    reservations: Reservations<monero::Amount>,
+    /// Bitcoin held back for swaps buying XMR that did not lock it yet.
+    btc_reservations: Reservations<bitcoin::Amount>,
+
+    /// The prices of the last quotes made from an up-to-date rate, quoted
+    /// without quantities while the rate is outdated.
+    last_quote_price: Option<bitcoin::Amount>,
+    last_ask_quote_price: Option<bitcoin::Amount>,
 }
 
 impl<LR> EventLoop<LR>
@@ -75,8 +145,10 @@ where
         latest_rate: LR,
         min_buy: bitcoin::Amount,
         max_buy: bitcoin::Amount,
-    ) -> Result<(Self, mpsc::Receiver<Swap>)> {
+        buy_xmr: Option<BuyXmr>,
+    ) -> Result<(Self, mpsc::Receiver<Swap>, mpsc::Receiver<bob::Swap>)> {
         let swap_channel = MpscChannels::default();
+        let bob_swap_channel = MpscChannels::default();
 
         let event_loop = EventLoop {
             swarm,
@@ -86,36 +158,110 @@ where
             db,
             latest_rate,
             swap_sender: swap_channel.sender,
+            bob_swap_sender: bob_swap_channel.sender,
             min_buy,
             max_buy,
+            buy_xmr,
             recv_encrypted_signature: Default::default(),
             inflight_encrypted_signatures: Default::default(),
             send_transfer_proof: Default::default(),
             buffered_transfer_proofs: Default::default(),
             inflight_transfer_proofs: Default::default(),
+            recv_transfer_proof: Default::default(),
+            inflight_transfer_proof_acks: Default::default(),
+            send_encrypted_signature: Default::default(),
+            buffered_encrypted_signatures: Default::default(),
+            inflight_encrypted_signature_requests: Default::default(),
+            connected_peers: Default::default(),
+            service_requests: MpscChannels::default(),
+            swap_events: SwapEvents::default(),
+            errors: broadcast::channel(ERRORS_CHANNEL_CAPACITY).0,
+            reservations: Default::default(),
+            btc_reservations: Default::default(),
+            last_quote_price: None,
+            last_ask_quote_price: None,
         };
-        Ok((event_loop, swap_channel.receiver))
+        Ok((event_loop, swap_channel.receiver, bob_swap_channel.receiver))
     }
 
     pub fn peer_id(&self) -> PeerId {
         *Swarm::local_peer_id(&self.swarm)
     }
 
+    /// The channel on which all swaps of this event loop publish their
+    /// progress.
+    pub fn swap_events(&self) -> SwapEvents {
+        self.swap_events.clone()
+    }
+
+    /// Subscribe to the errors the event loop runs into while handling
+    /// swaps and peers.
+    pub fn subscribe_errors(&self) -> broadcast::Receiver<EventLoopError> {
+        self.errors.subscribe()
+    }
+
+    fn report_error(&self, swap_id: Option<Uuid>, peer: Option<PeerId>, error: String) {
+        // Nobody might be subscribed, which is fine
+        let _ = self.errors.send(EventLoopError {
+            swap_id,
+            peer: peer.map(|peer| peer.to_string()),
+            error,
+        });
+    }
+
+    /// Create a service to query the state of the running event loop.
+    pub fn service(&self) -> EventLoopService {
+        EventLoopService {
+            sender: self.service_requests.sender.clone(),
+        }
+    }
+
     pub async fn run(mut self) {
         // ensure that these streams are NEVER empty, otherwise it will
         // terminate forever.
         self.send_transfer_proof.push(future::pending().boxed());
         self.inflight_encrypted_signatures
             .push(future::pending().boxed());
+        self.send_encrypted_signature
+            .push(future::pending().boxed());
+        self.inflight_transfer_proof_acks
+            .push(future::pending().boxed());
 
         let swaps = match self.db.all().await {
             Ok(swaps) => swaps,
             Err(e) => {
                 tracing::error!("Failed to load swaps from database: {}", e);
+                self.report_error(
+                    None,
+                    None,
+                    format!("Failed to load swaps from database: {}", e),
+                );
                 return;
             }
         };
 
+        match self.db.all_monero_reservations().await {
+            Ok(reservations) => {
+                for (swap_id, amount) in reservations {
+                    // Swaps that were not persisted before the restart are not resumed
+                    if self.db.get_state(swap_id).await.is_ok() {
+                        self.reservations.reserve(swap_id, amount);
+                    } else {
+                        self.release_xmr(swap_id).await;
+                    }
+                }
+            }
+            Err(e) => {
+                tracing::error!("Failed to load Monero reservations from database: {}", e);
+                self.report_error(
+                    None,
+                    None,
+                    format!("Failed to load Monero reservations from database: {}", e),
+                );
+                return;
+            }
+        }
+
         let unfinished_swaps = swaps
             .into_iter()
             .filter(|(_swap_id, state)| !state.swap_finished())
@@ -130,19 +276,34 @@ where
                 }
             };
 
-            let handle = self.new_handle(peer_id, swap_id);
+            let result = match state {
+                State::Alice(state) => {
+                    let handle = self.new_handle(peer_id, swap_id);
+
+                    let swap = Swap {
+                        event_loop_handle: handle,
+                        bitcoin_wallet: self.bitcoin_wallet.clone(),
+                        monero_wallet: self.monero_wallet.clone(),
+                        env_config: self.env_config,
+                        db: self.db.clone(),
+                        state,
+                        swap_id,
+                        events: self.swap_events.clone(),
+                    };
+
+                    self.swap_sender.send(swap).await.map_err(|_| ())
+                }
+                State::Bob(state) => {
+                    if let BobState::SwapSetupCompleted(state2) = &state {
+                        self.btc_reservations.reserve(swap_id, state2.lock_amount());
+                    }
+                    let swap = self.new_bob_swap(peer_id, swap_id, state);
 
-            let swap = Swap {
-                event_loop_handle: handle,
-                bitcoin_wallet: self.bitcoin_wallet.clone(),
-                monero_wallet: self.monero_wallet.clone(),
-                env_config: self.env_config,
-                db: self.db.clone(),
-                state: state.try_into().expect("Alice state loaded from db"),
-                swap_id,
+                    self.bob_swap_sender.send(swap).await.map_err(|_| ())
+                }
             };
 
-            match self.swap_sender.send(swap).await {
+            match result {
                 Ok(_) => tracing::info!(%swap_id, "Resuming swap"),
                 Err(_) => {
                     tracing::warn!(%swap_id, "Failed to resume swap because receiver has been dropped")
@@ -154,29 +315,106 @@ where
             tokio::select! {
                 swarm_event = self.swarm.select_next_some() => {
                     match swarm_event {
-                        SwarmEvent::Behaviour(OutEvent::SwapSetupInitiated { mut send_wallet_snapshot }) => {
+                        SwarmEvent::Behaviour(OutEvent::SwapSetupInitiated { peer_id, setup_id, mut send_wallet_snapshot }) => {
 
                             let (btc, responder) = match send_wallet_snapshot.recv().await {
                                 Ok((btc, responder)) => (btc, responder),
                                 Err(error) => {
                                     tracing::error!("Swap request will be ignored because of a failure when requesting information for the wallet snapshot: {:#}", error);
+                                    self.report_error(None, None, format!("Failed to request information for the wallet snapshot: {:#}", error));
                                     continue;
                                 }
                             };
 
-                            let wallet_snapshot = match WalletSnapshot::capture(&self.bitcoin_wallet, &self.monero_wallet, btc).await {
+                            let available_xmr = match self.available_xmr().await {
+                                Ok(available_xmr) => available_xmr,
+                                Err(error) => {
+                                    tracing::error!("Swap request will be ignored because we were unable to get the available Monero: {:#}", error);
+                                    self.report_error(None, Some(peer_id), format!("Failed to get the available Monero: {:#}", error));
+                                    continue;
+                                }
+                            };
+
+                            let lock_fee = match self.monero_wallet.estimate_fee().await {
+                                Ok(lock_fee) => lock_fee,
+                                Err(error) => {
+                                    tracing::error!("Swap request will be ignored because we were unable to estimate the Monero fee: {:#}", error);
+                                    self.report_error(None, Some(peer_id), format!("Failed to estimate the Monero fee: {:#}", error));
+                                    continue;
+                                }
+                            };
+
+                            let wallet_snapshot = match WalletSnapshot::capture(&self.bitcoin_wallet, available_xmr, lock_fee, btc).await {
                                 Ok(wallet_snapshot) => wallet_snapshot,
                                 Err(error) => {
                                     tracing::error!("Swap request will be ignored because we were unable to create wallet snapshot for swap: {:#}", error);
+                                    self.report_error(None, None, format!("Failed to create wallet snapshot: {:#}", error));
                                     continue;
                                 }
                             };
 
+                            // Hold back the Monero of this swap until its setup is done, so they are not
+                            // promised to another peer setting up a swap at the same time. Without the
+                            // amount of the swap we hold back all of them.
+                            let reserved = match self.latest_rate.latest_rate().map_err(anyhow::Error::from).and_then(|rate| rate.sell_quote(btc, available_xmr)) {
+                                Ok(xmr) => xmr + lock_fee,
+                                Err(error) => {
+                                    tracing::warn!(%peer_id, "Reserving all available Monero for swap setup because its amount is unknown: {:#}", error);
+                                    available_xmr
+                                }
+                            };
+                            self.reservations.reserve_for_setup(setup_id, reserved, Instant::now());
+
                             // Ignore result, we should never hit this because the receiver will alive as long as the connection is.
                             let _ = responder.respond(wallet_snapshot);
                         }
-                        SwarmEvent::Behaviour(OutEvent::SwapSetupCompleted{peer_id, swap_id, state3}) => {
-                            let _ = self.handle_execution_setup_done(peer_id, swap_id, state3).await;
+                        SwarmEvent::Behaviour(OutEvent::SwapSetupCompleted{peer_id, setup_id, swap_id, state3}) => {
+                            let _ = self.handle_execution_setup_done(peer_id, setup_id, swap_id, state3).await;
+                        }
+                        SwarmEvent::Behaviour(OutEvent::ReverseSwapSetupInitiated { peer_id, setup_id, mut reserve_btc }) => {
+                            let ((swap_id, btc), responder) = match reserve_btc.recv().await {
+                                Ok(request) => request,
+                                Err(error) => {
+                                    tracing::error!("Reverse swap request will be ignored because of a failure when receiving the amount to reserve: {:#}", error);
+                                    self.report_error(None, Some(peer_id), format!("Failed to receive the Bitcoin to reserve: {:#}", error));
+                                    continue;
+                                }
+                            };
+
+                            let available_btc = match self.available_btc().await {
+                                Ok(available_btc) => available_btc,
+                                Err(error) => {
+                                    tracing::error!("Reverse swap request will be ignored because we were unable to get the available Bitcoin: {:#}", error);
+                                    self.report_error(None, Some(peer_id), format!("Failed to get the available Bitcoin: {:#}", error));
+                                    continue;
+                                }
+                            };
+
+                            // Hold back the Bitcoin of this swap until its setup is done, so they are not
+                            // promised to another peer selling XMR at the same time
+                            let reservation = if self.swap_id_taken(swap_id).await {
+                                tracing::warn!(%peer_id, %swap_id, "Refusing reverse swap with a swap id that is already taken");
+                                BtcReservation::SwapIdTaken
+                            } else if btc <= available_btc {
+                                self.btc_reservations.reserve_for_setup(setup_id, btc, Instant::now());
+                                BtcReservation::Reserved
+                            } else {
+                                BtcReservation::BalanceTooLow { available: available_btc }
+                            };
+
+                            // Ignore result, we should never hit this because the receiver will alive as long as the connection is.
+                            let _ = responder.respond(reservation);
+                        }
+                        SwarmEvent::Behaviour(OutEvent::ReverseSwapSetupCompleted{peer_id, setup_id, swap_id, state2}) => {
+                            self.handle_reverse_swap_setup_done(peer_id, setup_id, swap_id, *state2).await;
+                        }
+                        SwarmEvent::Behaviour(OutEvent::SwapSetupFailed { peer, setup_id, error }) => {
+                            // Setup ids are unique across both directions, only the reservation of this setup is released
+                            self.reservations.release_setup(setup_id);
+                            self.btc_reservations.release_setup(setup_id);
+
+                            tracing::error!(%peer, "Swap setup failed: {:#}", error);
+                            self.report_error(None, Some(peer), format!("Swap setup failed: {:#}", error));
                         }
                         SwarmEvent::Behaviour(OutEvent::SwapDeclined { peer, error }) => {
                             tracing::warn!(%peer, "Ignoring spot price request: {}", error);
@@ -184,6 +422,21 @@ where
                         SwarmEvent::Behaviour(OutEvent::QuoteRequested { channel, peer }) => {
                             let quote = match self.make_quote(self.min_buy, self.max_buy).await {
                                 Ok(quote) => quote,
+                                Err(error) if is_outdated_rate(&error) => match self.last_quote_price {
+                                    Some(price) => {
+                                        tracing::warn!(%peer, "Quoting zero amount at the last known price because the rate is outdated");
+
+                                        BidQuote {
+                                            price,
+                                            min_quantity: bitcoin::Amount::ZERO,
+                                            max_quantity: bitcoin::Amount::ZERO,
+                                        }
+                                    }
+                                    None => {
+                                        tracing::warn!(%peer, "Not quoting because the rate is outdated and no price is known yet");
+                                        continue;
+                                    }
+                                },
                                 Err(error) => {
                                     tracing::warn!(%peer, "Failed to make quote: {:#}", error);
                                     continue;
@@ -194,12 +447,96 @@ where
                                 tracing::debug!(%peer, "Failed to respond with quote");
                             }
                         }
+                        SwarmEvent::Behaviour(OutEvent::AskQuoteRequested { channel, peer }) => {
+                            let quote = match self.make_ask_quote().await {
+                                Ok(quote) => quote,
+                                Err(error) if is_outdated_rate(&error) => match self.last_ask_quote_price {
+                                    Some(price) => {
+                                        tracing::warn!(%peer, "Quoting zero amount at the last known price because the rate is outdated");
+
+                                        AskQuote {
+                                            price,
+                                            min_quantity: monero::Amount::ZERO,
+                                            max_quantity: monero::Amount::ZERO,
+                                        }
+                                    }
+                                    None => {
+                                        tracing::warn!(%peer, "Not quoting because the rate is outdated and no price is known yet");
+                                        continue;
+                                    }
+                                },
+                                Err(error) => {
+                                    tracing::warn!(%peer, "Failed to make ask quote: {:#}", error);
+                                    continue;
+                                }
+                            };
+
+                            if self.swarm.behaviour_mut().ask_quote.send_response(channel, quote).is_err() {
+                                tracing::debug!(%peer, "Failed to respond with ask quote");
+                            }
+                        }
                         SwarmEvent::Behaviour(OutEvent::TransferProofAcknowledged { peer, id }) => {
                             tracing::debug!(%peer, "Bob acknowledged transfer proof");
                             if let Some(responder) = self.inflight_transfer_proofs.remove(&id) {
                                 let _ = responder.respond(());
                             }
                         }
+                        SwarmEvent::Behaviour(OutEvent::TransferProofReceived { msg, channel, peer }) => {
+                            let swap_id = msg.swap_id;
+
+                            // Ensure that an incoming transfer proof is sent by the peer-id associated with the swap
+                            match self.db.get_peer_id(swap_id).await {
+                                Ok(swap_peer) if swap_peer == peer => {}
+                                Ok(swap_peer) => {
+                                    tracing::warn!(
+                                        %swap_id,
+                                        received_from = %peer,
+                                        expected_from = %swap_peer,
+                                        "Ignoring malicious transfer proof which was not expected from this peer",
+                                    );
+                                    continue;
+                                }
+                                Err(_) => {
+                                    tracing::warn!(
+                                        unknown_swap_id = %swap_id,
+                                        from = %peer,
+                                        "Ignoring transfer proof for unknown swap");
+                                    continue;
+                                }
+                            }
+
+                            let sender = match self.recv_transfer_proof.remove(&swap_id) {
+                                Some(sender) => sender,
+                                None => {
+                                    tracing::warn!(%swap_id, "No sender for transfer proof, maybe already handled?");
+
+                                    // We still have to acknowledge the transfer proof, otherwise Alice keeps resending it
+                                    let _ = self.swarm.behaviour_mut().transfer_proof.send_response(channel, ());
+                                    continue;
+                                }
+                            };
+
+                            let mut responder = match sender.send(msg.tx_lock_proof).await {
+                                Ok(responder) => responder,
+                                Err(_) => {
+                                    tracing::warn!(%swap_id, "Failed to relay transfer proof to swap");
+                                    self.report_error(Some(swap_id), Some(peer), "Failed to relay transfer proof to swap".to_owned());
+                                    continue;
+                                }
+                            };
+
+                            self.inflight_transfer_proof_acks.push(async move {
+                                let _ = responder.recv().await;
+
+                                channel
+                            }.boxed());
+                        }
+                        SwarmEvent::Behaviour(OutEvent::EncryptedSignatureAcknowledged { peer, id }) => {
+                            tracing::debug!(%peer, "Alice acknowledged encrypted signature");
+                            if let Some(responder) = self.inflight_encrypted_signature_requests.remove(&id) {
+                                let _ = responder.respond(());
+                            }
+                        }
                         SwarmEvent::Behaviour(OutEvent::EncryptedSignatureReceived{ msg, channel, peer }) => {
                             let swap_id = msg.swap_id;
                             let swap_peer = self.db.get_peer_id(swap_id).await;
@@ -239,6 +576,7 @@ where
                                 Ok(responder) => responder,
                                 Err(_) => {
                                     tracing::warn!(%swap_id, "Failed to relay encrypted signature to swap");
+                                    self.report_error(Some(swap_id), Some(peer), "Failed to relay encrypted signature to swap".to_owned());
                                     continue;
                                 }
                             };
@@ -249,20 +587,24 @@ where
                                 channel
                             }.boxed());
                         }
-                        SwarmEvent::Behaviour(OutEvent::Rendezvous(libp2p::rendezvous::client::Event::Registered { .. })) => {
-                            tracing::info!("Successfully registered with rendezvous node");
+                        SwarmEvent::Behaviour(OutEvent::Rendezvous(libp2p::rendezvous::client::Event::Registered { rendezvous_node, .. })) => {
+                            tracing::info!(%rendezvous_node, "Successfully registered with rendezvous node");
                         }
                         SwarmEvent::Behaviour(OutEvent::Rendezvous(libp2p::rendezvous::client::Event::RegisterFailed(error))) => {
                             tracing::error!("Registration with rendezvous node failed: {:?}", error);
+                            self.report_error(None, None, format!("Registration with rendezvous node failed: {:?}", error));
                         }
                         SwarmEvent::Behaviour(OutEvent::Failure {peer, error}) => {
                             tracing::error!(
                                 %peer,
                                 "Communication error: {:#}", error);
+                            self.report_error(None, Some(peer), format!("Communication error: {:#}", error));
                         }
                         SwarmEvent::ConnectionEstablished { peer_id: peer, endpoint, .. } => {
                             tracing::debug!(%peer, address = %endpoint.get_remote_address(), "New connection established");
 
+                            self.connected_peers.insert(peer);
+
                             if let Some(transfer_proofs) = self.buffered_transfer_proofs.remove(&peer) {
                                 for (transfer_proof, responder) in transfer_proofs {
                                     tracing::debug!(%peer, "Found buffered transfer proof for peer");
@@ -271,15 +613,28 @@ where
                                     self.inflight_transfer_proofs.insert(id, responder);
                                 }
                             }
+
+                            if let Some(encrypted_signatures) = self.buffered_encrypted_signatures.remove(&peer) {
+                                for (encrypted_signature, responder) in encrypted_signatures {
+                                    tracing::debug!(%peer, "Found buffered encrypted signature for peer");
+
+                                    let id = self.swarm.behaviour_mut().encrypted_signature.send_request(&peer, encrypted_signature);
+                                    self.inflight_encrypted_signature_requests.insert(id, responder);
+                                }
+                            }
                         }
                         SwarmEvent::IncomingConnectionError { send_back_addr: address, error, .. } => {
                             tracing::warn!(%address, "Failed to set up connection with peer: {:#}", error);
                         }
                         SwarmEvent::ConnectionClosed { peer_id: peer, num_established, endpoint, cause: Some(error) } if num_established == 0 => {
                             tracing::debug!(%peer, address = %endpoint.get_remote_address(), "Lost connection to peer: {:#}", error);
+
+                            self.connected_peers.remove(&peer);
                         }
                         SwarmEvent::ConnectionClosed { peer_id: peer, num_established, endpoint, cause: None } if num_established == 0 => {
                             tracing::info!(%peer, address = %endpoint.get_remote_address(), "Successfully closed connection");
+
+                            self.connected_peers.remove(&peer);
                         }
                         SwarmEvent::NewListenAddr{address, ..} => {
                             tracing::info!(%address, "New listen address reported");
@@ -310,6 +665,48 @@ where
                 Some(response_channel) = self.inflight_encrypted_signatures.next() => {
                     let _ = self.swarm.behaviour_mut().encrypted_signature.send_response(response_channel, ());
                 }
+                next_encrypted_signature = self.send_encrypted_signature.next() => {
+                    match next_encrypted_signature {
+                        Some(Ok((peer, encrypted_signature, responder))) => {
+                            if !self.swarm.behaviour_mut().encrypted_signature.is_connected(&peer) {
+                                tracing::warn!(%peer, "No active connection to peer, buffering encrypted signature");
+                                self.buffered_encrypted_signatures.entry(peer).or_insert_with(Vec::new).push((encrypted_signature, responder));
+                                continue;
+                            }
+
+                            let id = self.swarm.behaviour_mut().encrypted_signature.send_request(&peer, encrypted_signature);
+                            self.inflight_encrypted_signature_requests.insert(id, responder);
+                        },
+                        Some(Err(error)) => {
+                            tracing::debug!("A swap stopped without sending an encrypted signature: {:#}", error);
+                        }
+                        None => {
+                            unreachable!("stream of encrypted signature receivers must never terminate")
+                        }
+                    }
+                }
+                Some(response_channel) = self.inflight_transfer_proof_acks.next() => {
+                    let _ = self.swarm.behaviour_mut().transfer_proof.send_response(response_channel, ());
+                }
+                Some(request) = self.service_requests.receiver.recv() => {
+                    self.handle_service_request(request).await;
+                }
+            }
+        }
+    }
+
+    async fn handle_service_request(&mut self, request: ServiceRequest) {
+        // Ignore the send results, the caller may have given up waiting
+        match request {
+            ServiceRequest::Quote(responder) => {
+                let quote = self.make_quote(self.min_buy, self.max_buy).await;
+                let _ = responder.send(quote);
+            }
+            ServiceRequest::AskQuote(responder) => {
+                let _ = responder.send(self.make_ask_quote().await);
+            }
+            ServiceRequest::ConnectedPeers(responder) => {
+                let _ = responder.send(self.connected_peers.iter().copied().collect());
             }
         }
     }
@@ -319,18 +716,20 @@ where
         min_buy: bitcoin::Amount,
         max_buy: bitcoin::Amount,
     ) -> Result<BidQuote> {
+        let balance = self.available_xmr().await?;
+        let lock_fee = self.monero_wallet.estimate_fee().await?;
+
+        // The spread may grow with the swap amount, so we quote the price of the
+        // largest swap we are willing to do
         let ask_price = self
             .latest_rate
             .latest_rate()
             .context("Failed to get latest rate")?
-            .ask()
+            .ask(max_buy, balance)
             .context("Failed to compute asking price")?;
+        self.last_quote_price = Some(ask_price);
 
-        let max_bitcoin_for_monero = self
-            .monero_wallet
-            .get_balance()
-            .await?
-            .max_bitcoin_for_price(ask_price);
+        let max_bitcoin_for_monero = balance.max_bitcoin_for_price(ask_price, lock_fee);
 
         if min_buy > max_bitcoin_for_monero {
             tracing::warn!(
@@ -364,17 +763,164 @@ where
         })
     }
 
+    /// The Monero balance minus the Monero reserved for swaps that did not
+    /// lock them yet.
+    ///
+    /// Releases the reservations of swaps that locked the Monero, which are no
+    /// longer part of the balance, or that ended without locking them.
+    async fn available_xmr(&mut self) -> Result<monero::Amount> {
+        for swap_id in self.reservations.swap_ids() {
+            match self.db.get_state(swap_id).await {
+                Ok(State::Alice(
+                    AliceState::Started { .. }
+                    | AliceState::BtcLockTransactionSeen { .. }
+                    | AliceState::BtcLocked { .. },
+                )) => {}
+                Ok(_) => self.release_xmr(swap_id).await,
+                // The initial state is not persisted, the swap is still being started
+                Err(_) => {}
+            }
+        }
+
+        let balance = self.monero_wallet.get_balance().await?;
+
+        Ok(self.reservations.available(balance, Instant::now()))
+    }
+
+    /// The Bitcoin we can lock minus the Bitcoin reserved for swaps that did
+    /// not lock it yet.
+    ///
+    /// Releases the reservations of swaps that locked the Bitcoin or ended
+    /// without locking it.
+    async fn available_btc(&mut self) -> Result<bitcoin::Amount> {
+        for swap_id in self.btc_reservations.swap_ids() {
+            match self.db.get_state(swap_id).await {
+                Ok(State::Bob(BobState::SwapSetupCompleted(_))) => {}
+                Ok(_) => self.btc_reservations.release(swap_id),
+                // The initial state is not persisted, the swap is still being started
+                Err(_) => {}
+            }
+        }
+
+        let balance = self
+            .bitcoin_wallet
+            .max_giveable(bitcoin::TxLock::script_size())
+            .await?;
+
+        Ok(self.btc_reservations.available(balance, Instant::now()))
+    }
+
+    /// Whether we already have a swap with this id, in either role.
+    ///
+    /// Peers choose the ids of the swaps they set up with us and must not be
+    /// able to take over a swap of another peer.
+    async fn swap_id_taken(&self, swap_id: Uuid) -> bool {
+        self.db.get_peer_id(swap_id).await.is_ok() || self.db.get_state(swap_id).await.is_ok()
+    }
+
+    async fn reserve_xmr(&mut self, swap_id: Uuid, amount: monero::Amount) {
+        self.reservations.reserve(swap_id, amount);
+
+        if let Err(error) = self.db.insert_monero_reservation(swap_id, amount).await {
+            tracing::warn!(%swap_id, "Failed to persist Monero reservation: {:#}", error);
+        }
+    }
+
+    async fn release_xmr(&mut self, swap_id: Uuid) {
+        self.reservations.release(swap_id);
+
+        if let Err(error) = self.db.remove_monero_reservation(swap_id).await {
+            tracing::warn!(%swap_id, "Failed to release Monero reservation: {:#}", error);
+        }
+    }
+
+    async fn make_ask_quote(&mut self) -> Result<AskQuote> {
+        let rate = self
+            .latest_rate
+            .latest_rate()
+            .context("Failed to get latest rate")?;
+
+        // not buying XMR, but still telling what it is worth
+        let buy_xmr = match self.buy_xmr {
+            Some(buy_xmr) => buy_xmr,
+            None => {
+                let price = rate.market_price();
+                self.last_ask_quote_price = Some(price);
+
+                return Ok(AskQuote {
+                    price,
+                    min_quantity: monero::Amount::ZERO,
+                    max_quantity: monero::Amount::ZERO,
+                });
+            }
+        };
+
+        let bid_price = rate
+            .bid(buy_xmr.bid_spread)
+            .context("Failed to compute bidding price")?;
+        self.last_ask_quote_price = Some(bid_price);
+
+        let balance = self.available_btc().await?;
+        let max_monero_for_bitcoin = rate.buy_limit(balance, buy_xmr.bid_spread)?;
+
+        if buy_xmr.min_buy_xmr > max_monero_for_bitcoin {
+            tracing::warn!(
+                        "Your Bitcoin balance is too low to buy XMR, as your minimum amount to buy is {}. You could at most buy {}",
+                        buy_xmr.min_buy_xmr, max_monero_for_bitcoin
+                    );
+
+            return Ok(AskQuote {
+                price: bid_price,
+                min_quantity: monero::Amount::ZERO,
+                max_quantity: monero::Amount::ZERO,
+            });
+        }
+
+        if buy_xmr.max_buy_xmr > max_monero_for_bitcoin {
+            tracing::warn!(
+                    "Your Bitcoin balance is too low to buy the maximum amount of XMR {} that you have specified in your config. You can at most buy {}",
+                    buy_xmr.max_buy_xmr, max_monero_for_bitcoin
+                );
+            return Ok(AskQuote {
+                price: bid_price,
+                min_quantity: buy_xmr.min_buy_xmr,
+                max_quantity: max_monero_for_bitcoin,
+            });
+        }
+
+        Ok(AskQuote {
+            price: bid_price,
+            min_quantity: buy_xmr.min_buy_xmr,
+            max_quantity: buy_xmr.max_buy_xmr,
+        })
+    }
+
     async fn handle_execution_setup_done(
         &mut self,
         bob_peer_id: PeerId,
+        setup_id: Uuid,
         swap_id: Uuid,
         state3: State3,
     ) {
+        self.reservations.release_setup(setup_id);
+        let lock_fee = match self.monero_wallet.estimate_fee().await {
+            Ok(lock_fee) => lock_fee,
+            Err(error) => {
+                tracing::warn!(%swap_id, "Failed to estimate the Monero fee, reserving the default fee: {:#}", error);
+                monero::MONERO_FEE
+            }
+        };
+        self.reserve_xmr(swap_id, state3.xmr() + lock_fee).await;
+
         let handle = self.new_handle(bob_peer_id, swap_id);
 
         let initial_state = AliceState::Started {
             state3: Box::new(state3),
         };
+        let started = SwapEvent::StateChanged {
+            swap_id,
+            state: initial_state.to_string(),
+        };
 
         let swap = Swap {
             event_loop_handle: handle,
@@ -384,23 +930,137 @@ where
             db: self.db.clone(),
             state: initial_state,
             swap_id,
+            events: self.swap_events.clone(),
         };
 
         // TODO: Consider adding separate components for start/resume of swaps
 
         // swaps save peer id so we can resume
         match self.db.insert_peer_id(swap_id, bob_peer_id).await {
-            Ok(_) => {
-                if let Err(error) = self.swap_sender.send(swap).await {
+            Ok(_) => match self.swap_sender.send(swap).await {
+                Ok(()) => self.swap_events.send(started),
+                Err(error) => {
                     tracing::warn!(%swap_id, "Failed to start swap: {}", error);
+                    self.release_xmr(swap_id).await;
+                    self.report_error(
+                        Some(swap_id),
+                        Some(bob_peer_id),
+                        format!("Failed to start swap: {}", error),
+                    );
                 }
+            },
+            Err(error) => {
+                tracing::warn!(%swap_id, "Unable to save peer-id in database: {}", error);
+                self.release_xmr(swap_id).await;
+                self.report_error(
+                    Some(swap_id),
+                    Some(bob_peer_id),
+                    format!("Unable to save peer-id in database: {}", error),
+                );
             }
+        }
+    }
+
+    async fn handle_reverse_swap_setup_done(
+        &mut self,
+        alice_peer_id: PeerId,
+        setup_id: Uuid,
+        swap_id: Uuid,
+        state2: bob::State2,
+    ) {
+        self.btc_reservations.release_setup(setup_id);
+
+        // Another setup with the same swap id may have completed in the meantime
+        if self.swap_id_taken(swap_id).await {
+            tracing::warn!(%swap_id, "Not starting reverse swap because its swap id is already taken");
+            self.report_error(
+                Some(swap_id),
+                Some(alice_peer_id),
+                "Not starting reverse swap because its swap id is already taken".to_owned(),
+            );
+            return;
+        }
+
+        self.btc_reservations.reserve(swap_id, state2.lock_amount());
+
+        let initial_state = BobState::SwapSetupCompleted(state2);
+        let started = SwapEvent::StateChanged {
+            swap_id,
+            state: initial_state.to_string(),
+        };
+        let swap = self.new_bob_swap(alice_peer_id, swap_id, initial_state);
+
+        // swaps save peer id so we can resume
+        match self.db.insert_peer_id(swap_id, alice_peer_id).await {
+            Ok(_) => match self.bob_swap_sender.send(swap).await {
+                Ok(()) => self.swap_events.send(started),
+                Err(error) => {
+                    tracing::warn!(%swap_id, "Failed to start swap: {}", error);
+                    self.btc_reservations.release(swap_id);
+                    self.report_error(
+                        Some(swap_id),
+                        Some(alice_peer_id),
+                        format!("Failed to start swap: {}", error),
+                    );
+                }
+            },
             Err(error) => {
                 tracing::warn!(%swap_id, "Unable to save peer-id in database: {}", error);
+                self.btc_reservations.release(swap_id);
+                self.report_error(
+                    Some(swap_id),
+                    Some(alice_peer_id),
+                    format!("Unable to save peer-id in database: {}", error),
+                );
             }
         }
     }
 
+    /// Create a swap in which we buy XMR from the given peer.
+    fn new_bob_swap(&mut self, alice_peer_id: PeerId, swap_id: Uuid, state: BobState) -> bob::Swap {
+        let handle = self.new_bob_handle(alice_peer_id, swap_id);
+
+        bob::Swap {
+            state,
+            event_loop_handle: handle,
+            db: self.db.clone(),
+            bitcoin_wallet: self.bitcoin_wallet.clone(),
+            monero_wallet: self.monero_wallet.clone(),
+            env_config: self.env_config,
+            id: swap_id,
+            role: bob::Role::Asb,
+            events: self.swap_events.clone(),
+        }
+    }
+
+    /// Create a new [`cli::EventLoopHandle`] that is scoped for communication
+    /// with the given peer, for swaps in which we are Bob.
+    fn new_bob_handle(&mut self, peer: PeerId, swap_id: Uuid) -> cli::EventLoopHandle {
+        // we deliberately don't put timeouts on these channels because the swap always
+        // races these futures against a timelock
+
+        let transfer_proof = bmrng::channel(1);
+        let (encrypted_signature_sender, mut encrypted_signature_receiver) = bmrng::channel(1);
+
+        self.recv_transfer_proof.insert(swap_id, transfer_proof.0);
+
+        self.send_encrypted_signature.push(
+            async move {
+                let (tx_redeem_encsig, responder) = encrypted_signature_receiver.recv().await?;
+
+                let request = encrypted_signature::Request {
+                    swap_id,
+                    tx_redeem_encsig,
+                };
+
+                Ok((peer, request, responder))
+            }
+            .boxed(),
+        );
+
+        cli::EventLoopHandle::new(transfer_proof.1, encrypted_signature_sender)
+    }
+
     /// Create a new [`EventLoopHandle`] that is scoped for communication with
     /// the given peer.
     fn new_handle(&mut self, peer: PeerId, swap_id: Uuid) -> EventLoopHandle {
@@ -446,6 +1106,10 @@ pub struct FixedRate(Rate);
 impl FixedRate {
     pub const RATE: f64 = 0.01;
 
+    pub fn new(rate: Rate) -> Self {
+        Self(rate)
+    }
+
     pub fn value(&self) -> Rate {
         self.0
     }
@@ -468,34 +1132,173 @@ impl LatestRate for FixedRate {
     }
 }
 
-/// Produces [`Rate`]s based on [`PriceUpdate`]s from kraken and a configured
-/// spread.
+/// The rate of swaps in which we sell XMR at a price agreed on with the other
+/// party, for which we do not follow any rate.
+#[derive(Clone, Copy, Debug)]
+pub struct NoRate;
+
+#[derive(Clone, Copy, Debug, thiserror::Error)]
+#[error("No rate is followed for this swap")]
+pub struct NoRateError;
+
+impl LatestRate for NoRate {
+    type Error = NoRateError;
+
+    fn latest_rate(&mut self) -> Result<Rate, Self::Error> {
+        Err(NoRateError)
+    }
+}
+
+/// Produces [`Rate`]s based on [`PriceUpdate`]s from a price source and a
+/// configured spread.
+///
+/// If a maximum age is configured, updates older than that are refused.
 #[derive(Debug, Clone)]
-pub struct KrakenRate {
+pub struct PriceFeedRate {
     ask_spread: Decimal,
-    price_updates: kraken::PriceUpdates,
+    price_updates: price_feed::PriceUpdates,
+    max_age: Option<Duration>,
+    /// Shared between all clones so that a rate becoming outdated is only
+    /// reported once.
+    outdated: Arc<AtomicBool>,
+    spread_curve: Option<SpreadCurve>,
+    /// Shared between all clones so that every price is taken into account
+    /// for the volatility, no matter which clone observed it.
+    history: Arc<Mutex<PriceHistory>>,
 }
 
-impl KrakenRate {
-    pub fn new(ask_spread: Decimal, price_updates: kraken::PriceUpdates) -> Self {
+impl PriceFeedRate {
+    pub fn new(ask_spread: Decimal, price_updates: price_feed::PriceUpdates) -> Self {
         Self {
             ask_spread,
             price_updates,
+            max_age: None,
+            outdated: Arc::new(AtomicBool::new(false)),
+            spread_curve: None,
+            history: Default::default(),
+        }
+    }
+
+    /// Widen the spread according to `curve`.
+    ///
+    /// The volatility is measured over the prices recorded by the future
+    /// returned by [`PriceFeedRate::record_prices`], without it running the
+    /// spread doesn't react to the volatility.
+    pub fn with_spread_curve(self, curve: SpreadCurve) -> Self {
+        Self {
+            spread_curve: Some(curve),
+            ..self
+        }
+    }
+
+    /// Refuse to produce a rate from updates older than `max_age`.
+    pub fn with_max_age(self, max_age: Duration) -> Self {
+        Self {
+            max_age: Some(max_age),
+            ..self
         }
     }
+
+    /// Records every price the feed reports for the volatility of the spread
+    /// curve, until the feed shuts down.
+    ///
+    /// Completes immediately if no spread curve is configured.
+    pub fn record_prices(&self) -> impl Future<Output = ()> + Send + 'static {
+        let price_updates = self.price_updates.clone();
+        let history = self.history.clone();
+        let window = self.spread_curve.map(|curve| curve.volatility_window());
+
+        async move {
+            if let Some(window) = window {
+                record_prices(price_updates, history, window).await;
+            }
+        }
+    }
+
+    fn check_age(&self, update: &price_feed::Ticker) -> Result<(), price_feed::Error> {
+        let max_age = match self.max_age {
+            Some(max_age) => max_age,
+            None => return Ok(()),
+        };
+        let age = update.age(Instant::now());
+
+        if age > max_age {
+            if !self.outdated.swap(true, Ordering::SeqCst) {
+                tracing::error!(
+                    age_secs = %age.as_secs(),
+                    max_age_secs = %max_age.as_secs(),
+                    "The latest price update is outdated! Refusing to quote and to set up swaps until a new price is received"
+                );
+            }
+
+            return Err(price_feed::Error::Outdated { age });
+        }
+
+        if self.outdated.swap(false, Ordering::SeqCst) {
+            tracing::info!("Received up-to-date price, resuming quoting and swap setup");
+        }
+
+        Ok(())
+    }
 }
 
-impl LatestRate for KrakenRate {
-    type Error = kraken::Error;
+impl LatestRate for PriceFeedRate {
+    type Error = price_feed::Error;
 
     fn latest_rate(&mut self) -> Result<Rate, Self::Error> {
         let update = self.price_updates.latest_update()?;
+        self.check_age(&update)?;
         let rate = Rate::new(update.ask, self.ask_spread);
 
+        let rate = match self.spread_curve {
+            Some(curve) => {
+                let volatility = self
+                    .history
+                    .lock()
+                    .expect("price history lock poisoned")
+                    .volatility(Instant::now(), curve.volatility_window());
+
+                rate.with_dynamic_spread(curve, volatility)
+            }
+            None => rate,
+        };
+
         Ok(rate)
     }
 }
 
+/// Records every price reported by `price_updates` until the feed shuts down.
+async fn record_prices(
+    mut price_updates: price_feed::PriceUpdates,
+    history: Arc<Mutex<PriceHistory>>,
+    window: Duration,
+) {
+    let mut update = price_updates.latest_update();
+
+    loop {
+        if let Ok(ticker) = update {
+            history.lock().expect("price history lock poisoned").record(
+                ticker.timestamp,
+                ticker.ask,
+                window,
+            );
+        }
+
+        update = match price_updates.wait_for_next_update().await {
+            Ok(update) => update,
+            Err(_) => return,
+        };
+    }
+}
+
+/// Whether quoting failed because the latest rate is outdated.
+fn is_outdated_rate(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<price_feed::Error>(),
+        Some(price_feed::Error::Outdated { .. })
+    )
+}
+
 #[derive(Debug)]
 pub struct EventLoopHandle {
     recv_encrypted_signature: Option<bmrng::RequestReceiver<bitcoin::EncryptedSignature, ()>>,
@@ -503,6 +1306,17 @@ pub struct EventLoopHandle {
 }
 
 impl EventLoopHandle {
+    /// Create a handle for a swap in which the CLI is Alice.
+    pub(crate) fn new(
+        recv_encrypted_signature: bmrng::RequestReceiver<bitcoin::EncryptedSignature, ()>,
+        send_transfer_proof: bmrng::RequestSender<monero::TransferProof, ()>,
+    ) -> Self {
+        Self {
+            recv_encrypted_signature: Some(recv_encrypted_signature),
+            send_transfer_proof: Some(send_transfer_proof),
+        }
+    }
+
     pub async fn recv_encrypted_signature(&mut self) -> Result<bitcoin::EncryptedSignature> {
         let (tx_redeem_encsig, responder) = self
             .recv_encrypted_signature
@@ -530,6 +1344,52 @@ impl EventLoopHandle {
     }
 }
 
+/// Lets the control API query the state of the running [`EventLoop`].
+#[derive(Clone, Debug)]
+pub struct EventLoopService {
+    sender: mpsc::Sender<ServiceRequest>,
+}
+
+impl EventLoopService {
+    /// The quote we currently give to CLIs buying XMR.
+    pub async fn quote(&self) -> Result<BidQuote> {
+        let (responder, response) = oneshot::channel();
+        self.request(ServiceRequest::Quote(responder)).await?;
+
+        response.await.context("Event loop dropped the request")?
+    }
+
+    /// The quote we currently give to CLIs selling XMR.
+    pub async fn ask_quote(&self) -> Result<AskQuote> {
+        let (responder, response) = oneshot::channel();
+        self.request(ServiceRequest::AskQuote(responder)).await?;
+
+        response.await.context("Event loop dropped the request")?
+    }
+
+    pub async fn connected_peers(&self) -> Result<Vec<PeerId>> {
+        let (responder, response) = oneshot::channel();
+        self.request(ServiceRequest::ConnectedPeers(responder))
+            .await?;
+
+        response.await.context("Event loop dropped the request")
+    }
+
+    async fn request(&self, request: ServiceRequest) -> Result<()> {
+        self.sender
+            .send(request)
+            .await
+            .map_err(|_| anyhow!("Event loop is not running"))
+    }
+}
+
+#[derive(Debug)]
+enum ServiceRequest {
+    Quote(oneshot::Sender<Result<BidQuote>>),
+    AskQuote(oneshot::Sender<Result<AskQuote>>),
+    ConnectedPeers(oneshot::Sender<Vec<PeerId>>),
+}
+
 #[allow(missing_debug_implementations)]
 struct MpscChannels<T> {
     sender: mpsc::Sender<T>,
@@ -542,3 +1402,76 @@ impl<T> Default for MpscChannels<T> {
         MpscChannels { sender, receiver }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn refuses_outdated_rate() {
+        let ask = bitcoin::Amount::from_sat(631_000);
+        let mut price_updates = price_feed::fixed(ask);
+        price_updates.wait_for_next_update().await.unwrap().unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let mut fresh = PriceFeedRate::new(Decimal::ZERO, price_updates.clone())
+            .with_max_age(Duration::from_secs(60));
+        let mut outdated =
+            PriceFeedRate::new(Decimal::ZERO, price_updates).with_max_age(Duration::from_millis(1));
+
+        assert_eq!(fresh.latest_rate().unwrap(), Rate::new(ask, Decimal::ZERO));
+        assert!(matches!(
+            outdated.latest_rate(),
+            Err(price_feed::Error::Outdated { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn spread_curve_takes_prices_into_account_that_were_never_quoted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("price");
+        tokio::fs::write(&path, "0.006").await.unwrap();
+        let mut price_updates = price_feed::PriceSource::File {
+            path: path.clone(),
+            poll_interval_secs: 1,
+        }
+        .connect(None)
+        .unwrap();
+        price_updates.wait_for_next_update().await.unwrap().unwrap();
+
+        let curve = SpreadCurve {
+            full_inventory_xmr: Decimal::ZERO,
+            max_inventory_spread: Decimal::ZERO,
+            size_spread_per_btc: Decimal::ZERO,
+            volatility_multiplier: Decimal::ONE,
+            volatility_window_secs: 60,
+            max_spread: Decimal::ONE,
+        };
+        let mut rate =
+            PriceFeedRate::new(Decimal::ZERO, price_updates.clone()).with_spread_curve(curve);
+        tokio::spawn(rate.record_prices());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        tokio::fs::write(&path, "0.0066").await.unwrap();
+        price_updates.wait_for_next_update().await.unwrap().unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let ask = bitcoin::Amount::from_sat(660_000);
+        let ten_percent = Decimal::new(1, 1);
+        assert_eq!(
+            rate.latest_rate().unwrap(),
+            Rate::new(ask, Decimal::ZERO).with_dynamic_spread(curve, ten_percent)
+        );
+    }
+
+    #[test]
+    fn detects_outdated_rate_behind_context() {
+        let error = anyhow::Error::from(price_feed::Error::Outdated {
+            age: Duration::from_secs(120),
+        })
+        .context("Failed to get latest rate");
+
+        assert!(is_outdated_rate(&error));
+        assert!(!is_outdated_rate(&anyhow::anyhow!("Failed to get balance")));
+    }
+}
@@ -1,12 +1,16 @@
+use crate::asb::config::BuyXmr;
 use crate::asb::event_loop::LatestRate;
-use crate::env;
+use crate::network::ask_quote::AskQuote;
 use crate::network::quote::BidQuote;
 use crate::network::rendezvous::XmrBtcNamespace;
-use crate::network::swap_setup::alice;
 use crate::network::swap_setup::alice::WalletSnapshot;
+use crate::network::swap_setup::{alice, reverse};
+use crate::network::tor_transport::TorDialOnlyTransport;
 use crate::network::transport::authenticate_and_multiplex;
-use crate::network::{encrypted_signature, quote, transfer_proof};
+use crate::network::{ask_quote, encrypted_signature, quote, transfer_proof};
 use crate::protocol::alice::State3;
+use crate::protocol::bob;
+use crate::{bitcoin, env};
 use anyhow::{anyhow, Error, Result};
 use futures::FutureExt;
 use libp2p::core::connection::ConnectionId;
@@ -22,6 +26,7 @@ use libp2p::swarm::{
 use libp2p::tcp::TokioTcpConfig;
 use libp2p::websocket::WsConfig;
 use libp2p::{identity, Multiaddr, NetworkBehaviour, PeerId, Transport};
+use std::sync::Arc;
 use std::task::Poll;
 use std::time::Duration;
 use uuid::Uuid;
@@ -30,14 +35,35 @@ pub mod transport {
     use super::*;
 
     /// Creates the libp2p transport for the ASB.
-    pub fn new(identity: &identity::Keypair) -> Result<Boxed<(PeerId, StreamMuxerBox)>> {
+    ///
+    /// If the socks5 port of Tor is given, all connections the ASB dials go
+    /// through Tor. Only addresses on this machine, which Tor refuses to
+    /// connect to, are dialed directly. DNS names are resolved by Tor, so the
+    /// system resolver is never asked.
+    pub fn new(
+        identity: &identity::Keypair,
+        tor_socks5_port: Option<u16>,
+    ) -> Result<Boxed<(PeerId, StreamMuxerBox)>> {
         let tcp = TokioTcpConfig::new().nodelay(true);
-        let tcp_with_dns = TokioDnsConfig::system(tcp)?;
-        let websocket_with_dns = WsConfig::new(tcp_with_dns.clone());
 
-        let transport = tcp_with_dns.or_transport(websocket_with_dns).boxed();
+        match tor_socks5_port {
+            Some(port) => {
+                let websocket = WsConfig::new(tcp.clone());
+                let transport = TorDialOnlyTransport::new(port)
+                    .or_transport(tcp)
+                    .or_transport(websocket)
+                    .boxed();
 
-        authenticate_and_multiplex(transport, identity)
+                authenticate_and_multiplex(transport, identity)
+            }
+            None => {
+                let tcp_with_dns = TokioDnsConfig::system(tcp)?;
+                let websocket_with_dns = WsConfig::new(tcp_with_dns.clone());
+                let transport = tcp_with_dns.or_transport(websocket_with_dns).boxed();
+
+                authenticate_and_multiplex(transport, identity)
+            }
+        }
     }
 }
 
@@ -48,13 +74,35 @@ pub mod behaviour {
     #[derive(Debug)]
     pub enum OutEvent {
         SwapSetupInitiated {
+            peer_id: PeerId,
+            setup_id: Uuid,
             send_wallet_snapshot: bmrng::RequestReceiver<bitcoin::Amount, WalletSnapshot>,
         },
         SwapSetupCompleted {
             peer_id: PeerId,
+            setup_id: Uuid,
             swap_id: Uuid,
             state3: State3,
         },
+        ReverseSwapSetupInitiated {
+            peer_id: PeerId,
+            setup_id: Uuid,
+            reserve_btc:
+                bmrng::RequestReceiver<(Uuid, bitcoin::Amount), reverse::bob::BtcReservation>,
+        },
+        ReverseSwapSetupCompleted {
+            peer_id: PeerId,
+            setup_id: Uuid,
+            swap_id: Uuid,
+            state2: Box<bob::State2>,
+        },
+        /// A swap setup, in either direction, failed. `setup_id` is the one of
+        /// the event that initiated it.
+        SwapSetupFailed {
+            peer: PeerId,
+            setup_id: Uuid,
+            error: Error,
+        },
         SwapDeclined {
             peer: PeerId,
             error: alice::Error,
@@ -63,15 +111,28 @@ pub mod behaviour {
             channel: ResponseChannel<BidQuote>,
             peer: PeerId,
         },
+        AskQuoteRequested {
+            channel: ResponseChannel<AskQuote>,
+            peer: PeerId,
+        },
         TransferProofAcknowledged {
             peer: PeerId,
             id: RequestId,
         },
+        TransferProofReceived {
+            msg: Box<transfer_proof::Request>,
+            channel: ResponseChannel<()>,
+            peer: PeerId,
+        },
         EncryptedSignatureReceived {
             msg: encrypted_signature::Request,
             channel: ResponseChannel<()>,
             peer: PeerId,
         },
+        EncryptedSignatureAcknowledged {
+            peer: PeerId,
+            id: RequestId,
+        },
         Rendezvous(libp2p::rendezvous::client::Event),
         Failure {
             peer: PeerId,
@@ -98,7 +159,8 @@ pub mod behaviour {
         }
     }
 
-    /// A `NetworkBehaviour` that represents an XMR/BTC swap node as Alice.
+    /// A `NetworkBehaviour` that represents an XMR/BTC swap node as Alice,
+    /// or as Bob for swaps in which the CLI sells XMR.
     #[derive(NetworkBehaviour)]
     #[behaviour(out_event = "OutEvent", event_process = false)]
     #[allow(missing_debug_implementations)]
@@ -108,7 +170,9 @@ pub mod behaviour {
     {
         pub rendezvous: libp2p::swarm::toggle::Toggle<rendezous::Behaviour>,
         pub quote: quote::Behaviour,
+        pub ask_quote: ask_quote::Behaviour,
         pub swap_setup: alice::Behaviour<LR>,
+        pub reverse_swap_setup: reverse::bob::Behaviour<LR>,
         pub transfer_proof: transfer_proof::Behaviour,
         pub encrypted_signature: encrypted_signature::Behaviour,
 
@@ -120,38 +184,46 @@ pub mod behaviour {
 
     impl<LR> Behaviour<LR>
     where
-        LR: LatestRate + Send + 'static,
+        LR: LatestRate + Send + 'static + Clone,
     {
+        #[allow(clippy::too_many_arguments)]
         pub fn new(
             min_buy: bitcoin::Amount,
             max_buy: bitcoin::Amount,
+            buy_xmr: Option<BuyXmr>,
             latest_rate: LR,
             resume_only: bool,
             env_config: env::Config,
-            rendezvous_params: Option<(identity::Keypair, PeerId, Multiaddr, XmrBtcNamespace)>,
+            bitcoin_wallet: Arc<bitcoin::Wallet>,
+            identity: identity::Keypair,
+            rendezvous_nodes: Vec<rendezous::RendezvousNode>,
         ) -> Self {
+            let rendezvous = if rendezvous_nodes.is_empty() {
+                None
+            } else {
+                Some(rendezous::Behaviour::new(identity, rendezvous_nodes))
+            };
+
             Self {
-                rendezvous: libp2p::swarm::toggle::Toggle::from(rendezvous_params.map(
-                    |(identity, rendezvous_peer_id, rendezvous_address, namespace)| {
-                        rendezous::Behaviour::new(
-                            identity,
-                            rendezvous_peer_id,
-                            rendezvous_address,
-                            namespace,
-                            None, // use default ttl on rendezvous point
-                        )
-                    },
-                )),
+                rendezvous: libp2p::swarm::toggle::Toggle::from(rendezvous),
                 quote: quote::asb(),
+                ask_quote: ask_quote::asb(),
                 swap_setup: alice::Behaviour::new(
                     min_buy,
                     max_buy,
                     env_config,
+                    latest_rate.clone(),
+                    resume_only,
+                ),
+                reverse_swap_setup: reverse::bob::Behaviour::new(
+                    buy_xmr,
+                    env_config,
+                    bitcoin_wallet,
                     latest_rate,
                     resume_only,
                 ),
-                transfer_proof: transfer_proof::alice(),
-                encrypted_signature: encrypted_signature::alice(),
+                transfer_proof: transfer_proof::new(),
+                encrypted_signature: encrypted_signature::new(),
                 ping: Ping::new(PingConfig::new().with_keep_alive(true)),
             }
         }
@@ -172,9 +244,12 @@ pub mod behaviour {
 
 pub mod rendezous {
     use super::*;
+    use libp2p::rendezvous::client::Event;
     use libp2p::swarm::DialError;
     use std::pin::Pin;
 
+    type Handler = <libp2p::rendezvous::client::Behaviour as NetworkBehaviour>::ProtocolsHandler;
+
     #[derive(PartialEq)]
     enum ConnectionStatus {
         Disconnected,
@@ -190,69 +265,91 @@ pub mod rendezous {
         },
     }
 
-    pub struct Behaviour {
-        inner: libp2p::rendezvous::client::Behaviour,
-        rendezvous_point: Multiaddr,
-        rendezvous_peer_id: PeerId,
-        namespace: XmrBtcNamespace,
+    /// A rendezvous point the ASB registers with.
+    ///
+    /// Every rendezvous point keeps track of its own connection and
+    /// registration, so the ASB re-registers with each of them independently.
+    pub struct RendezvousNode {
+        pub address: Multiaddr,
+        pub peer_id: PeerId,
+        pub namespace: XmrBtcNamespace,
         registration_status: RegistrationStatus,
         connection_status: ConnectionStatus,
         registration_ttl: Option<u64>,
     }
 
-    impl Behaviour {
+    impl RendezvousNode {
         pub fn new(
-            identity: identity::Keypair,
-            rendezvous_peer_id: PeerId,
-            rendezvous_address: Multiaddr,
+            address: Multiaddr,
+            peer_id: PeerId,
             namespace: XmrBtcNamespace,
             registration_ttl: Option<u64>,
         ) -> Self {
             Self {
-                inner: libp2p::rendezvous::client::Behaviour::new(identity),
-                rendezvous_point: rendezvous_address,
-                rendezvous_peer_id,
+                address,
+                peer_id,
                 namespace,
                 registration_status: RegistrationStatus::RegisterOnNextConnection,
                 connection_status: ConnectionStatus::Disconnected,
                 registration_ttl,
             }
         }
+    }
 
-        fn register(&mut self) {
-            self.inner.register(
-                self.namespace.into(),
-                self.rendezvous_peer_id,
-                self.registration_ttl,
-            );
+    pub struct Behaviour {
+        inner: libp2p::rendezvous::client::Behaviour,
+        rendezvous_nodes: Vec<RendezvousNode>,
+    }
+
+    impl Behaviour {
+        pub fn new(identity: identity::Keypair, rendezvous_nodes: Vec<RendezvousNode>) -> Self {
+            Self {
+                inner: libp2p::rendezvous::client::Behaviour::new(identity),
+                rendezvous_nodes,
+            }
+        }
+
+        fn register(inner: &mut libp2p::rendezvous::client::Behaviour, node: &RendezvousNode) {
+            inner.register(node.namespace.into(), node.peer_id, node.registration_ttl);
+        }
+
+        fn dial(node: &RendezvousNode) -> NetworkBehaviourAction<Event, Handler> {
+            NetworkBehaviourAction::DialPeer {
+                peer_id: node.peer_id,
+                condition: DialPeerCondition::Disconnected,
+                handler: Handler::new(Duration::from_secs(30)),
+            }
         }
     }
 
     impl NetworkBehaviour for Behaviour {
-        type ProtocolsHandler =
-            <libp2p::rendezvous::client::Behaviour as NetworkBehaviour>::ProtocolsHandler;
-        type OutEvent = libp2p::rendezvous::client::Event;
+        type ProtocolsHandler = Handler;
+        type OutEvent = Event;
 
         fn new_handler(&mut self) -> Self::ProtocolsHandler {
             self.inner.new_handler()
         }
 
         fn addresses_of_peer(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
-            if peer_id == &self.rendezvous_peer_id {
-                return vec![self.rendezvous_point.clone()];
-            }
-
-            vec![]
+            self.rendezvous_nodes
+                .iter()
+                .filter(|node| &node.peer_id == peer_id)
+                .map(|node| node.address.clone())
+                .collect()
         }
 
         fn inject_connected(&mut self, peer_id: &PeerId) {
-            if peer_id == &self.rendezvous_peer_id {
-                self.connection_status = ConnectionStatus::Connected;
+            for node in self
+                .rendezvous_nodes
+                .iter_mut()
+                .filter(|node| &node.peer_id == peer_id)
+            {
+                node.connection_status = ConnectionStatus::Connected;
 
-                match &self.registration_status {
+                match &node.registration_status {
                     RegistrationStatus::RegisterOnNextConnection => {
-                        self.register();
-                        self.registration_status = RegistrationStatus::Pending;
+                        Self::register(&mut self.inner, node);
+                        node.registration_status = RegistrationStatus::Pending;
                     }
                     RegistrationStatus::Registered { .. } => {}
                     RegistrationStatus::Pending => {}
@@ -261,8 +358,12 @@ pub mod rendezous {
         }
 
         fn inject_disconnected(&mut self, peer_id: &PeerId) {
-            if peer_id == &self.rendezvous_peer_id {
-                self.connection_status = ConnectionStatus::Disconnected;
+            for node in self
+                .rendezvous_nodes
+                .iter_mut()
+                .filter(|node| &node.peer_id == peer_id)
+            {
+                node.connection_status = ConnectionStatus::Disconnected;
             }
         }
 
@@ -281,8 +382,12 @@ pub mod rendezous {
             _handler: Self::ProtocolsHandler,
             _error: DialError,
         ) {
-            if peer_id == &self.rendezvous_peer_id {
-                self.connection_status = ConnectionStatus::Disconnected;
+            for node in self
+                .rendezvous_nodes
+                .iter_mut()
+                .filter(|node| &node.peer_id == peer_id)
+            {
+                node.connection_status = ConnectionStatus::Disconnected;
             }
         }
 
@@ -292,59 +397,61 @@ pub mod rendezous {
             cx: &mut std::task::Context<'_>,
             params: &mut impl PollParameters,
         ) -> Poll<NetworkBehaviourAction<Self::OutEvent, Self::ProtocolsHandler>> {
-            match &mut self.registration_status {
-                RegistrationStatus::RegisterOnNextConnection => match self.connection_status {
-                    ConnectionStatus::Disconnected => {
-                        self.connection_status = ConnectionStatus::Dialling;
-
-                        return Poll::Ready(NetworkBehaviourAction::DialPeer {
-                            peer_id: self.rendezvous_peer_id,
-                            condition: DialPeerCondition::Disconnected,
-                            handler: Self::ProtocolsHandler::new(Duration::from_secs(30)),
-                        });
-                    }
-                    ConnectionStatus::Dialling => {}
-                    ConnectionStatus::Connected => {
-                        self.registration_status = RegistrationStatus::Pending;
-                        self.register();
-                    }
-                },
-                RegistrationStatus::Registered { re_register_in } => {
-                    if let Poll::Ready(()) = re_register_in.poll_unpin(cx) {
-                        match self.connection_status {
-                            ConnectionStatus::Connected => {
-                                self.registration_status = RegistrationStatus::Pending;
-                                self.register();
-                            }
-                            ConnectionStatus::Disconnected => {
-                                self.registration_status =
-                                    RegistrationStatus::RegisterOnNextConnection;
-
-                                return Poll::Ready(NetworkBehaviourAction::DialPeer {
-                                    peer_id: self.rendezvous_peer_id,
-                                    condition: DialPeerCondition::Disconnected,
-                                    handler: Self::ProtocolsHandler::new(Duration::from_secs(30)),
-                                });
+            for node in self.rendezvous_nodes.iter_mut() {
+                match &mut node.registration_status {
+                    RegistrationStatus::RegisterOnNextConnection => match node.connection_status {
+                        ConnectionStatus::Disconnected => {
+                            node.connection_status = ConnectionStatus::Dialling;
+
+                            return Poll::Ready(Self::dial(node));
+                        }
+                        ConnectionStatus::Dialling => {}
+                        ConnectionStatus::Connected => {
+                            node.registration_status = RegistrationStatus::Pending;
+                            Self::register(&mut self.inner, node);
+                        }
+                    },
+                    RegistrationStatus::Registered { re_register_in } => {
+                        if let Poll::Ready(()) = re_register_in.poll_unpin(cx) {
+                            match node.connection_status {
+                                ConnectionStatus::Connected => {
+                                    node.registration_status = RegistrationStatus::Pending;
+                                    Self::register(&mut self.inner, node);
+                                }
+                                ConnectionStatus::Disconnected => {
+                                    node.registration_status =
+                                        RegistrationStatus::RegisterOnNextConnection;
+
+                                    return Poll::Ready(Self::dial(node));
+                                }
+                                ConnectionStatus::Dialling => {}
                             }
-                            ConnectionStatus::Dialling => {}
                         }
                     }
+                    RegistrationStatus::Pending => {}
                 }
-                RegistrationStatus::Pending => {}
             }
 
             let inner_poll = self.inner.poll(cx, params);
 
-            // reset the timer if we successfully registered
-            if let Poll::Ready(NetworkBehaviourAction::GenerateEvent(
-                libp2p::rendezvous::client::Event::Registered { ttl, .. },
-            )) = &inner_poll
+            // reset the timer of the rendezvous node we successfully registered with
+            if let Poll::Ready(NetworkBehaviourAction::GenerateEvent(Event::Registered {
+                rendezvous_node,
+                ttl,
+                ..
+            })) = &inner_poll
             {
                 let half_of_ttl = Duration::from_secs(*ttl) / 2;
 
-                self.registration_status = RegistrationStatus::Registered {
-                    re_register_in: Box::pin(tokio::time::sleep(half_of_ttl)),
-                };
+                for node in self
+                    .rendezvous_nodes
+                    .iter_mut()
+                    .filter(|node| &node.peer_id == rendezvous_node)
+                {
+                    node.registration_status = RegistrationStatus::Registered {
+                        re_register_in: Box::pin(tokio::time::sleep(half_of_ttl)),
+                    };
+                }
             }
 
             inner_poll
@@ -358,6 +465,7 @@ pub mod rendezous {
         use futures::StreamExt;
         use libp2p::rendezvous;
         use libp2p::swarm::SwarmEvent;
+        use std::collections::HashSet;
 
         #[tokio::test]
         async fn given_no_initial_connection_when_constructed_asb_connects_and_registers_with_rendezvous_node(
@@ -368,13 +476,12 @@ pub mod rendezous {
             let rendezvous_address = rendezvous_node.listen_on_random_memory_address().await;
 
             let mut asb = new_swarm(|_, identity| {
-                rendezous::Behaviour::new(
-                    identity,
-                    *rendezvous_node.local_peer_id(),
+                rendezous::Behaviour::new(identity, vec![rendezous::RendezvousNode::new(
                     rendezvous_address,
+                    *rendezvous_node.local_peer_id(),
                     XmrBtcNamespace::Testnet,
                     None,
-                )
+                )])
             });
             asb.listen_on_random_memory_address().await; // this adds an external address
 
@@ -399,6 +506,74 @@ pub mod rendezous {
                 .unwrap();
         }
 
+        #[tokio::test]
+        async fn given_several_rendezvous_nodes_asb_registers_with_each_of_them() {
+            let mut rendezvous_node_1 = new_swarm(|_, _| {
+                rendezvous::server::Behaviour::new(rendezvous::server::Config::default())
+            });
+            let rendezvous_address_1 = rendezvous_node_1.listen_on_random_memory_address().await;
+            let rendezvous_peer_id_1 = *rendezvous_node_1.local_peer_id();
+            let mut rendezvous_node_2 = new_swarm(|_, _| {
+                rendezvous::server::Behaviour::new(rendezvous::server::Config::default())
+            });
+            let rendezvous_address_2 = rendezvous_node_2.listen_on_random_memory_address().await;
+            let rendezvous_peer_id_2 = *rendezvous_node_2.local_peer_id();
+
+            let mut asb = new_swarm(|_, identity| {
+                rendezous::Behaviour::new(identity, vec![
+                    rendezous::RendezvousNode::new(
+                        rendezvous_address_1,
+                        rendezvous_peer_id_1,
+                        XmrBtcNamespace::Testnet,
+                        None,
+                    ),
+                    rendezous::RendezvousNode::new(
+                        rendezvous_address_2,
+                        rendezvous_peer_id_2,
+                        XmrBtcNamespace::Testnet,
+                        None,
+                    ),
+                ])
+            });
+            asb.listen_on_random_memory_address().await; // this adds an external address
+
+            tokio::spawn(async move {
+                loop {
+                    rendezvous_node_1.next().await;
+                }
+            });
+            tokio::spawn(async move {
+                loop {
+                    rendezvous_node_2.next().await;
+                }
+            });
+            let asb_registered_with_both = tokio::spawn(async move {
+                let mut registered_with = HashSet::new();
+
+                while registered_with.len() < 2 {
+                    if let SwarmEvent::Behaviour(rendezvous::client::Event::Registered {
+                        rendezvous_node,
+                        ..
+                    }) = asb.select_next_some().await
+                    {
+                        registered_with.insert(rendezvous_node);
+                    }
+                }
+
+                registered_with
+            });
+
+            let registered_with =
+                tokio::time::timeout(Duration::from_secs(10), asb_registered_with_both)
+                    .await
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(
+                registered_with,
+                HashSet::from([rendezvous_peer_id_1, rendezvous_peer_id_2])
+            );
+        }
+
         #[tokio::test]
         async fn asb_automatically_re_registers() {
             let mut rendezvous_node = new_swarm(|_, _| {
@@ -409,13 +584,12 @@ pub mod rendezous {
             let rendezvous_address = rendezvous_node.listen_on_random_memory_address().await;
 
             let mut asb = new_swarm(|_, identity| {
-                rendezous::Behaviour::new(
-                    identity,
-                    *rendezvous_node.local_peer_id(),
+                rendezous::Behaviour::new(identity, vec![rendezous::RendezvousNode::new(
                     rendezvous_address,
+                    *rendezvous_node.local_peer_id(),
                     XmrBtcNamespace::Testnet,
                     Some(5),
-                )
+                )])
             });
             asb.listen_on_random_memory_address().await; // this adds an external address
 
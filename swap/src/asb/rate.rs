@@ -2,7 +2,10 @@ use crate::{bitcoin, monero};
 use anyhow::{Context, Result};
 use rust_decimal::prelude::ToPrimitive;
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::fmt::{Debug, Display, Formatter};
+use std::time::{Duration, Instant};
 
 /// Represents the rate at which we are willing to trade 1 XMR.
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -11,6 +14,64 @@ pub struct Rate {
     ask: bitcoin::Amount,
     /// The spread which should be applied to the market asking price.
     ask_spread: Decimal,
+    /// Widens the spread depending on our inventory, the swap size and the
+    /// volatility of the market.
+    dynamic_spread: Option<DynamicSpread>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DynamicSpread {
+    curve: SpreadCurve,
+    /// The relative price range observed over the volatility window.
+    volatility: Decimal,
+}
+
+/// Parameters of the spread that is added on top of the configured
+/// `ask_spread`.
+///
+/// The total spread is `ask_spread + inventory + size + volatility`, capped at
+/// `max_spread` but never below `ask_spread`, where
+///
+/// - `inventory = max_inventory_spread * (1 - balance / full_inventory_xmr)^2`
+///   for a balance below `full_inventory_xmr` and `0` above,
+/// - `size = size_spread_per_btc * swap amount in BTC` and
+/// - `volatility = volatility_multiplier * (highest - lowest) / lowest` of the
+///   prices observed over the last `volatility_window_secs`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpreadCurve {
+    pub full_inventory_xmr: Decimal,
+    pub max_inventory_spread: Decimal,
+    pub size_spread_per_btc: Decimal,
+    pub volatility_multiplier: Decimal,
+    pub volatility_window_secs: u64,
+    pub max_spread: Decimal,
+}
+
+impl SpreadCurve {
+    pub fn volatility_window(&self) -> Duration {
+        Duration::from_secs(self.volatility_window_secs)
+    }
+
+    fn inventory_spread(&self, balance: monero::Amount) -> Decimal {
+        if self.full_inventory_xmr <= Decimal::ZERO {
+            return Decimal::ZERO;
+        }
+
+        let balance =
+            balance.as_piconero_decimal() / Decimal::from(monero::Amount::ONE_XMR.as_piconero());
+        let fill = (balance / self.full_inventory_xmr).min(Decimal::ONE);
+        let missing = Decimal::ONE - fill;
+
+        self.max_inventory_spread * missing * missing
+    }
+
+    fn size_spread(&self, quote: bitcoin::Amount) -> Decimal {
+        let quote =
+            Decimal::from(quote.as_sat()) / Decimal::from(bitcoin::Amount::ONE_BTC.as_sat());
+
+        self.size_spread_per_btc * quote
+    }
 }
 
 const ZERO_SPREAD: Decimal = Decimal::from_parts(0, 0, 0, false, 0);
@@ -19,20 +80,40 @@ impl Rate {
     pub const ZERO: Rate = Rate {
         ask: bitcoin::Amount::ZERO,
         ask_spread: ZERO_SPREAD,
+        dynamic_spread: None,
     };
 
     pub fn new(ask: bitcoin::Amount, ask_spread: Decimal) -> Self {
-        Self { ask, ask_spread }
+        Self {
+            ask,
+            ask_spread,
+            dynamic_spread: None,
+        }
+    }
+
+    /// The asking price of the market, before applying any spread.
+    pub fn market_price(&self) -> bitcoin::Amount {
+        self.ask
     }
 
-    /// Computes the asking price at which we are willing to sell 1 XMR.
+    /// Widen the spread according to `curve`, given the observed
+    /// `volatility` of the market.
+    pub fn with_dynamic_spread(self, curve: SpreadCurve, volatility: Decimal) -> Self {
+        Self {
+            dynamic_spread: Some(DynamicSpread { curve, volatility }),
+            ..self
+        }
+    }
+
+    /// Computes the asking price at which we are willing to sell XMR worth
+    /// `quote`, given our current Monero `balance`.
     ///
     /// This applies the spread to the market asking price.
-    pub fn ask(&self) -> Result<bitcoin::Amount> {
+    pub fn ask(&self, quote: bitcoin::Amount, balance: monero::Amount) -> Result<bitcoin::Amount> {
         let sats = self.ask.as_sat();
         let sats = Decimal::from(sats);
 
-        let additional_sats = sats * self.ask_spread;
+        let additional_sats = sats * self.spread(quote, balance);
         let additional_sats = bitcoin::Amount::from_sat(
             additional_sats
                 .to_u64()
@@ -42,9 +123,71 @@ impl Rate {
         Ok(self.ask + additional_sats)
     }
 
-    /// Calculate a sell quote for a given BTC amount.
-    pub fn sell_quote(&self, quote: bitcoin::Amount) -> Result<monero::Amount> {
-        Self::quote(self.ask()?, quote)
+    /// Calculate a sell quote for a given BTC amount, given our current Monero
+    /// `balance`.
+    pub fn sell_quote(
+        &self,
+        quote: bitcoin::Amount,
+        balance: monero::Amount,
+    ) -> Result<monero::Amount> {
+        Self::quote(self.ask(quote, balance)?, quote)
+    }
+
+    /// Computes the bidding price at which we are willing to buy XMR.
+    ///
+    /// The market only tells us its asking price, so the bid is derived from it
+    /// by deducting `bid_spread`.
+    pub fn bid(&self, bid_spread: Decimal) -> Result<bitcoin::Amount> {
+        let sats = Decimal::from(self.ask.as_sat());
+
+        let deducted_sats = (sats * bid_spread)
+            .ceil()
+            .to_u64()
+            .context("Failed to fit spread into u64")?;
+        let deducted_sats = bitcoin::Amount::from_sat(deducted_sats);
+
+        self.ask
+            .checked_sub(deducted_sats)
+            .context("Bid spread is larger than the asking price")
+    }
+
+    /// Calculate a buy quote for a given XMR amount, i.e. the amount of BTC we
+    /// pay for `xmr`.
+    pub fn buy_quote(&self, xmr: monero::Amount, bid_spread: Decimal) -> Result<bitcoin::Amount> {
+        let bid = Decimal::from(self.bid(bid_spread)?.as_sat());
+
+        let sats = (bid * xmr.as_piconero_decimal())
+            .checked_div(Decimal::from(monero::Amount::ONE_XMR.as_piconero()))
+            .context("Division overflow")?
+            .floor()
+            .to_u64()
+            .context("Failed to fit sats into u64")?;
+
+        Ok(bitcoin::Amount::from_sat(sats))
+    }
+
+    /// The most XMR we can buy with `balance`.
+    pub fn buy_limit(
+        &self,
+        balance: bitcoin::Amount,
+        bid_spread: Decimal,
+    ) -> Result<monero::Amount> {
+        Self::quote(self.bid(bid_spread)?, balance)
+    }
+
+    fn spread(&self, quote: bitcoin::Amount, balance: monero::Amount) -> Decimal {
+        let DynamicSpread { curve, volatility } = match self.dynamic_spread {
+            Some(dynamic_spread) => dynamic_spread,
+            None => return self.ask_spread,
+        };
+
+        let spread = self.ask_spread
+            + curve.inventory_spread(balance)
+            + curve.size_spread(quote)
+            + curve.volatility_multiplier * volatility;
+
+        // a cap below the static spread must not undercut it
+        spread.min(curve.max_spread.max(self.ask_spread))
     }
 
     fn quote(rate: bitcoin::Amount, quote: bitcoin::Amount) -> Result<monero::Amount> {
@@ -79,12 +222,73 @@ impl Display for Rate {
     }
 }
 
+/// Remembers the market asking prices observed over a window of time, in
+/// order to measure the volatility of the market.
+#[derive(Debug, Default)]
+pub struct PriceHistory {
+    samples: VecDeque<(Instant, bitcoin::Amount)>,
+}
+
+impl PriceHistory {
+    /// Record a price and forget all prices that are older than `window`.
+    pub fn record(&mut self, timestamp: Instant, ask: bitcoin::Amount, window: Duration) {
+        if self.samples.back().map(|(last, _)| *last) != Some(timestamp) {
+            self.samples.push_back((timestamp, ask));
+        }
+
+        while let Some((oldest, _)) = self.samples.front() {
+            if timestamp.saturating_duration_since(*oldest) <= window {
+                break;
+            }
+
+            self.samples.pop_front();
+        }
+    }
+
+    /// The relative range `(highest - lowest) / lowest` of the prices recorded
+    /// within `window` before `now`.
+    ///
+    /// Prices are only forgotten when a new one is recorded, so prices that
+    /// dropped out of the window while the feed paused are skipped here.
+    pub fn volatility(&self, now: Instant, window: Duration) -> Decimal {
+        let prices = self
+            .samples
+            .iter()
+            .filter(|(timestamp, _)| now.saturating_duration_since(*timestamp) <= window)
+            .map(|(_, ask)| ask.as_sat());
+
+        let (lowest, highest) = match (prices.clone().min(), prices.max()) {
+            (Some(lowest), Some(highest)) if lowest > 0 => (lowest, highest),
+            _ => return Decimal::ZERO,
+        };
+
+        Decimal::from(highest - lowest) / Decimal::from(lowest)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     const TWO_PERCENT: Decimal = Decimal::from_parts(2, 0, 0, false, 2);
+    const ONE_PERCENT: Decimal = Decimal::from_parts(1, 0, 0, false, 2);
     const ONE: Decimal = Decimal::from_parts(1, 0, 0, false, 0);
+    const TEN_PERCENT: Decimal = Decimal::from_parts(1, 0, 0, false, 1);
+
+    fn balance(xmr: f64) -> monero::Amount {
+        monero::Amount::from_monero(xmr).unwrap()
+    }
+
+    fn curve() -> SpreadCurve {
+        SpreadCurve {
+            full_inventory_xmr: Decimal::from(100),
+            max_inventory_spread: TEN_PERCENT,
+            size_spread_per_btc: ONE_PERCENT,
+            volatility_multiplier: ONE,
+            volatility_window_secs: 3600,
+            max_spread: Decimal::from_parts(5, 0, 0, false, 1),
+        }
+    }
 
     #[test]
     fn sell_quote() {
@@ -93,7 +297,7 @@ mod tests {
 
         let btc_amount = bitcoin::Amount::from_btc(2.5).unwrap();
 
-        let xmr_amount = rate.sell_quote(btc_amount).unwrap();
+        let xmr_amount = rate.sell_quote(btc_amount, balance(0.0)).unwrap();
 
         assert_eq!(xmr_amount, monero::Amount::from_monero(1000.0).unwrap())
     }
@@ -103,7 +307,7 @@ mod tests {
         let asking_price = bitcoin::Amount::from_sat(100);
         let rate = Rate::new(asking_price, TWO_PERCENT);
 
-        let amount = rate.ask().unwrap();
+        let amount = rate.ask(bitcoin::Amount::ONE_BTC, balance(0.0)).unwrap();
 
         assert_eq!(amount.as_sat(), 102);
     }
@@ -116,9 +320,11 @@ mod tests {
         let rate_no_spread = Rate::new(asking_price, ZERO_SPREAD);
         let rate_with_spread = Rate::new(asking_price, TWO_PERCENT);
 
-        let xmr_no_spread = rate_no_spread.sell_quote(bitcoin::Amount::ONE_BTC).unwrap();
+        let xmr_no_spread = rate_no_spread
+            .sell_quote(bitcoin::Amount::ONE_BTC, balance(0.0))
+            .unwrap();
         let xmr_with_spread = rate_with_spread
-            .sell_quote(bitcoin::Amount::ONE_BTC)
+            .sell_quote(bitcoin::Amount::ONE_BTC, balance(0.0))
             .unwrap();
 
         let xmr_factor =
@@ -130,4 +336,138 @@ mod tests {
                                                          // it is really close
                                                          // to two percent
     }
+
+    #[test]
+    fn deducts_bid_spread_from_asking_price() {
+        let asking_price = bitcoin::Amount::from_sat(100);
+        let rate = Rate::new(asking_price, TWO_PERCENT);
+
+        let amount = rate.bid(TWO_PERCENT).unwrap();
+
+        assert_eq!(amount.as_sat(), 98);
+    }
+
+    #[test]
+    fn buy_quote() {
+        let asking_price = bitcoin::Amount::from_btc(0.002_500).unwrap();
+        let rate = Rate::new(asking_price, ZERO_SPREAD);
+
+        let btc_amount = rate
+            .buy_quote(monero::Amount::from_monero(1000.0).unwrap(), TWO_PERCENT)
+            .unwrap();
+        let limit = rate.buy_limit(btc_amount, TWO_PERCENT).unwrap();
+
+        assert_eq!(btc_amount, bitcoin::Amount::from_btc(2.45).unwrap());
+        assert_eq!(limit, monero::Amount::from_monero(1000.0).unwrap());
+    }
+
+    #[test]
+    fn full_inventory_and_negligible_size_apply_only_ask_spread() {
+        let asking_price = bitcoin::Amount::from_sat(1_000_000);
+        let rate = Rate::new(asking_price, TWO_PERCENT).with_dynamic_spread(curve(), ZERO_SPREAD);
+
+        let amount = rate.ask(bitcoin::Amount::ZERO, balance(150.0)).unwrap();
+
+        assert_eq!(amount.as_sat(), 1_020_000);
+    }
+
+    #[test]
+    fn spread_widens_as_inventory_drops() {
+        let asking_price = bitcoin::Amount::from_sat(1_000_000);
+        let rate = Rate::new(asking_price, ZERO_SPREAD).with_dynamic_spread(curve(), ZERO_SPREAD);
+
+        let full = rate.ask(bitcoin::Amount::ZERO, balance(100.0)).unwrap();
+        let half = rate.ask(bitcoin::Amount::ZERO, balance(50.0)).unwrap();
+        let empty = rate.ask(bitcoin::Amount::ZERO, balance(0.0)).unwrap();
+
+        assert_eq!(full.as_sat(), 1_000_000);
+        // 10% * (1 - 0.5)^2 = 2.5%
+        assert_eq!(half.as_sat(), 1_025_000);
+        assert_eq!(empty.as_sat(), 1_100_000);
+    }
+
+    #[test]
+    fn spread_scales_with_swap_size_and_volatility() {
+        let asking_price = bitcoin::Amount::from_sat(1_000_000);
+        let rate = Rate::new(asking_price, ZERO_SPREAD).with_dynamic_spread(curve(), TWO_PERCENT);
+
+        let amount = rate
+            .ask(bitcoin::Amount::from_btc(2.0).unwrap(), balance(100.0))
+            .unwrap();
+
+        // 2 BTC * 1% + 1 * 2% volatility = 4%
+        assert_eq!(amount.as_sat(), 1_040_000);
+    }
+
+    #[test]
+    fn spread_is_capped() {
+        let asking_price = bitcoin::Amount::from_sat(1_000_000);
+        let rate = Rate::new(asking_price, TWO_PERCENT).with_dynamic_spread(curve(), ONE);
+
+        let amount = rate.ask(bitcoin::Amount::ONE_BTC, balance(0.0)).unwrap();
+
+        assert_eq!(amount.as_sat(), 1_500_000);
+    }
+
+    #[test]
+    fn cap_below_ask_spread_does_not_undercut_it() {
+        let asking_price = bitcoin::Amount::from_sat(1_000_000);
+        let curve = SpreadCurve {
+            max_spread: ONE_PERCENT,
+            ..curve()
+        };
+        let rate = Rate::new(asking_price, TWO_PERCENT).with_dynamic_spread(curve, ONE);
+
+        let amount = rate.ask(bitcoin::Amount::ONE_BTC, balance(0.0)).unwrap();
+
+        assert_eq!(amount.as_sat(), 1_020_000);
+    }
+
+    #[test]
+    fn volatility_is_relative_range_within_window() {
+        let window = Duration::from_secs(60);
+        let start = Instant::now();
+        let mut history = PriceHistory::default();
+
+        history.record(start, bitcoin::Amount::from_sat(500_000), window);
+        history.record(
+            start + Duration::from_secs(30),
+            bitcoin::Amount::from_sat(1_000_000),
+            window,
+        );
+        history.record(
+            start + Duration::from_secs(90),
+            bitcoin::Amount::from_sat(1_100_000),
+            window,
+        );
+
+        // the first price dropped out of the window
+        assert_eq!(
+            history.volatility(start + Duration::from_secs(90), window),
+            TEN_PERCENT
+        );
+    }
+
+    #[test]
+    fn volatility_ignores_prices_that_aged_out_while_the_feed_paused() {
+        let window = Duration::from_secs(60);
+        let start = Instant::now();
+        let mut history = PriceHistory::default();
+
+        history.record(start, bitcoin::Amount::from_sat(500_000), window);
+        history.record(
+            start + Duration::from_secs(30),
+            bitcoin::Amount::from_sat(1_000_000),
+            window,
+        );
+
+        assert_eq!(
+            history.volatility(start + Duration::from_secs(60), window),
+            ONE
+        );
+        assert_eq!(
+            history.volatility(start + Duration::from_secs(61), window),
+            Decimal::ZERO
+        );
+    }
 }
@@ -1,7 +1,7 @@
 use crate::bitcoin::{self, Txid};
 use crate::protocol::alice::AliceState;
 use crate::protocol::Database;
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use std::convert::TryInto;
 use std::sync::Arc;
 use uuid::Uuid;
@@ -15,9 +15,14 @@ pub enum Error {
 pub async fn punish(
     swap_id: Uuid,
     bitcoin_wallet: Arc<bitcoin::Wallet>,
-    db: Arc<dyn Database>,
+    db: Arc<dyn Database + Send + Sync>,
 ) -> Result<(Txid, AliceState)> {
-    let state = db.get_state(swap_id).await?.try_into()?;
+    let state = db.get_state(swap_id).await?.try_into().with_context(|| {
+        format!(
+            "Cannot punish swap {} because we bought XMR in it, only the seller of XMR can punish",
+            swap_id
+        )
+    })?;
 
     let state3 = match state {
         // Punish potentially possible (no knowledge of cancel transaction)
@@ -1,7 +1,7 @@
 use crate::bitcoin::{Txid, Wallet};
 use crate::protocol::alice::AliceState;
 use crate::protocol::Database;
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use std::convert::TryInto;
 use std::sync::Arc;
 use uuid::Uuid;
@@ -24,10 +24,15 @@ impl Finality {
 pub async fn redeem(
     swap_id: Uuid,
     bitcoin_wallet: Arc<Wallet>,
-    db: Arc<dyn Database>,
+    db: Arc<dyn Database + Send + Sync>,
     finality: Finality,
 ) -> Result<(Txid, AliceState)> {
-    let state = db.get_state(swap_id).await?.try_into()?;
+    let state = db.get_state(swap_id).await?.try_into().with_context(|| {
+        format!(
+            "Cannot redeem swap {} because we bought XMR in it, resume the swap to redeem the XMR",
+            swap_id
+        )
+    })?;
 
     match state {
         AliceState::EncSigLearned {
@@ -5,7 +5,10 @@ use std::convert::TryInto;
 use std::sync::Arc;
 use uuid::Uuid;
 
-pub async fn safely_abort(swap_id: Uuid, db: Arc<dyn Database>) -> Result<AliceState> {
+pub async fn safely_abort(
+    swap_id: Uuid,
+    db: Arc<dyn Database + Send + Sync>,
+) -> Result<AliceState> {
     let state = db.get_state(swap_id).await?.try_into()?;
 
     match state {
@@ -0,0 +1,206 @@
+use crate::{bitcoin, monero};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// After this time a provisional reservation of a swap setup that neither
+/// completed nor failed is dropped, the setup times out before.
+pub const SWAP_SETUP_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// An amount that can be reserved, counted in its smallest unit.
+pub trait Reservable: Copy {
+    fn to_smallest_unit(self) -> u64;
+    fn from_smallest_unit(amount: u64) -> Self;
+}
+
+impl Reservable for monero::Amount {
+    fn to_smallest_unit(self) -> u64 {
+        self.as_piconero()
+    }
+
+    fn from_smallest_unit(amount: u64) -> Self {
+        monero::Amount::from_piconero(amount)
+    }
+}
+
+impl Reservable for bitcoin::Amount {
+    fn to_smallest_unit(self) -> u64 {
+        self.as_sat()
+    }
+
+    fn from_smallest_unit(amount: u64) -> Self {
+        bitcoin::Amount::from_sat(amount)
+    }
+}
+
+/// The funds held back for swaps that did not lock them yet, so they are not
+/// promised to several swaps at once.
+///
+/// The event loop keeps one for the Monero it sells and one for the Bitcoin it
+/// pays when buying Monero.
+#[derive(Debug)]
+pub struct Reservations<A> {
+    /// Funds reserved for swaps.
+    swaps: HashMap<Uuid, A>,
+    /// Funds provisionally reserved for swap setups in progress, by the id of
+    /// the setup. A peer can run several setups at once.
+    setups: HashMap<Uuid, (A, Instant)>,
+}
+
+impl<A> Default for Reservations<A> {
+    fn default() -> Self {
+        Self {
+            swaps: HashMap::new(),
+            setups: HashMap::new(),
+        }
+    }
+}
+
+impl<A> Reservations<A>
+where
+    A: Reservable,
+{
+    pub fn reserve(&mut self, swap_id: Uuid, amount: A) {
+        self.swaps.insert(swap_id, amount);
+    }
+
+    pub fn release(&mut self, swap_id: Uuid) {
+        self.swaps.remove(&swap_id);
+    }
+
+    pub fn swap_ids(&self) -> Vec<Uuid> {
+        self.swaps.keys().copied().collect()
+    }
+
+    pub fn reserve_for_setup(&mut self, setup_id: Uuid, amount: A, now: Instant) {
+        self.setups.insert(setup_id, (amount, now));
+    }
+
+    pub fn release_setup(&mut self, setup_id: Uuid) {
+        self.setups.remove(&setup_id);
+    }
+
+    /// The part of the balance that is not reserved.
+    ///
+    /// Drops the reservations of swap setups that started more than
+    /// [`SWAP_SETUP_TIMEOUT`] before `now`.
+    pub fn available(&mut self, balance: A, now: Instant) -> A {
+        self.setups
+            .retain(|_, (_, reserved_at)| now.duration_since(*reserved_at) < SWAP_SETUP_TIMEOUT);
+
+        let reserved = self
+            .swaps
+            .values()
+            .chain(self.setups.values().map(|(amount, _)| amount))
+            .fold(0, |sum, amount| sum + amount.to_smallest_unit());
+
+        A::from_smallest_unit(balance.to_smallest_unit().saturating_sub(reserved))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xmr(amount: u64) -> monero::Amount {
+        monero::Amount::from_piconero(amount)
+    }
+
+    #[test]
+    fn reserved_monero_is_not_available_until_released() {
+        let mut reservations = Reservations::default();
+        let swap_id = Uuid::new_v4();
+        let now = Instant::now();
+
+        reservations.reserve(swap_id, xmr(300));
+        assert_eq!(reservations.available(xmr(1000), now), xmr(700));
+        assert_eq!(reservations.swap_ids(), vec![swap_id]);
+
+        reservations.release(swap_id);
+        assert_eq!(reservations.available(xmr(1000), now), xmr(1000));
+        assert!(reservations.swap_ids().is_empty());
+    }
+
+    #[test]
+    fn reservations_of_swaps_and_setups_add_up() {
+        let mut reservations = Reservations::default();
+        let now = Instant::now();
+
+        reservations.reserve(Uuid::new_v4(), xmr(300));
+        reservations.reserve(Uuid::new_v4(), xmr(200));
+        reservations.reserve_for_setup(Uuid::new_v4(), xmr(100), now);
+
+        assert_eq!(reservations.available(xmr(1000), now), xmr(400));
+    }
+
+    #[test]
+    fn more_reserved_than_the_balance_leaves_nothing_available() {
+        let mut reservations = Reservations::default();
+        let now = Instant::now();
+
+        reservations.reserve(Uuid::new_v4(), xmr(800));
+        reservations.reserve_for_setup(Uuid::new_v4(), xmr(300), now);
+
+        assert_eq!(reservations.available(xmr(1000), now), xmr(0));
+    }
+
+    #[test]
+    fn completed_setup_is_replaced_by_the_reservation_of_the_swap() {
+        let mut reservations = Reservations::default();
+        let setup_id = Uuid::new_v4();
+        let now = Instant::now();
+
+        reservations.reserve_for_setup(setup_id, xmr(300), now);
+        reservations.release_setup(setup_id);
+        reservations.reserve(Uuid::new_v4(), xmr(300));
+
+        assert_eq!(reservations.available(xmr(1000), now), xmr(700));
+    }
+
+    #[test]
+    fn releasing_a_setup_keeps_the_other_setups_reserved() {
+        let mut reservations = Reservations::default();
+        let first_setup = Uuid::new_v4();
+        let second_setup = Uuid::new_v4();
+        let now = Instant::now();
+
+        reservations.reserve_for_setup(first_setup, xmr(300), now);
+        reservations.reserve_for_setup(second_setup, xmr(200), now);
+        assert_eq!(reservations.available(xmr(1000), now), xmr(500));
+
+        reservations.release_setup(first_setup);
+        assert_eq!(reservations.available(xmr(1000), now), xmr(800));
+    }
+
+    #[test]
+    fn bitcoin_is_reserved_like_monero() {
+        let mut reservations = Reservations::default();
+        let now = Instant::now();
+
+        reservations.reserve_for_setup(Uuid::new_v4(), bitcoin::Amount::from_sat(300), now);
+        reservations.reserve_for_setup(Uuid::new_v4(), bitcoin::Amount::from_sat(500), now);
+
+        assert_eq!(
+            reservations.available(bitcoin::Amount::from_sat(1000), now),
+            bitcoin::Amount::from_sat(200)
+        );
+    }
+
+    #[test]
+    fn setup_reservations_expire_after_the_swap_setup_timeout() {
+        let mut reservations = Reservations::default();
+        let now = Instant::now();
+
+        reservations.reserve_for_setup(Uuid::new_v4(), xmr(300), now);
+        reservations.reserve(Uuid::new_v4(), xmr(200));
+
+        let just_before = now + SWAP_SETUP_TIMEOUT - Duration::from_secs(1);
+        assert_eq!(reservations.available(xmr(1000), just_before), xmr(500));
+
+        let after = now + SWAP_SETUP_TIMEOUT;
+        assert_eq!(reservations.available(xmr(1000), after), xmr(800));
+
+        // expired reservations stay dropped
+        assert_eq!(reservations.available(xmr(1000), just_before), xmr(800));
+    }
+}
@@ -0,0 +1,329 @@
+//! A JSON-RPC server to control a running ASB.
+//!
+//! The server accepts HTTP and websocket connections and authenticates them
+//! like the CLI, see [`crate::rpc`].
+//!
+//! The manual recovery methods refuse to act on swaps the ASB is running at
+//! the same time.
+
+use crate::asb::{punish, redeem, EventLoopService, Finality, LatestRate};
+use crate::monero::wallet::AccountBalance;
+use crate::protocol::event::SwapEvents;
+use crate::protocol::recovery::{cancel, refund, safely_abort};
+use crate::protocol::Database;
+use crate::rpc::{serve, to_rpc_error, Recovered, Swap, SwapIdParams};
+use crate::{bitcoin, monero};
+use anyhow::{bail, Result};
+use jsonrpsee::core::Error as RpcError;
+use jsonrpsee::server::{RpcModule, ServerHandle};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Everything the RPC methods need to access in the running ASB.
+#[allow(missing_debug_implementations)]
+pub struct Context<LR> {
+    pub db: Arc<dyn Database + Send + Sync>,
+    pub bitcoin_wallet: Arc<bitcoin::Wallet>,
+    pub monero_wallet: Arc<monero::Wallet>,
+    pub latest_rate: Mutex<LR>,
+    pub event_loop: EventLoopService,
+    pub swap_events: SwapEvents,
+    pub running_swaps: RunningSwaps,
+}
+
+/// The swaps the ASB is currently running, with the number of tasks running
+/// each of them.
+#[derive(Clone, Debug, Default)]
+pub struct RunningSwaps(Arc<Mutex<HashMap<Uuid, usize>>>);
+
+impl RunningSwaps {
+    /// Mark the swap as running until the returned guard is dropped.
+    pub fn start(&self, swap_id: Uuid) -> RunningSwap {
+        *self.lock().entry(swap_id).or_default() += 1;
+
+        RunningSwap {
+            swap_id,
+            running_swaps: self.clone(),
+        }
+    }
+
+    /// Like [`RunningSwaps::start`], but fails if the swap is already running.
+    fn try_start(&self, swap_id: Uuid) -> Result<RunningSwap> {
+        let mut running_swaps = self.lock();
+        if running_swaps.contains_key(&swap_id) {
+            bail!(
+                "Swap {} is running, wait for it to finish or stop the ASB and use the recovery commands",
+                swap_id
+            );
+        }
+        running_swaps.insert(swap_id, 1);
+
+        Ok(RunningSwap {
+            swap_id,
+            running_swaps: self.clone(),
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, usize>> {
+        // the map stays consistent even if a thread panicked while holding the lock
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Marks a swap as running as long as it is alive.
+#[derive(Debug)]
+pub struct RunningSwap {
+    swap_id: Uuid,
+    running_swaps: RunningSwaps,
+}
+
+impl Drop for RunningSwap {
+    fn drop(&mut self) {
+        let mut running_swaps = self.running_swaps.lock();
+
+        if let Some(count) = running_swaps.get_mut(&self.swap_id) {
+            *count -= 1;
+            if *count == 0 {
+                running_swaps.remove(&self.swap_id);
+            }
+        }
+    }
+}
+
+/// Start the RPC server, returning the address it actually listens on.
+///
+/// The server stops once the returned [`ServerHandle`] is dropped.
+pub async fn run_server<LR>(
+    listen: SocketAddr,
+    token: &str,
+    context: Context<LR>,
+) -> Result<(SocketAddr, ServerHandle)>
+where
+    LR: LatestRate + Clone + Send + 'static,
+{
+    serve(listen, token, methods(context)?).await
+}
+
+fn methods<LR>(context: Context<LR>) -> Result<RpcModule<Context<LR>>>
+where
+    LR: LatestRate + Clone + Send + 'static,
+{
+    let mut module = RpcModule::new(context);
+
+    module.register_async_method("get_swaps", |_, context| async move {
+        let swaps = context.db.all().await.map_err(to_rpc_error)?;
+
+        Ok(swaps
+            .into_iter()
+            .map(|(swap_id, state)| Swap::new(swap_id, state))
+            .collect::<Vec<_>>())
+    })?;
+
+    module.register_async_method("get_balances", |_, context| async move {
+        context.bitcoin_wallet.sync().await.map_err(to_rpc_error)?;
+
+        Ok(Balances {
+            bitcoin: context
+                .bitcoin_wallet
+                .balance()
+                .await
+                .map_err(to_rpc_error)?,
+            monero: context
+                .monero_wallet
+                .get_balance()
+                .await
+                .map_err(to_rpc_error)?,
+            monero_accounts: context
+                .monero_wallet
+                .get_account_balances()
+                .await
+                .map_err(to_rpc_error)?,
+        })
+    })?;
+
+    module.register_method("get_rate", |_, context| {
+        // `latest_rate` needs exclusive access, clone to not hold the lock while
+        // fetching it
+        let mut latest_rate = context
+            .latest_rate
+            .lock()
+            .map_err(|_| RpcError::Custom("Rate service is poisoned".to_owned()))?
+            .clone();
+        let rate = latest_rate
+            .latest_rate()
+            .map_err(|e| RpcError::Custom(format!("Failed to get latest rate: {}", e)))?;
+
+        Ok(Rate {
+            market_price: rate.market_price(),
+        })
+    })?;
+
+    module.register_async_method("get_quote", |_, context| async move {
+        context.event_loop.quote().await.map_err(to_rpc_error)
+    })?;
+
+    module.register_async_method("get_ask_quote", |_, context| async move {
+        context.event_loop.ask_quote().await.map_err(to_rpc_error)
+    })?;
+
+    module.register_async_method("get_connected_peers", |_, context| async move {
+        let peers = context
+            .event_loop
+            .connected_peers()
+            .await
+            .map_err(to_rpc_error)?;
+
+        Ok(peers
+            .into_iter()
+            .map(|peer| peer.to_string())
+            .collect::<Vec<_>>())
+    })?;
+
+    module.register_async_method("cancel", |params, context| async move {
+        let SwapIdParams { swap_id } = params.parse()?;
+        let _running = context
+            .running_swaps
+            .try_start(swap_id)
+            .map_err(to_rpc_error)?;
+
+        let (txid, state) = cancel(swap_id, context.bitcoin_wallet.clone(), context.db.clone())
+            .await
+            .map_err(to_rpc_error)?;
+
+        Ok(Recovered::new(Some(txid), state))
+    })?;
+
+    module.register_async_method("refund", |params, context| async move {
+        let SwapIdParams { swap_id } = params.parse()?;
+        let _running = context
+            .running_swaps
+            .try_start(swap_id)
+            .map_err(to_rpc_error)?;
+
+        let state = refund(
+            swap_id,
+            context.bitcoin_wallet.clone(),
+            Some(context.monero_wallet.clone()),
+            context.db.clone(),
+        )
+        .await
+        .map_err(to_rpc_error)?;
+
+        Ok(Recovered::new(None, state))
+    })?;
+
+    module.register_async_method("punish", |params, context| async move {
+        let SwapIdParams { swap_id } = params.parse()?;
+        let _running = context
+            .running_swaps
+            .try_start(swap_id)
+            .map_err(to_rpc_error)?;
+
+        let (txid, state) = punish(swap_id, context.bitcoin_wallet.clone(), context.db.clone())
+            .await
+            .map_err(to_rpc_error)?;
+
+        Ok(Recovered::new(Some(txid), state))
+    })?;
+
+    module.register_async_method("redeem", |params, context| async move {
+        let RedeemParams {
+            swap_id,
+            do_not_await_finality,
+        } = params.parse()?;
+        let _running = context
+            .running_swaps
+            .try_start(swap_id)
+            .map_err(to_rpc_error)?;
+
+        let (txid, state) = redeem(
+            swap_id,
+            context.bitcoin_wallet.clone(),
+            context.db.clone(),
+            Finality::from_bool(do_not_await_finality),
+        )
+        .await
+        .map_err(to_rpc_error)?;
+
+        Ok(Recovered::new(Some(txid), state))
+    })?;
+
+    module.register_async_method("safely_abort", |params, context| async move {
+        let SwapIdParams { swap_id } = params.parse()?;
+        let _running = context
+            .running_swaps
+            .try_start(swap_id)
+            .map_err(to_rpc_error)?;
+
+        let state = safely_abort(swap_id, context.db.clone())
+            .await
+            .map_err(to_rpc_error)?;
+
+        Ok(Recovered::new(None, state))
+    })?;
+
+    module.register_subscription(
+        "subscribe_swap_progress",
+        "swap_progress",
+        "unsubscribe_swap_progress",
+        |_, mut sink, context| {
+            sink.accept()?;
+
+            let events = context.swap_events.stream();
+
+            tokio::spawn(async move {
+                let _ = sink.pipe_from_stream(events).await;
+            });
+
+            Ok(())
+        },
+    )?;
+
+    Ok(module)
+}
+
+#[derive(Deserialize)]
+struct RedeemParams {
+    swap_id: Uuid,
+    #[serde(default)]
+    do_not_await_finality: bool,
+}
+
+#[derive(Serialize)]
+struct Balances {
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+    bitcoin: bitcoin::Amount,
+    /// The balance of the trading account.
+    #[serde(with = "crate::monero::monero_amount")]
+    monero: monero::Amount,
+    monero_accounts: Vec<AccountBalance>,
+}
+
+#[derive(Serialize)]
+struct Rate {
+    /// The price of 1 XMR before applying any spread.
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+    market_price: bitcoin::Amount,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_to_recover_running_swaps() {
+        let running_swaps = RunningSwaps::default();
+        let swap_id = Uuid::new_v4();
+
+        let running = running_swaps.start(swap_id);
+        assert!(running_swaps.try_start(swap_id).is_err());
+
+        drop(running);
+        assert!(running_swaps.try_start(swap_id).is_ok());
+    }
+}
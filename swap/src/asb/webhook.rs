@@ -0,0 +1,342 @@
+//! Notifies the operator about swaps through webhooks.
+//!
+//! Every [`SwapEvent`] of the swaps run by the event loop and every
+//! [`EventLoopError`] is POSTed as JSON to the configured webhooks that want
+//! it. Failed deliveries are retried with an exponential backoff.
+//!
+//! Every request carries the Unix time it was sent at in the `X-Asb-Timestamp`
+//! header. If a secret is configured, `<timestamp>.<payload>` is signed with
+//! HMAC-SHA256 and the hex-encoded signature is sent in the `X-Asb-Signature`
+//! header as `sha256=<signature>`. Signing the timestamp lets the receiver
+//! reject replayed requests.
+
+use crate::asb::config::Webhook;
+use crate::asb::EventLoopError;
+use crate::protocol::event::{SwapEvent, SwapEvents};
+use crate::tor;
+use anyhow::{Context, Result};
+use futures::{Stream, StreamExt};
+use hmac::{Hmac, Mac, NewMac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc};
+
+/// The notification types a webhook receives if not configured otherwise.
+pub const DEFAULT_EVENTS: &[&str] = &["state_changed", "failed", "event_loop_error"];
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+
+const SIGNATURE_HEADER: &str = "X-Asb-Signature";
+const TIMESTAMP_HEADER: &str = "X-Asb-Timestamp";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// How many notifications are queued for a webhook that is slow to accept
+/// them before new ones are dropped.
+const QUEUE_CAPACITY: usize = 100;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Notification {
+    Swap(SwapEvent),
+    EventLoopError(EventLoopError),
+}
+
+/// Spawn the tasks that deliver the notifications to the given webhooks.
+pub fn spawn(
+    webhooks: Vec<Webhook>,
+    swap_events: &SwapEvents,
+    errors: broadcast::Receiver<EventLoopError>,
+    tor_socks5_port: Option<u16>,
+) -> Result<()> {
+    if webhooks.is_empty() {
+        return Ok(());
+    }
+
+    let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT);
+    let client = match tor_socks5_port {
+        Some(port) => client.proxy(tor::reqwest_proxy(port)),
+        None => client,
+    };
+    let client = client
+        .build()
+        .context("Failed to create HTTP client for webhooks")?;
+
+    let queues = webhooks
+        .into_iter()
+        .map(|webhook| {
+            let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+            tokio::spawn(deliver_all(
+                client.clone(),
+                webhook.clone(),
+                receiver,
+                INITIAL_RETRY_DELAY,
+            ));
+
+            (webhook, sender)
+        })
+        .collect::<Vec<_>>();
+
+    let mut notifications = futures::stream::select(
+        swap_events.stream().map(Notification::Swap),
+        error_stream(errors).map(Notification::EventLoopError),
+    );
+
+    tokio::spawn(async move {
+        while let Some(notification) = notifications.next().await {
+            let payload = match serde_json::to_value(&notification) {
+                Ok(payload) => payload,
+                Err(error) => {
+                    tracing::error!("Failed to serialize webhook notification: {:#}", error);
+                    continue;
+                }
+            };
+
+            for (webhook, queue) in &queues {
+                if !wants(webhook, &payload) {
+                    continue;
+                }
+
+                if queue.try_send(payload.to_string()).is_err() {
+                    tracing::warn!(url = %webhook.url, "Webhook is not keeping up, dropping notification");
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Whether the webhook is configured to receive this notification.
+fn wants(webhook: &Webhook, payload: &serde_json::Value) -> bool {
+    let kind = payload["type"].as_str().unwrap_or_default();
+
+    if !webhook.events.iter().any(|event| event == kind) {
+        return false;
+    }
+
+    match payload["state"].as_str() {
+        Some(state) if !webhook.states.is_empty() => {
+            webhook.states.iter().any(|wanted| wanted == state)
+        }
+        _ => true,
+    }
+}
+
+/// Deliver the queued payloads one after another, so the webhook sees them in
+/// order.
+async fn deliver_all(
+    client: reqwest::Client,
+    webhook: Webhook,
+    mut queue: mpsc::Receiver<String>,
+    initial_retry_delay: Duration,
+) {
+    while let Some(payload) = queue.recv().await {
+        let mut delay = initial_retry_delay;
+        let mut attempt = 0;
+
+        loop {
+            match deliver(&client, &webhook, &payload).await {
+                Ok(()) => break,
+                Err(error) if attempt < webhook.max_retries => {
+                    attempt += 1;
+                    tracing::warn!(url = %webhook.url, %attempt, "Failed to deliver webhook notification, retrying in {}s: {:#}", delay.as_secs_f64(), error);
+
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                Err(error) => {
+                    tracing::error!(url = %webhook.url, "Giving up on webhook notification: {:#}", error);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+async fn deliver(client: &reqwest::Client, webhook: &Webhook, payload: &str) -> Result<()> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("System time is before the Unix epoch")?
+        .as_secs();
+
+    let mut request = client
+        .post(webhook.url.clone())
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(TIMESTAMP_HEADER, timestamp)
+        .body(payload.to_owned());
+
+    if let Some(secret) = &webhook.secret {
+        request = request.header(
+            SIGNATURE_HEADER,
+            sign(secret, timestamp, payload.as_bytes()),
+        );
+    }
+
+    request
+        .send()
+        .await
+        .context("Failed to send request")?
+        .error_for_status()
+        .context("Webhook rejected the notification")?;
+
+    Ok(())
+}
+
+fn sign(secret: &str, timestamp: u64, payload: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(payload);
+
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn error_stream(
+    receiver: broadcast::Receiver<EventLoopError>,
+) -> impl Stream<Item = EventLoopError> + Send + Unpin + 'static {
+    let stream = futures::stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(error) => return Some((error, receiver)),
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!(%skipped, "Webhooks missed event loop errors")
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Box::pin(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{self, Response};
+    use url::Url;
+    use uuid::Uuid;
+
+    #[test]
+    fn filters_by_event_type_and_state() {
+        let webhook = Webhook {
+            states: vec!["btc is punished".to_owned()],
+            ..webhook("http://127.0.0.1:1".parse().unwrap(), None)
+        };
+
+        let punished = state_changed("btc is punished");
+        let redeemed = state_changed("btc is redeemed");
+        let failed = serde_json::to_value(Notification::Swap(SwapEvent::Failed {
+            swap_id: Uuid::nil(),
+            error: "boom".to_owned(),
+        }))
+        .unwrap();
+        let countdown = serde_json::json!({ "type": "timelock_countdown" });
+
+        assert!(wants(&webhook, &punished));
+        assert!(!wants(&webhook, &redeemed));
+        assert!(wants(&webhook, &failed));
+        assert!(!wants(&webhook, &countdown));
+    }
+
+    #[test]
+    fn event_loop_errors_are_tagged_with_their_type() {
+        let notification = Notification::EventLoopError(EventLoopError {
+            swap_id: None,
+            peer: None,
+            error: "boom".to_owned(),
+        });
+
+        assert_eq!(
+            serde_json::to_value(notification).unwrap(),
+            serde_json::json!({ "type": "event_loop_error", "error": "boom" })
+        );
+    }
+
+    #[tokio::test]
+    async fn delivers_signed_payload() {
+        let (address, mut requests) = mock_server::http(vec![Response::status(200)]).await;
+        let payload = state_changed("xmr is locked").to_string();
+
+        let (queue, receiver) = mpsc::channel(1);
+        tokio::spawn(deliver_all(
+            reqwest::Client::new(),
+            webhook(hook_url(address), Some("secret")),
+            receiver,
+            Duration::from_millis(10),
+        ));
+        queue.send(payload.clone()).await.unwrap();
+
+        let request = requests.recv().await.unwrap();
+        let timestamp = request
+            .head
+            .lines()
+            .find_map(|line| line.strip_prefix("x-asb-timestamp: "))
+            .unwrap()
+            .trim()
+            .parse::<u64>()
+            .unwrap();
+        assert_eq!(request.body, payload);
+        assert!(request.head.contains(&format!(
+            "{}: {}",
+            SIGNATURE_HEADER.to_lowercase(),
+            sign("secret", timestamp, payload.as_bytes())
+        )));
+    }
+
+    #[test]
+    fn signature_covers_the_timestamp() {
+        let payload = b"{}";
+
+        assert_ne!(
+            sign("secret", 1_650_000_000, payload),
+            sign("secret", 1_650_000_001, payload)
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_failed_deliveries() {
+        let statuses = vec![500, 503, 200].into_iter().map(Response::status);
+        let (address, mut requests) = mock_server::http(statuses).await;
+        let payload = state_changed("btc is refunded").to_string();
+
+        let (queue, receiver) = mpsc::channel(1);
+        tokio::spawn(deliver_all(
+            reqwest::Client::new(),
+            webhook(hook_url(address), None),
+            receiver,
+            Duration::from_millis(10),
+        ));
+        queue.send(payload.clone()).await.unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(requests.recv().await.unwrap().body, payload);
+        }
+    }
+
+    fn webhook(url: Url, secret: Option<&str>) -> Webhook {
+        Webhook {
+            url,
+            secret: secret.map(ToOwned::to_owned),
+            events: DEFAULT_EVENTS
+                .iter()
+                .map(|event| event.to_string())
+                .collect(),
+            states: vec![],
+            max_retries: DEFAULT_MAX_RETRIES,
+        }
+    }
+
+    fn state_changed(state: &str) -> serde_json::Value {
+        serde_json::to_value(Notification::Swap(SwapEvent::StateChanged {
+            swap_id: Uuid::nil(),
+            state: state.to_owned(),
+        }))
+        .unwrap()
+    }
+
+    fn hook_url(address: std::net::SocketAddr) -> Url {
+        format!("http://{}/hook", address).parse().unwrap()
+    }
+}
@@ -18,25 +18,28 @@ use libp2p::core::multiaddr::Protocol;
 use libp2p::core::Multiaddr;
 use libp2p::swarm::AddressScore;
 use libp2p::Swarm;
-use std::convert::TryInto;
 use std::env;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use structopt::clap;
 use structopt::clap::ErrorKind;
 use swap::asb::command::{parse_args, Arguments, Command};
 use swap::asb::config::{
     initial_setup, query_user_for_initial_config, read_config, Config, ConfigNotInitialized,
 };
-use swap::asb::{cancel, punish, redeem, refund, safely_abort, EventLoop, Finality, KrakenRate};
+use swap::asb::{punish, redeem, rpc, webhook, EventLoop, Finality, PriceFeedRate};
 use swap::database::open_db;
 use swap::monero::Amount;
 use swap::network::rendezvous::XmrBtcNamespace;
 use swap::network::swarm;
-use swap::protocol::alice::{run, AliceState};
+use swap::protocol::alice::run;
+use swap::protocol::bob;
+use swap::protocol::recovery::{cancel, refund, safely_abort};
+use swap::rpc::read_or_generate_token;
 use swap::seed::Seed;
 use swap::tor::AuthenticatedClient;
-use swap::{asb, bitcoin, kraken, monero, tor};
+use swap::{asb, bitcoin, monero, tor};
 use tracing_subscriber::filter::LevelFilter;
 
 const DEFAULT_WALLET_NAME: &str = "asb-wallet";
@@ -91,14 +94,32 @@ async fn main() -> Result<()> {
         ));
     }
 
+    config.ensure_onion_only()?;
+
     let db = open_db(config.data.dir.join("sqlite")).await?;
 
     let seed =
         Seed::from_file_or_generate(&config.data.dir).expect("Could not retrieve/initialize seed");
 
+    // In onion-only mode every command that connects anywhere depends on Tor
+    if config.tor.embedded && (config.tor.onion_only || matches!(cmd, Command::Start { .. })) {
+        tor::start_embedded(
+            &config.data.dir.join("tor"),
+            config.tor.socks5_port,
+            Some(config.tor.control_port),
+        )
+        .await?;
+    }
+    if let Some(socks5_port) = config.tor.proxy_port() {
+        tor::Client::new(socks5_port)
+            .assert_tor_running()
+            .await
+            .context("Tor is not running, refusing to connect anywhere in onion-only mode")?;
+    }
+
     match cmd {
         Command::Start { resume_only } => {
-            let bitcoin_wallet = init_bitcoin_wallet(&config, &seed, env_config).await?;
+            let bitcoin_wallet = Arc::new(init_bitcoin_wallet(&config, &seed, env_config).await?);
 
             let monero_wallet = init_monero_wallet(&config, env_config).await?;
 
@@ -116,43 +137,70 @@ async fn main() -> Result<()> {
                 tracing::info!(%monero_balance, "Initialized Monero wallet");
             }
 
-            let kraken_price_updates = kraken::connect(config.maker.price_ticker_ws_url.clone())?;
+            let price_updates = match (&config.maker.price_aggregation, &config.maker.price_source)
+            {
+                (Some(_), Some(_)) => {
+                    bail!("Only one of `price_source` and `price_aggregation` can be configured")
+                }
+                (Some(price_aggregation), None) => {
+                    let sources = price_aggregation
+                        .sources
+                        .iter()
+                        .map(|source| source.name())
+                        .collect::<Vec<_>>();
+                    tracing::info!(?sources, "Connecting to price sources");
+                    price_aggregation.connect(config.tor.proxy_port())?
+                }
+                (None, _) => {
+                    let price_source = config.maker.price_source();
+                    tracing::info!(source = %price_source.name(), "Connecting to price source");
+                    price_source.connect(config.tor.proxy_port())?
+                }
+            };
 
             // setup Tor hidden services
             let tor_client =
                 tor::Client::new(config.tor.socks5_port).with_control_port(config.tor.control_port);
-            let _ac = match tor_client.assert_tor_running().await {
+            let (_ac, onion_addresses) = match tor_client.assert_tor_running().await {
                 Ok(_) => {
                     tracing::info!("Setting up Tor hidden service");
-                    let ac =
+                    let (ac, onion_addresses) =
                         register_tor_services(config.network.clone().listen, tor_client, &seed)
                             .await?;
-                    Some(ac)
+                    (Some(ac), onion_addresses)
+                }
+                Err(_) if config.tor.onion_only => {
+                    bail!("Tor not found, refusing to run on clear net in onion-only mode")
                 }
                 Err(_) => {
                     tracing::warn!("Tor not found. Running on clear net");
-                    None
+                    (None, vec![])
                 }
             };
 
-            let kraken_rate = KrakenRate::new(config.maker.ask_spread, kraken_price_updates);
+            let mut price_feed_rate = PriceFeedRate::new(config.maker.ask_spread, price_updates);
+            if let Some(max_age) = config.maker.max_price_age_secs {
+                price_feed_rate = price_feed_rate.with_max_age(Duration::from_secs(max_age));
+            }
+            if let Some(curve) = config.maker.dynamic_spread {
+                price_feed_rate = price_feed_rate.with_spread_curve(curve);
+            }
             let mut swarm = swarm::asb(
                 &seed,
                 config.maker.min_buy_btc,
                 config.maker.max_buy_btc,
-                kraken_rate.clone(),
+                config.maker.buy_xmr,
+                price_feed_rate.clone(),
                 resume_only,
                 env_config,
-                config.network.rendezvous_point.map(|rendezvous_point| {
-                    (
-                        rendezvous_point,
-                        if testnet {
-                            XmrBtcNamespace::Testnet
-                        } else {
-                            XmrBtcNamespace::Mainnet
-                        },
-                    )
-                }),
+                bitcoin_wallet.clone(),
+                config.network.rendezvous_point,
+                if testnet {
+                    XmrBtcNamespace::Testnet
+                } else {
+                    XmrBtcNamespace::Mainnet
+                },
+                config.tor.proxy_port(),
             )?;
 
             for listen in config.network.listen.clone() {
@@ -162,7 +210,12 @@ async fn main() -> Result<()> {
 
             tracing::info!(peer_id = %swarm.local_peer_id(), "Network layer initialized");
 
-            for external_address in config.network.external_addresses {
+            let mut external_addresses = config.network.external_addresses;
+            if config.tor.onion_only {
+                // The hidden service is the only way to reach us
+                external_addresses.extend(onion_addresses);
+            }
+            for external_address in external_addresses {
                 let _ = Swarm::add_external_address(
                     &mut swarm,
                     external_address,
@@ -170,23 +223,61 @@ async fn main() -> Result<()> {
                 );
             }
 
-            let (event_loop, mut swap_receiver) = EventLoop::new(
+            let monero_wallet = Arc::new(monero_wallet);
+            let (event_loop, mut swap_receiver, mut bob_swap_receiver) = EventLoop::new(
                 swarm,
                 env_config,
-                Arc::new(bitcoin_wallet),
-                Arc::new(monero_wallet),
-                db,
-                kraken_rate.clone(),
+                bitcoin_wallet.clone(),
+                monero_wallet.clone(),
+                db.clone(),
+                price_feed_rate.clone(),
                 config.maker.min_buy_btc,
                 config.maker.max_buy_btc,
+                config.maker.buy_xmr,
             )
             .unwrap();
 
+            let running_swaps = rpc::RunningSwaps::default();
+
+            // the server is stopped when the handle is dropped
+            let _rpc_server = match config.rpc {
+                Some(rpc_config) => {
+                    let token = read_or_generate_token(&config.data.dir)?;
+                    let context = rpc::Context {
+                        db,
+                        bitcoin_wallet,
+                        monero_wallet,
+                        latest_rate: Mutex::new(price_feed_rate.clone()),
+                        event_loop: event_loop.service(),
+                        swap_events: event_loop.swap_events(),
+                        running_swaps: running_swaps.clone(),
+                    };
+                    let (address, handle) =
+                        rpc::run_server(rpc_config.listen, &token, context).await?;
+
+                    tracing::info!(%address, "RPC server listening");
+                    Some(handle)
+                }
+                None => None,
+            };
+
+            webhook::spawn(
+                config.webhooks,
+                &event_loop.swap_events(),
+                event_loop.subscribe_errors(),
+                config.tor.proxy_port(),
+            )?;
+
+            tokio::spawn(price_feed_rate.record_prices());
+
+            let alice_running_swaps = running_swaps.clone();
             tokio::spawn(async move {
                 while let Some(swap) = swap_receiver.recv().await {
-                    let rate = kraken_rate.clone();
+                    let rate = price_feed_rate.clone();
+                    let running_swaps = alice_running_swaps.clone();
                     tokio::spawn(async move {
                         let swap_id = swap.swap_id;
+                        let _running = running_swaps.start(swap_id);
                         match run(swap, rate).await {
                             Ok(state) => {
                                 tracing::debug!(%swap_id, final_state=%state, "Swap completed")
@@ -199,6 +290,24 @@ async fn main() -> Result<()> {
                 }
             });
 
+            tokio::spawn(async move {
+                while let Some(swap) = bob_swap_receiver.recv().await {
+                    let running_swaps = running_swaps.clone();
+                    tokio::spawn(async move {
+                        let swap_id = swap.id;
+                        let _running = running_swaps.start(swap_id);
+                        match bob::run(swap).await {
+                            Ok(state) => {
+                                tracing::debug!(%swap_id, final_state=%state, "Swap completed")
+                            }
+                            Err(error) => {
+                                tracing::error!(%swap_id, "Swap failed: {:#}", error)
+                            }
+                        }
+                    });
+                }
+            });
+
             event_loop.run().await;
         }
         Command::History => {
@@ -207,7 +316,6 @@ async fn main() -> Result<()> {
             table.set_header(vec!["SWAP ID", "STATE"]);
 
             for (swap_id, state) in db.all().await? {
-                let state: AliceState = state.try_into()?;
                 table.add_row(vec![swap_id.to_string(), state.to_string()]);
             }
 
@@ -217,24 +325,25 @@ async fn main() -> Result<()> {
             let config_json = serde_json::to_string_pretty(&config)?;
             println!("{}", config_json);
         }
-        Command::WithdrawBtc { amount, address } => {
+        Command::WithdrawBtc {
+            recipients,
+            drain_to,
+            coin_control,
+            dry_run,
+        } => {
             let bitcoin_wallet = init_bitcoin_wallet(&config, &seed, env_config).await?;
 
-            let amount = match amount {
-                Some(amount) => amount,
-                None => {
-                    bitcoin_wallet
-                        .max_giveable(address.script_pubkey().len())
-                        .await?
-                }
-            };
-
             let psbt = bitcoin_wallet
-                .send_to_address(address, amount, None)
+                .send_to_many(recipients, drain_to, coin_control)
                 .await?;
-            let signed_tx = bitcoin_wallet.sign_and_finalize(psbt).await?;
 
-            bitcoin_wallet.broadcast(signed_tx, "withdraw").await?;
+            if dry_run {
+                println!("{}", base64::encode(::bitcoin::consensus::serialize(&psbt)));
+            } else {
+                let signed_tx = bitcoin_wallet.sign_and_finalize(psbt).await?;
+
+                bitcoin_wallet.broadcast(signed_tx, "withdraw").await?;
+            }
         }
         Command::Balance => {
             let bitcoin_wallet = init_bitcoin_wallet(&config, &seed, env_config).await?;
@@ -247,6 +356,15 @@ async fn main() -> Result<()> {
                 %bitcoin_balance,
                 %monero_balance,
                 "Current balance");
+
+            for account in monero_wallet.get_account_balances().await? {
+                tracing::info!(
+                    account_index = %account.index,
+                    label = %account.label,
+                    balance = %account.balance,
+                    unlocked_balance = %account.unlocked_balance,
+                    "Monero account balance");
+            }
         }
         Command::Cancel { swap_id } => {
             let bitcoin_wallet = init_bitcoin_wallet(&config, &seed, env_config).await?;
@@ -262,12 +380,12 @@ async fn main() -> Result<()> {
             refund(
                 swap_id,
                 Arc::new(bitcoin_wallet),
-                Arc::new(monero_wallet),
+                Some(Arc::new(monero_wallet)),
                 db,
             )
             .await?;
 
-            tracing::info!("Monero successfully refunded");
+            tracing::info!("Swap successfully refunded");
         }
         Command::Punish { swap_id } => {
             let bitcoin_wallet = init_bitcoin_wallet(&config, &seed, env_config).await?;
@@ -316,11 +434,12 @@ async fn init_bitcoin_wallet(
     let wallet_dir = config.data.dir.join("wallet");
 
     let wallet = bitcoin::Wallet::new(
-        config.bitcoin.electrum_rpc_url.clone(),
+        config.bitcoin.backend()?,
         &wallet_dir,
         seed.derive_extended_private_key(env_config.bitcoin_network)?,
         env_config,
         config.bitcoin.target_block,
+        config.tor.proxy_port(),
     )
     .await
     .context("Failed to initialize Bitcoin wallet")?;
@@ -339,19 +458,54 @@ async fn init_monero_wallet(
         config.monero.wallet_rpc_url.clone(),
         DEFAULT_WALLET_NAME.to_string(),
         env_config,
+        config.tor.proxy_port(),
     )
     .await?;
 
+    let wallet = match &config.monero.daemon_url {
+        Some(daemon_url) => {
+            let monerod = monero_rpc::monerod::Client::from_url(daemon_url.clone())?;
+            let monerod = match config.tor.proxy_port() {
+                Some(socks5_port) => monerod.with_proxy(tor::reqwest_proxy(socks5_port))?,
+                None => monerod,
+            };
+
+            wallet.with_fee_estimation(monerod)
+        }
+        None => wallet,
+    };
+
+    let accounts = &config.monero.accounts;
+    let wallet = wallet.with_account(accounts.trading).await?;
+    let wallet = match (accounts.refund, accounts.refund_address) {
+        (Some(_), Some(_)) => {
+            bail!("Only one of `refund` and `refund_address` can be configured for the Monero accounts")
+        }
+        (Some(account_index), None) => wallet.with_refund_account(account_index).await?,
+        (None, Some(address)) => {
+            if address.network != env_config.monero_network {
+                bail!(
+                    "Monero refund address {} is not on the {:?} network",
+                    address,
+                    env_config.monero_network
+                )
+            }
+            wallet.with_refund_address(address)
+        }
+        (None, None) => wallet,
+    };
+
     Ok(wallet)
 }
 
-/// Registers a hidden service for each network.
+/// Registers a hidden service for each network and returns the onion addresses
+/// of the services.
 /// Note: Once ac goes out of scope, the services will be de-registered.
 async fn register_tor_services(
     networks: Vec<Multiaddr>,
     tor_client: tor::Client,
     seed: &Seed,
-) -> Result<AuthenticatedClient> {
+) -> Result<(AuthenticatedClient, Vec<Multiaddr>)> {
     let mut ac = tor_client.into_authenticated_client().await?;
 
     let hidden_services_details = networks
@@ -380,10 +534,23 @@ async fn register_tor_services(
         .get_onion_address()
         .get_address_without_dot_onion();
 
-    hidden_services_details.iter().for_each(|(port, _)| {
-        let onion_address = format!("/onion3/{}:{}", onion_address, port);
-        tracing::info!(%onion_address, "Successfully created hidden service");
-    });
+    // Onion addresses keep what follows the port of the network, e.g. `/ws`
+    let mut onion_addresses = Vec::new();
+    for network in &networks {
+        let mut protocols = network
+            .iter()
+            .skip_while(|protocol| !matches!(protocol, Protocol::Tcp(_)));
+
+        if let Some(Protocol::Tcp(port)) = protocols.next() {
+            let onion_address = format!("/onion3/{}:{}", onion_address, port)
+                .parse::<Multiaddr>()
+                .context("Failed to parse onion address of hidden service")?;
+            let onion_address = protocols.fold(onion_address, Multiaddr::with);
+            tracing::info!(%onion_address, "Successfully created hidden service");
+
+            onion_addresses.push(onion_address);
+        }
+    }
 
-    Ok(ac)
+    Ok((ac, onion_addresses))
 }
@@ -17,25 +17,32 @@ use comfy_table::Table;
 use qrcode::render::unicode;
 use qrcode::QrCode;
 use std::cmp::min;
+use std::collections::HashSet;
 use std::convert::TryInto;
 use std::env;
 use std::future::Future;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
-use swap::bitcoin::TxLock;
-use swap::cli::command::{parse_args_and_apply_defaults, Arguments, Command, ParseResult};
-use swap::cli::{list_sellers, EventLoop, SellerStatus};
+use swap::asb::NoRate;
+use swap::bitcoin::{BackendConfig, TxLock};
+use swap::cli::command::{
+    parse_args_and_apply_defaults, Arguments, Command, ParseResult, SellerSelection,
+};
+use swap::cli::{list_sellers, rpc, seller_directory, SellerStatus, UnfinishedSwap};
 use swap::database::open_db;
 use swap::env::Config;
 use swap::libp2p_ext::MultiAddrExt;
+use swap::network::ask_quote::AskQuote;
 use swap::network::quote::{BidQuote, ZeroQuoteReceived};
-use swap::network::swarm;
-use swap::protocol::bob;
+use swap::network::swap_setup::reverse::alice::{min_btc, NewSwap as NewReverseSwap};
+use swap::protocol::alice::AliceState;
 use swap::protocol::bob::{BobState, Swap};
+use swap::protocol::event::SwapEvents;
+use swap::protocol::{alice, bob, recovery, State};
+use swap::rpc::read_or_generate_token;
 use swap::seed::Seed;
-use swap::{bitcoin, cli, monero};
-use url::Url;
+use swap::{bitcoin, cli, monero, tor};
 use uuid::Uuid;
 
 #[tokio::main]
@@ -57,45 +64,70 @@ async fn main() -> Result<()> {
     match cmd {
         Command::BuyXmr {
             seller,
-            bitcoin_electrum_rpc_url,
+            bitcoin_backend,
             bitcoin_target_block,
             bitcoin_change_address,
             monero_receive_address,
             monero_daemon_address,
             tor_socks5_port,
+            embedded_tor,
+            tor_only,
         } => {
             let swap_id = Uuid::new_v4();
 
             cli::tracing::init(debug, json, data_dir.join("logs"), Some(swap_id))?;
+            let tor_proxy = init_tor(&data_dir, tor_socks5_port, embedded_tor, tor_only).await?;
             let db = open_db(data_dir.join("sqlite")).await?;
             let seed = Seed::from_file_or_generate(data_dir.as_path())
                 .context("Failed to read in seed file")?;
 
             let bitcoin_wallet = init_bitcoin_wallet(
-                bitcoin_electrum_rpc_url,
+                bitcoin_backend,
                 &seed,
                 data_dir.clone(),
                 env_config,
                 bitcoin_target_block,
+                tor_proxy,
             )
             .await?;
+            let seller = match seller {
+                SellerSelection::Address(seller) => seller,
+                SellerSelection::Best => {
+                    let max_giveable = bitcoin_wallet.max_giveable(TxLock::script_size()).await?;
+                    let seller =
+                        seller_directory::best_seller(db.clone(), max_giveable, |seller| {
+                            seller_directory::request_quote(
+                                seller,
+                                seed.derive_libp2p_identity(),
+                                tor_socks5_port,
+                                tor_only,
+                            )
+                        })
+                        .await?;
+                    tracing::info!(%seller, "Selected the best ranked seller");
+
+                    seller
+                }
+            };
             let (monero_wallet, _process) =
-                init_monero_wallet(data_dir, monero_daemon_address, env_config).await?;
+                init_monero_wallet(data_dir, monero_daemon_address, env_config, tor_proxy).await?;
             let bitcoin_wallet = Arc::new(bitcoin_wallet);
             let seller_peer_id = seller
                 .extract_peer_id()
                 .context("Seller address must contain peer ID")?;
             db.insert_address(seller_peer_id, seller.clone()).await?;
 
-            let behaviour = cli::Behaviour::new(seller_peer_id, env_config, bitcoin_wallet.clone());
-            let mut swarm =
-                swarm::cli(seed.derive_libp2p_identity(), tor_socks5_port, behaviour).await?;
-            swarm.behaviour_mut().add_address(seller_peer_id, seller);
-
-            tracing::debug!(peer_id = %swarm.local_peer_id(), "Network layer initialized");
-
-            let (event_loop, mut event_loop_handle) =
-                EventLoop::new(swap_id, swarm, seller_peer_id)?;
+            let (event_loop, mut event_loop_handle) = cli::connect_to_seller(
+                swap_id,
+                seller_peer_id,
+                vec![seller],
+                env_config,
+                bitcoin_wallet.clone(),
+                seed.derive_libp2p_identity(),
+                tor_socks5_port,
+                tor_only,
+            )
+            .await?;
             let event_loop = tokio::spawn(event_loop.run());
 
             let max_givable = || bitcoin_wallet.max_giveable(TxLock::script_size());
@@ -146,6 +178,120 @@ async fn main() -> Result<()> {
                 }
             }
         }
+        Command::SellXmr {
+            seller,
+            bitcoin_backend,
+            bitcoin_target_block,
+            bitcoin_receive_address,
+            monero_daemon_address,
+            tor_socks5_port,
+            embedded_tor,
+            tor_only,
+        } => {
+            let swap_id = Uuid::new_v4();
+
+            cli::tracing::init(debug, json, data_dir.join("logs"), Some(swap_id))?;
+            let tor_proxy = init_tor(&data_dir, tor_socks5_port, embedded_tor, tor_only).await?;
+            let db = open_db(data_dir.join("sqlite")).await?;
+            let seed = Seed::from_file_or_generate(data_dir.as_path())
+                .context("Failed to read in seed file")?;
+
+            let bitcoin_wallet = init_bitcoin_wallet(
+                bitcoin_backend,
+                &seed,
+                data_dir.clone(),
+                env_config,
+                bitcoin_target_block,
+                tor_proxy,
+            )
+            .await?;
+            let (monero_wallet, _process) =
+                init_monero_wallet(data_dir, monero_daemon_address, env_config, tor_proxy).await?;
+            let bitcoin_wallet = Arc::new(bitcoin_wallet);
+            let monero_wallet = Arc::new(monero_wallet);
+            let seller_peer_id = seller
+                .extract_peer_id()
+                .context("Seller address must contain peer ID")?;
+            db.insert_address(seller_peer_id, seller.clone()).await?;
+
+            let (event_loop, mut event_loop_handle) = cli::connect_to_seller(
+                swap_id,
+                seller_peer_id,
+                vec![seller],
+                env_config,
+                bitcoin_wallet.clone(),
+                seed.derive_libp2p_identity(),
+                tor_socks5_port,
+                tor_only,
+            )
+            .await?;
+            let event_loop = tokio::spawn(event_loop.run());
+
+            let lock_fee = monero_wallet.estimate_fee().await?;
+            let (xmr, price) = match determine_xmr_to_swap(
+                json,
+                event_loop_handle.request_ask_quote(),
+                monero_wallet.get_main_address(),
+                lock_fee,
+                || monero_wallet.get_unlocked_balance(),
+                || async { monero_wallet.refresh().await.map(|_| ()) },
+            )
+            .await
+            {
+                Ok(val) => val,
+                Err(error) => match error.downcast::<ZeroQuoteReceived>() {
+                    Ok(_) => {
+                        bail!("Seller is currently not buying XMR, please try again later")
+                    }
+                    Err(other) => bail!(other),
+                },
+            };
+
+            tracing::info!(%xmr, "Determined swap amount");
+
+            // The seller has to stick to its quote when setting up the swap
+            let min_btc = min_btc(price, xmr)?;
+
+            db.insert_peer_id(swap_id, seller_peer_id).await?;
+
+            let state3 = event_loop_handle
+                .setup_reverse_swap(NewReverseSwap {
+                    swap_id,
+                    xmr,
+                    min_btc,
+                    bitcoin_redeem_address: bitcoin_receive_address,
+                })
+                .await?;
+
+            // Store the state right away, so the swap can be resumed as soon as the buyer
+            // might lock the BTC
+            let state = AliceState::Started {
+                state3: Box::new(state3),
+            };
+            db.insert_latest_state(swap_id, state.clone().into())
+                .await?;
+
+            let swap = alice::Swap {
+                state,
+                event_loop_handle: event_loop_handle.alice_handle()?,
+                bitcoin_wallet,
+                monero_wallet,
+                env_config,
+                swap_id,
+                db,
+                events: SwapEvents::default(),
+            };
+
+            tokio::select! {
+                result = event_loop => {
+                    result
+                        .context("EventLoop panicked")?;
+                },
+                result = alice::run(swap, NoRate) => {
+                    result.context("Failed to complete swap")?;
+                }
+            }
+        }
         Command::History => {
             cli::tracing::init(debug, json, data_dir.join("logs"), None)?;
 
@@ -154,7 +300,6 @@ async fn main() -> Result<()> {
 
             if json {
                 for (swap_id, state) in swaps {
-                    let state: BobState = state.try_into()?;
                     tracing::info!(swap_id=%swap_id.to_string(), state=%state.to_string(), "Read swap state from database");
                 }
             } else {
@@ -163,7 +308,6 @@ async fn main() -> Result<()> {
                 table.set_header(vec!["SWAP ID", "STATE"]);
 
                 for (swap_id, state) in swaps {
-                    let state: BobState = state.try_into()?;
                     table.add_row(vec![swap_id.to_string(), state.to_string()]);
                 }
 
@@ -181,53 +325,61 @@ async fn main() -> Result<()> {
             tracing::info!(path=%format!("{}/wallet", data_dir.display()), "Internal bitcoin wallet directory");
         }
         Command::WithdrawBtc {
-            bitcoin_electrum_rpc_url,
+            bitcoin_backend,
             bitcoin_target_block,
-            amount,
-            address,
+            recipients,
+            drain_to,
+            coin_control,
+            dry_run,
+            tor_socks5_port,
+            embedded_tor,
+            tor_only,
         } => {
             cli::tracing::init(debug, json, data_dir.join("logs"), None)?;
+            let tor_proxy = init_tor(&data_dir, tor_socks5_port, embedded_tor, tor_only).await?;
             let seed = Seed::from_file_or_generate(data_dir.as_path())
                 .context("Failed to read in seed file")?;
             let bitcoin_wallet = init_bitcoin_wallet(
-                bitcoin_electrum_rpc_url,
+                bitcoin_backend,
                 &seed,
                 data_dir.clone(),
                 env_config,
                 bitcoin_target_block,
+                tor_proxy,
             )
             .await?;
 
-            let amount = match amount {
-                Some(amount) => amount,
-                None => {
-                    bitcoin_wallet
-                        .max_giveable(address.script_pubkey().len())
-                        .await?
-                }
-            };
-
             let psbt = bitcoin_wallet
-                .send_to_address(address, amount, None)
+                .send_to_many(recipients, drain_to, coin_control)
                 .await?;
-            let signed_tx = bitcoin_wallet.sign_and_finalize(psbt).await?;
 
-            bitcoin_wallet.broadcast(signed_tx, "withdraw").await?;
+            if dry_run {
+                println!("{}", base64::encode(::bitcoin::consensus::serialize(&psbt)));
+            } else {
+                let signed_tx = bitcoin_wallet.sign_and_finalize(psbt).await?;
+
+                bitcoin_wallet.broadcast(signed_tx, "withdraw").await?;
+            }
         }
 
         Command::Balance {
-            bitcoin_electrum_rpc_url,
+            bitcoin_backend,
             bitcoin_target_block,
+            tor_socks5_port,
+            embedded_tor,
+            tor_only,
         } => {
             cli::tracing::init(debug, json, data_dir.join("logs"), None)?;
+            let tor_proxy = init_tor(&data_dir, tor_socks5_port, embedded_tor, tor_only).await?;
             let seed = Seed::from_file_or_generate(data_dir.as_path())
                 .context("Failed to read in seed file")?;
             let bitcoin_wallet = init_bitcoin_wallet(
-                bitcoin_electrum_rpc_url,
+                bitcoin_backend,
                 &seed,
                 data_dir.clone(),
                 env_config,
                 bitcoin_target_block,
+                tor_proxy,
             )
             .await?;
 
@@ -239,55 +391,56 @@ async fn main() -> Result<()> {
         }
         Command::Resume {
             swap_id,
-            bitcoin_electrum_rpc_url,
+            bitcoin_backend,
             bitcoin_target_block,
             monero_daemon_address,
             tor_socks5_port,
+            embedded_tor,
+            tor_only,
         } => {
             cli::tracing::init(debug, json, data_dir.join("logs"), Some(swap_id))?;
+            let tor_proxy = init_tor(&data_dir, tor_socks5_port, embedded_tor, tor_only).await?;
             let db = open_db(data_dir.join("sqlite")).await?;
             let seed = Seed::from_file_or_generate(data_dir.as_path())
                 .context("Failed to read in seed file")?;
 
             let bitcoin_wallet = init_bitcoin_wallet(
-                bitcoin_electrum_rpc_url,
+                bitcoin_backend,
                 &seed,
                 data_dir.clone(),
                 env_config,
                 bitcoin_target_block,
+                tor_proxy,
             )
             .await?;
             let (monero_wallet, _process) =
-                init_monero_wallet(data_dir, monero_daemon_address, env_config).await?;
+                init_monero_wallet(data_dir, monero_daemon_address, env_config, tor_proxy).await?;
             let bitcoin_wallet = Arc::new(bitcoin_wallet);
 
             let seller_peer_id = db.get_peer_id(swap_id).await?;
             let seller_addresses = db.get_addresses(seller_peer_id).await?;
 
-            let behaviour = cli::Behaviour::new(seller_peer_id, env_config, bitcoin_wallet.clone());
-            let mut swarm =
-                swarm::cli(seed.derive_libp2p_identity(), tor_socks5_port, behaviour).await?;
-            let our_peer_id = swarm.local_peer_id();
-            tracing::debug!(peer_id = %our_peer_id, "Network layer initialized");
-
-            for seller_address in seller_addresses {
-                swarm
-                    .behaviour_mut()
-                    .add_address(seller_peer_id, seller_address);
-            }
-
-            let (event_loop, event_loop_handle) = EventLoop::new(swap_id, swarm, seller_peer_id)?;
+            let (event_loop, event_loop_handle) = cli::connect_to_seller(
+                swap_id,
+                seller_peer_id,
+                seller_addresses,
+                env_config,
+                bitcoin_wallet.clone(),
+                seed.derive_libp2p_identity(),
+                tor_socks5_port,
+                tor_only,
+            )
+            .await?;
             let handle = tokio::spawn(event_loop.run());
 
-            let monero_receive_address = db.get_monero_address(swap_id).await?;
-            let swap = Swap::from_db(
-                db,
+            let swap = UnfinishedSwap::load(
                 swap_id,
+                db,
                 bitcoin_wallet,
                 Arc::new(monero_wallet),
                 env_config,
                 event_loop_handle,
-                monero_receive_address,
+                SwapEvents::default(),
             )
             .await?;
 
@@ -295,94 +448,146 @@ async fn main() -> Result<()> {
                 event_loop_result = handle => {
                     event_loop_result?;
                 },
-                swap_result = bob::run(swap) => {
+                swap_result = swap.run() => {
                     swap_result?;
                 }
             }
         }
         Command::Cancel {
             swap_id,
-            bitcoin_electrum_rpc_url,
+            bitcoin_backend,
             bitcoin_target_block,
+            tor_socks5_port,
+            embedded_tor,
+            tor_only,
         } => {
             cli::tracing::init(debug, json, data_dir.join("logs"), Some(swap_id))?;
+            let tor_proxy = init_tor(&data_dir, tor_socks5_port, embedded_tor, tor_only).await?;
             let db = open_db(data_dir.join("sqlite")).await?;
             let seed = Seed::from_file_or_generate(data_dir.as_path())
                 .context("Failed to read in seed file")?;
 
             let bitcoin_wallet = init_bitcoin_wallet(
-                bitcoin_electrum_rpc_url,
+                bitcoin_backend,
                 &seed,
                 data_dir,
                 env_config,
                 bitcoin_target_block,
+                tor_proxy,
             )
             .await?;
 
-            let (txid, _) = cli::cancel(swap_id, Arc::new(bitcoin_wallet), db).await?;
+            let (txid, _) = recovery::cancel(swap_id, Arc::new(bitcoin_wallet), db).await?;
             tracing::debug!("Cancel transaction successfully published with id {}", txid);
         }
         Command::Refund {
             swap_id,
-            bitcoin_electrum_rpc_url,
+            bitcoin_backend,
             bitcoin_target_block,
+            monero_daemon_address,
+            tor_socks5_port,
+            embedded_tor,
+            tor_only,
         } => {
             cli::tracing::init(debug, json, data_dir.join("logs"), Some(swap_id))?;
+            let tor_proxy = init_tor(&data_dir, tor_socks5_port, embedded_tor, tor_only).await?;
             let db = open_db(data_dir.join("sqlite")).await?;
             let seed = Seed::from_file_or_generate(data_dir.as_path())
                 .context("Failed to read in seed file")?;
 
             let bitcoin_wallet = init_bitcoin_wallet(
-                bitcoin_electrum_rpc_url,
+                bitcoin_backend,
                 &seed,
-                data_dir,
+                data_dir.clone(),
                 env_config,
                 bitcoin_target_block,
+                tor_proxy,
             )
             .await?;
 
-            cli::refund(swap_id, Arc::new(bitcoin_wallet), db).await?;
+            // only swaps in which we sold XMR need a Monero wallet to refund them
+            let monero_wallet = match db.get_state(swap_id).await? {
+                State::Alice(_) => {
+                    let (monero_wallet, process) =
+                        init_monero_wallet(data_dir, monero_daemon_address, env_config, tor_proxy)
+                            .await?;
+
+                    Some((Arc::new(monero_wallet), process))
+                }
+                State::Bob(_) => None,
+            };
+
+            recovery::refund(
+                swap_id,
+                Arc::new(bitcoin_wallet),
+                monero_wallet
+                    .as_ref()
+                    .map(|(wallet, _process)| wallet.clone()),
+                db,
+            )
+            .await?;
         }
         Command::ListSellers {
-            rendezvous_point,
+            rendezvous_points,
             namespace,
             tor_socks5_port,
+            embedded_tor,
+            tor_only,
         } => {
-            let rendezvous_node_peer_id = rendezvous_point
-                .extract_peer_id()
-                .context("Rendezvous node address must contain peer ID")?;
-
             cli::tracing::init(debug, json, data_dir.join("logs"), None)?;
+            init_tor(&data_dir, tor_socks5_port, embedded_tor, tor_only).await?;
             let seed = Seed::from_file_or_generate(data_dir.as_path())
                 .context("Failed to read in seed file")?;
             let identity = seed.derive_libp2p_identity();
 
             let sellers = list_sellers(
-                rendezvous_node_peer_id,
-                rendezvous_point,
+                rendezvous_points,
                 namespace,
                 tor_socks5_port,
+                tor_only,
                 identity,
             )
             .await?;
 
+            // rank the sellers we just listed by everything we know about them
+            let db = open_db(data_dir.join("sqlite")).await?;
+            seller_directory::record(db.clone(), &sellers).await?;
+            let listed = sellers
+                .iter()
+                .filter_map(|seller| seller.multiaddr.extract_peer_id())
+                .collect::<HashSet<_>>();
+            let sellers = seller_directory::known_sellers(db)
+                .await?
+                .into_iter()
+                .filter(|seller| listed.contains(&seller.peer_id));
+
             if json {
                 for seller in sellers {
+                    let reachable = format!("{}/{}", seller.times_reachable, seller.times_listed);
+
                     match seller.status {
                         SellerStatus::Online(quote) => {
                             tracing::info!(
                                 price = %quote.price.to_string(),
                                 min_quantity = %quote.min_quantity.to_string(),
                                 max_quantity = %quote.max_quantity.to_string(),
-                                status = "Online",
+                                status = seller.status_label(),
                                 address = %seller.multiaddr.to_string(),
+                                %reachable,
+                                swaps_redeemed = seller.swaps_redeemed,
+                                swaps_refunded = seller.swaps_refunded,
+                                swaps_punished = seller.swaps_punished,
                                 "Fetched peer status"
                             );
                         }
                         SellerStatus::Unreachable => {
                             tracing::info!(
-                                status = "Unreachable",
+                                status = seller.status_label(),
                                 address = %seller.multiaddr.to_string(),
+                                %reachable,
+                                swaps_redeemed = seller.swaps_redeemed,
+                                swaps_refunded = seller.swaps_refunded,
+                                swaps_punished = seller.swaps_punished,
                                 "Fetched peer status"
                             );
                         }
@@ -396,18 +601,21 @@ async fn main() -> Result<()> {
                     "MIN_QUANTITY",
                     "MAX_QUANTITY",
                     "STATUS",
+                    "REACHABLE",
+                    "REDEEMED",
+                    "REFUNDED",
+                    "PUNISHED",
                     "ADDRESS",
                 ]);
 
                 for seller in sellers {
-                    let row = match seller.status {
+                    let mut row = match seller.status {
                         SellerStatus::Online(quote) => {
                             vec![
                                 quote.price.to_string(),
                                 quote.min_quantity.to_string(),
                                 quote.max_quantity.to_string(),
-                                "Online".to_owned(),
-                                seller.multiaddr.to_string(),
+                                seller.status_label().to_owned(),
                             ]
                         }
                         SellerStatus::Unreachable => {
@@ -415,11 +623,17 @@ async fn main() -> Result<()> {
                                 "???".to_owned(),
                                 "???".to_owned(),
                                 "???".to_owned(),
-                                "Unreachable".to_owned(),
-                                seller.multiaddr.to_string(),
+                                seller.status_label().to_owned(),
                             ]
                         }
                     };
+                    row.extend([
+                        format!("{}/{}", seller.times_reachable, seller.times_listed),
+                        seller.swaps_redeemed.to_string(),
+                        seller.swaps_refunded.to_string(),
+                        seller.swaps_punished.to_string(),
+                        seller.multiaddr.to_string(),
+                    ]);
 
                     table.add_row(row);
                 }
@@ -428,24 +642,79 @@ async fn main() -> Result<()> {
             }
         }
         Command::ExportBitcoinWallet {
-            bitcoin_electrum_rpc_url,
+            bitcoin_backend,
             bitcoin_target_block,
+            tor_socks5_port,
+            embedded_tor,
+            tor_only,
         } => {
             cli::tracing::init(debug, json, data_dir.join("logs"), None)?;
+            let tor_proxy = init_tor(&data_dir, tor_socks5_port, embedded_tor, tor_only).await?;
 
             let seed = Seed::from_file_or_generate(data_dir.as_path())
                 .context("Failed to read in seed file")?;
             let bitcoin_wallet = init_bitcoin_wallet(
-                bitcoin_electrum_rpc_url,
+                bitcoin_backend,
                 &seed,
                 data_dir.clone(),
                 env_config,
                 bitcoin_target_block,
+                tor_proxy,
             )
             .await?;
             let wallet_export = bitcoin_wallet.wallet_export("cli").await?;
             tracing::info!(descriptor=%wallet_export.to_string(), "Exported bitcoin wallet");
         }
+        Command::Rpc {
+            server_address,
+            bitcoin_backend,
+            bitcoin_target_block,
+            monero_daemon_address,
+            namespace,
+            tor_socks5_port,
+            embedded_tor,
+            tor_only,
+        } => {
+            cli::tracing::init(debug, json, data_dir.join("logs"), None)?;
+            let tor_proxy = init_tor(&data_dir, tor_socks5_port, embedded_tor, tor_only).await?;
+            let db = open_db(data_dir.join("sqlite")).await?;
+            let seed = Seed::from_file_or_generate(data_dir.as_path())
+                .context("Failed to read in seed file")?;
+
+            let bitcoin_wallet = init_bitcoin_wallet(
+                bitcoin_backend,
+                &seed,
+                data_dir.clone(),
+                env_config,
+                bitcoin_target_block,
+                tor_proxy,
+            )
+            .await?;
+            let (monero_wallet, _process) = init_monero_wallet(
+                data_dir.clone(),
+                monero_daemon_address,
+                env_config,
+                tor_proxy,
+            )
+            .await?;
+
+            let token = read_or_generate_token(&data_dir)?;
+            let context = rpc::Context::new(
+                db,
+                Arc::new(bitcoin_wallet),
+                Arc::new(monero_wallet),
+                env_config,
+                seed.derive_libp2p_identity(),
+                namespace,
+                tor_socks5_port,
+                tor_only,
+            );
+            let (address, server) = rpc::run_server(server_address, &token, context).await?;
+
+            tracing::info!(%address, "Started RPC server");
+
+            server.stopped().await;
+        }
         Command::MoneroRecovery { swap_id } => {
             let db = open_db(data_dir.join("sqlite")).await?;
 
@@ -487,21 +756,47 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Starts the embedded Tor if requested and returns the socks5 port all
+/// traffic has to go through, which is only the case with `--tor-only`.
+async fn init_tor(
+    data_dir: &Path,
+    tor_socks5_port: u16,
+    embedded_tor: bool,
+    tor_only: bool,
+) -> Result<Option<u16>> {
+    if embedded_tor {
+        tor::start_embedded(&data_dir.join("tor"), tor_socks5_port, None).await?;
+    }
+
+    if !tor_only {
+        return Ok(None);
+    }
+
+    tor::Client::new(tor_socks5_port)
+        .assert_tor_running()
+        .await
+        .context("Tor is not running, refusing to connect anywhere with --tor-only")?;
+
+    Ok(Some(tor_socks5_port))
+}
+
 async fn init_bitcoin_wallet(
-    electrum_rpc_url: Url,
+    bitcoin_backend: BackendConfig,
     seed: &Seed,
     data_dir: PathBuf,
     env_config: Config,
     bitcoin_target_block: usize,
+    tor_proxy: Option<u16>,
 ) -> Result<bitcoin::Wallet> {
     let wallet_dir = data_dir.join("wallet");
 
     let wallet = bitcoin::Wallet::new(
-        electrum_rpc_url.clone(),
+        bitcoin_backend,
         &wallet_dir,
         seed.derive_extended_private_key(env_config.bitcoin_network)?,
         env_config,
         bitcoin_target_block,
+        tor_proxy,
     )
     .await
     .context("Failed to initialize Bitcoin wallet")?;
@@ -515,27 +810,55 @@ async fn init_monero_wallet(
     data_dir: PathBuf,
     monero_daemon_address: String,
     env_config: Config,
+    tor_proxy: Option<u16>,
 ) -> Result<(monero::Wallet, monero::WalletRpcProcess)> {
     let network = env_config.monero_network;
 
     const MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME: &str = "swap-tool-blockchain-monitoring-wallet";
 
-    let monero_wallet_rpc = monero::WalletRpc::new(data_dir.join("monero")).await?;
+    let monero_wallet_rpc = monero::WalletRpc::new(data_dir.join("monero"), tor_proxy).await?;
 
     let monero_wallet_rpc_process = monero_wallet_rpc
-        .run(network, monero_daemon_address.as_str())
+        .run(network, monero_daemon_address.as_str(), tor_proxy)
         .await?;
 
     let monero_wallet = monero::Wallet::open_or_create(
         monero_wallet_rpc_process.endpoint(),
         MONERO_BLOCKCHAIN_MONITORING_WALLET_NAME.to_string(),
         env_config,
+        tor_proxy,
     )
     .await?;
 
+    let monerod = monerod_client(&monero_daemon_address, tor_proxy)?;
+    let monero_wallet = monero_wallet
+        .with_monerod(monerod.clone())
+        .with_fee_estimation(monerod);
+
     Ok((monero_wallet, monero_wallet_rpc_process))
 }
 
+/// A client for the Monero daemon at `<host>:<port>`, connecting through the
+/// socks5 port of Tor if one is given.
+fn monerod_client(
+    daemon_address: &str,
+    tor_socks5_port: Option<u16>,
+) -> Result<monero_rpc::monerod::Client> {
+    let (host, port) = daemon_address
+        .rsplit_once(':')
+        .with_context(|| format!("Monero daemon address {} has no port", daemon_address))?;
+    let port = port
+        .parse()
+        .with_context(|| format!("Invalid port in Monero daemon address {}", daemon_address))?;
+
+    let client = monero_rpc::monerod::Client::new(host.to_owned(), port)?;
+
+    match tor_socks5_port {
+        Some(socks5_port) => client.with_proxy(tor::reqwest_proxy(socks5_port)),
+        None => Ok(client),
+    }
+}
+
 fn qr_code(value: &impl ToString) -> Result<String> {
     let code = QrCode::new(value.to_string())?;
     let qr_code = code
@@ -629,6 +952,89 @@ where
     Ok((btc_swap_amount, fees))
 }
 
+async fn determine_xmr_to_swap<FB, TB, FS, TS>(
+    json: bool,
+    ask_quote: impl Future<Output = Result<AskQuote>>,
+    deposit_address: monero::Address,
+    lock_fee: monero::Amount,
+    balance: FB,
+    sync: FS,
+) -> Result<(monero::Amount, bitcoin::Amount)>
+where
+    TB: Future<Output = Result<monero::Amount>>,
+    FB: Fn() -> TB,
+    TS: Future<Output = Result<()>>,
+    FS: Fn() -> TS,
+{
+    tracing::debug!("Requesting ask quote");
+    let ask_quote = ask_quote.await?;
+
+    if ask_quote.max_quantity == monero::Amount::ZERO {
+        bail!(ZeroQuoteReceived)
+    }
+
+    tracing::info!(
+        price = %ask_quote.price,
+        minimum_amount = %ask_quote.min_quantity,
+        maximum_amount = %ask_quote.max_quantity,
+        "Received ask quote",
+    );
+
+    let giveable = |balance: monero::Amount| {
+        monero::Amount::from_piconero(balance.as_piconero().saturating_sub(lock_fee.as_piconero()))
+    };
+
+    let mut max_giveable = giveable(balance().await?);
+
+    if max_giveable == monero::Amount::ZERO || max_giveable < ask_quote.min_quantity {
+        let minimum_amount = ask_quote.min_quantity;
+        let maximum_amount = ask_quote.max_quantity;
+
+        if !json {
+            eprintln!("{}", qr_code(&deposit_address)?);
+        }
+
+        loop {
+            tracing::info!(
+                %deposit_address,
+                %max_giveable,
+                %minimum_amount,
+                %maximum_amount,
+                "Waiting for Monero deposit",
+            );
+
+            let new_balance = loop {
+                sync().await?;
+                let new_balance = balance().await?;
+
+                if giveable(new_balance) > max_giveable {
+                    break new_balance;
+                }
+
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            };
+
+            max_giveable = giveable(new_balance);
+            tracing::info!(%new_balance, %max_giveable, "Received Monero");
+
+            if max_giveable < ask_quote.min_quantity {
+                tracing::info!("Deposited amount is less than `min_quantity`");
+                continue;
+            }
+
+            break;
+        }
+    }
+
+    let xmr_swap_amount = if max_giveable > ask_quote.max_quantity {
+        ask_quote.max_quantity
+    } else {
+        max_giveable
+    };
+
+    Ok((xmr_swap_amount, ask_quote.price))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -976,6 +1382,13 @@ mod tests {
         }
     }
 
+    #[test]
+    fn monerod_client_needs_host_and_port() {
+        assert!(monerod_client("node.community.rino.io:18081", None).is_ok());
+        assert!(monerod_client("node.community.rino.io", None).is_err());
+        assert!(monerod_client("node.community.rino.io:port", None).is_err());
+    }
+
     fn quote_with_max(btc: f64) -> BidQuote {
         BidQuote {
             price: Amount::from_btc(0.001).unwrap(),
@@ -1,3 +1,5 @@
+pub mod backend;
+pub mod taproot;
 pub mod wallet;
 
 mod cancel;
@@ -7,6 +9,7 @@ mod redeem;
 mod refund;
 mod timelocks;
 
+pub use crate::bitcoin::backend::BackendConfig;
 pub use crate::bitcoin::cancel::{CancelTimelock, PunishTimelock, TxCancel};
 pub use crate::bitcoin::lock::TxLock;
 pub use crate::bitcoin::punish::TxPunish;
@@ -19,7 +22,7 @@ pub use ::bitcoin::{Address, Network, Transaction, Txid};
 pub use ecdsa_fun::adaptor::EncryptedSignature;
 pub use ecdsa_fun::fun::Scalar;
 pub use ecdsa_fun::Signature;
-pub use wallet::Wallet;
+pub use wallet::{parse_fee_rate, CoinControl, Recipient, Wallet};
 
 #[cfg(test)]
 pub use wallet::WalletBuilder;
@@ -271,34 +274,35 @@ impl From<RpcErrorCode> for i64 {
 }
 
 pub fn parse_rpc_error_code(error: &anyhow::Error) -> anyhow::Result<i64> {
-    let string = match error.downcast_ref::<bdk::Error>() {
-        Some(bdk::Error::Electrum(bdk::electrum_client::Error::Protocol(
-            serde_json::Value::String(string),
-        ))) => string,
-        _ => bail!("Error is of incorrect variant:{}", error),
-    };
-
-    let json = serde_json::from_str(&string.replace("sendrawtransaction RPC error:", ""))?;
-
-    let json_map = match json {
-        serde_json::Value::Object(map) => map,
-        _ => bail!("Json error is not json object "),
-    };
-
-    let error_code_value = match json_map.get("code") {
-        Some(val) => val,
-        None => bail!("No error code field"),
-    };
-
-    let error_code_number = match error_code_value {
-        serde_json::Value::Number(num) => num,
-        _ => bail!("Error code is not a number"),
-    };
-
-    if let Some(int) = error_code_number.as_i64() {
-        Ok(int)
-    } else {
-        bail!("Error code is not an unsigned integer")
+    match error.downcast_ref::<TransactionRejected>() {
+        Some(rejected) => Ok(rejected.code),
+        None => bail!("Error is of incorrect variant:{}", error),
+    }
+}
+
+/// The node refused to accept a transaction we broadcast.
+#[derive(Clone, Debug, thiserror::Error, PartialEq)]
+#[error("transaction was rejected with code {code}: {message}")]
+pub struct TransactionRejected {
+    /// One of the bitcoind error codes, see [`RpcErrorCode`].
+    pub code: i64,
+    pub message: String,
+}
+
+impl TransactionRejected {
+    /// Parse the bitcoind error that Electrum and Esplora servers relay, e.g.
+    /// `sendrawtransaction RPC error: {"code":-26,"message":"..."}`.
+    pub fn from_relayed_error(error: &str) -> Option<Self> {
+        #[derive(Deserialize)]
+        struct RpcError {
+            code: i64,
+            message: String,
+        }
+
+        let RpcError { code, message } =
+            serde_json::from_str(error.replace("sendrawtransaction RPC error:", "").trim()).ok()?;
+
+        Some(Self { code, message })
     }
 }
 
@@ -452,6 +456,49 @@ mod tests {
         assert_weight(refund_transaction, TxRefund::weight(), "TxRefund");
     }
 
+    #[test]
+    fn parses_relayed_rpc_error() {
+        let error = r#"sendrawtransaction RPC error: {"code":-27,"message":"Transaction already in block chain"}"#;
+
+        let rejected = TransactionRejected::from_relayed_error(error).unwrap();
+
+        assert_eq!(rejected, TransactionRejected {
+            code: i64::from(RpcErrorCode::RpcVerifyAlreadyInChain),
+            message: "Transaction already in block chain".to_owned()
+        });
+        assert_eq!(
+            parse_rpc_error_code(&anyhow::Error::new(rejected).context("Failed to broadcast"))
+                .unwrap(),
+            -27
+        );
+    }
+
+    #[test]
+    fn cancel_fee_bumps_stay_within_the_wallet_fee_bounds() {
+        let fee = Amount::from_sat(1_000);
+
+        let bumps = TxCancel::fee_bumps(fee, Amount::from_sat(1_000_000));
+        assert_eq!(bumps, vec![
+            Amount::from_sat(2_000),
+            Amount::from_sat(4_000),
+            Amount::from_sat(8_000)
+        ]);
+
+        // 3% of the locked amount
+        let bumps = TxCancel::fee_bumps(fee, Amount::from_sat(200_000));
+        assert_eq!(bumps, vec![
+            Amount::from_sat(2_000),
+            Amount::from_sat(4_000)
+        ]);
+
+        // never more than 100_000 sats, no matter the locked amount
+        let bumps = TxCancel::fee_bumps(Amount::from_sat(20_000), Amount::from_btc(10.0).unwrap());
+        assert_eq!(bumps, vec![
+            Amount::from_sat(40_000),
+            Amount::from_sat(80_000)
+        ]);
+    }
+
     // Weights fluctuate because of the length of the signatures. Valid ecdsa
     // signatures can have 68, 69, 70, 71, or 72 bytes. Since most of our
     // transactions have 2 signatures the weight can be up to 8 bytes less than
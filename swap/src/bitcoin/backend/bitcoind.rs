@@ -0,0 +1,156 @@
+use crate::bitcoin::backend::Backend;
+use crate::bitcoin::wallet::{EstimateFeeRate, ScriptStatus};
+use crate::bitcoin::{Transaction, TransactionRejected};
+use ::bitcoin::{Script, Txid};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use bdk::FeeRate;
+use bitcoincore_rpc::jsonrpc::error::{Error as JsonRpcError, RpcError};
+use bitcoincore_rpc::{Auth, Client, Error, RpcApi};
+use std::sync::Arc;
+
+/// Returned by bitcoind for transactions it does not know about.
+const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5;
+
+/// Talks to the JSON-RPC interface of a bitcoind node.
+///
+/// Looking up transactions that are not in the mempool requires the node to
+/// run with `-txindex`.
+///
+/// The RPC client is blocking, so every call runs on the blocking thread pool
+/// of tokio instead of stalling a worker thread.
+pub struct Bitcoind {
+    client: Arc<Client>,
+}
+
+impl Bitcoind {
+    pub fn new(url: &str, credentials: Option<(String, String)>) -> Result<Self> {
+        let auth = match credentials {
+            Some((username, password)) => Auth::UserPass(username, password),
+            None => Auth::None,
+        };
+
+        let client = Client::new(url, auth).context("Failed to initialize bitcoind RPC client")?;
+
+        Ok(Self {
+            client: Arc::new(client),
+        })
+    }
+
+    /// Run `call` against the RPC client on the blocking thread pool.
+    ///
+    /// The outer result fails if the call panicked, the inner one holds the
+    /// response of bitcoind.
+    async fn blocking<T, F>(&self, call: F) -> Result<Result<T, Error>>
+    where
+        F: FnOnce(&Client) -> Result<T, Error> + Send + 'static,
+        T: Send + 'static,
+    {
+        let client = self.client.clone();
+
+        tokio::task::spawn_blocking(move || call(&client))
+            .await
+            .context("bitcoind RPC call panicked")
+    }
+}
+
+#[async_trait]
+impl Backend for Bitcoind {
+    async fn sync(&mut self) -> Result<()> {
+        // Every status is fetched from the node, there is nothing to refresh
+        Ok(())
+    }
+
+    async fn status_of_script(&mut self, txid: Txid, _: &Script) -> Result<ScriptStatus> {
+        let info = self
+            .blocking(move |client| client.get_raw_transaction_info(&txid, None))
+            .await?;
+
+        match info {
+            Ok(info) => Ok(match info.confirmations {
+                None | Some(0) => ScriptStatus::InMempool,
+                Some(confirmations) => ScriptStatus::from_confirmations(confirmations),
+            }),
+            Err(error) if rpc_error(&error).map(|e| e.code) == Some(RPC_INVALID_ADDRESS_OR_KEY) => {
+                Ok(ScriptStatus::Unseen)
+            }
+            Err(error) => Err(error).context("Failed to get transaction from bitcoind"),
+        }
+    }
+
+    async fn broadcast(&self, transaction: &Transaction) -> Result<()> {
+        let transaction = transaction.clone();
+        let result = self
+            .blocking(move |client| client.send_raw_transaction(&transaction))
+            .await?;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(error) => match rpc_error(&error) {
+                Some(RpcError { code, message, .. }) => Err(TransactionRejected {
+                    code: i64::from(*code),
+                    message: message.clone(),
+                }
+                .into()),
+                None => Err(error.into()),
+            },
+        }
+    }
+
+    async fn get_raw_transaction(&self, txid: Txid) -> Result<Option<Transaction>> {
+        let transaction = self
+            .blocking(move |client| client.get_raw_transaction(&txid, None))
+            .await?;
+
+        match transaction {
+            Ok(transaction) => Ok(Some(transaction)),
+            Err(error) if rpc_error(&error).map(|e| e.code) == Some(RPC_INVALID_ADDRESS_OR_KEY) => {
+                Ok(None)
+            }
+            Err(error) => Err(error).context("Failed to get transaction from bitcoind"),
+        }
+    }
+}
+
+#[async_trait]
+impl EstimateFeeRate for Bitcoind {
+    async fn estimate_feerate(&self, target_block: usize) -> Result<FeeRate> {
+        let target_block = u16::try_from(target_block).context("Target block too far ahead")?;
+        let estimate = self
+            .blocking(move |client| client.estimate_smart_fee(target_block, None))
+            .await??;
+
+        let fee_rate = match estimate.fee_rate {
+            Some(fee_rate) => fee_rate,
+            None => {
+                // Happens until the node has seen enough blocks, e.g. on regtest
+                let relay_fee = self.min_relay_fee().await?;
+                tracing::warn!(
+                    errors = ?estimate.errors.unwrap_or_default(),
+                    "bitcoind has no fee estimate, defaulting to min relay fee {}",
+                    relay_fee
+                );
+
+                relay_fee
+            }
+        };
+
+        // we do not expect fees being that high.
+        #[allow(clippy::cast_possible_truncation)]
+        Ok(FeeRate::from_btc_per_kvb(fee_rate.as_btc() as f32))
+    }
+
+    async fn min_relay_fee(&self) -> Result<bitcoin::Amount> {
+        // Returned fee is in BTC/kb
+        let network_info = self.blocking(|client| client.get_network_info()).await??;
+
+        Ok(network_info.relay_fee)
+    }
+}
+
+fn rpc_error(error: &Error) -> Option<&RpcError> {
+    match error {
+        Error::JsonRpc(JsonRpcError::Rpc(error)) => Some(error),
+        _ => None,
+    }
+}
@@ -0,0 +1,179 @@
+use crate::bitcoin::backend::Backend;
+use crate::bitcoin::wallet::{Confirmed, EstimateFeeRate, ScriptStatus};
+use crate::bitcoin::{BlockHeight, Transaction, TransactionRejected};
+use ::bitcoin::{Script, Txid};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use bdk::electrum_client::{ElectrumApi, GetHistoryRes};
+use bdk::FeeRate;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+use url::Url;
+
+pub struct Electrum {
+    electrum: bdk::electrum_client::Client,
+    latest_block_height: BlockHeight,
+    last_sync: Instant,
+    sync_interval: Duration,
+    script_history: BTreeMap<Script, Vec<GetHistoryRes>>,
+}
+
+impl Electrum {
+    pub fn new(
+        url: &Url,
+        interval: Duration,
+        config: bdk::electrum_client::Config,
+    ) -> Result<Self> {
+        let electrum = bdk::electrum_client::Client::from_config(url.as_str(), config)
+            .context("Failed to initialize Electrum RPC client")?;
+
+        // Initially fetch the latest block for storing the height.
+        // We do not act on this subscription after this call.
+        let latest_block = electrum
+            .block_headers_subscribe()
+            .context("Failed to subscribe to header notifications")?;
+
+        Ok(Self {
+            electrum,
+            latest_block_height: BlockHeight::try_from(latest_block)?,
+            last_sync: Instant::now(),
+            sync_interval: interval,
+            script_history: Default::default(),
+        })
+    }
+
+    fn update_latest_block(&mut self) -> Result<()> {
+        // Fetch the latest block for storing the height.
+        // We do not act on this subscription after this call, as we cannot rely on
+        // subscription push notifications because eventually the Electrum server will
+        // close the connection and subscriptions are not automatically renewed
+        // upon renewing the connection.
+        let latest_block = self
+            .electrum
+            .block_headers_subscribe()
+            .context("Failed to subscribe to header notifications")?;
+        let latest_block_height = BlockHeight::try_from(latest_block)?;
+
+        if latest_block_height > self.latest_block_height {
+            tracing::debug!(
+                block_height = u32::from(latest_block_height),
+                "Got notification for new block"
+            );
+            self.latest_block_height = latest_block_height;
+        }
+
+        Ok(())
+    }
+
+    fn update_script_histories(&mut self) -> Result<()> {
+        let histories = self
+            .electrum
+            .batch_script_get_history(self.script_history.keys())
+            .context("Failed to get script histories")?;
+
+        if histories.len() != self.script_history.len() {
+            bail!(
+                "Expected {} history entries, received {}",
+                self.script_history.len(),
+                histories.len()
+            );
+        }
+
+        let scripts = self.script_history.keys().cloned();
+        let histories = histories.into_iter();
+
+        self.script_history = scripts.zip(histories).collect::<BTreeMap<_, _>>();
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Backend for Electrum {
+    async fn sync(&mut self) -> Result<()> {
+        let now = Instant::now();
+        if now < self.last_sync + self.sync_interval {
+            return Ok(());
+        }
+
+        self.last_sync = now;
+        self.update_latest_block()?;
+        self.update_script_histories()?;
+
+        Ok(())
+    }
+
+    async fn status_of_script(&mut self, txid: Txid, script: &Script) -> Result<ScriptStatus> {
+        if !self.script_history.contains_key(script) {
+            self.script_history.insert(script.clone(), vec![]);
+        }
+
+        self.sync().await?;
+
+        let history = self.script_history.entry(script.clone()).or_default();
+
+        let history_of_tx = history
+            .iter()
+            .filter(|entry| entry.tx_hash == txid)
+            .collect::<Vec<_>>();
+
+        match history_of_tx.as_slice() {
+            [] => Ok(ScriptStatus::Unseen),
+            [remaining @ .., last] => {
+                if !remaining.is_empty() {
+                    tracing::warn!("Found more than a single history entry for script. This is highly unexpected and those history entries will be ignored")
+                }
+
+                if last.height <= 0 {
+                    Ok(ScriptStatus::InMempool)
+                } else {
+                    Ok(ScriptStatus::Confirmed(
+                        Confirmed::from_inclusion_and_latest_block(
+                            u32::try_from(last.height)?,
+                            u32::from(self.latest_block_height),
+                        ),
+                    ))
+                }
+            }
+        }
+    }
+
+    async fn broadcast(&self, transaction: &Transaction) -> Result<()> {
+        match self.electrum.transaction_broadcast(transaction) {
+            Ok(_) => Ok(()),
+            Err(bdk::electrum_client::Error::Protocol(serde_json::Value::String(error))) => {
+                match TransactionRejected::from_relayed_error(&error) {
+                    Some(rejected) => Err(rejected.into()),
+                    None => bail!("Electrum server failed to broadcast transaction: {}", error),
+                }
+            }
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    async fn get_raw_transaction(&self, txid: Txid) -> Result<Option<Transaction>> {
+        let transaction = self.electrum.transaction_get(&txid)?;
+
+        Ok(Some(transaction))
+    }
+}
+
+#[async_trait]
+impl EstimateFeeRate for Electrum {
+    async fn estimate_feerate(&self, target_block: usize) -> Result<FeeRate> {
+        // https://github.com/romanz/electrs/blob/f9cf5386d1b5de6769ee271df5eef324aa9491bc/src/rpc.rs#L213
+        // Returned estimated fees are per BTC/kb.
+        let fee_per_byte = self.electrum.estimate_fee(target_block)?;
+        // we do not expect fees being that high.
+        #[allow(clippy::cast_possible_truncation)]
+        Ok(FeeRate::from_btc_per_kvb(fee_per_byte as f32))
+    }
+
+    async fn min_relay_fee(&self) -> Result<bitcoin::Amount> {
+        // https://github.com/romanz/electrs/blob/f9cf5386d1b5de6769ee271df5eef324aa9491bc/src/rpc.rs#L219
+        // Returned fee is in BTC/kb
+        let relay_fee = bitcoin::Amount::from_btc(self.electrum.relay_fee()?)?;
+        Ok(relay_fee)
+    }
+}
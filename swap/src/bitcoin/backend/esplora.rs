@@ -0,0 +1,275 @@
+use crate::bitcoin::backend::Backend;
+use crate::bitcoin::wallet::{Confirmed, EstimateFeeRate, ScriptStatus};
+use crate::bitcoin::{BlockHeight, Transaction, TransactionRejected};
+use crate::tor;
+use ::bitcoin::consensus::encode::{deserialize, serialize_hex};
+use ::bitcoin::{Script, Txid};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use bdk::FeeRate;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use url::Url;
+
+/// Esplora does not tell us the relay fee of its node, assume the default of
+/// bitcoind.
+const DEFAULT_MIN_RELAY_FEE_SAT_PER_KB: u64 = 1_000;
+
+/// Talks to the REST API of an Esplora server, e.g. the HTTP interface of
+/// Blockstream's electrs.
+pub struct Esplora {
+    client: reqwest::Client,
+    url: Url,
+    latest_block_height: BlockHeight,
+    last_sync: Instant,
+    sync_interval: Duration,
+}
+
+impl Esplora {
+    pub async fn new(url: &Url, interval: Duration, tor_socks5_port: Option<u16>) -> Result<Self> {
+        let mut url = url.clone();
+        // Make sure joining keeps the last segment of the path, e.g. `/api`
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+
+        let client = match tor_socks5_port {
+            Some(port) => reqwest::Client::builder()
+                .proxy(tor::reqwest_proxy(port))
+                .build()?,
+            None => reqwest::Client::new(),
+        };
+
+        let mut esplora = Self {
+            client,
+            url,
+            latest_block_height: BlockHeight::from(0),
+            last_sync: Instant::now(),
+            sync_interval: interval,
+        };
+        esplora.update_latest_block().await?;
+
+        Ok(esplora)
+    }
+
+    fn endpoint(&self, path: &str) -> Result<Url> {
+        self.url
+            .join(path)
+            .with_context(|| format!("Failed to build Esplora URL for {}", path))
+    }
+
+    async fn update_latest_block(&mut self) -> Result<()> {
+        let latest_block_height = self
+            .client
+            .get(self.endpoint("blocks/tip/height")?)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?
+            .trim()
+            .parse::<u32>()
+            .context("Failed to parse latest block height")?;
+        let latest_block_height = BlockHeight::from(latest_block_height);
+
+        if latest_block_height > self.latest_block_height {
+            tracing::debug!(
+                block_height = u32::from(latest_block_height),
+                "Got notification for new block"
+            );
+            self.latest_block_height = latest_block_height;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Backend for Esplora {
+    async fn sync(&mut self) -> Result<()> {
+        let now = Instant::now();
+        if now < self.last_sync + self.sync_interval {
+            return Ok(());
+        }
+
+        self.last_sync = now;
+        self.update_latest_block().await?;
+
+        Ok(())
+    }
+
+    async fn status_of_script(&mut self, txid: Txid, _: &Script) -> Result<ScriptStatus> {
+        self.sync().await?;
+
+        let response = self
+            .client
+            .get(self.endpoint(&format!("tx/{}", txid))?)
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(ScriptStatus::Unseen);
+        }
+
+        let body = response.error_for_status()?.bytes().await?;
+        let EsploraTransaction { status } =
+            serde_json::from_slice(&body).context("Failed to deserialize transaction status")?;
+
+        match status {
+            TransactionStatus {
+                confirmed: true,
+                block_height: Some(block_height),
+            } => Ok(ScriptStatus::Confirmed(
+                Confirmed::from_inclusion_and_latest_block(
+                    block_height,
+                    u32::from(self.latest_block_height),
+                ),
+            )),
+            _ => Ok(ScriptStatus::InMempool),
+        }
+    }
+
+    async fn broadcast(&self, transaction: &Transaction) -> Result<()> {
+        let response = self
+            .client
+            .post(self.endpoint("tx")?)
+            .body(serialize_hex(transaction))
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            return Ok(());
+        }
+
+        let error = response.text().await?;
+        match TransactionRejected::from_relayed_error(&error) {
+            Some(rejected) => Err(rejected.into()),
+            None => bail!("Esplora server failed to broadcast transaction: {}", error),
+        }
+    }
+
+    async fn get_raw_transaction(&self, txid: Txid) -> Result<Option<Transaction>> {
+        let response = self
+            .client
+            .get(self.endpoint(&format!("tx/{}/raw", txid))?)
+            .send()
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let bytes = response.error_for_status()?.bytes().await?;
+        let transaction = deserialize(&bytes).context("Failed to deserialize transaction")?;
+
+        Ok(Some(transaction))
+    }
+}
+
+#[async_trait]
+impl EstimateFeeRate for Esplora {
+    async fn estimate_feerate(&self, target_block: usize) -> Result<FeeRate> {
+        // Returned estimates are in sat/vB, keyed by the confirmation target
+        let body = self
+            .client
+            .get(self.endpoint("fee-estimates")?)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let estimates = serde_json::from_slice::<HashMap<String, f64>>(&body)
+            .context("Failed to deserialize fee estimates")?
+            .into_iter()
+            .filter_map(|(target, fee_rate)| Some((target.parse().ok()?, fee_rate)))
+            .collect();
+
+        match fee_rate_for_target(&estimates, target_block) {
+            Some(fee_rate) => Ok(fee_rate),
+            None => {
+                // Happens until the node has seen enough blocks, e.g. on regtest
+                let relay_fee = self.min_relay_fee().await?;
+                tracing::warn!(
+                    "Esplora has no fee estimate, defaulting to min relay fee {}",
+                    relay_fee
+                );
+
+                // we do not expect fees being that high.
+                #[allow(clippy::cast_possible_truncation)]
+                Ok(FeeRate::from_btc_per_kvb(relay_fee.as_btc() as f32))
+            }
+        }
+    }
+
+    async fn min_relay_fee(&self) -> Result<bitcoin::Amount> {
+        Ok(bitcoin::Amount::from_sat(DEFAULT_MIN_RELAY_FEE_SAT_PER_KB))
+    }
+}
+
+/// The estimate for the highest target that still confirms within
+/// `target_block`, or the one for the closest target if all of them are
+/// further out.
+fn fee_rate_for_target(estimates: &HashMap<usize, f64>, target_block: usize) -> Option<FeeRate> {
+    let within_target = estimates
+        .iter()
+        .filter(|(target, _)| **target <= target_block)
+        .max_by_key(|(target, _)| **target);
+    let closest = || estimates.iter().min_by_key(|(target, _)| **target);
+
+    // we do not expect fees being that high.
+    #[allow(clippy::cast_possible_truncation)]
+    within_target
+        .or_else(closest)
+        .map(|(_, fee_rate)| FeeRate::from_sat_per_vb(*fee_rate as f32))
+}
+
+#[derive(Deserialize)]
+struct EsploraTransaction {
+    status: TransactionStatus,
+}
+
+#[derive(Deserialize)]
+struct TransactionStatus {
+    confirmed: bool,
+    block_height: Option<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_estimate_closest_to_target() {
+        let estimates = vec![(1, 20.0), (3, 10.0), (6, 5.0)]
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+
+        assert_eq!(
+            fee_rate_for_target(&estimates, 4),
+            Some(FeeRate::from_sat_per_vb(10.0))
+        );
+        assert_eq!(
+            fee_rate_for_target(&estimates, 1),
+            Some(FeeRate::from_sat_per_vb(20.0))
+        );
+        assert_eq!(
+            fee_rate_for_target(&estimates, 100),
+            Some(FeeRate::from_sat_per_vb(5.0))
+        );
+    }
+
+    #[test]
+    fn falls_back_to_closest_target_if_all_are_further_out() {
+        let estimates = vec![(3, 10.0), (6, 5.0)]
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+
+        assert_eq!(
+            fee_rate_for_target(&estimates, 1),
+            Some(FeeRate::from_sat_per_vb(10.0))
+        );
+        assert_eq!(fee_rate_for_target(&HashMap::new(), 1), None);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::bitcoin;
-use crate::bitcoin::wallet::Watchable;
+use crate::bitcoin::wallet::{max_tx_fee, Watchable};
 use crate::bitcoin::{
     build_shared_output_descriptor, Address, Amount, BlockHeight, PublicKey, Transaction, TxLock,
 };
@@ -36,6 +36,12 @@ impl Add<CancelTimelock> for BlockHeight {
     }
 }
 
+impl From<CancelTimelock> for u32 {
+    fn from(timelock: CancelTimelock) -> Self {
+        timelock.0
+    }
+}
+
 impl PartialOrd<CancelTimelock> for u32 {
     fn partial_cmp(&self, other: &CancelTimelock) -> Option<Ordering> {
         self.partial_cmp(&other.0)
@@ -76,6 +82,12 @@ impl Add<PunishTimelock> for BlockHeight {
     }
 }
 
+impl From<PunishTimelock> for u32 {
+    fn from(timelock: PunishTimelock) -> Self {
+        timelock.0
+    }
+}
+
 impl PartialOrd<PunishTimelock> for u32 {
     fn partial_cmp(&self, other: &PunishTimelock) -> Option<Ordering> {
         self.partial_cmp(&other.0)
@@ -88,6 +100,10 @@ impl PartialEq<PunishTimelock> for u32 {
     }
 }
 
+/// The multiples of the negotiated fee the cancel transaction is signed at in
+/// addition, see [`TxCancel::fee_bumps`].
+const FEE_BUMP_FACTORS: [u64; 3] = [2, 4, 8];
+
 #[derive(Debug)]
 pub struct TxCancel {
     inner: Transaction,
@@ -140,6 +156,23 @@ impl TxCancel {
         }
     }
 
+    /// The fees the cancel transaction is signed at in addition to
+    /// `spending_fee`, to replace it with if it gets stuck.
+    ///
+    /// Neither party can spend the output of the cancel transaction on their
+    /// own, so its fee can't be bumped through CPFP. Fees above the bounds the
+    /// wallet applies to its own transactions for the locked amount are left
+    /// out.
+    pub fn fee_bumps(spending_fee: Amount, lock_amount: Amount) -> Vec<Amount> {
+        let max_fee = max_tx_fee(lock_amount);
+
+        FEE_BUMP_FACTORS
+            .iter()
+            .map(|factor| spending_fee * *factor)
+            .take_while(|fee| *fee <= max_fee)
+            .collect()
+    }
+
     pub fn txid(&self) -> Txid {
         self.inner.txid()
     }
@@ -0,0 +1,281 @@
+//! Taproot outputs that can only be spent through the key path, and the MuSig2
+//! aggregation of the keys of both parties into their internal key.
+//!
+//! These are the building blocks for a lock output that pays to an aggregate
+//! of `A` and `B` instead of the 2-of-2 multisig script of
+//! [`build_shared_output_descriptor`](crate::bitcoin::build_shared_output_descriptor).
+//! The swap protocol does not use them yet.
+
+use ::bitcoin::blockdata::opcodes;
+use ::bitcoin::blockdata::script::{Builder, Script};
+use ::bitcoin::consensus::serialize;
+use ::bitcoin::hashes::{sha256, Hash, HashEngine};
+use ::bitcoin::secp256k1::{self, schnorrsig, Message, Secp256k1};
+use ::bitcoin::{Transaction, TxOut};
+use anyhow::{bail, Context, Result};
+
+/// The hash type of signatures committing to the whole transaction,
+/// `SIGHASH_DEFAULT` of BIP341.
+const SIGHASH_DEFAULT: u8 = 0x00;
+
+/// The key aggregation coefficient of the second distinct key, one as a
+/// big-endian scalar.
+const ONE: [u8; 32] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+];
+
+/// The tagged hash of BIP340 over the concatenation of `data`.
+pub fn tagged_hash(tag: &str, data: &[&[u8]]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(tag.as_bytes());
+
+    let mut engine = sha256::HashEngine::default();
+    engine.input(&tag[..]);
+    engine.input(&tag[..]);
+    for data in data {
+        engine.input(data);
+    }
+
+    sha256::Hash::from_engine(engine).into_inner()
+}
+
+/// The MuSig2 aggregate of `keys` as defined by `KeyAgg` of BIP327.
+///
+/// The aggregate depends on the order of the keys, all parties have to agree
+/// on it.
+pub fn aggregate_keys(keys: &[secp256k1::PublicKey]) -> Result<secp256k1::PublicKey> {
+    let secp = Secp256k1::verification_only();
+
+    let summands = keys
+        .iter()
+        .map(|key| {
+            let mut summand = *key;
+            summand
+                .mul_assign(&secp, &key_agg_coefficient(keys, key))
+                .context("Failed to apply the key aggregation coefficient")?;
+
+            Ok(summand)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let summands = summands.iter().collect::<Vec<_>>();
+
+    secp256k1::PublicKey::combine_keys(&summands).context("Failed to aggregate keys")
+}
+
+/// The factor by which `key` enters the aggregate of `keys`, a party signs
+/// with its secret key multiplied by it.
+///
+/// The first key that differs from the first key of the list gets a
+/// coefficient of one, all others one derived from the hash of the list.
+pub fn key_agg_coefficient(keys: &[secp256k1::PublicKey], key: &secp256k1::PublicKey) -> [u8; 32] {
+    let second_key = keys.iter().find(|other| *other != &keys[0]);
+    if second_key == Some(key) {
+        return ONE;
+    }
+
+    let serialized = keys.iter().map(|key| key.serialize()).collect::<Vec<_>>();
+    let list = tagged_hash(
+        "KeyAgg list",
+        &serialized.iter().map(|key| &key[..]).collect::<Vec<_>>(),
+    );
+
+    tagged_hash("KeyAgg coefficient", &[&list, &key.serialize()])
+}
+
+/// The tweak that commits the output key to `internal_key` and no script
+/// tree, so the output can only be spent through the key path.
+pub fn tap_tweak(internal_key: &schnorrsig::PublicKey) -> [u8; 32] {
+    tagged_hash("TapTweak", &[&internal_key.serialize()])
+}
+
+/// The output key of a taproot output with the given internal key and no
+/// script tree.
+pub fn output_key(internal_key: &secp256k1::PublicKey) -> Result<schnorrsig::PublicKey> {
+    let secp = Secp256k1::verification_only();
+
+    let mut output_key = x_only(internal_key)?;
+    output_key
+        .tweak_add_assign(&secp, &tap_tweak(&output_key))
+        .context("Failed to tweak the internal key")?;
+
+    Ok(output_key)
+}
+
+/// The x-only form of `key` used by BIP340, which drops the parity of its y
+/// coordinate.
+pub fn x_only(key: &secp256k1::PublicKey) -> Result<schnorrsig::PublicKey> {
+    schnorrsig::PublicKey::from_slice(&key.serialize()[1..])
+        .context("Failed to convert key to x-only key")
+}
+
+/// The script of a segwit v1 output paying to `output_key`.
+pub fn script_pubkey(output_key: &schnorrsig::PublicKey) -> Script {
+    Builder::new()
+        .push_opcode(opcodes::all::OP_PUSHNUM_1)
+        .push_slice(&output_key.serialize())
+        .into_script()
+}
+
+/// The message signed to spend input `input_index` of `tx` through the key
+/// path with `SIGHASH_DEFAULT`, as defined by BIP341.
+///
+/// `prevouts` are the outputs spent by all inputs of `tx`, in the order of the
+/// inputs.
+pub fn key_path_sighash(
+    tx: &Transaction,
+    input_index: usize,
+    prevouts: &[TxOut],
+) -> Result<Message> {
+    if input_index >= tx.input.len() {
+        bail!(
+            "Input {} does not exist, the transaction has {} inputs",
+            input_index,
+            tx.input.len()
+        )
+    }
+    if prevouts.len() != tx.input.len() {
+        bail!(
+            "Got {} spent outputs for {} inputs",
+            prevouts.len(),
+            tx.input.len()
+        )
+    }
+
+    let mut outpoints = sha256::HashEngine::default();
+    let mut sequences = sha256::HashEngine::default();
+    for input in &tx.input {
+        outpoints.input(&serialize(&input.previous_output));
+        sequences.input(&input.sequence.to_le_bytes());
+    }
+
+    let mut amounts = sha256::HashEngine::default();
+    let mut script_pubkeys = sha256::HashEngine::default();
+    for prevout in prevouts {
+        amounts.input(&prevout.value.to_le_bytes());
+        script_pubkeys.input(&serialize(&prevout.script_pubkey));
+    }
+
+    let mut outputs = sha256::HashEngine::default();
+    for output in &tx.output {
+        outputs.input(&serialize(output));
+    }
+
+    let sighash = tagged_hash("TapSighash", &[
+        // epoch
+        &[0],
+        &[SIGHASH_DEFAULT],
+        &tx.version.to_le_bytes(),
+        &tx.lock_time.to_le_bytes(),
+        &sha256::Hash::from_engine(outpoints)[..],
+        &sha256::Hash::from_engine(amounts)[..],
+        &sha256::Hash::from_engine(script_pubkeys)[..],
+        &sha256::Hash::from_engine(sequences)[..],
+        &sha256::Hash::from_engine(outputs)[..],
+        // spend type: key path without annex
+        &[0],
+        &(input_index as u32).to_le_bytes(),
+    ]);
+
+    Message::from_slice(&sighash).context("Failed to create message from sighash")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::bitcoin::consensus::deserialize;
+    use ::bitcoin::hashes::hex::FromHex;
+
+    fn key_pair(byte: u8) -> (secp256k1::SecretKey, secp256k1::PublicKey) {
+        let secp = Secp256k1::signing_only();
+        let secret_key = secp256k1::SecretKey::from_slice(&[byte; 32]).unwrap();
+
+        (
+            secret_key,
+            secp256k1::PublicKey::from_secret_key(&secp, &secret_key),
+        )
+    }
+
+    #[test]
+    fn key_path_sighash_matches_bitcoin_core() {
+        // taken from the taproot tests of Bitcoin Core's functional test framework
+        let tx: Transaction = deserialize(&Vec::from_hex("020000000164eb050a5e3da0c2a65e4786f26d753b7bc69691fabccafb11f7acef36641f1846010000003101b2b404392a22000000000017a9147f2bde86fe78bf68a0544a4f290e12f0b7e0a08c87580200000000000017a91425d11723074ecfb96a0a83c3956bfaf362ae0c908758020000000000001600147e20f938993641de67bb0cdd71682aa34c4d29ad5802000000000000160014c64984dc8761acfa99418bd6bedc79b9287d652d72000000").unwrap()).unwrap();
+        let prevouts: Vec<TxOut> = deserialize(&Vec::from_hex("01365724000000000023542156b39dab4f8f3508e0432cfb41fab110170acaa2d4c42539cb90a4dc7c093bc500").unwrap()).unwrap();
+
+        let sighash = key_path_sighash(&tx, 0, &prevouts).unwrap();
+
+        assert_eq!(
+            sighash,
+            Message::from_slice(
+                &Vec::from_hex("33ca0ebfb4a945eeee9569fc0f5040221275f88690b7f8592ada88ce3bdf6703")
+                    .unwrap()
+            )
+            .unwrap()
+        );
+        assert!(key_path_sighash(&tx, 1, &prevouts).is_err());
+        assert!(key_path_sighash(&tx, 0, &[]).is_err());
+    }
+
+    #[test]
+    fn aggregate_of_secret_keys_signs_for_the_output_key() {
+        let secp = Secp256k1::new();
+        let (a, A) = key_pair(1);
+        let (b, B) = key_pair(2);
+        let keys = [A, B];
+
+        let aggregate_key = aggregate_keys(&keys).unwrap();
+
+        // what the MuSig2 partial signatures of both parties will add up to
+        let mut a_share = a;
+        a_share.mul_assign(&key_agg_coefficient(&keys, &A)).unwrap();
+        let mut aggregate_secret_key = b;
+        aggregate_secret_key
+            .mul_assign(&key_agg_coefficient(&keys, &B))
+            .unwrap();
+        aggregate_secret_key.add_assign(&a_share[..]).unwrap();
+        assert_eq!(
+            secp256k1::PublicKey::from_secret_key(&secp, &aggregate_secret_key),
+            aggregate_key
+        );
+
+        let mut key_pair = schnorrsig::KeyPair::from_secret_key(&secp, aggregate_secret_key);
+        key_pair
+            .tweak_add_assign(&secp, &tap_tweak(&x_only(&aggregate_key).unwrap()))
+            .unwrap();
+        let message = Message::from_slice(&[42; 32]).unwrap();
+        let signature = secp.schnorrsig_sign_no_aux_rand(&message, &key_pair);
+
+        let output_key = output_key(&aggregate_key).unwrap();
+        secp.schnorrsig_verify(&signature, &message, &output_key)
+            .unwrap();
+        assert_eq!(
+            schnorrsig::PublicKey::from_keypair(&secp, &key_pair),
+            output_key
+        );
+    }
+
+    #[test]
+    fn aggregate_depends_on_the_order_of_the_keys() {
+        let (_, A) = key_pair(1);
+        let (_, B) = key_pair(2);
+
+        assert_eq!(key_agg_coefficient(&[A, B], &B), ONE);
+        assert_ne!(key_agg_coefficient(&[A, B], &A), ONE);
+        assert_ne!(
+            aggregate_keys(&[A, B]).unwrap(),
+            aggregate_keys(&[B, A]).unwrap()
+        );
+    }
+
+    #[test]
+    fn script_pubkey_is_a_segwit_v1_program_of_the_output_key() {
+        let (_, A) = key_pair(1);
+        let output_key = output_key(&A).unwrap();
+
+        let script = script_pubkey(&output_key);
+
+        let script = script.as_bytes();
+        assert_eq!(script.len(), 34);
+        assert_eq!(script[0], opcodes::all::OP_PUSHNUM_1.into_u8());
+        assert_eq!(script[1], 32);
+        assert_eq!(&script[2..], &output_key.serialize()[..]);
+    }
+}
@@ -1,29 +1,31 @@
-use crate::bitcoin::timelocks::BlockHeight;
+use crate::bitcoin::backend::{Backend, BackendConfig};
 use crate::bitcoin::{Address, Amount, Transaction};
 use crate::env;
 use ::bitcoin::util::psbt::PartiallySignedTransaction;
-use ::bitcoin::Txid;
+use ::bitcoin::{OutPoint, Txid};
 use anyhow::{bail, Context, Result};
-use bdk::blockchain::{noop_progress, Blockchain, ElectrumBlockchain};
+use async_trait::async_trait;
+use bdk::blockchain::{noop_progress, AnyBlockchain, Blockchain};
 use bdk::database::BatchDatabase;
 use bdk::descriptor::Segwitv0;
-use bdk::electrum_client::{ElectrumApi, GetHistoryRes};
 use bdk::keys::DerivableKey;
 use bdk::wallet::export::WalletExport;
-use bdk::wallet::AddressIndex;
+use bdk::wallet::{wallet_name_from_descriptor, AddressIndex};
 use bdk::{FeeRate, KeychainKind, SignOptions};
+use bitcoin::secp256k1::Secp256k1;
 use bitcoin::{Network, Script};
-use reqwest::Url;
 use rust_decimal::prelude::*;
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt;
 use std::path::Path;
+use std::str::FromStr;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use tokio::sync::{watch, Mutex};
+use tokio::time::timeout;
 
 const SLED_TREE_NAME: &str = "default_tree";
 
@@ -33,27 +35,44 @@ const MAX_RELATIVE_TX_FEE: Decimal = dec!(0.03);
 const MAX_ABSOLUTE_TX_FEE: Decimal = dec!(100_000);
 const DUST_AMOUNT: u64 = 546;
 
-pub struct Wallet<B = ElectrumBlockchain, D = bdk::sled::Tree, C = Client> {
+/// How many blocks a transaction may go unconfirmed before we consider it
+/// stuck and bump its fee.
+const BLOCKS_UNTIL_STUCK: u32 = 3;
+
+/// The virtual size of a transaction spending a single P2WPKH output to
+/// another one.
+const CPFP_CHILD_VSIZE: u64 = 110;
+
+pub struct Wallet<B = AnyBlockchain, D = bdk::sled::Tree, C = Client> {
     client: Arc<Mutex<C>>,
     wallet: Arc<Mutex<bdk::Wallet<B, D>>>,
     finality_confirmations: u32,
     network: Network,
     target_block: usize,
+    stuck_timeout: Duration,
 }
 
 impl Wallet {
+    /// Open the wallet in `wallet_dir` and connect it to `backend`, through
+    /// the socks5 port of Tor if one is given.
     pub async fn new(
-        electrum_rpc_url: Url,
+        backend: BackendConfig,
         wallet_dir: &Path,
         key: impl DerivableKey<Segwitv0> + Clone,
         env_config: env::Config,
         target_block: usize,
+        tor_socks5_port: Option<u16>,
     ) -> Result<Self> {
-        let config = bdk::electrum_client::ConfigBuilder::default()
-            .retry(5)
-            .build();
-        let client = bdk::electrum_client::Client::from_config(electrum_rpc_url.as_str(), config)
-            .context("Failed to initialize Electrum RPC client")?;
+        tracing::debug!(%backend, "Connecting Bitcoin wallet");
+
+        let wallet_name = wallet_name_from_descriptor(
+            bdk::template::Bip84(key.clone(), KeychainKind::External),
+            Some(bdk::template::Bip84(key.clone(), KeychainKind::Internal)),
+            env_config.bitcoin_network,
+            &Secp256k1::new(),
+        )?;
+        let blockchain =
+            backend.blockchain(wallet_name, env_config.bitcoin_network, tor_socks5_port)?;
 
         let db = bdk::sled::open(wallet_dir)?.open_tree(SLED_TREE_NAME)?;
 
@@ -62,23 +81,22 @@ impl Wallet {
             Some(bdk::template::Bip84(key, KeychainKind::Internal)),
             env_config.bitcoin_network,
             db,
-            ElectrumBlockchain::from(client),
+            blockchain,
         )?;
 
-        let electrum = bdk::electrum_client::Client::new(electrum_rpc_url.as_str())
-            .context("Failed to initialize Electrum RPC client")?;
+        let backend = backend
+            .connect(env_config.bitcoin_sync_interval(), tor_socks5_port)
+            .await?;
 
         let network = wallet.network();
 
         Ok(Self {
-            client: Arc::new(Mutex::new(Client::new(
-                electrum,
-                env_config.bitcoin_sync_interval(),
-            )?)),
+            client: Arc::new(Mutex::new(Client::new(backend))),
             wallet: Arc::new(Mutex::new(wallet)),
             finality_confirmations: env_config.bitcoin_finality_confirmations,
             network,
             target_block,
+            stuck_timeout: env_config.bitcoin_avg_block_time * BLOCKS_UNTIL_STUCK,
         })
     }
 
@@ -99,10 +117,12 @@ impl Wallet {
             .subscribe_to((txid, transaction.output[0].script_pubkey.clone()))
             .await;
 
-        self.wallet
+        self.client
             .lock()
             .await
+            .backend
             .broadcast(&transaction)
+            .await
             .with_context(|| {
                 format!("Failed to broadcast Bitcoin {} transaction {}", kind, txid)
             })?;
@@ -112,6 +132,15 @@ impl Wallet {
         Ok((txid, subscription))
     }
 
+    pub async fn get_tx(&self, txid: Txid) -> Result<Option<Transaction>> {
+        self.client
+            .lock()
+            .await
+            .backend
+            .get_raw_transaction(txid)
+            .await
+    }
+
     pub async fn get_raw_transaction(&self, txid: Txid) -> Result<Transaction> {
         self.get_tx(txid)
             .await?
@@ -122,7 +151,12 @@ impl Wallet {
     where
         T: Watchable,
     {
-        self.client.lock().await.status_of_script(tx)
+        self.client
+            .lock()
+            .await
+            .backend
+            .status_of_script(tx.id(), &tx.script())
+            .await
     }
 
     pub async fn subscribe_to(&self, tx: impl Watchable + Send + 'static) -> Subscription {
@@ -145,7 +179,13 @@ impl Wallet {
                     loop {
                         tokio::time::sleep(Duration::from_secs(5)).await;
 
-                        let new_status = match client.lock().await.status_of_script(&tx) {
+                        let new_status = match client
+                            .lock()
+                            .await
+                            .backend
+                            .status_of_script(txid, &script)
+                            .await
+                        {
                             Ok(new_status) => new_status,
                             Err(error) => {
                                 tracing::warn!(%txid, "Failed to get status of script: {:#}", error);
@@ -179,6 +219,249 @@ impl Wallet {
         sub
     }
 
+    /// The status of whichever of the given conflicting transactions has made
+    /// it into the mempool or the chain, along with its index.
+    pub async fn status_of_conflicting<T>(&self, txs: &[T]) -> Result<Option<(usize, ScriptStatus)>>
+    where
+        T: Watchable,
+    {
+        for (index, tx) in txs.iter().enumerate() {
+            let status = self.status_of_script(tx).await?;
+
+            if status.has_been_seen() {
+                return Ok(Some((index, status)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Wait until one of the given transactions is confirmed, replacing the
+    /// published one with the next whenever it gets stuck.
+    ///
+    /// The transactions are expected to spend the same inputs at increasing
+    /// fees. We pick up from the one published last, e.g. by the other party
+    /// of a swap, or broadcast the first if none of them has been published.
+    pub async fn replace_until_confirmed(
+        &self,
+        transactions: Vec<Transaction>,
+        kind: &str,
+    ) -> Result<Txid> {
+        let watchables = transactions
+            .iter()
+            .map(|tx| (tx.txid(), tx.output[0].script_pubkey.clone()))
+            .collect::<Vec<_>>();
+
+        let mut published = match self.status_of_conflicting(&watchables).await? {
+            Some((index, _)) => index,
+            None => {
+                let transaction = transactions
+                    .first()
+                    .context("No transaction to broadcast")?
+                    .clone();
+                self.broadcast(transaction, kind).await?;
+
+                0
+            }
+        };
+
+        loop {
+            let subscription = self.subscribe_to(watchables[published].clone()).await;
+
+            if let Ok(confirmed) = timeout(
+                self.stuck_timeout,
+                subscription.wait_until_confirmed_with(1),
+            )
+            .await
+            {
+                confirmed?;
+
+                return Ok(watchables[published].0);
+            }
+
+            // Someone else might have replaced it in the meantime
+            if let Some((index, status)) = self.status_of_conflicting(&watchables).await? {
+                if status.is_confirmed() {
+                    return Ok(watchables[index].0);
+                }
+                published = published.max(index);
+            }
+
+            let txid = watchables[published].0;
+            let replacement = match transactions.get(published + 1) {
+                Some(replacement) => replacement.clone(),
+                None => {
+                    tracing::warn!(%txid, %kind, "Bitcoin transaction is stuck and there is no replacement with a higher fee left");
+                    continue;
+                }
+            };
+
+            tracing::warn!(%txid, %kind, "Bitcoin transaction is stuck, replacing it with one paying a higher fee");
+
+            match self.broadcast(replacement, kind).await {
+                Ok(_) => published += 1,
+                Err(error) => {
+                    tracing::warn!(%txid, %kind, "Failed to replace Bitcoin transaction: {:#}", error)
+                }
+            }
+        }
+    }
+
+    /// Wait until the transaction of the subscription is confirmed, bumping
+    /// its fee through child-pays-for-parent whenever it goes unconfirmed for
+    /// [`BLOCKS_UNTIL_STUCK`] blocks.
+    ///
+    /// Every bump spends the output of the wallet in the transaction bumped
+    /// last, at least doubling the fee rate of the unconfirmed package. The
+    /// package never pays more than the fee bounds of the wallet for the amount
+    /// the transaction sends, once they are reached we stop bumping and only
+    /// wait.
+    pub async fn wait_until_confirmed_or_bump(
+        &self,
+        subscription: &Subscription,
+        kind: &str,
+    ) -> Result<()> {
+        let txid = subscription.txid();
+        let mut package = None;
+
+        loop {
+            if let Ok(confirmed) = timeout(
+                self.stuck_timeout,
+                subscription.wait_until_confirmed_with(1),
+            )
+            .await
+            {
+                return confirmed;
+            }
+
+            tracing::warn!(%txid, %kind, "Bitcoin transaction is stuck, bumping its fee through CPFP");
+
+            let bumped = async {
+                let (tip, package_fee, package_vsize, max_fee) = match &package {
+                    Some((tip, fee, size, max_fee)) => (tip.clone(), *fee, *size, *max_fee),
+                    None => {
+                        let transaction = self.get_raw_transaction(txid).await?;
+                        let fee = self.fee_paid_by(&transaction).await?;
+                        let size = vsize(&transaction);
+                        let sent = transaction.output.iter().map(|output| output.value).sum();
+                        let max_fee = max_tx_fee(Amount::from_sat(sent));
+
+                        (transaction, fee, size, max_fee)
+                    }
+                };
+
+                let child = self
+                    .spend_as_child(&tip, package_fee, package_vsize, max_fee)
+                    .await?;
+
+                Result::<_, anyhow::Error>::Ok(child.map(|(child, child_fee)| {
+                    let child_vsize = vsize(&child);
+
+                    (
+                        child,
+                        package_fee + child_fee,
+                        package_vsize + child_vsize,
+                        max_fee,
+                    )
+                }))
+            };
+
+            match bumped.await {
+                Ok(Some(bumped)) => package = Some(bumped),
+                Ok(None) => {
+                    tracing::warn!(%txid, %kind, "Fee of the stuck Bitcoin transaction reached the maximum, waiting for it to confirm without bumping it further");
+
+                    return subscription.wait_until_confirmed_with(1).await;
+                }
+                Err(error) => {
+                    tracing::warn!(%txid, %kind, "Failed to bump fee of Bitcoin transaction: {:#}", error)
+                }
+            }
+        }
+    }
+
+    /// Spend the output of the wallet in the given unconfirmed transaction,
+    /// paying enough fee to get the whole package confirmed.
+    ///
+    /// Returns `None` if the package already pays `max_fee`.
+    async fn spend_as_child(
+        &self,
+        parent: &Transaction,
+        package_fee: Amount,
+        package_vsize: u64,
+        max_fee: Amount,
+    ) -> Result<Option<(Transaction, Amount)>> {
+        // Pick up the parent as an unconfirmed output of the wallet
+        self.sync().await?;
+
+        let address = self.new_address().await?;
+        let fee_rate = self
+            .client
+            .lock()
+            .await
+            .estimate_feerate(self.target_block)
+            .await?;
+        let child_fee = match cpfp_child_fee(package_fee, package_vsize, fee_rate, max_fee)? {
+            Some(child_fee) => child_fee,
+            None => return Ok(None),
+        };
+
+        let psbt = {
+            let wallet = self.wallet.lock().await;
+
+            let vout = parent
+                .output
+                .iter()
+                .position(|output| wallet.is_mine(&output.script_pubkey).unwrap_or(false))
+                .context("Transaction has no output of the wallet to spend")?;
+            let outpoint = OutPoint::new(parent.txid(), u32::try_from(vout)?);
+
+            let mut tx_builder = wallet.build_tx();
+            tx_builder
+                .add_utxo(outpoint)?
+                .manually_selected_only()
+                .drain_to(address.script_pubkey())
+                .fee_absolute(child_fee.as_sat())
+                .enable_rbf();
+            let (psbt, _details) = tx_builder.finish()?;
+
+            psbt
+        };
+
+        let child = self.sign_and_finalize(psbt).await?;
+        self.broadcast(child.clone(), "cpfp").await?;
+
+        Ok(Some((child, child_fee)))
+    }
+
+    /// The fee the given transaction pays, looking up the outputs it spends.
+    async fn fee_paid_by(&self, transaction: &Transaction) -> Result<Amount> {
+        let mut spent = 0;
+
+        for input in &transaction.input {
+            let previous = input.previous_output;
+            spent += self
+                .get_raw_transaction(previous.txid)
+                .await?
+                .output
+                .get(previous.vout as usize)
+                .with_context(|| {
+                    format!(
+                        "Transaction {} has no output {}",
+                        previous.txid, previous.vout
+                    )
+                })?
+                .value;
+        }
+
+        let sent = transaction.output.iter().map(|output| output.value).sum();
+
+        spent
+            .checked_sub(sent)
+            .map(Amount::from_sat)
+            .context("Transaction spends more than its inputs")
+    }
+
     pub async fn wallet_export(&self, role: &str) -> Result<WalletExport> {
         let wallet = self.wallet.lock().await;
         match bdk::wallet::export::WalletExport::export_wallet(
@@ -255,7 +538,17 @@ impl Subscription {
             .await
     }
 
-    async fn wait_until(&self, mut predicate: impl FnMut(&ScriptStatus) -> bool) -> Result<()> {
+    pub fn txid(&self) -> Txid {
+        self.txid
+    }
+
+    pub fn finality_confirmations(&self) -> u32 {
+        self.finality_confirmations
+    }
+
+    /// Wait until `predicate` holds, calling it with every status update of
+    /// the transaction.
+    pub async fn wait_until(&self, mut predicate: impl FnMut(&ScriptStatus) -> bool) -> Result<()> {
         let mut receiver = self.receiver.clone();
 
         while !predicate(&receiver.borrow()) {
@@ -326,7 +619,7 @@ where
             .find(|tx| tx.txid == txid)
             .context("Could not find tx in bdk wallet when trying to determine fees")?
             .fee
-            .expect("fees are always present with Electrum backend");
+            .expect("fees are always present for transactions of the wallet");
 
         Ok(Amount::from_sat(fees))
     }
@@ -353,7 +646,7 @@ where
 
         let wallet = self.wallet.lock().await;
         let client = self.client.lock().await;
-        let fee_rate = client.estimate_feerate(self.target_block)?;
+        let fee_rate = client.estimate_feerate(self.target_block).await?;
         let script = address.script_pubkey();
 
         let mut tx_builder = wallet.build_tx();
@@ -391,6 +684,67 @@ where
         Ok(psbt)
     }
 
+    /// Builds a partially signed transaction paying every recipient.
+    ///
+    /// Whatever is left after paying the recipients and the fee goes to
+    /// `drain_to` if given, and to a change address of the wallet otherwise.
+    /// With `drain_to`, all outputs of the wallet allowed by `coin_control`
+    /// are spent.
+    pub async fn send_to_many(
+        &self,
+        recipients: Vec<Recipient>,
+        drain_to: Option<Address>,
+        coin_control: CoinControl,
+    ) -> Result<PartiallySignedTransaction> {
+        if recipients.is_empty() && drain_to.is_none() {
+            bail!("Cannot build PSBT without any recipient");
+        }
+
+        for address in recipients
+            .iter()
+            .map(|recipient| &recipient.address)
+            .chain(drain_to.as_ref())
+        {
+            if self.network != address.network {
+                bail!("Cannot build PSBT because network of given address is {} but wallet is on network {}", address.network, self.network);
+            }
+        }
+
+        let wallet = self.wallet.lock().await;
+        let fee_rate = match coin_control.fee_rate {
+            Some(sats_per_vb) => FeeRate::from_sat_per_vb(sats_per_vb),
+            None => {
+                self.client
+                    .lock()
+                    .await
+                    .estimate_feerate(self.target_block)
+                    .await?
+            }
+        };
+
+        let mut tx_builder = wallet.build_tx();
+        for recipient in recipients {
+            tx_builder.add_recipient(recipient.address.script_pubkey(), recipient.amount.as_sat());
+        }
+        if !coin_control.spend.is_empty() {
+            tx_builder
+                .add_utxos(&coin_control.spend)?
+                .manually_selected_only();
+        }
+        if let Some(drain_to) = drain_to {
+            tx_builder.drain_to(drain_to.script_pubkey());
+
+            if coin_control.spend.is_empty() {
+                tx_builder.drain_wallet();
+            }
+        }
+        tx_builder.unspendable(coin_control.avoid);
+        tx_builder.fee_rate(fee_rate);
+        let (psbt, _details) = tx_builder.finish()?;
+
+        Ok(psbt)
+    }
+
     /// Calculates the maximum "giveable" amount of this wallet.
     ///
     /// We define this as the maximum amount we can pay to a single output,
@@ -403,13 +757,13 @@ where
             return Ok(Amount::ZERO);
         }
         let client = self.client.lock().await;
-        let min_relay_fee = client.min_relay_fee()?.as_sat();
+        let min_relay_fee = client.min_relay_fee().await?.as_sat();
 
         if balance < min_relay_fee {
             return Ok(Amount::ZERO);
         }
 
-        let fee_rate = client.estimate_feerate(self.target_block)?;
+        let fee_rate = client.estimate_feerate(self.target_block).await?;
 
         let mut tx_builder = wallet.build_tx();
 
@@ -424,7 +778,7 @@ where
                 let max_giveable = details.sent
                     - details
                         .fee
-                        .expect("fees are always present with Electrum backend");
+                        .expect("fees are always present for transactions of the wallet");
                 Ok(Amount::from_sat(max_giveable))
             }
             Err(bdk::Error::InsufficientFunds { .. }) => Ok(Amount::ZERO),
@@ -441,8 +795,8 @@ where
         transfer_amount: bitcoin::Amount,
     ) -> Result<bitcoin::Amount> {
         let client = self.client.lock().await;
-        let fee_rate = client.estimate_feerate(self.target_block)?;
-        let min_relay_fee = client.min_relay_fee()?;
+        let fee_rate = client.estimate_feerate(self.target_block).await?;
+        let min_relay_fee = client.min_relay_fee().await?;
 
         estimate_fee(weight, transfer_amount, fee_rate, min_relay_fee)
     }
@@ -518,17 +872,66 @@ fn estimate_fee(
     Ok(amount)
 }
 
+/// The virtual size of a transaction, i.e. its weight in vbytes.
+fn vsize(transaction: &Transaction) -> u64 {
+    (transaction.get_weight() as u64 + 3) / 4
+}
+
+/// The most a transaction sending `amount` may pay in fees, the relative bound
+/// of [`estimate_fee`] but never more than its absolute bound.
+pub(super) fn max_tx_fee(amount: Amount) -> Amount {
+    let relative = Decimal::from(amount.as_sat()) * MAX_RELATIVE_TX_FEE;
+    let max_fee = relative
+        .min(MAX_ABSOLUTE_TX_FEE)
+        .floor()
+        .to_u64()
+        .expect("fee between zero and the absolute bound to fit into u64");
+
+    Amount::from_sat(max_fee)
+}
+
+/// The fee a child has to pay for an unconfirmed package to reach
+/// `fee_rate`.
+///
+/// The package already failed to confirm at its current fee rate, so the fee
+/// rate is at least doubled, whatever the estimate says. The package pays no
+/// more than `max_package_fee` in total though, `None` is returned if there is
+/// no room left for the child's fee.
+fn cpfp_child_fee(
+    package_fee: Amount,
+    package_vsize: u64,
+    fee_rate: FeeRate,
+    max_package_fee: Amount,
+) -> Result<Option<Amount>> {
+    let package_fee = Decimal::from(package_fee.as_sat());
+    let package_vsize = Decimal::from(package_vsize);
+    let child_vsize = Decimal::from(CPFP_CHILD_VSIZE);
+    let max_child_fee = Decimal::from(max_package_fee.as_sat()) - package_fee;
+
+    // The child has to pay at least the min relay fee of 1 sat/vB for itself
+    if max_child_fee < child_vsize {
+        return Ok(None);
+    }
+
+    let fee_rate = Decimal::from_f32(fee_rate.as_sat_vb()).context("Failed to parse fee rate")?;
+    let fee_rate = fee_rate.max(package_fee / package_vsize * dec!(2));
+
+    let child_fee = (fee_rate * (package_vsize + child_vsize) - package_fee)
+        .ceil()
+        .max(child_vsize)
+        .min(max_child_fee);
+
+    child_fee
+        .to_u64()
+        .map(|fee| Some(Amount::from_sat(fee)))
+        .context("Could not compute fee of child transaction")
+}
+
 impl<B, D, C> Wallet<B, D, C>
 where
     B: Blockchain,
     D: BatchDatabase,
 {
-    pub async fn get_tx(&self, txid: Txid) -> Result<Option<Transaction>> {
-        let tx = self.wallet.lock().await.client().get_tx(&txid)?;
-
-        Ok(tx)
-    }
-
     pub async fn sync(&self) -> Result<()> {
         self.wallet
             .lock()
@@ -547,9 +950,10 @@ impl<B, D, C> Wallet<B, D, C> {
     }
 }
 
+#[async_trait]
 pub trait EstimateFeeRate {
-    fn estimate_feerate(&self, target_block: usize) -> Result<FeeRate>;
-    fn min_relay_fee(&self) -> Result<bitcoin::Amount>;
+    async fn estimate_feerate(&self, target_block: usize) -> Result<FeeRate>;
+    async fn min_relay_fee(&self) -> Result<bitcoin::Amount>;
 }
 
 #[cfg(test)]
@@ -559,12 +963,13 @@ pub struct StaticFeeRate {
 }
 
 #[cfg(test)]
+#[async_trait]
 impl EstimateFeeRate for StaticFeeRate {
-    fn estimate_feerate(&self, _target_block: usize) -> Result<FeeRate> {
+    async fn estimate_feerate(&self, _target_block: usize) -> Result<FeeRate> {
         Ok(self.fee_rate)
     }
 
-    fn min_relay_fee(&self) -> Result<bitcoin::Amount> {
+    async fn min_relay_fee(&self) -> Result<bitcoin::Amount> {
         Ok(self.min_relay_fee)
     }
 }
@@ -651,10 +1056,59 @@ impl WalletBuilder {
             finality_confirmations: 1,
             network: Network::Regtest,
             target_block: 1,
+            stuck_timeout: Duration::from_secs(15),
         }
     }
 }
 
+/// An output paying `amount` to `address`, parsed from
+/// `<address>=<amount>`, e.g. `bc1q...=0.1 BTC`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recipient {
+    pub address: Address,
+    pub amount: Amount,
+}
+
+impl FromStr for Recipient {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (address, amount) = s
+            .split_once('=')
+            .context("Recipient has to be given as <address>=<amount>")?;
+
+        Ok(Self {
+            address: address.parse().context("Failed to parse address")?,
+            amount: amount.parse().context("Failed to parse amount")?,
+        })
+    }
+}
+
+/// Parse a fee rate in sat/vB, which has to be a finite, positive number.
+pub fn parse_fee_rate(s: &str) -> Result<f32> {
+    let fee_rate = s
+        .parse::<f32>()
+        .with_context(|| format!("Failed to parse {} as a fee rate", s))?;
+
+    if !fee_rate.is_finite() || fee_rate <= 0.0 {
+        bail!("Fee rate has to be a positive number of sat/vB, got {}", s)
+    }
+
+    Ok(fee_rate)
+}
+
+/// Which outputs of the wallet a transaction may spend and at which fee rate.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CoinControl {
+    /// Spend exactly these outputs if any are given, and let the wallet
+    /// select them otherwise.
+    pub spend: Vec<OutPoint>,
+    /// Never spend these outputs.
+    pub avoid: Vec<OutPoint>,
+    /// Pay this fee rate in sat/vB instead of the estimated one.
+    pub fee_rate: Option<f32>,
+}
+
 /// Defines a watchable transaction.
 ///
 /// For a transaction to be watchable, we need to know two things: Its
@@ -676,148 +1130,30 @@ impl Watchable for (Txid, Script) {
     }
 }
 
+/// The [`Backend`] of a wallet, along with the subscriptions to the statuses
+/// of transactions.
 pub struct Client {
-    electrum: bdk::electrum_client::Client,
-    latest_block_height: BlockHeight,
-    last_sync: Instant,
-    sync_interval: Duration,
-    script_history: BTreeMap<Script, Vec<GetHistoryRes>>,
+    backend: Box<dyn Backend>,
     subscriptions: HashMap<(Txid, Script), Subscription>,
 }
 
 impl Client {
-    fn new(electrum: bdk::electrum_client::Client, interval: Duration) -> Result<Self> {
-        // Initially fetch the latest block for storing the height.
-        // We do not act on this subscription after this call.
-        let latest_block = electrum
-            .block_headers_subscribe()
-            .context("Failed to subscribe to header notifications")?;
-
-        Ok(Self {
-            electrum,
-            latest_block_height: BlockHeight::try_from(latest_block)?,
-            last_sync: Instant::now(),
-            sync_interval: interval,
-            script_history: Default::default(),
+    fn new(backend: Box<dyn Backend>) -> Self {
+        Self {
+            backend,
             subscriptions: Default::default(),
-        })
-    }
-
-    fn update_state(&mut self) -> Result<()> {
-        let now = Instant::now();
-        if now < self.last_sync + self.sync_interval {
-            return Ok(());
-        }
-
-        self.last_sync = now;
-        self.update_latest_block()?;
-        self.update_script_histories()?;
-
-        Ok(())
-    }
-
-    fn status_of_script<T>(&mut self, tx: &T) -> Result<ScriptStatus>
-    where
-        T: Watchable,
-    {
-        let txid = tx.id();
-        let script = tx.script();
-
-        if !self.script_history.contains_key(&script) {
-            self.script_history.insert(script.clone(), vec![]);
-        }
-
-        self.update_state()?;
-
-        let history = self.script_history.entry(script).or_default();
-
-        let history_of_tx = history
-            .iter()
-            .filter(|entry| entry.tx_hash == txid)
-            .collect::<Vec<_>>();
-
-        match history_of_tx.as_slice() {
-            [] => Ok(ScriptStatus::Unseen),
-            [remaining @ .., last] => {
-                if !remaining.is_empty() {
-                    tracing::warn!("Found more than a single history entry for script. This is highly unexpected and those history entries will be ignored")
-                }
-
-                if last.height <= 0 {
-                    Ok(ScriptStatus::InMempool)
-                } else {
-                    Ok(ScriptStatus::Confirmed(
-                        Confirmed::from_inclusion_and_latest_block(
-                            u32::try_from(last.height)?,
-                            u32::from(self.latest_block_height),
-                        ),
-                    ))
-                }
-            }
-        }
-    }
-
-    fn update_latest_block(&mut self) -> Result<()> {
-        // Fetch the latest block for storing the height.
-        // We do not act on this subscription after this call, as we cannot rely on
-        // subscription push notifications because eventually the Electrum server will
-        // close the connection and subscriptions are not automatically renewed
-        // upon renewing the connection.
-        let latest_block = self
-            .electrum
-            .block_headers_subscribe()
-            .context("Failed to subscribe to header notifications")?;
-        let latest_block_height = BlockHeight::try_from(latest_block)?;
-
-        if latest_block_height > self.latest_block_height {
-            tracing::debug!(
-                block_height = u32::from(latest_block_height),
-                "Got notification for new block"
-            );
-            self.latest_block_height = latest_block_height;
-        }
-
-        Ok(())
-    }
-
-    fn update_script_histories(&mut self) -> Result<()> {
-        let histories = self
-            .electrum
-            .batch_script_get_history(self.script_history.keys())
-            .context("Failed to get script histories")?;
-
-        if histories.len() != self.script_history.len() {
-            bail!(
-                "Expected {} history entries, received {}",
-                self.script_history.len(),
-                histories.len()
-            );
         }
-
-        let scripts = self.script_history.keys().cloned();
-        let histories = histories.into_iter();
-
-        self.script_history = scripts.zip(histories).collect::<BTreeMap<_, _>>();
-
-        Ok(())
     }
 }
 
+#[async_trait]
 impl EstimateFeeRate for Client {
-    fn estimate_feerate(&self, target_block: usize) -> Result<FeeRate> {
-        // https://github.com/romanz/electrs/blob/f9cf5386d1b5de6769ee271df5eef324aa9491bc/src/rpc.rs#L213
-        // Returned estimated fees are per BTC/kb.
-        let fee_per_byte = self.electrum.estimate_fee(target_block)?;
-        // we do not expect fees being that high.
-        #[allow(clippy::cast_possible_truncation)]
-        Ok(FeeRate::from_btc_per_kvb(fee_per_byte as f32))
+    async fn estimate_feerate(&self, target_block: usize) -> Result<FeeRate> {
+        self.backend.estimate_feerate(target_block).await
     }
 
-    fn min_relay_fee(&self) -> Result<bitcoin::Amount> {
-        // https://github.com/romanz/electrs/blob/f9cf5386d1b5de6769ee271df5eef324aa9491bc/src/rpc.rs#L219
-        // Returned fee is in BTC/kb
-        let relay_fee = bitcoin::Amount::from_btc(self.electrum.relay_fee()?)?;
-        Ok(relay_fee)
+    async fn min_relay_fee(&self) -> Result<bitcoin::Amount> {
+        self.backend.min_relay_fee().await
     }
 }
 
@@ -1017,6 +1353,63 @@ mod tests {
         assert_eq!(is_fee.as_sat(), MAX_ABSOLUTE_TX_FEE.to_u64().unwrap());
     }
 
+    #[test]
+    fn given_stuck_package_and_low_estimate_cpfp_doubles_fee_rate() {
+        // 1 sat/vB for a package of 200 vbyte
+        let package_fee = bitcoin::Amount::from_sat(200);
+        let fee_rate = FeeRate::from_sat_per_vb(1.0);
+
+        let child_fee = cpfp_child_fee(package_fee, 200, fee_rate, bitcoin::Amount::ONE_BTC)
+            .unwrap()
+            .unwrap();
+
+        // 2 sat/vB for the package and the child
+        assert_eq!(child_fee, bitcoin::Amount::from_sat(2 * 310 - 200));
+    }
+
+    #[test]
+    fn given_stuck_package_and_high_estimate_cpfp_reaches_estimate() {
+        let package_fee = bitcoin::Amount::from_sat(200);
+        let fee_rate = FeeRate::from_sat_per_vb(10.0);
+
+        let child_fee = cpfp_child_fee(package_fee, 200, fee_rate, bitcoin::Amount::ONE_BTC)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(child_fee, bitcoin::Amount::from_sat(10 * 310 - 200));
+    }
+
+    #[test]
+    fn given_stuck_package_cpfp_stays_within_max_fee() {
+        let package_fee = bitcoin::Amount::from_sat(200);
+        let fee_rate = FeeRate::from_sat_per_vb(10.0);
+        let max_fee = bitcoin::Amount::from_sat(1_000);
+
+        let child_fee = cpfp_child_fee(package_fee, 200, fee_rate, max_fee)
+            .unwrap()
+            .unwrap();
+        assert_eq!(child_fee, bitcoin::Amount::from_sat(800));
+
+        // no room left for the child to pay the min relay fee for itself
+        let package_fee = bitcoin::Amount::from_sat(1_000 - 100);
+        assert_eq!(
+            cpfp_child_fee(package_fee, 310, fee_rate, max_fee).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn max_tx_fee_is_relative_up_to_the_absolute_bound() {
+        assert_eq!(
+            max_tx_fee(bitcoin::Amount::from_sat(1_000_000)),
+            bitcoin::Amount::from_sat(30_000)
+        );
+        assert_eq!(
+            max_tx_fee(bitcoin::Amount::from_btc(10.0).unwrap()),
+            bitcoin::Amount::from_sat(100_000)
+        );
+    }
+
     proptest! {
         #[test]
         fn given_randon_amount_random_fee_and_random_relay_rate_but_fix_weight_does_not_error(
@@ -1191,6 +1584,125 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn send_to_many_pays_every_recipient() {
+        let wallet = WalletBuilder::new(50_000).build();
+        let first = wallet.new_address().await.unwrap();
+        let second = wallet.new_address().await.unwrap();
+
+        let psbt = wallet
+            .send_to_many(
+                vec![
+                    Recipient {
+                        address: first.clone(),
+                        amount: Amount::from_sat(10_000),
+                    },
+                    Recipient {
+                        address: second.clone(),
+                        amount: Amount::from_sat(20_000),
+                    },
+                ],
+                None,
+                CoinControl::default(),
+            )
+            .await
+            .unwrap();
+        let transaction = wallet.sign_and_finalize(psbt).await.unwrap();
+
+        let paid = |address: &Address| {
+            transaction
+                .output
+                .iter()
+                .find(|output| output.script_pubkey == address.script_pubkey())
+                .map(|output| output.value)
+        };
+        assert_eq!(paid(&first), Some(10_000));
+        assert_eq!(paid(&second), Some(20_000));
+    }
+
+    #[tokio::test]
+    async fn send_to_many_drains_only_the_selected_outputs() {
+        let wallet = WalletBuilder::new(50_000).with_num_utxos(3).build();
+        let selected = wallet.wallet.lock().await.list_unspent().unwrap()[1].outpoint;
+        let drain_to = wallet.new_address().await.unwrap();
+
+        let psbt = wallet
+            .send_to_many(vec![], Some(drain_to.clone()), CoinControl {
+                spend: vec![selected],
+                ..CoinControl::default()
+            })
+            .await
+            .unwrap();
+        let transaction = wallet.sign_and_finalize(psbt).await.unwrap();
+
+        let inputs = transaction
+            .input
+            .iter()
+            .map(|input| input.previous_output)
+            .collect::<Vec<_>>();
+        assert_eq!(inputs, vec![selected]);
+        match transaction.output.as_slice() {
+            [output] => assert_eq!(output.script_pubkey, drain_to.script_pubkey()),
+            _ => panic!("expected exactly one output"),
+        }
+    }
+
+    #[tokio::test]
+    async fn send_to_many_never_spends_avoided_outputs() {
+        let wallet = WalletBuilder::new(50_000).with_num_utxos(3).build();
+        let utxos = wallet.wallet.lock().await.list_unspent().unwrap();
+        let avoid = vec![utxos[0].outpoint, utxos[2].outpoint];
+
+        let psbt = wallet
+            .send_to_many(
+                vec![Recipient {
+                    address: wallet.new_address().await.unwrap(),
+                    amount: Amount::from_sat(10_000),
+                }],
+                None,
+                CoinControl {
+                    avoid: avoid.clone(),
+                    fee_rate: Some(2.0),
+                    ..CoinControl::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        let inputs = psbt
+            .global
+            .unsigned_tx
+            .input
+            .iter()
+            .map(|input| input.previous_output)
+            .collect::<Vec<_>>();
+        assert_eq!(inputs, vec![utxos[1].outpoint]);
+    }
+
+    #[test]
+    fn recipient_parses_from_address_and_amount() {
+        let recipient = "bcrt1q08pfqpsyrt7acllzyjm8q5qsz5capvyahm49rw=0.1 BTC"
+            .parse::<Recipient>()
+            .unwrap();
+
+        assert_eq!(recipient, Recipient {
+            address: "bcrt1q08pfqpsyrt7acllzyjm8q5qsz5capvyahm49rw"
+                .parse()
+                .unwrap(),
+            amount: Amount::from_sat(10_000_000),
+        });
+    }
+
+    #[test]
+    fn fee_rate_has_to_be_finite_and_positive() {
+        assert_eq!(parse_fee_rate("5").unwrap(), 5.0);
+        assert_eq!(parse_fee_rate("1.5").unwrap(), 1.5);
+
+        for invalid in ["0", "-1", "NaN", "inf", "-inf", "five"] {
+            assert!(parse_fee_rate(invalid).is_err(), "{}", invalid);
+        }
+    }
+
     #[test]
     fn printing_status_change_doesnt_spam_on_same_status() {
         let writer = capture_logs(LevelFilter::DEBUG);
@@ -1,17 +1,23 @@
 mod behaviour;
 pub mod cancel;
 pub mod command;
+mod connect;
 mod event_loop;
 mod list_sellers;
 pub mod refund;
+mod resume;
+pub mod rpc;
+pub mod seller_directory;
 pub mod tracing;
 pub mod transport;
 
 pub use behaviour::{Behaviour, OutEvent};
 pub use cancel::cancel;
+pub use connect::connect_to_seller;
 pub use event_loop::{EventLoop, EventLoopHandle};
 pub use list_sellers::{list_sellers, Seller, Status as SellerStatus};
 pub use refund::refund;
+pub use resume::UnfinishedSwap;
 
 #[cfg(test)]
 mod tests {
@@ -35,16 +41,59 @@ mod tests {
     async fn list_sellers_should_report_all_registered_asbs_with_a_quote() {
         let namespace = XmrBtcNamespace::Mainnet;
         let (rendezvous_address, rendezvous_peer_id) = setup_rendezvous_point().await;
-        let expected_seller_1 =
-            setup_asb(rendezvous_peer_id, rendezvous_address.clone(), namespace).await;
-        let expected_seller_2 =
-            setup_asb(rendezvous_peer_id, rendezvous_address.clone(), namespace).await;
+        let expected_seller_1 = setup_asb(
+            vec![(rendezvous_address.clone(), rendezvous_peer_id)],
+            namespace,
+        )
+        .await;
+        let expected_seller_2 = setup_asb(
+            vec![(rendezvous_address.clone(), rendezvous_peer_id)],
+            namespace,
+        )
+        .await;
+
+        let list_sellers = list_sellers(
+            vec![rendezvous_address.with(Protocol::P2p(rendezvous_peer_id.into()))],
+            namespace,
+            0,
+            false,
+            identity::Keypair::generate_ed25519(),
+        );
+        let sellers = tokio::time::timeout(Duration::from_secs(15), list_sellers)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            HashSet::<Seller>::from_iter(sellers),
+            HashSet::<Seller>::from_iter([expected_seller_1, expected_seller_2])
+        )
+    }
+
+    #[tokio::test]
+    async fn list_sellers_should_report_asbs_of_all_rendezvous_points_once() {
+        let namespace = XmrBtcNamespace::Mainnet;
+        let rendezvous_point_1 = setup_rendezvous_point().await;
+        let rendezvous_point_2 = setup_rendezvous_point().await;
+        let expected_seller_1 = setup_asb(
+            vec![rendezvous_point_1.clone(), rendezvous_point_2.clone()],
+            namespace,
+        )
+        .await;
+        let expected_seller_2 = setup_asb(vec![rendezvous_point_2.clone()], namespace).await;
 
         let list_sellers = list_sellers(
-            rendezvous_peer_id,
-            rendezvous_address,
+            vec![
+                rendezvous_point_1
+                    .0
+                    .with(Protocol::P2p(rendezvous_point_1.1.into())),
+                rendezvous_point_2
+                    .0
+                    .with(Protocol::P2p(rendezvous_point_2.1.into())),
+            ],
             namespace,
             0,
+            false,
             identity::Keypair::generate_ed25519(),
         );
         let sellers = tokio::time::timeout(Duration::from_secs(15), list_sellers)
@@ -52,6 +101,7 @@ mod tests {
             .unwrap()
             .unwrap();
 
+        assert_eq!(sellers.len(), 2);
         assert_eq!(
             HashSet::<Seller>::from_iter(sellers),
             HashSet::<Seller>::from_iter([expected_seller_1, expected_seller_2])
@@ -73,10 +123,10 @@ mod tests {
     }
 
     async fn setup_asb(
-        rendezvous_peer_id: PeerId,
-        rendezvous_address: Multiaddr,
+        rendezvous_points: Vec<(Multiaddr, PeerId)>,
         namespace: XmrBtcNamespace,
     ) -> Seller {
+        let number_of_rendezvous_points = rendezvous_points.len();
         let static_quote = BidQuote {
             price: bitcoin::Amount::from_sat(1337),
             min_quantity: bitcoin::Amount::from_sat(42),
@@ -86,15 +136,17 @@ mod tests {
         let mut asb = new_swarm(|_, identity| StaticQuoteAsbBehaviour {
             rendezvous: asb::rendezous::Behaviour::new(
                 identity,
-                rendezvous_peer_id,
-                rendezvous_address,
-                namespace,
-                None,
+                rendezvous_points
+                    .into_iter()
+                    .map(|(address, peer_id)| {
+                        asb::rendezous::RendezvousNode::new(address, peer_id, namespace, None)
+                    })
+                    .collect(),
             ),
             ping: Default::default(),
             quote: quote::asb(),
             static_quote,
-            registered: false,
+            registrations: 0,
         });
 
         let asb_address = asb.listen_on_tcp_localhost().await;
@@ -104,7 +156,7 @@ mod tests {
 
         // avoid race condition where `list_sellers` tries to discover before we are
         // registered block this function until we are registered
-        while !asb.behaviour().registered {
+        while asb.behaviour().registrations < number_of_rendezvous_points {
             asb.next().await;
         }
 
@@ -131,12 +183,12 @@ mod tests {
         #[behaviour(ignore)]
         static_quote: BidQuote,
         #[behaviour(ignore)]
-        registered: bool,
+        registrations: usize,
     }
     impl NetworkBehaviourEventProcess<rendezvous::client::Event> for StaticQuoteAsbBehaviour {
         fn inject_event(&mut self, event: rendezvous::client::Event) {
             if let rendezvous::client::Event::Registered { .. } = event {
-                self.registered = true;
+                self.registrations += 1;
             }
         }
     }
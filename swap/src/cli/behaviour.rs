@@ -1,6 +1,8 @@
+use crate::network::ask_quote::AskQuote;
 use crate::network::quote::BidQuote;
-use crate::network::swap_setup::bob;
-use crate::network::{encrypted_signature, quote, redial, transfer_proof};
+use crate::network::swap_setup::{bob, reverse};
+use crate::network::{ask_quote, encrypted_signature, quote, redial, transfer_proof};
+use crate::protocol::alice::State3;
 use crate::protocol::bob::State2;
 use crate::{bitcoin, env};
 use anyhow::{anyhow, Error, Result};
@@ -17,12 +19,25 @@ pub enum OutEvent {
         id: RequestId,
         response: BidQuote,
     },
+    AskQuoteReceived {
+        id: RequestId,
+        response: AskQuote,
+    },
     SwapSetupCompleted(Box<Result<State2>>),
+    ReverseSwapSetupCompleted(Box<Result<State3>>),
     TransferProofReceived {
         msg: Box<transfer_proof::Request>,
         channel: ResponseChannel<()>,
         peer: PeerId,
     },
+    TransferProofAcknowledged {
+        id: RequestId,
+    },
+    EncryptedSignatureReceived {
+        msg: Box<encrypted_signature::Request>,
+        channel: ResponseChannel<()>,
+        peer: PeerId,
+    },
     EncryptedSignatureAcknowledged {
         id: RequestId,
     },
@@ -54,13 +69,16 @@ impl OutEvent {
     }
 }
 
-/// A `NetworkBehaviour` that represents an XMR/BTC swap node as Bob.
+/// A `NetworkBehaviour` that represents an XMR/BTC swap node as Bob, or as
+/// Alice when selling XMR.
 #[derive(NetworkBehaviour)]
 #[behaviour(out_event = "OutEvent", event_process = false)]
 #[allow(missing_debug_implementations)]
 pub struct Behaviour {
     pub quote: quote::Behaviour,
+    pub ask_quote: ask_quote::Behaviour,
     pub swap_setup: bob::Behaviour,
+    pub reverse_swap_setup: reverse::alice::Behaviour,
     pub transfer_proof: transfer_proof::Behaviour,
     pub encrypted_signature: encrypted_signature::Behaviour,
     pub redial: redial::Behaviour,
@@ -79,9 +97,11 @@ impl Behaviour {
     ) -> Self {
         Self {
             quote: quote::cli(),
-            swap_setup: bob::Behaviour::new(env_config, bitcoin_wallet),
-            transfer_proof: transfer_proof::bob(),
-            encrypted_signature: encrypted_signature::bob(),
+            ask_quote: ask_quote::cli(),
+            swap_setup: bob::Behaviour::new(env_config, bitcoin_wallet.clone()),
+            reverse_swap_setup: reverse::alice::Behaviour::new(env_config, bitcoin_wallet),
+            transfer_proof: transfer_proof::new(),
+            encrypted_signature: encrypted_signature::new(),
             redial: redial::Behaviour::new(alice, Duration::from_secs(2)),
             ping: Ping::new(PingConfig::new().with_keep_alive(true)),
         }
@@ -90,6 +110,7 @@ impl Behaviour {
     /// Add a known address for the given peer
     pub fn add_address(&mut self, peer_id: PeerId, address: Multiaddr) {
         self.quote.add_address(&peer_id, address.clone());
+        self.ask_quote.add_address(&peer_id, address.clone());
         self.transfer_proof.add_address(&peer_id, address.clone());
         self.encrypted_signature.add_address(&peer_id, address);
     }
@@ -0,0 +1,34 @@
+use crate::cli::{Behaviour, EventLoop, EventLoopHandle};
+use crate::network::swarm;
+use crate::{bitcoin, env};
+use anyhow::Result;
+use libp2p::{identity, Multiaddr, PeerId};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Set up the network layer for a swap with the given seller, returning the
+/// event loop that has to run for the swap to make progress.
+#[allow(clippy::too_many_arguments)]
+pub async fn connect_to_seller(
+    swap_id: Uuid,
+    seller_peer_id: PeerId,
+    seller_addresses: Vec<Multiaddr>,
+    env_config: env::Config,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    identity: identity::Keypair,
+    tor_socks5_port: u16,
+    tor_only: bool,
+) -> Result<(EventLoop, EventLoopHandle)> {
+    let behaviour = Behaviour::new(seller_peer_id, env_config, bitcoin_wallet);
+    let mut swarm = swarm::cli(identity, tor_socks5_port, tor_only, behaviour).await?;
+
+    tracing::debug!(peer_id = %swarm.local_peer_id(), "Network layer initialized");
+
+    for seller_address in seller_addresses {
+        swarm
+            .behaviour_mut()
+            .add_address(seller_peer_id, seller_address);
+    }
+
+    EventLoop::new(swap_id, swarm, seller_peer_id)
+}
@@ -1,10 +1,13 @@
 use crate::bitcoin::EncryptedSignature;
 use crate::cli::behaviour::{Behaviour, OutEvent};
-use crate::monero;
-use crate::network::encrypted_signature;
+use crate::network::ask_quote::AskQuote;
 use crate::network::quote::BidQuote;
 use crate::network::swap_setup::bob::NewSwap;
+use crate::network::swap_setup::reverse::alice::NewSwap as NewReverseSwap;
+use crate::network::{encrypted_signature, transfer_proof};
+use crate::protocol::alice::State3;
 use crate::protocol::bob::State2;
+use crate::{asb, monero};
 use anyhow::{Context, Result};
 use futures::future::{BoxFuture, OptionFuture};
 use futures::{FutureExt, StreamExt};
@@ -23,15 +26,21 @@ pub struct EventLoop {
 
     // these streams represents outgoing requests that we have to make
     quote_requests: bmrng::RequestReceiverStream<(), BidQuote>,
+    ask_quote_requests: bmrng::RequestReceiverStream<(), AskQuote>,
     encrypted_signatures: bmrng::RequestReceiverStream<EncryptedSignature, ()>,
+    transfer_proofs: bmrng::RequestReceiverStream<monero::TransferProof, ()>,
     swap_setup_requests: bmrng::RequestReceiverStream<NewSwap, Result<State2>>,
+    reverse_swap_setup_requests: bmrng::RequestReceiverStream<NewReverseSwap, Result<State3>>,
 
     // these represents requests that are currently in-flight.
     // once we get a response to a matching [`RequestId`], we will use the responder to relay the
     // response.
     inflight_quote_requests: HashMap<RequestId, bmrng::Responder<BidQuote>>,
+    inflight_ask_quote_requests: HashMap<RequestId, bmrng::Responder<AskQuote>>,
     inflight_encrypted_signature_requests: HashMap<RequestId, bmrng::Responder<()>>,
+    inflight_transfer_proof_requests: HashMap<RequestId, bmrng::Responder<()>>,
     inflight_swap_setup: Option<bmrng::Responder<Result<State2>>>,
+    inflight_reverse_swap_setup: Option<bmrng::Responder<Result<State3>>>,
 
     /// The sender we will use to relay incoming transfer proofs.
     transfer_proof: bmrng::RequestSender<monero::TransferProof, ()>,
@@ -43,6 +52,12 @@ pub struct EventLoop {
     /// resolves, we use the `ResponseChannel` returned from it to send an ACK
     /// to Alice that we have successfully processed the transfer proof.
     pending_transfer_proof: OptionFuture<BoxFuture<'static, ResponseChannel<()>>>,
+
+    /// The sender we will use to relay incoming encrypted signatures when
+    /// selling XMR.
+    encrypted_signature: bmrng::RequestSender<EncryptedSignature, ()>,
+    /// The same as `pending_transfer_proof` for incoming encrypted signatures.
+    pending_encrypted_signature: OptionFuture<BoxFuture<'static, ResponseChannel<()>>>,
 }
 
 impl EventLoop {
@@ -55,26 +70,47 @@ impl EventLoop {
         let transfer_proof = bmrng::channel_with_timeout(1, Duration::from_secs(60));
         let encrypted_signature = bmrng::channel(1);
         let quote = bmrng::channel_with_timeout(1, Duration::from_secs(60));
+        let ask_quote = bmrng::channel_with_timeout(1, Duration::from_secs(60));
+        let reverse_swap_setup = bmrng::channel_with_timeout(1, Duration::from_secs(60));
+
+        // When selling XMR the swap runs as Alice, the messages flow in the other
+        // direction
+        let outgoing_transfer_proof = bmrng::channel(1);
+        let incoming_encrypted_signature = bmrng::channel(1);
 
         let event_loop = EventLoop {
             swap_id,
             swarm,
             alice_peer_id,
             swap_setup_requests: execution_setup.1.into(),
+            reverse_swap_setup_requests: reverse_swap_setup.1.into(),
             transfer_proof: transfer_proof.0,
             encrypted_signatures: encrypted_signature.1.into(),
+            transfer_proofs: outgoing_transfer_proof.1.into(),
             quote_requests: quote.1.into(),
+            ask_quote_requests: ask_quote.1.into(),
             inflight_quote_requests: HashMap::default(),
+            inflight_ask_quote_requests: HashMap::default(),
             inflight_swap_setup: None,
+            inflight_reverse_swap_setup: None,
             inflight_encrypted_signature_requests: HashMap::default(),
+            inflight_transfer_proof_requests: HashMap::default(),
             pending_transfer_proof: OptionFuture::from(None),
+            encrypted_signature: incoming_encrypted_signature.0,
+            pending_encrypted_signature: OptionFuture::from(None),
         };
 
         let handle = EventLoopHandle {
             swap_setup: execution_setup.0,
+            reverse_swap_setup: reverse_swap_setup.0,
             transfer_proof: transfer_proof.1,
             encrypted_signature: encrypted_signature.0,
             quote: quote.0,
+            ask_quote: ask_quote.0,
+            alice: Some(asb::EventLoopHandle::new(
+                incoming_encrypted_signature.1,
+                outgoing_transfer_proof.0,
+            )),
         };
 
         Ok((event_loop, handle))
@@ -99,11 +135,21 @@ impl EventLoop {
                                 let _ = responder.respond(response);
                             }
                         }
+                        SwarmEvent::Behaviour(OutEvent::AskQuoteReceived { id, response }) => {
+                            if let Some(responder) = self.inflight_ask_quote_requests.remove(&id) {
+                                let _ = responder.respond(response);
+                            }
+                        }
                         SwarmEvent::Behaviour(OutEvent::SwapSetupCompleted(response)) => {
                             if let Some(responder) = self.inflight_swap_setup.take() {
                                 let _ = responder.respond(*response);
                             }
                         }
+                        SwarmEvent::Behaviour(OutEvent::ReverseSwapSetupCompleted(response)) => {
+                            if let Some(responder) = self.inflight_reverse_swap_setup.take() {
+                                let _ = responder.respond(*response);
+                            }
+                        }
                         SwarmEvent::Behaviour(OutEvent::TransferProofReceived { msg, channel, peer }) => {
                             let swap_id = msg.swap_id;
 
@@ -145,6 +191,45 @@ impl EventLoop {
                                 let _ = responder.respond(());
                             }
                         }
+                        SwarmEvent::Behaviour(OutEvent::EncryptedSignatureReceived { msg, channel, peer }) => {
+                            let swap_id = msg.swap_id;
+
+                            if peer != self.alice_peer_id {
+                                tracing::warn!(
+                                            %swap_id,
+                                            "Ignoring malicious encrypted signature from {}, expected to receive it from {}",
+                                            peer,
+                                            self.alice_peer_id);
+                                        continue;
+                            }
+
+                            if swap_id != self.swap_id {
+                                tracing::warn!("Received unexpected encrypted signature for swap {} while running swap {}. This encrypted signature will be ignored", swap_id, self.swap_id);
+
+                                // When receiving an encrypted signature that is unexpected we still have to acknowledge that it was received
+                                let _ = self.swarm.behaviour_mut().encrypted_signature.send_response(channel, ());
+                                continue;
+                            }
+
+                            let mut responder = match self.encrypted_signature.send(msg.tx_redeem_encsig).await {
+                                Ok(responder) => responder,
+                                Err(e) => {
+                                    tracing::warn!("Failed to pass on encrypted signature: {:#}", e);
+                                    continue;
+                                }
+                            };
+
+                            self.pending_encrypted_signature = OptionFuture::from(Some(async move {
+                                let _ = responder.recv().await;
+
+                                channel
+                            }.boxed()));
+                        }
+                        SwarmEvent::Behaviour(OutEvent::TransferProofAcknowledged { id }) => {
+                            if let Some(responder) = self.inflight_transfer_proof_requests.remove(&id) {
+                                let _ = responder.respond(());
+                            }
+                        }
                         SwarmEvent::Behaviour(OutEvent::AllRedialAttemptsExhausted { peer }) if peer == self.alice_peer_id => {
                             tracing::error!("Exhausted all re-dial attempts to Alice");
                             return;
@@ -184,10 +269,27 @@ impl EventLoop {
                     let id = self.swarm.behaviour_mut().quote.send_request(&self.alice_peer_id, ());
                     self.inflight_quote_requests.insert(id, responder);
                 },
+                Some(((), responder)) = self.ask_quote_requests.next().fuse(), if self.is_connected_to_alice() => {
+                    let id = self.swarm.behaviour_mut().ask_quote.send_request(&self.alice_peer_id, ());
+                    self.inflight_ask_quote_requests.insert(id, responder);
+                },
                 Some((swap, responder)) = self.swap_setup_requests.next().fuse(), if self.is_connected_to_alice() => {
                     self.swarm.behaviour_mut().swap_setup.start(self.alice_peer_id, swap).await;
                     self.inflight_swap_setup = Some(responder);
                 },
+                Some((swap, responder)) = self.reverse_swap_setup_requests.next().fuse(), if self.is_connected_to_alice() => {
+                    self.swarm.behaviour_mut().reverse_swap_setup.start(self.alice_peer_id, swap).await;
+                    self.inflight_reverse_swap_setup = Some(responder);
+                },
+                Some((tx_lock_proof, responder)) = self.transfer_proofs.next().fuse(), if self.is_connected_to_alice() => {
+                    let request = transfer_proof::Request {
+                        swap_id: self.swap_id,
+                        tx_lock_proof
+                    };
+
+                    let id = self.swarm.behaviour_mut().transfer_proof.send_request(&self.alice_peer_id, request);
+                    self.inflight_transfer_proof_requests.insert(id, responder);
+                },
                 Some((tx_redeem_encsig, responder)) = self.encrypted_signatures.next().fuse(), if self.is_connected_to_alice() => {
                     let request = encrypted_signature::Request {
                         swap_id: self.swap_id,
@@ -203,6 +305,12 @@ impl EventLoop {
 
                     self.pending_transfer_proof = OptionFuture::from(None);
                 }
+
+                Some(response_channel) = &mut self.pending_encrypted_signature => {
+                    let _ = self.swarm.behaviour_mut().encrypted_signature.send_response(response_channel, ());
+
+                    self.pending_encrypted_signature = OptionFuture::from(None);
+                }
             }
         }
     }
@@ -215,12 +323,50 @@ impl EventLoop {
 #[derive(Debug)]
 pub struct EventLoopHandle {
     swap_setup: bmrng::RequestSender<NewSwap, Result<State2>>,
+    reverse_swap_setup: bmrng::RequestSender<NewReverseSwap, Result<State3>>,
     transfer_proof: bmrng::RequestReceiver<monero::TransferProof, ()>,
     encrypted_signature: bmrng::RequestSender<EncryptedSignature, ()>,
     quote: bmrng::RequestSender<(), BidQuote>,
+    ask_quote: bmrng::RequestSender<(), AskQuote>,
+    /// The handle for the swap in the role of Alice when selling XMR.
+    alice: Option<asb::EventLoopHandle>,
 }
 
 impl EventLoopHandle {
+    /// Create a handle for a swap which has already been set up, i.e. a swap in
+    /// which the ASB buys XMR.
+    ///
+    /// Setting up swaps and requesting quotes through this handle fails.
+    pub(crate) fn new(
+        transfer_proof: bmrng::RequestReceiver<monero::TransferProof, ()>,
+        encrypted_signature: bmrng::RequestSender<EncryptedSignature, ()>,
+    ) -> Self {
+        Self {
+            swap_setup: bmrng::channel(1).0,
+            reverse_swap_setup: bmrng::channel(1).0,
+            transfer_proof,
+            encrypted_signature,
+            quote: bmrng::channel(1).0,
+            ask_quote: bmrng::channel(1).0,
+            alice: None,
+        }
+    }
+
+    pub async fn setup_reverse_swap(&mut self, swap: NewReverseSwap) -> Result<State3> {
+        self.reverse_swap_setup.send_receive(swap).await?
+    }
+
+    pub async fn request_ask_quote(&mut self) -> Result<AskQuote> {
+        Ok(self.ask_quote.send_receive(()).await?)
+    }
+
+    /// Take the handle for running a swap in the role of Alice.
+    pub fn alice_handle(&mut self) -> Result<asb::EventLoopHandle> {
+        self.alice
+            .take()
+            .context("Handle for Alice was already taken")
+    }
+
     pub async fn setup_swap(&mut self, swap: NewSwap) -> Result<State2> {
         self.swap_setup.send_receive(swap).await?
     }
@@ -1,8 +1,9 @@
+use crate::libp2p_ext::MultiAddrExt;
 use crate::network::quote::BidQuote;
 use crate::network::rendezvous::XmrBtcNamespace;
 use crate::network::{quote, swarm};
 use anyhow::{Context, Result};
-use futures::StreamExt;
+use futures::{future, StreamExt};
 use libp2p::multiaddr::Protocol;
 use libp2p::ping::{Ping, PingConfig, PingEvent};
 use libp2p::request_response::{RequestResponseEvent, RequestResponseMessage};
@@ -16,17 +17,64 @@ use std::time::Duration;
 
 /// Returns sorted list of sellers, with [Online](Status::Online) listed first.
 ///
-/// First uses the rendezvous node to discover peers in the given namespace,
+/// First uses the rendezvous nodes to discover peers in the given namespace,
 /// then fetches a quote from each peer that was discovered. If fetching a quote
 /// from a discovered peer fails the seller's status will be
 /// [Unreachable](Status::Unreachable).
+///
+/// The rendezvous nodes are queried concurrently. A seller registered with
+/// several of them is only listed once, as online if any of the rendezvous
+/// nodes led to a quote.
 pub async fn list_sellers(
-    rendezvous_node_peer_id: PeerId,
-    rendezvous_node_addr: Multiaddr,
+    rendezvous_points: Vec<Multiaddr>,
     namespace: XmrBtcNamespace,
     tor_socks5_port: u16,
+    tor_only: bool,
     identity: identity::Keypair,
 ) -> Result<Vec<Seller>> {
+    let sellers_per_rendezvous_point =
+        future::try_join_all(rendezvous_points.into_iter().map(|rendezvous_point| {
+            list_sellers_of(
+                rendezvous_point,
+                namespace,
+                tor_socks5_port,
+                tor_only,
+                identity.clone(),
+            )
+        }))
+        .await?;
+
+    let mut sellers = HashMap::<PeerId, Seller>::new();
+    for (peer_id, seller) in sellers_per_rendezvous_point.into_iter().flatten() {
+        match sellers.entry(peer_id) {
+            Entry::Occupied(mut entry) => {
+                if seller < *entry.get() {
+                    entry.insert(seller);
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(seller);
+            }
+        }
+    }
+
+    let mut sellers = sellers.into_values().collect::<Vec<_>>();
+    sellers.sort();
+
+    Ok(sellers)
+}
+
+async fn list_sellers_of(
+    rendezvous_point: Multiaddr,
+    namespace: XmrBtcNamespace,
+    tor_socks5_port: u16,
+    tor_only: bool,
+    identity: identity::Keypair,
+) -> Result<HashMap<PeerId, Seller>> {
+    let rendezvous_node_peer_id = rendezvous_point
+        .extract_peer_id()
+        .context("Rendezvous node address must contain peer ID")?;
+
     let behaviour = Behaviour {
         rendezvous: rendezvous::client::Behaviour::new(identity.clone()),
         quote: quote::cli(),
@@ -36,22 +84,17 @@ pub async fn list_sellers(
                 .with_interval(Duration::from_secs(86_400)),
         ),
     };
-    let mut swarm = swarm::cli(identity, tor_socks5_port, behaviour).await?;
+    let mut swarm = swarm::cli(identity, tor_socks5_port, tor_only, behaviour).await?;
 
     swarm
         .behaviour_mut()
         .quote
-        .add_address(&rendezvous_node_peer_id, rendezvous_node_addr.clone());
+        .add_address(&rendezvous_node_peer_id, rendezvous_point.clone());
     swarm
         .dial(&rendezvous_node_peer_id)
         .context("Failed to dial rendezvous node")?;
 
-    let event_loop = EventLoop::new(
-        swarm,
-        rendezvous_node_peer_id,
-        rendezvous_node_addr,
-        namespace,
-    );
+    let event_loop = EventLoop::new(swarm, rendezvous_node_peer_id, rendezvous_point, namespace);
     let sellers = event_loop.run().await;
 
     Ok(sellers)
@@ -141,7 +184,7 @@ impl EventLoop {
         }
     }
 
-    async fn run(mut self) -> Vec<Seller> {
+    async fn run(mut self) -> HashMap<PeerId, Seller> {
         loop {
             tokio::select! {
                 swarm_event = self.swarm.select_next_some() => {
@@ -173,7 +216,7 @@ impl EventLoop {
                                 );
 
                                 // if the rendezvous node is unreachable we just stop
-                                return Vec::new();
+                                return HashMap::new();
                             } else {
                                 tracing::debug!(
                                     "Failed to connect to peer at {}: {}",
@@ -274,10 +317,10 @@ impl EventLoop {
                                     .get(peer_id)
                                     .expect("if we got a quote we must have stored an address");
 
-                                Ok(Seller {
+                                Ok((*peer_id, Seller {
                                     multiaddr: address.clone(),
                                     status: Status::Online(*quote),
-                                })
+                                }))
                             }
                             QuoteStatus::Received(Status::Unreachable) => {
                                 let address = self
@@ -285,17 +328,16 @@ impl EventLoop {
                                     .get(peer_id)
                                     .expect("if we got a quote we must have stored an address");
 
-                                Ok(Seller {
+                                Ok((*peer_id, Seller {
                                     multiaddr: address.clone(),
                                     status: Status::Unreachable,
-                                })
+                                }))
                             }
                         })
-                        .collect::<Result<Vec<_>, _>>();
+                        .collect::<Result<HashMap<_, _>, _>>();
 
                     match all_quotes_fetched {
-                        Ok(mut sellers) => {
-                            sellers.sort();
+                        Ok(sellers) => {
                             break sellers;
                         }
                         Err(StillPending {}) => continue,
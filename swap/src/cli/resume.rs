@@ -0,0 +1,69 @@
+use crate::asb::NoRate;
+use crate::cli::EventLoopHandle;
+use crate::protocol::event::SwapEvents;
+use crate::protocol::{alice, bob, Database, State};
+use crate::{bitcoin, env, monero};
+use anyhow::Result;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// A swap loaded from the database to be resumed, in the role we had in it.
+#[allow(missing_debug_implementations)]
+pub enum UnfinishedSwap {
+    /// A swap in which we sell XMR.
+    Alice(Box<alice::Swap>),
+    /// A swap in which we buy XMR.
+    Bob(Box<bob::Swap>),
+}
+
+impl UnfinishedSwap {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn load(
+        swap_id: Uuid,
+        db: Arc<dyn Database + Send + Sync>,
+        bitcoin_wallet: Arc<bitcoin::Wallet>,
+        monero_wallet: Arc<monero::Wallet>,
+        env_config: env::Config,
+        mut event_loop_handle: EventLoopHandle,
+        events: SwapEvents,
+    ) -> Result<Self> {
+        let swap = match db.get_state(swap_id).await? {
+            State::Alice(state) => UnfinishedSwap::Alice(Box::new(alice::Swap {
+                state,
+                event_loop_handle: event_loop_handle.alice_handle()?,
+                bitcoin_wallet,
+                monero_wallet,
+                env_config,
+                swap_id,
+                db,
+                events,
+            })),
+            State::Bob(_) => {
+                let monero_receive_address = db.get_monero_address(swap_id).await?;
+                let swap = bob::Swap::from_db(
+                    db,
+                    swap_id,
+                    bitcoin_wallet,
+                    monero_wallet,
+                    env_config,
+                    event_loop_handle,
+                    monero_receive_address,
+                )
+                .await?
+                .with_events(events);
+
+                UnfinishedSwap::Bob(Box::new(swap))
+            }
+        };
+
+        Ok(swap)
+    }
+
+    /// Run the swap until it is finished, returning the final state.
+    pub async fn run(self) -> Result<State> {
+        Ok(match self {
+            UnfinishedSwap::Alice(swap) => alice::run(*swap, NoRate).await?.into(),
+            UnfinishedSwap::Bob(swap) => bob::run(*swap).await?.into(),
+        })
+    }
+}
@@ -0,0 +1,512 @@
+//! A JSON-RPC server that keeps the wallets of the CLI open and runs swaps on
+//! request.
+//!
+//! Swaps are started in the background, the calls that start them return the
+//! swap id right away. Clients follow a swap by subscribing to
+//! `subscribe_swap_progress`. Only one swap runs at a time because all swaps
+//! share the same Monero wallet-rpc, and swaps can only be cancelled or
+//! refunded and Bitcoin only be withdrawn while none is running.
+//!
+//! Like the ASB, the server authenticates requests as described in
+//! [`crate::rpc`].
+
+use crate::bitcoin::TxLock;
+use crate::cli::command::{validate_bitcoin_address, validate_monero_address};
+use crate::cli::{
+    connect_to_seller, list_sellers, seller_directory, EventLoopHandle, Seller, UnfinishedSwap,
+};
+use crate::libp2p_ext::MultiAddrExt;
+use crate::network::quote::ZeroQuoteReceived;
+use crate::network::rendezvous::XmrBtcNamespace;
+use crate::protocol::event::{SwapEvent, SwapEvents};
+use crate::protocol::recovery::{cancel, refund};
+use crate::protocol::{bob, Database, State};
+use crate::rpc::{serve, to_rpc_error, Recovered, Swap, SwapIdParams};
+use crate::{bitcoin, env, monero};
+use ::bitcoin::Txid;
+use anyhow::{anyhow, bail, Context as _, Result};
+use futures::Future;
+use jsonrpsee::server::{RpcModule, ServerHandle};
+use libp2p::{identity, Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use serde_with::formats::PreferMany;
+use serde_with::{serde_as, DisplayFromStr, OneOrMany};
+use std::cmp::min;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+/// The wallets and network settings shared by all swaps of the daemon.
+#[allow(missing_debug_implementations)]
+pub struct Context {
+    db: Arc<dyn Database + Send + Sync>,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    monero_wallet: Arc<monero::Wallet>,
+    env_config: env::Config,
+    identity: identity::Keypair,
+    namespace: XmrBtcNamespace,
+    tor_socks5_port: u16,
+    tor_only: bool,
+    events: SwapEvents,
+    swap_lock: Arc<Mutex<()>>,
+}
+
+impl Context {
+    pub fn new(
+        db: Arc<dyn Database + Send + Sync>,
+        bitcoin_wallet: Arc<bitcoin::Wallet>,
+        monero_wallet: Arc<monero::Wallet>,
+        env_config: env::Config,
+        identity: identity::Keypair,
+        namespace: XmrBtcNamespace,
+        tor_socks5_port: u16,
+        tor_only: bool,
+    ) -> Self {
+        Self {
+            db,
+            bitcoin_wallet,
+            monero_wallet,
+            env_config,
+            identity,
+            namespace,
+            tor_socks5_port,
+            tor_only,
+            events: SwapEvents::default(),
+            swap_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    async fn buy_xmr(
+        &self,
+        seller: Multiaddr,
+        bitcoin_change_address: bitcoin::Address,
+        monero_receive_address: monero::Address,
+    ) -> Result<Uuid> {
+        let is_testnet = self.env_config.bitcoin_network != ::bitcoin::Network::Bitcoin;
+        let bitcoin_change_address = validate_bitcoin_address(bitcoin_change_address, is_testnet)?;
+        let monero_receive_address = validate_monero_address(monero_receive_address, is_testnet)?;
+
+        let guard = self.lock_swap()?;
+        let swap_id = Uuid::new_v4();
+
+        let seller_peer_id = seller
+            .extract_peer_id()
+            .context("Seller address must contain peer ID")?;
+        self.db.insert_address(seller_peer_id, seller).await?;
+
+        let (event_loop, mut event_loop_handle) =
+            self.spawn_event_loop(swap_id, seller_peer_id).await?;
+
+        let amount = match self.determine_btc_to_swap(&mut event_loop_handle).await {
+            Ok(amount) => amount,
+            Err(error) => {
+                event_loop.abort();
+                return Err(error);
+            }
+        };
+
+        tracing::info!(%swap_id, %amount, "Determined swap amount");
+
+        self.db.insert_peer_id(swap_id, seller_peer_id).await?;
+        self.db
+            .insert_monero_address(swap_id, monero_receive_address)
+            .await?;
+
+        let swap = bob::Swap::new(
+            self.db.clone(),
+            swap_id,
+            self.bitcoin_wallet.clone(),
+            self.monero_wallet.clone(),
+            self.env_config,
+            event_loop_handle,
+            monero_receive_address,
+            bitcoin_change_address,
+            amount,
+        )
+        .with_events(self.events.clone());
+        self.spawn_swap(swap_id, event_loop, bob::run(swap), guard);
+
+        Ok(swap_id)
+    }
+
+    async fn resume(&self, swap_id: Uuid) -> Result<()> {
+        let guard = self.lock_swap()?;
+
+        let state = self.db.get_state(swap_id).await?;
+        if state.swap_finished() {
+            bail!("Swap {} is already finished in state {}", swap_id, state)
+        }
+
+        let seller_peer_id = self.db.get_peer_id(swap_id).await?;
+        let (event_loop, event_loop_handle) =
+            self.spawn_event_loop(swap_id, seller_peer_id).await?;
+
+        let swap = UnfinishedSwap::load(
+            swap_id,
+            self.db.clone(),
+            self.bitcoin_wallet.clone(),
+            self.monero_wallet.clone(),
+            self.env_config,
+            event_loop_handle,
+            self.events.clone(),
+        )
+        .await;
+        let swap = match swap {
+            Ok(swap) => swap,
+            Err(error) => {
+                event_loop.abort();
+                return Err(error);
+            }
+        };
+        self.spawn_swap(swap_id, event_loop, swap.run(), guard);
+
+        Ok(())
+    }
+
+    /// Publish the cancel transaction of a swap, unless a swap is running.
+    async fn cancel(&self, swap_id: Uuid) -> Result<(Txid, State)> {
+        let _guard = self.lock_swap()?;
+
+        cancel(swap_id, self.bitcoin_wallet.clone(), self.db.clone()).await
+    }
+
+    /// Refund a swap, unless a swap is running.
+    async fn refund(&self, swap_id: Uuid) -> Result<State> {
+        let _guard = self.lock_swap()?;
+
+        refund(
+            swap_id,
+            self.bitcoin_wallet.clone(),
+            Some(self.monero_wallet.clone()),
+            self.db.clone(),
+        )
+        .await
+    }
+
+    /// Withdraw Bitcoin from the wallet, unless a swap is running.
+    ///
+    /// A running swap may be about to spend the same outputs, e.g. to lock
+    /// the Bitcoin of a swap that was just started.
+    async fn withdraw_btc(
+        &self,
+        address: bitcoin::Address,
+        amount: Option<bitcoin::Amount>,
+    ) -> Result<(Txid, bitcoin::Amount)> {
+        let _guard = self.lock_swap()?;
+
+        withdraw_btc(&self.bitcoin_wallet, self.env_config, address, amount).await
+    }
+
+    /// Non-interactive counterpart of the deposit loop of the `buy-xmr`
+    /// command: swaps whatever the wallet can give right now.
+    async fn determine_btc_to_swap(
+        &self,
+        event_loop_handle: &mut EventLoopHandle,
+    ) -> Result<bitcoin::Amount> {
+        let bid_quote = event_loop_handle.request_quote().await?;
+
+        if bid_quote.max_quantity == bitcoin::Amount::ZERO {
+            return Err(anyhow!(ZeroQuoteReceived).context(
+                "Seller's XMR balance is currently too low to initiate a swap, please try again later",
+            ));
+        }
+
+        self.bitcoin_wallet.sync().await?;
+        let max_giveable = self
+            .bitcoin_wallet
+            .max_giveable(TxLock::script_size())
+            .await?;
+
+        if max_giveable == bitcoin::Amount::ZERO || max_giveable < bid_quote.min_quantity {
+            let deposit_address = self.bitcoin_wallet.new_address().await?;
+
+            bail!(
+                "Insufficient funds, can give {} but the seller's minimum is {}. Deposit Bitcoin to {} and try again",
+                max_giveable,
+                bid_quote.min_quantity,
+                deposit_address
+            )
+        }
+
+        Ok(min(max_giveable, bid_quote.max_quantity))
+    }
+
+    async fn spawn_event_loop(
+        &self,
+        swap_id: Uuid,
+        seller_peer_id: PeerId,
+    ) -> Result<(JoinHandle<()>, EventLoopHandle)> {
+        let seller_addresses = self.db.get_addresses(seller_peer_id).await?;
+
+        let (event_loop, event_loop_handle) = connect_to_seller(
+            swap_id,
+            seller_peer_id,
+            seller_addresses,
+            self.env_config,
+            self.bitcoin_wallet.clone(),
+            self.identity.clone(),
+            self.tor_socks5_port,
+            self.tor_only,
+        )
+        .await?;
+
+        Ok((tokio::spawn(event_loop.run()), event_loop_handle))
+    }
+
+    fn lock_swap(&self) -> Result<OwnedMutexGuard<()>> {
+        self.swap_lock
+            .clone()
+            .try_lock_owned()
+            .map_err(|_| anyhow!("Another swap is already running"))
+    }
+
+    /// Run the swap in the background, holding `guard` until it stops.
+    fn spawn_swap<S>(
+        &self,
+        swap_id: Uuid,
+        mut event_loop: JoinHandle<()>,
+        swap: impl Future<Output = Result<S>> + Send + 'static,
+        guard: OwnedMutexGuard<()>,
+    ) where
+        S: Display + Send + 'static,
+    {
+        let events = self.events.clone();
+
+        tokio::spawn(async move {
+            let _guard = guard;
+
+            tokio::select! {
+                result = &mut event_loop => {
+                    let error = match result {
+                        Ok(()) => anyhow!("EventLoop stopped"),
+                        Err(error) => anyhow!(error).context("EventLoop panicked"),
+                    };
+                    tracing::error!(%swap_id, "Swap failed: {:#}", error);
+
+                    // The swap reports its own failures, but not the ones of the event loop
+                    events.send(SwapEvent::Failed {
+                        swap_id,
+                        error: format!("{:#}", error),
+                    });
+                },
+                result = swap => match result {
+                    Ok(state) => tracing::info!(%swap_id, %state, "Swap completed"),
+                    Err(error) => tracing::error!(%swap_id, "Swap failed: {:#}", error),
+                },
+            }
+            event_loop.abort();
+        });
+    }
+}
+
+/// Start the RPC server, returning the address it actually listens on.
+///
+/// The server stops once the returned [`ServerHandle`] is dropped.
+pub async fn run_server(
+    listen: SocketAddr,
+    token: &str,
+    context: Context,
+) -> Result<(SocketAddr, ServerHandle)> {
+    serve(listen, token, methods(context)?).await
+}
+
+fn methods(context: Context) -> Result<RpcModule<Context>> {
+    let mut module = RpcModule::new(context);
+
+    module.register_async_method("buy_xmr", |params, context| async move {
+        let BuyXmrParams {
+            seller,
+            bitcoin_change_address,
+            monero_receive_address,
+        } = params.parse()?;
+
+        let swap_id = context
+            .buy_xmr(seller, bitcoin_change_address, monero_receive_address)
+            .await
+            .map_err(to_rpc_error)?;
+
+        Ok(SwapIdParams { swap_id })
+    })?;
+
+    module.register_async_method("resume", |params, context| async move {
+        let SwapIdParams { swap_id } = params.parse()?;
+
+        context.resume(swap_id).await.map_err(to_rpc_error)?;
+
+        Ok(SwapIdParams { swap_id })
+    })?;
+
+    module.register_async_method("cancel", |params, context| async move {
+        let SwapIdParams { swap_id } = params.parse()?;
+
+        let (txid, state) = context.cancel(swap_id).await.map_err(to_rpc_error)?;
+
+        Ok(Recovered::new(Some(txid), state))
+    })?;
+
+    module.register_async_method("refund", |params, context| async move {
+        let SwapIdParams { swap_id } = params.parse()?;
+
+        let state = context.refund(swap_id).await.map_err(to_rpc_error)?;
+
+        Ok(Recovered::new(None, state))
+    })?;
+
+    module.register_async_method("list_sellers", |params, context| async move {
+        let ListSellersParams { rendezvous_points } = params.parse()?;
+
+        let sellers: Vec<Seller> = list_sellers(
+            rendezvous_points,
+            context.namespace,
+            context.tor_socks5_port,
+            context.tor_only,
+            context.identity.clone(),
+        )
+        .await
+        .map_err(to_rpc_error)?;
+        seller_directory::record(context.db.clone(), &sellers)
+            .await
+            .map_err(to_rpc_error)?;
+
+        Ok(sellers)
+    })?;
+
+    module.register_async_method("known_sellers", |_, context| async move {
+        let sellers = seller_directory::known_sellers(context.db.clone())
+            .await
+            .map_err(to_rpc_error)?;
+
+        Ok(sellers)
+    })?;
+
+    module.register_async_method("balance", |_, context| async move {
+        context.bitcoin_wallet.sync().await.map_err(to_rpc_error)?;
+
+        Ok(Balances {
+            bitcoin: context
+                .bitcoin_wallet
+                .balance()
+                .await
+                .map_err(to_rpc_error)?,
+            monero: context
+                .monero_wallet
+                .get_balance()
+                .await
+                .map_err(to_rpc_error)?,
+        })
+    })?;
+
+    module.register_async_method("history", |_, context| async move {
+        let swaps = context.db.all().await.map_err(to_rpc_error)?;
+
+        Ok(swaps
+            .into_iter()
+            .map(|(swap_id, state)| Swap::new(swap_id, state))
+            .collect::<Vec<_>>())
+    })?;
+
+    module.register_async_method("withdraw_btc", |params, context| async move {
+        let WithdrawBtcParams { address, amount } = params.parse()?;
+
+        let (txid, amount) = context
+            .withdraw_btc(address, amount)
+            .await
+            .map_err(to_rpc_error)?;
+
+        Ok(Withdrawn { txid, amount })
+    })?;
+
+    module.register_subscription(
+        "subscribe_swap_progress",
+        "swap_progress",
+        "unsubscribe_swap_progress",
+        |_, mut sink, context| {
+            sink.accept()?;
+
+            let events = context.events.stream();
+
+            tokio::spawn(async move {
+                let _ = sink.pipe_from_stream(events).await;
+            });
+
+            Ok(())
+        },
+    )?;
+
+    Ok(module)
+}
+
+async fn withdraw_btc(
+    bitcoin_wallet: &bitcoin::Wallet,
+    env_config: env::Config,
+    address: bitcoin::Address,
+    amount: Option<bitcoin::Amount>,
+) -> Result<(Txid, bitcoin::Amount)> {
+    if address.network != env_config.bitcoin_network {
+        bail!(
+            "Invalid Bitcoin address provided; expected network {} but provided address is for {}",
+            env_config.bitcoin_network,
+            address.network
+        )
+    }
+
+    let amount = match amount {
+        Some(amount) => amount,
+        None => {
+            bitcoin_wallet
+                .max_giveable(address.script_pubkey().len())
+                .await?
+        }
+    };
+
+    let psbt = bitcoin_wallet
+        .send_to_address(address, amount, None)
+        .await?;
+    let signed_tx = bitcoin_wallet.sign_and_finalize(psbt).await?;
+
+    let (txid, _) = bitcoin_wallet.broadcast(signed_tx, "withdraw").await?;
+
+    Ok((txid, amount))
+}
+
+#[serde_as]
+#[derive(Deserialize)]
+struct BuyXmrParams {
+    seller: Multiaddr,
+    bitcoin_change_address: bitcoin::Address,
+    #[serde_as(as = "DisplayFromStr")]
+    monero_receive_address: monero::Address,
+}
+
+#[serde_as]
+#[derive(Deserialize)]
+struct ListSellersParams {
+    #[serde_as(as = "OneOrMany<_, PreferMany>")]
+    #[serde(rename = "rendezvous_point")]
+    rendezvous_points: Vec<Multiaddr>,
+}
+
+#[derive(Deserialize)]
+struct WithdrawBtcParams {
+    address: bitcoin::Address,
+    #[serde(default, with = "::bitcoin::util::amount::serde::as_sat::opt")]
+    amount: Option<bitcoin::Amount>,
+}
+
+#[derive(Serialize)]
+struct Balances {
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+    bitcoin: bitcoin::Amount,
+    #[serde(with = "crate::monero::monero_amount")]
+    monero: monero::Amount,
+}
+
+#[derive(Serialize)]
+struct Withdrawn {
+    txid: Txid,
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+    amount: bitcoin::Amount,
+}
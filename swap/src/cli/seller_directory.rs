@@ -0,0 +1,420 @@
+use crate::bitcoin;
+use crate::cli::list_sellers::{Seller, Status};
+use crate::libp2p_ext::MultiAddrExt;
+use crate::network::quote::BidQuote;
+use crate::network::{quote, swarm};
+use crate::protocol::bob::BobState;
+use crate::protocol::{Database, State};
+use anyhow::{bail, Context, Result};
+use futures::StreamExt;
+use libp2p::request_response::{RequestResponseEvent, RequestResponseMessage};
+use libp2p::swarm::SwarmEvent;
+use libp2p::{identity, Multiaddr, PeerId};
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+/// A seller we listed at least once, with its history.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct KnownSeller {
+    #[serde_as(as = "DisplayFromStr")]
+    pub peer_id: PeerId,
+    /// The address the seller was last listed with.
+    #[serde_as(as = "DisplayFromStr")]
+    pub multiaddr: Multiaddr,
+    /// The status of the seller when it was last listed.
+    pub status: Status,
+    pub times_listed: u64,
+    pub times_reachable: u64,
+    pub swaps_redeemed: u64,
+    pub swaps_refunded: u64,
+    /// Doesn't affect the rank, we are only punished if we miss our own refund
+    /// window, which says nothing about the seller.
+    pub swaps_punished: u64,
+}
+
+impl KnownSeller {
+    fn new(peer_id: PeerId, multiaddr: Multiaddr) -> Self {
+        Self {
+            peer_id,
+            multiaddr,
+            status: Status::Unreachable,
+            times_listed: 0,
+            times_reachable: 0,
+            swaps_redeemed: 0,
+            swaps_refunded: 0,
+            swaps_punished: 0,
+        }
+    }
+
+    /// Whether the seller was online and bought Bitcoin when last listed.
+    ///
+    /// A seller quoting no quantities, e.g. because its price is outdated, is
+    /// online but doesn't take swaps.
+    pub fn is_buying(&self) -> bool {
+        match self.status {
+            Status::Online(quote) => buys_bitcoin(&quote),
+            Status::Unreachable => false,
+        }
+    }
+
+    /// The status shown by `list-sellers`.
+    pub fn status_label(&self) -> &'static str {
+        match self.status {
+            Status::Online(_) if self.is_buying() => "Online",
+            Status::Online(_) => "Not buying",
+            Status::Unreachable => "Unreachable",
+        }
+    }
+
+    /// The share of redeemed and refunded swaps that were redeemed, as a
+    /// fraction.
+    ///
+    /// Counts one redeemed and one refunded swap on top, so a seller we never
+    /// swapped with sits between a seller that always delivered and one that
+    /// never did.
+    fn success_rate(&self) -> (u64, u64) {
+        let finished = self.swaps_redeemed + self.swaps_refunded;
+
+        (self.swaps_redeemed + 1, finished + 2)
+    }
+
+    /// The share of listings the seller answered with a quote, as a fraction.
+    fn reachability(&self) -> (u64, u64) {
+        (self.times_reachable, self.times_listed.max(1))
+    }
+
+    fn price(&self) -> Option<bitcoin::Amount> {
+        match self.status {
+            Status::Online(BidQuote { price, .. }) => Some(price),
+            Status::Unreachable => None,
+        }
+    }
+}
+
+/// How a swap with a seller ended, from the latest state in the database.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    Redeemed,
+    Refunded,
+    Punished,
+}
+
+impl Outcome {
+    fn of(state: &State) -> Option<Self> {
+        match state {
+            State::Bob(BobState::XmrRedeemed { .. }) => Some(Outcome::Redeemed),
+            State::Bob(BobState::BtcRefunded(..)) => Some(Outcome::Refunded),
+            State::Bob(BobState::BtcPunished { .. }) => Some(Outcome::Punished),
+            _ => None,
+        }
+    }
+}
+
+/// Stores the sellers of a listing, so they count towards their history.
+pub async fn record(db: Arc<dyn Database + Send + Sync>, sellers: &[Seller]) -> Result<()> {
+    for seller in sellers {
+        let peer_id = match seller.multiaddr.extract_peer_id() {
+            Some(peer_id) => peer_id,
+            None => {
+                tracing::debug!(address = %seller.multiaddr, "Not recording seller without peer ID");
+                continue;
+            }
+        };
+        let quote = match seller.status {
+            Status::Online(quote) => Some(quote),
+            Status::Unreachable => None,
+        };
+
+        db.insert_seller_observation(peer_id, seller.multiaddr.clone(), quote)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Returns all sellers we ever listed, the best ranked first.
+///
+/// Sellers that were online and buying when last listed come first. Among
+/// those, sellers with a higher share of redeemed swaps rank higher, then
+/// sellers that answered more of our listings, then sellers with a lower price.
+pub async fn known_sellers(db: Arc<dyn Database + Send + Sync>) -> Result<Vec<KnownSeller>> {
+    let mut sellers = HashMap::<PeerId, KnownSeller>::new();
+
+    for (peer_id, address, quote) in db.all_seller_observations().await? {
+        let seller = sellers
+            .entry(peer_id)
+            .or_insert_with(|| KnownSeller::new(peer_id, address.clone()));
+
+        seller.multiaddr = address;
+        seller.times_listed += 1;
+        seller.status = match quote {
+            Some(quote) => {
+                seller.times_reachable += 1;
+                Status::Online(quote)
+            }
+            None => Status::Unreachable,
+        };
+    }
+
+    for (swap_id, state) in db.all().await? {
+        let outcome = match Outcome::of(&state) {
+            Some(outcome) => outcome,
+            None => continue,
+        };
+        let peer_id = db.get_peer_id(swap_id).await?;
+
+        // swaps with sellers we never listed don't say anything about the sellers we
+        // know
+        if let Some(seller) = sellers.get_mut(&peer_id) {
+            match outcome {
+                Outcome::Redeemed => seller.swaps_redeemed += 1,
+                Outcome::Refunded => seller.swaps_refunded += 1,
+                Outcome::Punished => seller.swaps_punished += 1,
+            }
+        }
+    }
+
+    let mut sellers = sellers.into_values().collect::<Vec<_>>();
+    sellers.sort_by(rank);
+
+    Ok(sellers)
+}
+
+/// Returns the address of the best ranked seller that is online and buys
+/// Bitcoin in an amount we can give.
+///
+/// Only sellers that were online and buying when last listed are considered.
+/// Their quotes may be outdated, so a fresh quote is requested from each of
+/// them in the order of their rank until one accepts a swap.
+pub async fn best_seller<F, Fut>(
+    db: Arc<dyn Database + Send + Sync>,
+    max_giveable: bitcoin::Amount,
+    request_quote: F,
+) -> Result<Multiaddr>
+where
+    F: Fn(Multiaddr) -> Fut,
+    Fut: Future<Output = Result<BidQuote>>,
+{
+    let sellers = known_sellers(db)
+        .await?
+        .into_iter()
+        .filter(KnownSeller::is_buying)
+        .collect::<Vec<_>>();
+
+    if sellers.is_empty() {
+        bail!("None of the known sellers was online and buying when last listed, run `list-sellers` to discover sellers")
+    }
+
+    for seller in sellers {
+        let quote = match request_quote(seller.multiaddr.clone()).await {
+            Ok(quote) => quote,
+            Err(error) => {
+                tracing::debug!(address = %seller.multiaddr, "Skipping seller: {:#}", error);
+                continue;
+            }
+        };
+
+        if !accepts_swap(&quote, max_giveable) {
+            tracing::debug!(address = %seller.multiaddr, ?quote, %max_giveable, "Skipping seller that does not buy the amount we can give");
+            continue;
+        }
+
+        return Ok(seller.multiaddr);
+    }
+
+    bail!("None of the known sellers is online and buys the amount of Bitcoin we can give, run `list-sellers` to discover sellers")
+}
+
+/// Requests a quote from the seller at the given address.
+pub async fn request_quote(
+    seller: Multiaddr,
+    identity: identity::Keypair,
+    tor_socks5_port: u16,
+    tor_only: bool,
+) -> Result<BidQuote> {
+    let seller_peer_id = seller
+        .extract_peer_id()
+        .context("Seller address must contain peer ID")?;
+
+    let mut swarm = swarm::cli(identity, tor_socks5_port, tor_only, quote::cli()).await?;
+    swarm
+        .behaviour_mut()
+        .add_address(&seller_peer_id, seller.clone());
+    let quote_request = swarm.behaviour_mut().send_request(&seller_peer_id, ());
+
+    // the request is answered or times out, dialing the seller included
+    loop {
+        match swarm.select_next_some().await {
+            SwarmEvent::Behaviour(RequestResponseEvent::Message {
+                message:
+                    RequestResponseMessage::Response {
+                        request_id,
+                        response,
+                    },
+                ..
+            }) if request_id == quote_request => return Ok(response),
+            SwarmEvent::Behaviour(RequestResponseEvent::OutboundFailure {
+                request_id,
+                error,
+                ..
+            }) if request_id == quote_request => {
+                return Err(error)
+                    .with_context(|| format!("Failed to request quote from {}", seller))
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whether a seller with this quote takes a swap of at most `max_giveable`.
+///
+/// With an empty wallet we don't know how much will be deposited, so any
+/// seller that buys Bitcoin at all takes the swap.
+fn accepts_swap(quote: &BidQuote, max_giveable: bitcoin::Amount) -> bool {
+    buys_bitcoin(quote)
+        && (max_giveable == bitcoin::Amount::ZERO || quote.min_quantity <= max_giveable)
+}
+
+/// Whether a seller with this quote takes swaps at all.
+fn buys_bitcoin(quote: &BidQuote) -> bool {
+    quote.max_quantity > bitcoin::Amount::ZERO && quote.min_quantity <= quote.max_quantity
+}
+
+fn rank(a: &KnownSeller, b: &KnownSeller) -> Ordering {
+    b.is_buying()
+        .cmp(&a.is_buying())
+        .then_with(|| compare_fractions(b.success_rate(), a.success_rate()))
+        .then_with(|| compare_fractions(b.reachability(), a.reachability()))
+        .then_with(|| a.price().cmp(&b.price()))
+        .then_with(|| a.multiaddr.to_string().cmp(&b.multiaddr.to_string()))
+}
+
+fn compare_fractions(
+    (a_numerator, a_denominator): (u64, u64),
+    (b_numerator, b_denominator): (u64, u64),
+) -> Ordering {
+    (a_numerator as u128 * b_denominator as u128)
+        .cmp(&(b_numerator as u128 * a_denominator as u128))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seller(address: &str, status: Status) -> KnownSeller {
+        KnownSeller {
+            status,
+            times_listed: 1,
+            times_reachable: matches!(status, Status::Online(_)) as u64,
+            ..KnownSeller::new(PeerId::random(), address.parse().unwrap())
+        }
+    }
+
+    fn online(price: u64) -> Status {
+        Status::Online(BidQuote {
+            price: bitcoin::Amount::from_sat(price),
+            min_quantity: bitcoin::Amount::ZERO,
+            max_quantity: bitcoin::Amount::from_sat(100_000),
+        })
+    }
+
+    #[test]
+    fn online_sellers_rank_before_unreachable_ones() {
+        let unreachable = seller("/ip4/127.0.0.1/tcp/1", Status::Unreachable);
+        let online = seller("/ip4/127.0.0.1/tcp/2", online(1000));
+
+        let mut sellers = vec![unreachable.clone(), online.clone()];
+        sellers.sort_by(rank);
+
+        assert_eq!(sellers, vec![online, unreachable]);
+    }
+
+    #[test]
+    fn sellers_quoting_no_quantities_rank_after_buying_ones() {
+        let not_buying = seller(
+            "/ip4/127.0.0.1/tcp/1",
+            Status::Online(BidQuote {
+                price: bitcoin::Amount::from_sat(500),
+                min_quantity: bitcoin::Amount::ZERO,
+                max_quantity: bitcoin::Amount::ZERO,
+            }),
+        );
+        let buying = seller("/ip4/127.0.0.1/tcp/2", online(1000));
+
+        let mut sellers = vec![not_buying.clone(), buying.clone()];
+        sellers.sort_by(rank);
+
+        assert!(!not_buying.is_buying());
+        assert_eq!(sellers, vec![buying, not_buying]);
+    }
+
+    #[test]
+    fn sellers_with_refunded_swaps_rank_after_cheaper_ones_without() {
+        let mut refunded = seller("/ip4/127.0.0.1/tcp/1", online(1000));
+        refunded.swaps_refunded = 1;
+        let new = seller("/ip4/127.0.0.1/tcp/2", online(2000));
+        let mut redeemed = seller("/ip4/127.0.0.1/tcp/3", online(3000));
+        redeemed.swaps_redeemed = 2;
+
+        let mut sellers = vec![refunded.clone(), new.clone(), redeemed.clone()];
+        sellers.sort_by(rank);
+
+        assert_eq!(sellers, vec![redeemed, new, refunded]);
+    }
+
+    #[test]
+    fn punished_swaps_do_not_count_against_the_seller() {
+        let punished = State::Bob(BobState::BtcPunished {
+            tx_lock_id: bitcoin::Txid::default(),
+        });
+        assert_eq!(Outcome::of(&punished), Some(Outcome::Punished));
+
+        let mut punished = seller("/ip4/127.0.0.1/tcp/1", online(1000));
+        punished.swaps_punished = 3;
+        let expensive = seller("/ip4/127.0.0.1/tcp/2", online(2000));
+
+        let mut sellers = vec![expensive.clone(), punished.clone()];
+        sellers.sort_by(rank);
+
+        assert_eq!(sellers, vec![punished, expensive]);
+    }
+
+    #[test]
+    fn seller_takes_a_swap_within_its_quantities() {
+        let quote = |min, max| BidQuote {
+            price: bitcoin::Amount::from_sat(1000),
+            min_quantity: bitcoin::Amount::from_sat(min),
+            max_quantity: bitcoin::Amount::from_sat(max),
+        };
+        let btc = bitcoin::Amount::from_sat;
+
+        assert!(accepts_swap(&quote(1000, 5000), btc(1000)));
+        assert!(accepts_swap(&quote(1000, 5000), btc(10_000)));
+        assert!(!accepts_swap(&quote(1000, 5000), btc(999)));
+        assert!(!accepts_swap(&quote(0, 0), btc(1000)));
+        assert!(!accepts_swap(&quote(5000, 1000), btc(10_000)));
+
+        // the deposit is still to come
+        assert!(accepts_swap(&quote(1000, 5000), btc(0)));
+        assert!(!accepts_swap(&quote(0, 0), btc(0)));
+    }
+
+    #[test]
+    fn given_same_history_reachable_and_cheap_sellers_rank_first() {
+        let mut flaky = seller("/ip4/127.0.0.1/tcp/1", online(1000));
+        flaky.times_listed = 4;
+        flaky.times_reachable = 2;
+        let expensive = seller("/ip4/127.0.0.1/tcp/2", online(2000));
+        let cheap = seller("/ip4/127.0.0.1/tcp/3", online(1500));
+
+        let mut sellers = vec![flaky.clone(), expensive.clone(), cheap.clone()];
+        sellers.sort_by(rank);
+
+        assert_eq!(sellers, vec![cheap, expensive, flaky]);
+    }
+}
@@ -30,3 +30,20 @@ pub fn new(
 
     authenticate_and_multiplex(transport, identity)
 }
+
+/// Creates the libp2p transport for the swap CLI that connects to everyone
+/// through Tor.
+///
+/// Only addresses on this machine, which Tor refuses to connect to, are dialed
+/// directly. DNS names are resolved by Tor, never by the system resolver.
+pub fn new_tor_only(
+    identity: &identity::Keypair,
+    tor_socks5_port: u16,
+) -> Result<Boxed<(PeerId, StreamMuxerBox)>> {
+    let tcp = TokioTcpConfig::new().nodelay(true);
+    let transport = TorDialOnlyTransport::new(tor_socks5_port)
+        .or_transport(tcp)
+        .boxed();
+
+    authenticate_and_multiplex(transport, identity)
+}
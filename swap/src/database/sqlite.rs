@@ -1,6 +1,8 @@
 use crate::database::Swap;
 use crate::monero::Address;
+use crate::network::quote::BidQuote;
 use crate::protocol::{Database, State};
+use crate::{bitcoin, monero};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use libp2p::{Multiaddr, PeerId};
@@ -249,6 +251,139 @@ impl Database for SqliteDatabase {
 
         result
     }
+
+    async fn insert_monero_reservation(&self, swap_id: Uuid, amount: monero::Amount) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+
+        let swap_id = swap_id.to_string();
+        let amount = amount.as_piconero().to_string();
+
+        sqlx::query!(
+            r#"
+        insert or replace into monero_reservations (
+            swap_id,
+            amount
+            ) values (?, ?);
+        "#,
+            swap_id,
+            amount
+        )
+        .execute(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn remove_monero_reservation(&self, swap_id: Uuid) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+
+        let swap_id = swap_id.to_string();
+
+        sqlx::query!(
+            r#"
+        DELETE FROM monero_reservations
+        WHERE swap_id = ?
+        "#,
+            swap_id
+        )
+        .execute(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn all_monero_reservations(&self) -> Result<Vec<(Uuid, monero::Amount)>> {
+        let mut conn = self.pool.acquire().await?;
+
+        let rows = sqlx::query!(
+            r#"
+        SELECT swap_id, amount
+        FROM monero_reservations
+        "#
+        )
+        .fetch_all(&mut conn)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let swap_id = Uuid::from_str(&row.swap_id)?;
+                let amount = monero::Amount::from_piconero(row.amount.parse()?);
+
+                Ok((swap_id, amount))
+            })
+            .collect()
+    }
+
+    async fn insert_seller_observation(
+        &self,
+        peer_id: PeerId,
+        address: Multiaddr,
+        quote: Option<BidQuote>,
+    ) -> Result<()> {
+        let mut conn = self.pool.acquire().await?;
+        let observed_at = OffsetDateTime::now_utc();
+
+        let peer_id = peer_id.to_string();
+        let address = address.to_string();
+        let observed_at = observed_at.to_string();
+        let price = quote.map(|quote| quote.price.as_sat().to_string());
+        let min_quantity = quote.map(|quote| quote.min_quantity.as_sat().to_string());
+        let max_quantity = quote.map(|quote| quote.max_quantity.as_sat().to_string());
+
+        sqlx::query!(
+            r#"
+        insert into seller_observations (
+            peer_id,
+            address,
+            observed_at,
+            price,
+            min_quantity,
+            max_quantity
+            ) values (?, ?, ?, ?, ?, ?);
+        "#,
+            peer_id,
+            address,
+            observed_at,
+            price,
+            min_quantity,
+            max_quantity
+        )
+        .execute(&mut conn)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn all_seller_observations(&self) -> Result<Vec<(PeerId, Multiaddr, Option<BidQuote>)>> {
+        let mut conn = self.pool.acquire().await?;
+
+        let rows = sqlx::query!(
+            r#"
+        SELECT peer_id, address, price, min_quantity, max_quantity
+        FROM seller_observations
+        ORDER BY id
+        "#
+        )
+        .fetch_all(&mut conn)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let peer_id = PeerId::from_str(&row.peer_id)?;
+                let address = Multiaddr::from_str(&row.address)?;
+                let quote = match (&row.price, &row.min_quantity, &row.max_quantity) {
+                    (Some(price), Some(min_quantity), Some(max_quantity)) => Some(BidQuote {
+                        price: bitcoin::Amount::from_sat(price.parse()?),
+                        min_quantity: bitcoin::Amount::from_sat(min_quantity.parse()?),
+                        max_quantity: bitcoin::Amount::from_sat(max_quantity.parse()?),
+                    }),
+                    _ => None,
+                };
+
+                Ok((peer_id, address, quote))
+            })
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -370,6 +505,53 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_insert_and_remove_monero_reservations() -> Result<()> {
+        let db = setup_test_db().await?;
+
+        let swap_id_1 = Uuid::new_v4();
+        let swap_id_2 = Uuid::new_v4();
+        let amount_1 = monero::Amount::from_piconero(1_000_000_000_000);
+        let amount_2 = monero::Amount::from_piconero(2_500_000_000_000);
+
+        db.insert_monero_reservation(swap_id_1, amount_1).await?;
+        db.insert_monero_reservation(swap_id_2, amount_2).await?;
+        db.remove_monero_reservation(swap_id_1).await?;
+
+        let reservations = db.all_monero_reservations().await?;
+
+        assert_eq!(reservations, vec![(swap_id_2, amount_2)]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_insert_and_load_seller_observations() -> Result<()> {
+        let db = setup_test_db().await?;
+
+        let peer_id = PeerId::random();
+        let address = "/ip4/127.0.0.1/tcp/9939".parse::<Multiaddr>()?;
+        let quote = BidQuote {
+            price: bitcoin::Amount::from_sat(1337),
+            min_quantity: bitcoin::Amount::from_sat(42),
+            max_quantity: bitcoin::Amount::from_sat(9001),
+        };
+
+        db.insert_seller_observation(peer_id, address.clone(), Some(quote))
+            .await?;
+        db.insert_seller_observation(peer_id, address.clone(), None)
+            .await?;
+
+        let observations = db.all_seller_observations().await?;
+
+        assert_eq!(observations, vec![
+            (peer_id, address.clone(), Some(quote)),
+            (peer_id, address, None)
+        ]);
+
+        Ok(())
+    }
+
     async fn setup_test_db() -> Result<SqliteDatabase> {
         let temp_db = tempdir().unwrap().into_path().join("tempdb");
 
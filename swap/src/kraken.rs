@@ -1,10 +1,8 @@
-use anyhow::{anyhow, Context, Result};
-use futures::{SinkExt, StreamExt, TryStreamExt};
+use crate::price_feed::{connect_websocket, spawn, FeedEvent, PriceUpdates, Ticker, TickerStream};
+use anyhow::{Context, Result};
+use futures::{FutureExt, SinkExt, StreamExt, TryStreamExt};
 use serde::Deserialize;
-use std::convert::{Infallible, TryFrom};
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::watch;
+use std::convert::TryFrom;
 use url::Url;
 
 /// Connect to Kraken websocket API for a constant stream of rate updates.
@@ -14,93 +12,24 @@ use url::Url;
 /// price_ticker_ws_url must point to a websocket server that follows the kraken
 /// price ticker protocol
 /// See: https://docs.kraken.com/websockets/
-pub fn connect(price_ticker_ws_url: Url) -> Result<PriceUpdates> {
-    let (price_update, price_update_receiver) = watch::channel(Err(Error::NotYetAvailable));
-    let price_update = Arc::new(price_update);
-
-    tokio::spawn(async move {
-        // The default backoff config is fine for us apart from one thing:
-        // `max_elapsed_time`. If we don't get an error within this timeframe,
-        // backoff won't actually retry the operation.
-        let backoff = backoff::ExponentialBackoff {
-            max_elapsed_time: None,
-            ..backoff::ExponentialBackoff::default()
-        };
-
-        let result = backoff::future::retry_notify::<Infallible, _, _, _, _, _>(
-            backoff,
-            || {
-                let price_update = price_update.clone();
-                let price_ticker_ws_url = price_ticker_ws_url.clone();
-                async move {
-                    let mut stream = connection::new(price_ticker_ws_url).await?;
-
-                    while let Some(update) = stream.try_next().await.map_err(to_backoff)? {
-                        let send_result = price_update.send(Ok(update));
-
-                        if send_result.is_err() {
-                            return Err(backoff::Error::Permanent(anyhow!(
-                                "receiver disconnected"
-                            )));
-                        }
-                    }
-
-                    Err(backoff::Error::transient(anyhow!("stream ended")))
-                }
-            },
-            |error, next: Duration| {
-                tracing::info!(
-                    "Kraken websocket connection failed, retrying in {}ms. Error {:#}",
-                    next.as_millis(),
-                    error
-                );
-            },
-        )
-        .await;
+pub fn connect(price_ticker_ws_url: Url, tor_socks5_port: Option<u16>) -> Result<PriceUpdates> {
+    let price_updates = spawn("kraken", move || {
+        let price_ticker_ws_url = price_ticker_ws_url.clone();
 
-        match result {
-            Err(e) => {
-                tracing::warn!("Rate updates incurred an unrecoverable error: {:#}", e);
+        async move {
+            let stream: TickerStream = connection::new(price_ticker_ws_url, tor_socks5_port)
+                .await?
+                .map_err(to_backoff)
+                .boxed();
 
-                // in case the retries fail permanently, let the subscribers know
-                price_update.send(Err(Error::PermanentFailure))
-            }
-            Ok(never) => match never {},
+            Ok::<_, backoff::Error<anyhow::Error>>(stream)
         }
+        .boxed()
     });
 
-    Ok(PriceUpdates {
-        inner: price_update_receiver,
-    })
-}
-
-#[derive(Clone, Debug)]
-pub struct PriceUpdates {
-    inner: watch::Receiver<PriceUpdate>,
+    Ok(price_updates)
 }
 
-impl PriceUpdates {
-    pub async fn wait_for_next_update(&mut self) -> Result<PriceUpdate> {
-        self.inner.changed().await?;
-
-        Ok(self.inner.borrow().clone())
-    }
-
-    pub fn latest_update(&mut self) -> PriceUpdate {
-        self.inner.borrow().clone()
-    }
-}
-
-#[derive(Clone, Debug, thiserror::Error)]
-pub enum Error {
-    #[error("Rate is not yet available")]
-    NotYetAvailable,
-    #[error("Permanently failed to retrieve rate from Kraken")]
-    PermanentFailure,
-}
-
-type PriceUpdate = Result<wire::PriceUpdate, Error>;
-
 /// Maps a [`connection::Error`] to a backoff error, effectively defining our
 /// retry strategy.
 fn to_backoff(e: connection::Error) -> backoff::Error<anyhow::Error> {
@@ -129,8 +58,11 @@ mod connection {
     use futures::stream::BoxStream;
     use tokio_tungstenite::tungstenite;
 
-    pub async fn new(ws_url: Url) -> Result<BoxStream<'static, Result<wire::PriceUpdate, Error>>> {
-        let (mut rate_stream, _) = tokio_tungstenite::connect_async(ws_url)
+    pub async fn new(
+        ws_url: Url,
+        tor_socks5_port: Option<u16>,
+    ) -> Result<BoxStream<'static, Result<FeedEvent, Error>>> {
+        let mut rate_stream = connect_websocket(ws_url, tor_socks5_port)
             .await
             .context("Failed to connect to Kraken websocket API")?;
 
@@ -143,12 +75,12 @@ mod connection {
         Ok(stream)
     }
 
-    /// Parse a websocket message into a [`wire::PriceUpdate`].
+    /// Parse a websocket message into a [`FeedEvent`].
     ///
-    /// Messages which are not actually ticker updates are ignored and result in
-    /// `None` being returned. In the context of a [`TryStream`], these will
-    /// simply be filtered out.
-    async fn parse_message(msg: tungstenite::Message) -> Result<Option<wire::PriceUpdate>, Error> {
+    /// Messages which are neither ticker updates nor heartbeats are ignored and
+    /// result in `None` being returned. In the context of a [`TryStream`],
+    /// these will simply be filtered out.
+    async fn parse_message(msg: tungstenite::Message) -> Result<Option<FeedEvent>, Error> {
         let msg = match msg {
             tungstenite::Message::Text(msg) => msg,
             tungstenite::Message::Close(close_frame) => {
@@ -188,7 +120,7 @@ mod connection {
             Ok(wire::Event::Heartbeat) => {
                 tracing::trace!("Received heartbeat message");
 
-                return Ok(None);
+                return Ok(Some(FeedEvent::Heartbeat));
             }
             // if the message is not an event, it is a ticker update or an unknown event
             Err(_) => match serde_json::from_str::<wire::PriceUpdate>(&msg) {
@@ -200,7 +132,9 @@ mod connection {
             },
         };
 
-        Ok(Some(update))
+        let ticker = Ticker::new(update.ask).with_volume(update.volume);
+
+        Ok(Some(FeedEvent::Ticker(ticker)))
     }
 
     #[derive(Debug, thiserror::Error)]
@@ -228,7 +162,9 @@ mod connection {
 mod wire {
     use super::*;
     use bitcoin::util::amount::ParseAmountError;
+    use rust_decimal::Decimal;
     use serde_json::Value;
+    use std::str::FromStr;
 
     #[derive(Debug, Deserialize, PartialEq)]
     #[serde(tag = "event")]
@@ -258,6 +194,7 @@ mod wire {
     #[serde(try_from = "TickerUpdate")]
     pub struct PriceUpdate {
         pub ask: bitcoin::Amount,
+        pub volume: Option<Decimal>,
     }
 
     #[derive(Debug, Deserialize)]
@@ -275,6 +212,9 @@ mod wire {
     pub struct TickerData {
         #[serde(rename = "a")]
         ask: Vec<RateElement>,
+        /// Volume of today and of the last 24 hours.
+        #[serde(rename = "v", default)]
+        volume: Vec<RateElement>,
     }
 
     #[derive(Debug, Deserialize)]
@@ -303,8 +243,12 @@ mod wire {
                 }
                 _ => return Err(Error::UnexpectedAskRateElementType),
             };
+            let volume = match data.volume.get(1) {
+                Some(RateElement::Text(volume)) => Decimal::from_str(volume).ok(),
+                _ => None,
+            };
 
-            Ok(PriceUpdate { ask })
+            Ok(PriceUpdate { ask, volume })
         }
     }
 
@@ -336,5 +280,60 @@ mod wire {
 
             let _ = serde_json::from_str::<TickerUpdate>(message).unwrap();
         }
+
+        #[test]
+        fn parses_ask_and_volume_from_ticker_update() {
+            let message = r#"[980,{"a":["0.00440700",7,"7.35318535"],"b":["0.00440200",7,"7.57416678"],"c":["0.00440700","0.22579000"],"v":["273.75489000","4049.91233351"],"p":["0.00446205","0.00441699"],"t":[123,1310],"l":["0.00439400","0.00429900"],"h":["0.00450000","0.00450000"],"o":["0.00449100","0.00433700"]},"ticker","XMR/XBT"]"#;
+
+            let update = serde_json::from_str::<PriceUpdate>(message).unwrap();
+
+            assert_eq!(update.ask, bitcoin::Amount::from_sat(440_700));
+            assert_eq!(
+                update.volume,
+                Some(Decimal::from_str("4049.91233351").unwrap())
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server;
+    use rust_decimal::Decimal;
+    use std::str::FromStr;
+    use tokio_tungstenite::tungstenite::Message;
+
+    #[tokio::test]
+    async fn receives_ticker_from_mock_server() {
+        let address = mock_server::websocket(|mut ws| async move {
+            ws.send(Message::Text(
+                r#"{"connectionID":1,"event":"systemStatus","status":"online","version":"1.8.1"}"#
+                    .to_owned(),
+            ))
+            .await
+            .unwrap();
+            let _subscribe = ws.next().await.unwrap().unwrap();
+            ws.send(Message::Text(r#"{"channelID":980,"channelName":"ticker","event":"subscriptionStatus","pair":"XMR/XBT","status":"subscribed","subscription":{"name":"ticker"}}"#.to_owned()))
+                .await
+                .unwrap();
+            ws.send(Message::Text(r#"[980,{"a":["0.00440700",7,"7.35318535"],"b":["0.00440200",7,"7.57416678"],"c":["0.00440700","0.22579000"],"v":["273.75489000","4049.91233351"],"p":["0.00446205","0.00441699"],"t":[123,1310],"l":["0.00439400","0.00429900"],"h":["0.00450000","0.00450000"],"o":["0.00449100","0.00433700"]},"ticker","XMR/XBT"]"#.to_owned()))
+                .await
+                .unwrap();
+
+            futures::future::pending::<()>().await;
+        })
+        .await;
+
+        let mut price_updates =
+            connect(format!("ws://{}", address).parse().unwrap(), None).unwrap();
+
+        let ticker = price_updates.wait_for_next_update().await.unwrap().unwrap();
+
+        assert_eq!(ticker.ask, bitcoin::Amount::from_sat(440_700));
+        assert_eq!(
+            ticker.volume,
+            Some(Decimal::from_str("4049.91233351").unwrap())
+        );
     }
 }
@@ -26,12 +26,16 @@ pub mod kraken;
 pub mod libp2p_ext;
 pub mod monero;
 pub mod network;
+pub mod price_feed;
 pub mod protocol;
+pub mod rpc;
 pub mod seed;
 pub mod tor;
 pub mod tracing_ext;
 
 mod monero_ext;
 
+#[cfg(test)]
+mod mock_server;
 #[cfg(test)]
 mod proptest;
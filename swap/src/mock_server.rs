@@ -0,0 +1,130 @@
+//! Minimal HTTP and websocket servers on a random local port, to test the
+//! clients of remote APIs against.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_tungstenite::WebSocketStream;
+
+/// A request received by [`http`].
+#[derive(Debug)]
+pub struct Request {
+    /// The request line and the headers, lowercased.
+    pub head: String,
+    pub body: String,
+}
+
+/// A response sent by [`http`].
+#[derive(Clone, Debug)]
+pub struct Response {
+    status: u16,
+    body: String,
+}
+
+impl Response {
+    /// An empty response with the given status code.
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            body: String::new(),
+        }
+    }
+
+    /// A `200 OK` response with the given JSON body.
+    pub fn json(body: &str) -> Self {
+        Self {
+            status: 200,
+            body: body.to_owned(),
+        }
+    }
+}
+
+/// Answers one request per connection with the next of `responses`, until
+/// they run out.
+///
+/// Every request is reported on the returned channel once it was answered.
+pub async fn http<R>(responses: R) -> (SocketAddr, mpsc::UnboundedReceiver<Request>)
+where
+    R: IntoIterator<Item = Response>,
+    R::IntoIter: Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let responses = responses.into_iter();
+
+    tokio::spawn(async move {
+        for response in responses {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let request = read_request(&mut stream).await;
+
+            let response = format!(
+                "HTTP/1.1 {} Stub\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                response.status,
+                response.body.len(),
+                response.body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+
+            // the test may not be interested in the requests
+            let _ = sender.send(request);
+        }
+    });
+
+    (address, receiver)
+}
+
+/// Accepts a single websocket connection and hands it to `handle`.
+pub async fn websocket<F, Fut>(handle: F) -> SocketAddr
+where
+    F: FnOnce(WebSocketStream<TcpStream>) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+
+        handle(ws).await;
+    });
+
+    address
+}
+
+async fn read_request(stream: &mut TcpStream) -> Request {
+    let mut buffer = Vec::new();
+    let head_len = loop {
+        read_chunk(stream, &mut buffer).await;
+
+        if let Some(position) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break position + 4;
+        }
+    };
+    let head = String::from_utf8(buffer[..head_len].to_vec())
+        .unwrap()
+        .to_lowercase();
+    let content_length = head
+        .lines()
+        .find_map(|line| line.strip_prefix("content-length: "))
+        .map(|length| length.trim().parse::<usize>().unwrap())
+        .unwrap_or_default();
+
+    while buffer.len() < head_len + content_length {
+        read_chunk(stream, &mut buffer).await;
+    }
+    let body = String::from_utf8(buffer[head_len..].to_vec()).unwrap();
+
+    Request { head, body }
+}
+
+async fn read_chunk(stream: &mut TcpStream, buffer: &mut Vec<u8>) {
+    let mut chunk = [0u8; 1024];
+    let read = stream.read(&mut chunk).await.unwrap();
+    assert_ne!(read, 0, "client closed the connection mid-request");
+
+    buffer.extend_from_slice(&chunk[..read]);
+}
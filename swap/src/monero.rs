@@ -82,6 +82,8 @@ pub struct PublicViewKey(PublicKey);
 pub struct Amount(u64);
 
 // Median tx fees on Monero as found here: https://www.monero.how/monero-transaction-fees, XMR 0.000_015 * 2 (to be on the safe side)
+// Only used if the wallet has no daemon to estimate the fee with, see
+// `Wallet::estimate_fee`.
 pub const MONERO_FEE: Amount = Amount::from_piconero(30000000);
 
 impl Amount {
@@ -99,8 +101,14 @@ impl Amount {
         self.0
     }
 
-    pub fn max_bitcoin_for_price(&self, ask_price: bitcoin::Amount) -> bitcoin::Amount {
-        let piconero_minus_fee = self.as_piconero().saturating_sub(MONERO_FEE.as_piconero());
+    /// The most Bitcoin that can be bought with this amount at the given price,
+    /// after paying the fee for locking the Monero.
+    pub fn max_bitcoin_for_price(
+        &self,
+        ask_price: bitcoin::Amount,
+        lock_fee: Amount,
+    ) -> bitcoin::Amount {
+        let piconero_minus_fee = self.as_piconero().saturating_sub(lock_fee.as_piconero());
 
         if piconero_minus_fee == 0 {
             return bitcoin::Amount::ZERO;
@@ -312,6 +320,37 @@ pub mod monero_amount {
     }
 }
 
+/// Serializes an [`Amount`] as a floating point number of XMR, for human
+/// readable formats like the config file.
+pub mod as_xmr {
+    use crate::monero::{Amount, PICONERO_OFFSET};
+    use rust_decimal::prelude::ToPrimitive;
+    use rust_decimal::Decimal;
+    use serde::de::Error as _;
+    use serde::ser::Error as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(x: &Amount, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let xmr = (x.as_piconero_decimal() / Decimal::from(PICONERO_OFFSET))
+            .to_f64()
+            .ok_or_else(|| S::Error::custom(format!("{} does not fit into a f64", x)))?;
+
+        s.serialize_f64(xmr)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Amount, <D as Deserializer<'de>>::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let xmr = f64::deserialize(deserializer)?;
+
+        Amount::from_monero(xmr).map_err(D::Error::custom)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -379,7 +418,7 @@ mod tests {
         let amount = Amount::parse_monero("10").unwrap();
         let bitcoin_price_sats = bitcoin::Amount::from_sat(382_900);
 
-        let monero_max_from_bitcoin = amount.max_bitcoin_for_price(bitcoin_price_sats);
+        let monero_max_from_bitcoin = amount.max_bitcoin_for_price(bitcoin_price_sats, MONERO_FEE);
 
         assert_eq!(
             bitcoin::Amount::from_sat(3_828_988),
@@ -393,7 +432,7 @@ mod tests {
         let amount = Amount::parse_monero(monero).unwrap();
         let bitcoin_price_sats = bitcoin::Amount::from_sat(382_900);
 
-        let monero_max_from_bitcoin = amount.max_bitcoin_for_price(bitcoin_price_sats);
+        let monero_max_from_bitcoin = amount.max_bitcoin_for_price(bitcoin_price_sats, MONERO_FEE);
 
         assert_eq!(bitcoin::Amount::ZERO, monero_max_from_bitcoin);
     }
@@ -1,30 +1,58 @@
 use crate::env::Config;
 use crate::monero::{
-    Amount, InsufficientFunds, PrivateViewKey, PublicViewKey, TransferProof, TxHash,
+    Amount, InsufficientFunds, PrivateViewKey, PublicViewKey, TransferProof, TxHash, MONERO_FEE,
 };
+use crate::tor;
 use ::monero::{Address, Network, PrivateKey, PublicKey};
 use anyhow::{Context, Result};
-use monero_rpc::wallet::{BlockHeight, MoneroWalletRpc as _, Refreshed};
-use monero_rpc::{jsonrpc, wallet};
+use async_trait::async_trait;
+use monero_rpc::wallet::{BlockHeight, CheckTxKey, ErrorCode, MoneroWalletRpc as _, Refreshed};
+use monero_rpc::{monerod, wallet};
+use serde::Serialize;
 use std::str::FromStr;
 use std::time::Duration;
 use tokio::sync::Mutex;
 use tokio::time::Interval;
 use url::Url;
 
+/// The weight of a transfer to a single address, with two outputs and a few
+/// inputs, rounded up.
+const TRANSFER_WEIGHT: u64 = 2_000;
+
 #[derive(Debug)]
 pub struct Wallet {
     inner: Mutex<wallet::Client>,
     network: Network,
     name: String,
     main_address: monero::Address,
+    /// The account funding transfers, whose primary address is the
+    /// `main_address`.
+    account_index: u32,
+    /// Where the Monero of generated wallets is swept to.
+    refund_address: monero::Address,
     sync_interval: Duration,
+    /// The daemon incoming transfers are verified with.
+    monerod: Option<monero_wallet::Wallet>,
+    /// The daemon fees are estimated with.
+    fee_estimator: Option<monero_wallet::Wallet>,
 }
 
 impl Wallet {
     /// Connect to a wallet RPC and load the given wallet by name.
-    pub async fn open_or_create(url: Url, name: String, env_config: Config) -> Result<Self> {
+    ///
+    /// Unless the wallet RPC runs on this machine, it is connected to through
+    /// the socks5 port of Tor if one is given.
+    pub async fn open_or_create(
+        url: Url,
+        name: String,
+        env_config: Config,
+        tor_socks5_port: Option<u16>,
+    ) -> Result<Self> {
         let client = wallet::Client::new(url)?;
+        let client = match tor_socks5_port {
+            Some(port) => client.with_proxy(tor::reqwest_proxy(port))?,
+            None => client,
+        };
 
         let open_wallet_response = client.open_wallet(name.clone()).await;
         if open_wallet_response.is_err() {
@@ -49,10 +77,106 @@ impl Wallet {
             network: env_config.monero_network,
             name,
             main_address,
+            account_index: 0,
+            refund_address: main_address,
             sync_interval: env_config.monero_sync_interval(),
+            monerod: None,
+            fee_estimator: None,
         })
     }
 
+    /// Fund transfers from the given account instead of the primary one,
+    /// creating the account if the wallet does not have it yet.
+    ///
+    /// Refunds follow the account unless they were directed elsewhere before.
+    pub async fn with_account(self, account_index: u32) -> Result<Self> {
+        let address = self.ensure_account(account_index).await?;
+        let refund_address = if self.refund_address == self.main_address {
+            address
+        } else {
+            self.refund_address
+        };
+
+        Ok(Self {
+            main_address: address,
+            account_index,
+            refund_address,
+            ..self
+        })
+    }
+
+    /// Sweep the Monero of generated wallets into the given account, creating
+    /// it if the wallet does not have it yet.
+    pub async fn with_refund_account(self, account_index: u32) -> Result<Self> {
+        let refund_address = self.ensure_account(account_index).await?;
+
+        Ok(self.with_refund_address(refund_address))
+    }
+
+    /// Sweep the Monero of generated wallets to the given address.
+    pub fn with_refund_address(self, refund_address: Address) -> Self {
+        Self {
+            refund_address,
+            ..self
+        }
+    }
+
+    /// The primary address of the account, creating accounts up to the given
+    /// index if necessary.
+    async fn ensure_account(&self, account_index: u32) -> Result<Address> {
+        let inner = self.inner.lock().await;
+
+        let accounts = inner
+            .get_accounts(String::new())
+            .await?
+            .subaddress_accounts
+            .len();
+        for index in accounts..=account_index as usize {
+            inner.create_account(String::new()).await?;
+            tracing::debug!(account_index = %index, "Created Monero account");
+        }
+
+        let address = inner.get_address(account_index).await?.address;
+
+        Ok(Address::from_str(&address)?)
+    }
+
+    /// Estimate the fee of a transfer, such as the one locking the Monero of a
+    /// swap, from the fee estimate of monerod.
+    ///
+    /// Falls back to [`MONERO_FEE`] unless a monerod was given with
+    /// [`Wallet::with_fee_estimation`].
+    pub async fn estimate_fee(&self) -> Result<Amount> {
+        let fee_estimator = match &self.fee_estimator {
+            Some(fee_estimator) => fee_estimator,
+            None => return Ok(MONERO_FEE),
+        };
+
+        let fee = fee_estimator
+            .estimate_fee(TRANSFER_WEIGHT)
+            .await
+            .context("Failed to estimate Monero fee")?;
+
+        Ok(Amount::from_piconero(fee))
+    }
+
+    /// Verify incoming transfers with the transactions served by the given
+    /// monerod instead of asking `monero-wallet-rpc` to check them.
+    pub fn with_monerod(self, client: monerod::Client) -> Self {
+        Self {
+            monerod: Some(monero_wallet::Wallet::new(client)),
+            ..self
+        }
+    }
+
+    /// Estimate fees with the fee estimate of the given monerod.
+    pub fn with_fee_estimation(self, client: monerod::Client) -> Self {
+        Self {
+            fee_estimator: Some(monero_wallet::Wallet::new(client)),
+            ..self
+        }
+    }
+
     /// Re-open the wallet using the internally stored name.
     pub async fn re_open(&self) -> Result<()> {
         self.inner
@@ -109,8 +233,8 @@ impl Wallet {
 
     /// Close the wallet and open (load) another wallet by generating it from
     /// keys. The generated wallet will be opened, all funds sweeped to the
-    /// main_address and then the wallet will be re-loaded using the internally
-    /// stored name.
+    /// refund address and then the wallet will be re-loaded using the
+    /// internally stored name.
     pub async fn create_from(
         &self,
         file_name: String,
@@ -142,21 +266,21 @@ impl Wallet {
             )
             .await?;
 
-        // Try to send all the funds from the generated wallet to the default wallet
+        // Try to send all the funds from the generated wallet to the refund address
         match wallet.refresh().await {
-            Ok(_) => match wallet.sweep_all(self.main_address.to_string()).await {
+            Ok(_) => match wallet.sweep_all(self.refund_address.to_string()).await {
                 Ok(sweep_all) => {
                     for tx in sweep_all.tx_hash_list {
                         tracing::info!(
                             %tx,
-                            monero_address = %self.main_address,
-                            "Monero transferred back to default wallet");
+                            monero_address = %self.refund_address,
+                            "Monero transferred to refund address");
                     }
                 }
                 Err(error) => {
                     tracing::warn!(
-                        address = %self.main_address,
-                        "Failed to transfer Monero to default wallet: {:#}", error
+                        address = %self.refund_address,
+                        "Failed to transfer Monero to refund address: {:#}", error
                     );
                 }
             },
@@ -170,6 +294,72 @@ impl Wallet {
         Ok(())
     }
 
+    /// Generate a wallet from keys, sweep all of its funds to the main
+    /// address and re-load the wallet using the internally stored name.
+    ///
+    /// This is how the Monero bought in a swap reach the account funding
+    /// transfers, refunded Monero go to the refund address through
+    /// [`Wallet::create_from`] instead.
+    ///
+    /// Unlike [`Wallet::create_from`], failing to sweep is an error, so the
+    /// caller can try again. A wallet that was already generated by an earlier
+    /// attempt is opened instead.
+    pub async fn sweep_from(
+        &self,
+        file_name: String,
+        private_spend_key: PrivateKey,
+        private_view_key: PrivateViewKey,
+        restore_height: BlockHeight,
+    ) -> Result<Vec<TxHash>> {
+        let public_spend_key = PublicKey::from_private_key(&private_spend_key);
+        let public_view_key = PublicKey::from_private_key(&private_view_key.into());
+
+        let address = Address::standard(self.network, public_spend_key, public_view_key);
+
+        let wallet = self.inner.lock().await;
+
+        let _ = wallet
+            .close_wallet()
+            .await
+            .context("Failed to close wallet")?;
+
+        if let Err(error) = wallet
+            .generate_from_keys(
+                file_name.clone(),
+                address.to_string(),
+                private_spend_key.to_string(),
+                PrivateKey::from(private_view_key).to_string(),
+                restore_height.height,
+                String::from(""),
+                true,
+            )
+            .await
+        {
+            tracing::debug!(%file_name, "Failed to generate wallet from keys, opening it instead: {:#}", error);
+            wallet.open_wallet(file_name.clone()).await?;
+        }
+
+        let swept = async {
+            wallet
+                .refresh()
+                .await
+                .context("Failed to refresh generated wallet")?;
+
+            wallet
+                .sweep_all(self.main_address.to_string())
+                .await
+                .with_context(|| format!("Failed to transfer Monero to {}", self.main_address))
+        }
+        .await;
+
+        wallet
+            .open_wallet(self.name.clone())
+            .await
+            .with_context(|| format!("Failed to re-open wallet {}", self.name))?;
+
+        Ok(swept?.tx_hash_list.into_iter().map(TxHash).collect())
+    }
+
     pub async fn transfer(&self, request: TransferRequest) -> Result<TransferProof> {
         let inner = self.inner.lock().await;
 
@@ -188,7 +378,11 @@ impl Wallet {
             Address::standard(self.network, public_spend_key, public_view_key.into());
 
         let res = inner
-            .transfer_single(0, amount.as_piconero(), &destination_address.to_string())
+            .transfer_single(
+                self.account_index,
+                amount.as_piconero(),
+                &destination_address.to_string(),
+            )
             .await?;
 
         tracing::debug!(
@@ -226,16 +420,32 @@ impl Wallet {
 
         let check_interval = tokio::time::interval(self.sync_interval);
 
-        wait_for_confirmations(
-            &self.inner,
-            transfer_proof,
-            address,
-            expected,
-            conf_target,
-            check_interval,
-            self.name.clone(),
-        )
-        .await?;
+        match &self.monerod {
+            Some(monerod) => {
+                wait_for_confirmations(
+                    monerod,
+                    transfer_proof,
+                    address,
+                    expected,
+                    conf_target,
+                    check_interval,
+                    self.name.clone(),
+                )
+                .await?
+            }
+            None => {
+                wait_for_confirmations(
+                    &self.inner,
+                    transfer_proof,
+                    address,
+                    expected,
+                    conf_target,
+                    check_interval,
+                    self.name.clone(),
+                )
+                .await?
+            }
+        }
 
         Ok(())
     }
@@ -252,13 +462,49 @@ impl Wallet {
         Ok(tx_hashes)
     }
 
-    /// Get the balance of the primary account.
+    /// Get the balance of the account funding transfers.
     pub async fn get_balance(&self) -> Result<Amount> {
-        let amount = self.inner.lock().await.get_balance(0).await?.balance;
+        let amount = self
+            .inner
+            .lock()
+            .await
+            .get_balance(self.account_index)
+            .await?
+            .balance;
 
         Ok(Amount::from_piconero(amount))
     }
 
+    /// Get the unlocked balance of the account funding transfers, i.e. the
+    /// part of the balance that can be spent right away.
+    pub async fn get_unlocked_balance(&self) -> Result<Amount> {
+        let amount = self
+            .inner
+            .lock()
+            .await
+            .get_balance(self.account_index)
+            .await?
+            .unlocked_balance;
+
+        Ok(Amount::from_piconero(amount))
+    }
+
+    /// Get the balances of all accounts of the wallet.
+    pub async fn get_account_balances(&self) -> Result<Vec<AccountBalance>> {
+        let accounts = self.inner.lock().await.get_accounts(String::new()).await?;
+
+        Ok(accounts
+            .subaddress_accounts
+            .into_iter()
+            .map(|account| AccountBalance {
+                index: account.account_index,
+                label: account.label,
+                balance: Amount::from_piconero(account.balance),
+                unlocked_balance: Amount::from_piconero(account.unlocked_balance),
+            })
+            .collect())
+    }
+
     pub async fn block_height(&self) -> Result<BlockHeight> {
         Ok(self.inner.lock().await.get_height().await?)
     }
@@ -272,6 +518,16 @@ impl Wallet {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AccountBalance {
+    pub index: u32,
+    pub label: String,
+    #[serde(with = "crate::monero::monero_amount")]
+    pub balance: Amount,
+    #[serde(with = "crate::monero::monero_amount")]
+    pub unlocked_balance: Amount,
+}
+
 #[derive(Debug)]
 pub struct TransferRequest {
     pub public_spend_key: PublicKey,
@@ -288,8 +544,8 @@ pub struct WatchRequest {
     pub expected: Amount,
 }
 
-async fn wait_for_confirmations<C: monero_rpc::wallet::MoneroWalletRpc<reqwest::Client> + Sync>(
-    client: &Mutex<C>,
+async fn wait_for_confirmations<C: CheckTransfer>(
+    client: &C,
     transfer_proof: TransferProof,
     to_address: Address,
     expected: Amount,
@@ -303,9 +559,67 @@ async fn wait_for_confirmations<C: monero_rpc::wallet::MoneroWalletRpc<reqwest::
         check_interval.tick().await; // tick() at the beginning of the loop so every `continue` tick()s as well
 
         let txid = transfer_proof.tx_hash().to_string();
-        let client = client.lock().await;
 
         let tx = match client
+            .check(&transfer_proof, to_address, &wallet_name)
+            .await
+        {
+            Some(tx) => tx,
+            None => continue,
+        };
+
+        let received = Amount::from_piconero(tx.received);
+
+        if received != expected {
+            return Err(InsufficientFunds {
+                expected,
+                actual: received,
+            });
+        }
+
+        if tx.confirmations > seen_confirmations {
+            seen_confirmations = tx.confirmations;
+            tracing::info!(
+                %txid,
+                %seen_confirmations,
+                needed_confirmations = %conf_target,
+                "Received new confirmation for Monero lock tx"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Looks up how much a transfer sent to an address and how many
+/// confirmations it has.
+#[async_trait]
+trait CheckTransfer: Sync {
+    /// Returns `None` if the transfer could not be checked right now and
+    /// should be checked again later.
+    async fn check(
+        &self,
+        transfer_proof: &TransferProof,
+        to_address: Address,
+        wallet_name: &str,
+    ) -> Option<CheckTxKey>;
+}
+
+#[async_trait]
+impl<C> CheckTransfer for Mutex<C>
+where
+    C: monero_rpc::wallet::MoneroWalletRpc<reqwest::Client> + Send + Sync,
+{
+    async fn check(
+        &self,
+        transfer_proof: &TransferProof,
+        to_address: Address,
+        wallet_name: &str,
+    ) -> Option<CheckTxKey> {
+        let txid = transfer_proof.tx_hash().to_string();
+        let client = self.lock().await;
+
+        match client
             .check_tx_key(
                 txid.clone(),
                 transfer_proof.tx_key.to_string(),
@@ -313,60 +627,80 @@ async fn wait_for_confirmations<C: monero_rpc::wallet::MoneroWalletRpc<reqwest::
             )
             .await
         {
-            Ok(proof) => proof,
-            Err(jsonrpc::Error::JsonRpc(jsonrpc::JsonRpcError { code: -1, .. })) => {
+            Ok(proof) => Some(proof),
+            Err(error) if ErrorCode::of(&error) == Some(ErrorCode::Unknown) => {
                 tracing::warn!(%txid, "`monero-wallet-rpc` failed to fetch transaction, may need to be restarted");
-                continue;
+                None
             }
             // TODO: Implement this using a generic proxy for each function call once https://github.com/thomaseizinger/rust-jsonrpc-client/issues/47 is fixed.
-            Err(jsonrpc::Error::JsonRpc(jsonrpc::JsonRpcError { code: -13, .. })) => {
+            Err(error) if ErrorCode::of(&error) == Some(ErrorCode::NotOpen) => {
                 tracing::debug!(
                     "Opening wallet `{}` because no wallet is loaded",
                     wallet_name
                 );
-                let _ = client.open_wallet(wallet_name.clone()).await;
-                continue;
+                let _ = client.open_wallet(wallet_name.to_owned()).await;
+                None
             }
             Err(other) => {
                 tracing::debug!(
                     %txid,
                     "Failed to retrieve tx from blockchain: {:#}", other
                 );
-                continue; // treating every error as transient and retrying
-                          // is obviously wrong but the jsonrpc client is
-                          // too primitive to differentiate between all the
-                          // cases
+                None // treating every error as transient and retrying
+                     // is obviously wrong but the jsonrpc client is
+                     // too primitive to differentiate between all the
+                     // cases
             }
-        };
+        }
+    }
+}
 
-        let received = Amount::from_piconero(tx.received);
+#[async_trait]
+impl CheckTransfer for monero_wallet::Wallet {
+    async fn check(
+        &self,
+        transfer_proof: &TransferProof,
+        to_address: Address,
+        _: &str,
+    ) -> Option<CheckTxKey> {
+        let txid = transfer_proof.tx_hash().to_string();
 
-        if received != expected {
-            return Err(InsufficientFunds {
-                expected,
-                actual: received,
-            });
-        }
+        let tx_hash = match txid.parse() {
+            Ok(tx_hash) => tx_hash,
+            Err(error) => {
+                tracing::warn!(%txid, "Invalid transaction hash in transfer proof: {:?}", error);
+                return None;
+            }
+        };
 
-        if tx.confirmations > seen_confirmations {
-            seen_confirmations = tx.confirmations;
-            tracing::info!(
-                %txid,
-                %seen_confirmations,
-                needed_confirmations = %conf_target,
-                "Received new confirmation for Monero lock tx"
-            );
+        match self
+            .check_transfer(
+                tx_hash,
+                &transfer_proof.tx_key,
+                &to_address.public_spend,
+                &to_address.public_view,
+            )
+            .await
+        {
+            Ok(transfer) => Some(CheckTxKey {
+                confirmations: transfer.confirmations,
+                received: transfer.received,
+            }),
+            Err(error) => {
+                tracing::debug!(
+                    %txid,
+                    "Failed to verify tx against monerod: {:#}", error
+                );
+                None
+            }
         }
     }
-
-    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::tracing_ext::capture_logs;
-    use monero_rpc::wallet::CheckTxKey;
     use std::sync::atomic::{AtomicU32, Ordering};
     use tracing::metadata::LevelFilter;
 
@@ -1,3 +1,4 @@
+use crate::tor;
 use ::monero::Network;
 use anyhow::{Context, Result};
 use big_bytes::BigByte;
@@ -57,7 +58,12 @@ pub struct WalletRpc {
 }
 
 impl WalletRpc {
-    pub async fn new(working_dir: impl AsRef<Path>) -> Result<WalletRpc> {
+    /// Download `monero-wallet-rpc` into `working_dir` unless it is there
+    /// already, through the socks5 port of Tor if one is given.
+    pub async fn new(
+        working_dir: impl AsRef<Path>,
+        tor_socks5_port: Option<u16>,
+    ) -> Result<WalletRpc> {
         let working_dir = working_dir.as_ref();
 
         if !working_dir.exists() {
@@ -81,7 +87,13 @@ impl WalletRpc {
                 .open(monero_wallet_rpc.archive_path())
                 .await?;
 
-            let response = reqwest::get(DOWNLOAD_URL).await?;
+            let client = match tor_socks5_port {
+                Some(port) => reqwest::Client::builder()
+                    .proxy(tor::reqwest_proxy(port))
+                    .build()?,
+                None => reqwest::Client::new(),
+            };
+            let response = client.get(DOWNLOAD_URL).send().await?;
 
             let content_length = response.headers()[CONTENT_LENGTH]
                 .to_str()
@@ -120,7 +132,16 @@ impl WalletRpc {
         Ok(monero_wallet_rpc)
     }
 
-    pub async fn run(&self, network: Network, daemon_address: &str) -> Result<WalletRpcProcess> {
+    /// Start `monero-wallet-rpc` connected to the daemon at `daemon_address`.
+    ///
+    /// Unless the daemon runs on this machine, `monero-wallet-rpc` connects to
+    /// it through the socks5 port of Tor if one is given.
+    pub async fn run(
+        &self,
+        network: Network,
+        daemon_address: &str,
+        tor_socks5_port: Option<u16>,
+    ) -> Result<WalletRpcProcess> {
         let port = tokio::net::TcpListener::bind("127.0.0.1:0")
             .await?
             .local_addr()?
@@ -143,6 +164,14 @@ impl WalletRpc {
             }
         };
 
+        let daemon_host = daemon_address
+            .rsplit_once(':')
+            .map_or(daemon_address, |(host, _)| host);
+        let proxy_flag = match tor::socks5_port_for(daemon_host, tor_socks5_port) {
+            Some(port) => vec!["--proxy".to_owned(), tor::socks5_address(port)],
+            None => vec![],
+        };
+
         let mut child = Command::new(self.exec_path())
             .env("LANG", "en_AU.UTF-8")
             .stdout(Stdio::piped())
@@ -150,6 +179,7 @@ impl WalletRpc {
             .args(network_flag)
             .arg("--daemon-address")
             .arg(daemon_address)
+            .args(proxy_flag)
             .arg("--rpc-bind-port")
             .arg(format!("{}", port))
             .arg("--disable-rpc-login")
@@ -0,0 +1,90 @@
+use crate::network::json_pull_codec::JsonPullCodec;
+use crate::{asb, bitcoin, cli, monero};
+use libp2p::core::ProtocolName;
+use libp2p::request_response::{
+    ProtocolSupport, RequestResponse, RequestResponseConfig, RequestResponseEvent,
+    RequestResponseMessage,
+};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+const PROTOCOL: &str = "/comit/xmr/btc/ask-quote/1.0.0";
+pub type OutEvent = RequestResponseEvent<(), AskQuote>;
+pub type Message = RequestResponseMessage<(), AskQuote>;
+
+pub type Behaviour = RequestResponse<JsonPullCodec<AskQuoteProtocol, AskQuote>>;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AskQuoteProtocol;
+
+impl ProtocolName for AskQuoteProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        PROTOCOL.as_bytes()
+    }
+}
+
+/// Represents a quote for selling XMR to the maker.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct AskQuote {
+    /// The price at which the maker is willing to buy 1 XMR.
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+    pub price: bitcoin::Amount,
+    /// The minimum quantity of XMR the maker is willing to buy.
+    #[serde(with = "crate::monero::monero_amount")]
+    pub min_quantity: monero::Amount,
+    /// The maximum quantity of XMR the maker is willing to buy.
+    #[serde(with = "crate::monero::monero_amount")]
+    pub max_quantity: monero::Amount,
+}
+
+/// Constructs a new instance of the `ask_quote` behaviour to be used by the
+/// ASB.
+///
+/// The ASB is always listening and only supports inbound connections, i.e.
+/// handing out quotes.
+pub fn asb() -> Behaviour {
+    Behaviour::new(
+        JsonPullCodec::default(),
+        vec![(AskQuoteProtocol, ProtocolSupport::Inbound)],
+        RequestResponseConfig::default(),
+    )
+}
+
+/// Constructs a new instance of the `ask_quote` behaviour to be used by the
+/// CLI.
+///
+/// The CLI is always dialing and only supports outbound connections, i.e.
+/// requesting quotes.
+pub fn cli() -> Behaviour {
+    Behaviour::new(
+        JsonPullCodec::default(),
+        vec![(AskQuoteProtocol, ProtocolSupport::Outbound)],
+        RequestResponseConfig::default(),
+    )
+}
+
+impl From<(PeerId, Message)> for asb::OutEvent {
+    fn from((peer, message): (PeerId, Message)) -> Self {
+        match message {
+            Message::Request { channel, .. } => Self::AskQuoteRequested { channel, peer },
+            Message::Response { .. } => Self::unexpected_response(peer),
+        }
+    }
+}
+crate::impl_from_rr_event!(OutEvent, asb::OutEvent, PROTOCOL);
+
+impl From<(PeerId, Message)> for cli::OutEvent {
+    fn from((peer, message): (PeerId, Message)) -> Self {
+        match message {
+            Message::Request { .. } => Self::unexpected_request(peer),
+            Message::Response {
+                response,
+                request_id,
+            } => Self::AskQuoteReceived {
+                id: request_id,
+                response,
+            },
+        }
+    }
+}
+crate::impl_from_rr_event!(OutEvent, cli::OutEvent, PROTOCOL);
@@ -30,18 +30,14 @@ pub struct Request {
     pub tx_redeem_encsig: crate::bitcoin::EncryptedSignature,
 }
 
-pub fn alice() -> Behaviour {
+/// Constructs a new instance of the `encrypted_signature` behaviour.
+///
+/// The ASB and the CLI can both be in the role of Alice or Bob, hence they both
+/// have to send and receive these messages.
+pub fn new() -> Behaviour {
     Behaviour::new(
         CborCodec::default(),
-        vec![(EncryptedSignatureProtocol, ProtocolSupport::Inbound)],
-        RequestResponseConfig::default(),
-    )
-}
-
-pub fn bob() -> Behaviour {
-    Behaviour::new(
-        CborCodec::default(),
-        vec![(EncryptedSignatureProtocol, ProtocolSupport::Outbound)],
+        vec![(EncryptedSignatureProtocol, ProtocolSupport::Full)],
         RequestResponseConfig::default(),
     )
 }
@@ -56,7 +52,10 @@ impl From<(PeerId, Message)> for asb::OutEvent {
                 channel,
                 peer,
             },
-            Message::Response { .. } => Self::unexpected_response(peer),
+            Message::Response { request_id, .. } => Self::EncryptedSignatureAcknowledged {
+                peer,
+                id: request_id,
+            },
         }
     }
 }
@@ -65,7 +64,13 @@ crate::impl_from_rr_event!(OutEvent, asb::OutEvent, PROTOCOL);
 impl From<(PeerId, Message)> for cli::OutEvent {
     fn from((peer, message): (PeerId, Message)) -> Self {
         match message {
-            Message::Request { .. } => Self::unexpected_request(peer),
+            Message::Request {
+                request, channel, ..
+            } => Self::EncryptedSignatureReceived {
+                msg: Box::new(request),
+                channel,
+                peer,
+            },
             Message::Response { request_id, .. } => {
                 Self::EncryptedSignatureAcknowledged { id: request_id }
             }
@@ -4,37 +4,95 @@ use libp2p::core::upgrade;
 use libp2p::swarm::NegotiatedSubstream;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 pub mod alice;
 pub mod bob;
+pub mod reverse;
 
 pub const BUF_SIZE: usize = 1024 * 1024;
 
 pub mod protocol {
     use futures::future;
-    use libp2p::core::upgrade::{from_fn, FromFnUpgrade};
-    use libp2p::core::Endpoint;
+    use libp2p::core::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
     use libp2p::swarm::NegotiatedSubstream;
+    use std::iter;
     use void::Void;
 
+    /// The version of a swap setup protocol negotiated with a peer.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Version {
+        /// The cancel transaction is signed at every fee of
+        /// [`TxCancel::fee_bumps`](crate::bitcoin::TxCancel::fee_bumps) in
+        /// addition to the negotiated one.
+        Current,
+        /// The cancel transaction is only signed at the negotiated fee.
+        Legacy,
+    }
+
     pub fn new() -> SwapSetup {
-        from_fn(
-            b"/comit/xmr/btc/swap_setup/1.0.0",
-            Box::new(|socket, _| future::ready(Ok(socket))),
-        )
+        SwapSetup {
+            current: b"/comit/xmr/btc/swap_setup/2.0.0",
+            legacy: Some(b"/comit/xmr/btc/swap_setup/1.0.0"),
+        }
+    }
+
+    /// The protocol for setting up a swap in which the CLI sells XMR, i.e.
+    /// the ASB takes the role of Bob.
+    pub fn reverse() -> SwapSetup {
+        SwapSetup {
+            current: b"/comit/xmr/btc/swap_setup/reverse/1.0.0",
+            legacy: None,
+        }
+    }
+
+    /// Upgrades a substream to a swap setup protocol, preferring the current
+    /// version, and tells which version was negotiated.
+    #[derive(Clone, Copy, Debug)]
+    pub struct SwapSetup {
+        current: &'static [u8],
+        legacy: Option<&'static [u8]>,
+    }
+
+    impl SwapSetup {
+        fn version(&self, info: &[u8]) -> Version {
+            if Some(info) == self.legacy {
+                Version::Legacy
+            } else {
+                Version::Current
+            }
+        }
+    }
+
+    impl UpgradeInfo for SwapSetup {
+        type Info = &'static [u8];
+        type InfoIter =
+            iter::Chain<iter::Once<&'static [u8]>, std::option::IntoIter<&'static [u8]>>;
+
+        fn protocol_info(&self) -> Self::InfoIter {
+            iter::once(self.current).chain(self.legacy)
+        }
     }
 
-    pub type SwapSetup = FromFnUpgrade<
-        &'static [u8],
-        Box<
-            dyn Fn(
-                    NegotiatedSubstream,
-                    Endpoint,
-                ) -> future::Ready<Result<NegotiatedSubstream, Void>>
-                + Send
-                + 'static,
-        >,
-    >;
+    impl InboundUpgrade<NegotiatedSubstream> for SwapSetup {
+        type Output = (NegotiatedSubstream, Version);
+        type Error = Void;
+        type Future = future::Ready<Result<Self::Output, Void>>;
+
+        fn upgrade_inbound(self, socket: NegotiatedSubstream, info: Self::Info) -> Self::Future {
+            future::ready(Ok((socket, self.version(info))))
+        }
+    }
+
+    impl OutboundUpgrade<NegotiatedSubstream> for SwapSetup {
+        type Output = (NegotiatedSubstream, Version);
+        type Error = Void;
+        type Future = future::Ready<Result<Self::Output, Void>>;
+
+        fn upgrade_outbound(self, socket: NegotiatedSubstream, info: Self::Info) -> Self::Future {
+            future::ready(Ok((socket, self.version(info))))
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
@@ -86,6 +144,46 @@ pub enum SpotPriceError {
     Other,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReverseSpotPriceRequest {
+    /// The id of the swap is chosen by the CLI because it has to know it
+    /// before the swap is set up in order to route the messages of the swap.
+    pub swap_id: Uuid,
+    pub xmr: monero::Amount,
+    pub blockchain_network: BlockchainNetwork,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ReverseSpotPriceResponse {
+    Btc(#[serde(with = "::bitcoin::util::amount::serde::as_sat")] bitcoin::Amount),
+    Error(ReverseSpotPriceError),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ReverseSpotPriceError {
+    NoSwapsAccepted,
+    AmountBelowMinimum {
+        min: monero::Amount,
+        sell: monero::Amount,
+    },
+    AmountAboveMaximum {
+        max: monero::Amount,
+        sell: monero::Amount,
+    },
+    BalanceTooLow {
+        sell: monero::Amount,
+    },
+    BlockchainNetworkMismatch {
+        cli: BlockchainNetwork,
+        asb: BlockchainNetwork,
+    },
+    /// The seller already has a swap with the swap id of the request.
+    SwapIdTaken,
+    /// To be used for errors that cannot be explained on the CLI side (e.g.
+    /// rate update problems on the seller side)
+    Other,
+}
+
 pub async fn read_cbor_message<T>(substream: &mut NegotiatedSubstream) -> Result<T>
 where
     T: DeserializeOwned,
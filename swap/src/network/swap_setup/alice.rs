@@ -27,15 +27,19 @@ use void::Void;
 #[allow(clippy::large_enum_variant)]
 pub enum OutEvent {
     Initiated {
+        peer_id: PeerId,
+        setup_id: Uuid,
         send_wallet_snapshot: bmrng::RequestReceiver<bitcoin::Amount, WalletSnapshot>,
     },
     Completed {
         peer_id: PeerId,
+        setup_id: Uuid,
         swap_id: Uuid,
         state3: State3,
     },
     Error {
         peer_id: PeerId,
+        setup_id: Uuid,
         error: anyhow::Error,
     },
 }
@@ -55,12 +59,14 @@ pub struct WalletSnapshot {
 }
 
 impl WalletSnapshot {
+    /// The `balance` is the Monero available for the swap, i.e. not reserved
+    /// by other swaps, and `lock_fee` the estimated fee for locking it.
     pub async fn capture(
         bitcoin_wallet: &bitcoin::Wallet,
-        monero_wallet: &monero::Wallet,
+        balance: monero::Amount,
+        lock_fee: monero::Amount,
         transfer_amount: bitcoin::Amount,
     ) -> Result<Self> {
-        let balance = monero_wallet.get_balance().await?;
         let redeem_address = bitcoin_wallet.new_address().await?;
         let punish_address = bitcoin_wallet.new_address().await?;
         let redeem_fee = bitcoin_wallet
@@ -72,7 +78,7 @@ impl WalletSnapshot {
 
         Ok(Self {
             balance,
-            lock_fee: monero::MONERO_FEE,
+            lock_fee,
             redeem_address,
             punish_address,
             redeem_fee,
@@ -85,21 +91,32 @@ impl From<OutEvent> for asb::OutEvent {
     fn from(event: OutEvent) -> Self {
         match event {
             OutEvent::Initiated {
+                peer_id,
+                setup_id,
                 send_wallet_snapshot,
             } => asb::OutEvent::SwapSetupInitiated {
+                peer_id,
+                setup_id,
                 send_wallet_snapshot,
             },
             OutEvent::Completed {
                 peer_id: bob_peer_id,
+                setup_id,
                 swap_id,
                 state3,
             } => asb::OutEvent::SwapSetupCompleted {
                 peer_id: bob_peer_id,
+                setup_id,
                 swap_id,
                 state3,
             },
-            OutEvent::Error { peer_id, error } => asb::OutEvent::Failure {
+            OutEvent::Error {
+                peer_id,
+                setup_id,
+                error,
+            } => asb::OutEvent::SwapSetupFailed {
                 peer: peer_id,
+                setup_id,
                 error: anyhow!(error),
             },
         }
@@ -163,21 +180,31 @@ where
 
     fn inject_event(&mut self, peer_id: PeerId, _: ConnectionId, event: HandlerOutEvent) {
         match event {
-            HandlerOutEvent::Initiated(send_wallet_snapshot) => {
-                self.events.push_back(OutEvent::Initiated {
-                    send_wallet_snapshot,
-                })
-            }
-            HandlerOutEvent::Completed(Ok((swap_id, state3))) => {
-                self.events.push_back(OutEvent::Completed {
-                    peer_id,
-                    swap_id,
-                    state3,
-                })
-            }
-            HandlerOutEvent::Completed(Err(error)) => {
-                self.events.push_back(OutEvent::Error { peer_id, error })
-            }
+            HandlerOutEvent::Initiated {
+                setup_id,
+                send_wallet_snapshot,
+            } => self.events.push_back(OutEvent::Initiated {
+                peer_id,
+                setup_id,
+                send_wallet_snapshot,
+            }),
+            HandlerOutEvent::Completed {
+                setup_id,
+                result: Ok((swap_id, state3)),
+            } => self.events.push_back(OutEvent::Completed {
+                peer_id,
+                setup_id,
+                swap_id,
+                state3,
+            }),
+            HandlerOutEvent::Completed {
+                setup_id,
+                result: Err(error),
+            } => self.events.push_back(OutEvent::Error {
+                peer_id,
+                setup_id,
+                error,
+            }),
         }
     }
 
@@ -194,7 +221,8 @@ where
     }
 }
 
-type InboundStream = BoxFuture<'static, Result<(Uuid, State3)>>;
+/// Resolves to the id of the setup together with its outcome.
+type InboundStream = BoxFuture<'static, (Uuid, Result<(Uuid, State3)>)>;
 
 pub struct Handler<LR> {
     inbound_stream: OptionFuture<InboundStream>,
@@ -236,8 +264,14 @@ impl<LR> Handler<LR> {
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum HandlerOutEvent {
-    Initiated(bmrng::RequestReceiver<bitcoin::Amount, WalletSnapshot>),
-    Completed(Result<(Uuid, State3)>),
+    Initiated {
+        setup_id: Uuid,
+        send_wallet_snapshot: bmrng::RequestReceiver<bitcoin::Amount, WalletSnapshot>,
+    },
+    Completed {
+        setup_id: Uuid,
+        result: Result<(Uuid, State3)>,
+    },
 }
 
 impl<LR> ProtocolsHandler for Handler<LR>
@@ -258,7 +292,7 @@ where
 
     fn inject_fully_negotiated_inbound(
         &mut self,
-        mut substream: NegotiatedSubstream,
+        (mut substream, version): (NegotiatedSubstream, protocol::Version),
         _: Self::InboundOpenInfo,
     ) {
         self.keep_alive = KeepAlive::Yes;
@@ -320,7 +354,7 @@ where
 
                 let rate = latest_rate.map_err(|e| Error::LatestRateFetchFailed(Box::new(e)))?;
                 let xmr = rate
-                    .sell_quote(btc)
+                    .sell_quote(btc, wallet_snapshot.balance)
                     .map_err(Error::SellQuoteCalculationFailed)?;
 
                 if wallet_snapshot.balance < xmr + wallet_snapshot.lock_fee {
@@ -372,6 +406,10 @@ where
             let state2 = state1
                 .receive(message2)
                 .context("Failed to transition state1 -> state2 using message2")?;
+            let state2 = match version {
+                protocol::Version::Current => state2,
+                protocol::Version::Legacy => state2.without_fee_bumps(),
+            };
 
             swap_setup::write_cbor_message(&mut substream, state2.next_message())
                 .await
@@ -396,17 +434,24 @@ where
             Ok((swap_id, state3))
         });
 
+        // Identifies the setup towards the event loop, a peer may run several at once
+        let setup_id = Uuid::new_v4();
         let max_seconds = self.timeout.as_secs();
         self.inbound_stream = OptionFuture::from(Some(
             async move {
-                protocol.await.with_context(|| {
+                let result = protocol.await.with_context(|| {
                     format!("Failed to complete execution setup within {}s", max_seconds)
-                })?
+                });
+
+                (setup_id, result.and_then(|outcome| outcome))
             }
             .boxed(),
         ));
 
-        self.events.push_back(HandlerOutEvent::Initiated(receiver));
+        self.events.push_back(HandlerOutEvent::Initiated {
+            setup_id,
+            send_wallet_snapshot: receiver,
+        });
     }
 
     fn inject_fully_negotiated_outbound(&mut self, _: Void, _: Self::OutboundOpenInfo) {
@@ -445,11 +490,12 @@ where
             return Poll::Ready(ProtocolsHandlerEvent::Custom(event));
         }
 
-        if let Some(result) = futures::ready!(self.inbound_stream.poll_unpin(cx)) {
+        if let Some((setup_id, result)) = futures::ready!(self.inbound_stream.poll_unpin(cx)) {
             self.inbound_stream = OptionFuture::from(None);
-            return Poll::Ready(ProtocolsHandlerEvent::Custom(HandlerOutEvent::Completed(
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(HandlerOutEvent::Completed {
+                setup_id,
                 result,
-            )));
+            }));
         }
 
         Poll::Pending
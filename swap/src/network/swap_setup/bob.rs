@@ -148,7 +148,7 @@ impl ProtocolsHandler for Handler {
 
     fn inject_fully_negotiated_outbound(
         &mut self,
-        mut substream: NegotiatedSubstream,
+        (mut substream, version): (NegotiatedSubstream, protocol::Version),
         info: Self::OutboundOpenInfo,
     ) {
         let bitcoin_wallet = self.bitcoin_wallet.clone();
@@ -182,6 +182,10 @@ impl ProtocolsHandler for Handler {
             write_cbor_message(&mut substream, state0.next_message()).await?;
             let message1 = read_cbor_message::<Message1>(&mut substream).await?;
             let state1 = state0.receive(bitcoin_wallet.as_ref(), message1).await?;
+            let state1 = match version {
+                protocol::Version::Current => state1,
+                protocol::Version::Legacy => state1.without_fee_bumps(),
+            };
 
             write_cbor_message(&mut substream, state1.next_message()).await?;
             let message3 = read_cbor_message::<Message3>(&mut substream).await?;
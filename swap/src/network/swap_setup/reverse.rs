@@ -0,0 +1,8 @@
+//! Setup of swaps in which the CLI sells XMR to the ASB.
+//!
+//! The roles of the protocol are swapped compared to a regular swap: the ASB
+//! holds the BTC and acts as Bob while the CLI holds the XMR and acts as Alice.
+//! The CLI dials and the ASB listens, just like in a regular swap.
+
+pub mod alice;
+pub mod bob;
@@ -0,0 +1,388 @@
+use crate::network::swap_setup::{
+    protocol, read_cbor_message, write_cbor_message, BlockchainNetwork, ReverseSpotPriceError,
+    ReverseSpotPriceRequest, ReverseSpotPriceResponse,
+};
+use crate::protocol::alice::{State0, State3};
+use crate::protocol::{Message0, Message2, Message4};
+use crate::{bitcoin, cli, env, monero};
+use anyhow::{anyhow, ensure, Context as _, Result};
+use futures::future::{BoxFuture, OptionFuture};
+use futures::{AsyncWriteExt, FutureExt};
+use libp2p::core::connection::ConnectionId;
+use libp2p::core::upgrade;
+use libp2p::swarm::{
+    KeepAlive, NegotiatedSubstream, NetworkBehaviour, NetworkBehaviourAction, NotifyHandler,
+    PollParameters, ProtocolsHandler, ProtocolsHandlerEvent, ProtocolsHandlerUpgrErr,
+    SubstreamProtocol,
+};
+use libp2p::{Multiaddr, PeerId};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use uuid::Uuid;
+use void::Void;
+
+#[allow(missing_debug_implementations)]
+pub struct Behaviour {
+    env_config: env::Config,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    new_swaps: VecDeque<(PeerId, NewSwap)>,
+    completed_swaps: VecDeque<(PeerId, Completed)>,
+}
+
+impl Behaviour {
+    pub fn new(env_config: env::Config, bitcoin_wallet: Arc<bitcoin::Wallet>) -> Self {
+        Self {
+            env_config,
+            bitcoin_wallet,
+            new_swaps: VecDeque::default(),
+            completed_swaps: VecDeque::default(),
+        }
+    }
+
+    pub async fn start(&mut self, bob: PeerId, swap: NewSwap) {
+        self.new_swaps.push_back((bob, swap))
+    }
+}
+
+impl From<Completed> for cli::OutEvent {
+    fn from(completed: Completed) -> Self {
+        cli::OutEvent::ReverseSwapSetupCompleted(Box::new(completed.0))
+    }
+}
+
+impl NetworkBehaviour for Behaviour {
+    type ProtocolsHandler = Handler;
+    type OutEvent = Completed;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        Handler::new(self.env_config, self.bitcoin_wallet.clone())
+    }
+
+    fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+
+    fn inject_connected(&mut self, _: &PeerId) {}
+
+    fn inject_disconnected(&mut self, _: &PeerId) {}
+
+    fn inject_event(&mut self, peer: PeerId, _: ConnectionId, completed: Completed) {
+        self.completed_swaps.push_back((peer, completed));
+    }
+
+    fn poll(
+        &mut self,
+        _cx: &mut Context<'_>,
+        _params: &mut impl PollParameters,
+    ) -> Poll<NetworkBehaviourAction<Self::OutEvent, Self::ProtocolsHandler>> {
+        if let Some((_, event)) = self.completed_swaps.pop_front() {
+            return Poll::Ready(NetworkBehaviourAction::GenerateEvent(event));
+        }
+
+        if let Some((peer, event)) = self.new_swaps.pop_front() {
+            return Poll::Ready(NetworkBehaviourAction::NotifyHandler {
+                peer_id: peer,
+                handler: NotifyHandler::Any,
+                event,
+            });
+        }
+
+        Poll::Pending
+    }
+}
+
+type OutboundStream = BoxFuture<'static, Result<State3>>;
+
+pub struct Handler {
+    outbound_stream: OptionFuture<OutboundStream>,
+    env_config: env::Config,
+    timeout: Duration,
+    new_swaps: VecDeque<NewSwap>,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    keep_alive: KeepAlive,
+}
+
+impl Handler {
+    fn new(env_config: env::Config, bitcoin_wallet: Arc<bitcoin::Wallet>) -> Self {
+        Self {
+            env_config,
+            outbound_stream: OptionFuture::from(None),
+            timeout: Duration::from_secs(120),
+            new_swaps: VecDeque::default(),
+            bitcoin_wallet,
+            keep_alive: KeepAlive::Yes,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct NewSwap {
+    pub swap_id: Uuid,
+    pub xmr: monero::Amount,
+    /// The least BTC the seller has to offer for `xmr`, see [`min_btc`].
+    pub min_btc: bitcoin::Amount,
+    /// Receives the BTC if the swap is redeemed or punished.
+    pub bitcoin_redeem_address: bitcoin::Address,
+}
+
+/// The percentage by which the seller's offer may fall short of its quote,
+/// because its price can move between the quote and the swap setup.
+pub const QUOTE_TOLERANCE_PERCENT: u64 = 2;
+
+/// The least BTC to accept for `xmr` from a seller that quoted `price` per
+/// XMR.
+pub fn min_btc(price: bitcoin::Amount, xmr: monero::Amount) -> Result<bitcoin::Amount> {
+    let price = Decimal::from(price.as_sat()) * Decimal::from(100 - QUOTE_TOLERANCE_PERCENT)
+        / Decimal::from(100);
+
+    let sats = (price * xmr.as_piconero_decimal())
+        .checked_div(Decimal::from(monero::Amount::ONE_XMR.as_piconero()))
+        .context("Division overflow")?
+        .floor()
+        .to_u64()
+        .context("Failed to fit sats into u64")?;
+
+    Ok(bitcoin::Amount::from_sat(sats))
+}
+
+#[derive(Debug)]
+pub struct Completed(Result<State3>);
+
+impl ProtocolsHandler for Handler {
+    type InEvent = NewSwap;
+    type OutEvent = Completed;
+    type Error = Void;
+    type InboundProtocol = upgrade::DeniedUpgrade;
+    type OutboundProtocol = protocol::SwapSetup;
+    type InboundOpenInfo = ();
+    type OutboundOpenInfo = NewSwap;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        SubstreamProtocol::new(upgrade::DeniedUpgrade, ())
+    }
+
+    fn inject_fully_negotiated_inbound(&mut self, _: Void, _: Self::InboundOpenInfo) {
+        unreachable!("The CLI does not support inbound substreams")
+    }
+
+    fn inject_fully_negotiated_outbound(
+        &mut self,
+        // the reverse protocol has no legacy version
+        (mut substream, _): (NegotiatedSubstream, protocol::Version),
+        info: Self::OutboundOpenInfo,
+    ) {
+        let bitcoin_wallet = self.bitcoin_wallet.clone();
+        let env_config = self.env_config;
+
+        let protocol = tokio::time::timeout(self.timeout, async move {
+            write_cbor_message(&mut substream, ReverseSpotPriceRequest {
+                swap_id: info.swap_id,
+                xmr: info.xmr,
+                blockchain_network: BlockchainNetwork {
+                    bitcoin: env_config.bitcoin_network,
+                    monero: env_config.monero_network,
+                },
+            })
+            .await?;
+
+            let btc =
+                Result::from(read_cbor_message::<ReverseSpotPriceResponse>(&mut substream).await?)?;
+            if btc < info.min_btc {
+                return Err(Error::OfferBelowQuote {
+                    offer: btc,
+                    min: info.min_btc,
+                }
+                .into());
+            }
+
+            let tx_redeem_fee = bitcoin_wallet
+                .estimate_fee(bitcoin::TxRedeem::weight(), btc)
+                .await?;
+            let tx_punish_fee = bitcoin_wallet
+                .estimate_fee(bitcoin::TxPunish::weight(), btc)
+                .await?;
+
+            let state0 = State0::new(
+                btc,
+                info.xmr,
+                env_config,
+                info.bitcoin_redeem_address.clone(),
+                info.bitcoin_redeem_address,
+                tx_redeem_fee,
+                tx_punish_fee,
+                &mut rand::thread_rng(),
+            );
+
+            let message0 = read_cbor_message::<Message0>(&mut substream).await?;
+            let (swap_id, state1) = state0.receive(message0)?;
+            ensure!(
+                swap_id == info.swap_id,
+                "Seller set up swap {} instead of the requested swap {}",
+                swap_id,
+                info.swap_id
+            );
+
+            write_cbor_message(&mut substream, state1.next_message()).await?;
+            let message2 = read_cbor_message::<Message2>(&mut substream).await?;
+            let state2 = state1.receive(message2)?;
+
+            write_cbor_message(&mut substream, state2.next_message()).await?;
+            let message4 = read_cbor_message::<Message4>(&mut substream).await?;
+            let state3 = state2.receive(message4)?;
+
+            substream.flush().await?;
+            substream.close().await?;
+
+            Ok(state3)
+        });
+
+        let max_seconds = self.timeout.as_secs();
+        self.outbound_stream = OptionFuture::from(Some(
+            async move {
+                protocol.await.map_err(|_| Error::Timeout {
+                    seconds: max_seconds,
+                })?
+            }
+            .boxed(),
+        ));
+    }
+
+    fn inject_event(&mut self, new_swap: Self::InEvent) {
+        self.new_swaps.push_back(new_swap);
+    }
+
+    fn inject_dial_upgrade_error(
+        &mut self,
+        _: Self::OutboundOpenInfo,
+        error: ProtocolsHandlerUpgrErr<Void>,
+    ) {
+        // Unlike for regular swaps, many sellers do not support this protocol, so the
+        // user has to learn about it instead of waiting for a timeout.
+        let error = anyhow!(
+            "Failed to negotiate swap setup, the seller might not buy XMR: {}",
+            error
+        );
+
+        self.outbound_stream = OptionFuture::from(Some(futures::future::ready(Err(error)).boxed()));
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        self.keep_alive
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<
+        ProtocolsHandlerEvent<
+            Self::OutboundProtocol,
+            Self::OutboundOpenInfo,
+            Self::OutEvent,
+            Self::Error,
+        >,
+    > {
+        if let Some(new_swap) = self.new_swaps.pop_front() {
+            self.keep_alive = KeepAlive::Yes;
+            return Poll::Ready(ProtocolsHandlerEvent::OutboundSubstreamRequest {
+                protocol: SubstreamProtocol::new(protocol::reverse(), new_swap),
+            });
+        }
+
+        if let Some(result) = futures::ready!(self.outbound_stream.poll_unpin(cx)) {
+            self.outbound_stream = OptionFuture::from(None);
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(Completed(result)));
+        }
+
+        Poll::Pending
+    }
+}
+
+impl From<ReverseSpotPriceResponse> for Result<bitcoin::Amount, Error> {
+    fn from(response: ReverseSpotPriceResponse) -> Self {
+        match response {
+            ReverseSpotPriceResponse::Btc(amount) => Ok(amount),
+            ReverseSpotPriceResponse::Error(e) => Err(e.into()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, thiserror::Error, PartialEq)]
+pub enum Error {
+    #[error("Seller currently does not buy XMR, please try again later")]
+    NoSwapsAccepted,
+    #[error("Seller refused to buy {sell} because the minimum configured buy limit is {min}")]
+    AmountBelowMinimum {
+        min: monero::Amount,
+        sell: monero::Amount,
+    },
+    #[error("Seller refused to buy {sell} because the maximum configured buy limit is {max}")]
+    AmountAboveMaximum {
+        max: monero::Amount,
+        sell: monero::Amount,
+    },
+    #[error("Seller's BTC balance is currently too low to buy {sell}, please try again later")]
+    BalanceTooLow { sell: monero::Amount },
+
+    #[error("Seller blockchain network {asb:?} setup did not match your blockchain network setup {cli:?}")]
+    BlockchainNetworkMismatch {
+        cli: BlockchainNetwork,
+        asb: BlockchainNetwork,
+    },
+
+    #[error("Seller already has a swap with the same swap id, please try again")]
+    SwapIdTaken,
+
+    #[error("Seller offered {offer} which is less than the {min} its quote promised")]
+    OfferBelowQuote {
+        offer: bitcoin::Amount,
+        min: bitcoin::Amount,
+    },
+
+    #[error("Failed to complete swap setup within {seconds}s")]
+    Timeout { seconds: u64 },
+
+    /// To be used for errors that cannot be explained on the CLI side (e.g.
+    /// rate update problems on the seller side)
+    #[error("Seller encountered a problem, please try again later.")]
+    Other,
+}
+
+impl From<ReverseSpotPriceError> for Error {
+    fn from(error: ReverseSpotPriceError) -> Self {
+        match error {
+            ReverseSpotPriceError::NoSwapsAccepted => Error::NoSwapsAccepted,
+            ReverseSpotPriceError::AmountBelowMinimum { min, sell } => {
+                Error::AmountBelowMinimum { min, sell }
+            }
+            ReverseSpotPriceError::AmountAboveMaximum { max, sell } => {
+                Error::AmountAboveMaximum { max, sell }
+            }
+            ReverseSpotPriceError::BalanceTooLow { sell } => Error::BalanceTooLow { sell },
+            ReverseSpotPriceError::BlockchainNetworkMismatch { cli, asb } => {
+                Error::BlockchainNetworkMismatch { cli, asb }
+            }
+            ReverseSpotPriceError::SwapIdTaken => Error::SwapIdTaken,
+            ReverseSpotPriceError::Other => Error::Other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn min_btc_deducts_the_tolerance_from_the_quoted_price() {
+        let price = bitcoin::Amount::from_btc(0.01).unwrap();
+        let xmr = monero::Amount::from_monero(2.5).unwrap();
+
+        let min_btc = min_btc(price, xmr).unwrap();
+
+        assert_eq!(min_btc, bitcoin::Amount::from_sat(2_450_000));
+    }
+}
@@ -0,0 +1,561 @@
+use crate::asb::config::BuyXmr;
+use crate::asb::LatestRate;
+use crate::network::swap_setup;
+use crate::network::swap_setup::{
+    protocol, BlockchainNetwork, ReverseSpotPriceError, ReverseSpotPriceRequest,
+    ReverseSpotPriceResponse,
+};
+use crate::protocol::bob::{State0, State2};
+use crate::protocol::{Message1, Message3};
+use crate::{asb, bitcoin, env, monero};
+use anyhow::{anyhow, Context, Result};
+use futures::future::{BoxFuture, OptionFuture};
+use futures::{AsyncWriteExt, FutureExt};
+use libp2p::core::connection::ConnectionId;
+use libp2p::core::upgrade;
+use libp2p::swarm::{
+    KeepAlive, NegotiatedSubstream, NetworkBehaviour, NetworkBehaviourAction, PollParameters,
+    ProtocolsHandler, ProtocolsHandlerEvent, ProtocolsHandlerUpgrErr, SubstreamProtocol,
+};
+use libp2p::{Multiaddr, PeerId};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use void::Void;
+
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum OutEvent {
+    Initiated {
+        peer_id: PeerId,
+        setup_id: Uuid,
+        reserve_btc: bmrng::RequestReceiver<(Uuid, bitcoin::Amount), BtcReservation>,
+    },
+    Completed {
+        peer_id: PeerId,
+        setup_id: Uuid,
+        swap_id: Uuid,
+        state2: State2,
+    },
+    Error {
+        peer_id: PeerId,
+        setup_id: Uuid,
+        error: anyhow::Error,
+    },
+}
+
+impl From<OutEvent> for asb::OutEvent {
+    fn from(event: OutEvent) -> Self {
+        match event {
+            OutEvent::Initiated {
+                peer_id,
+                setup_id,
+                reserve_btc,
+            } => asb::OutEvent::ReverseSwapSetupInitiated {
+                peer_id,
+                setup_id,
+                reserve_btc,
+            },
+            OutEvent::Completed {
+                peer_id,
+                setup_id,
+                swap_id,
+                state2,
+            } => asb::OutEvent::ReverseSwapSetupCompleted {
+                peer_id,
+                setup_id,
+                swap_id,
+                state2: Box::new(state2),
+            },
+            OutEvent::Error {
+                peer_id,
+                setup_id,
+                error,
+            } => asb::OutEvent::SwapSetupFailed {
+                peer: peer_id,
+                setup_id,
+                error: anyhow!(error),
+            },
+        }
+    }
+}
+
+/// The answer of the event loop to a request to hold back Bitcoin for a
+/// reverse swap setup.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BtcReservation {
+    Reserved,
+    /// Less Bitcoin than requested is neither locked nor reserved by other
+    /// swaps, nothing was reserved.
+    BalanceTooLow {
+        available: bitcoin::Amount,
+    },
+    /// We already have a swap with the requested swap id, nothing was
+    /// reserved.
+    SwapIdTaken,
+}
+
+#[allow(missing_debug_implementations)]
+pub struct Behaviour<LR> {
+    events: VecDeque<OutEvent>,
+    buy_xmr: Option<BuyXmr>,
+    env_config: env::Config,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+
+    latest_rate: LR,
+    resume_only: bool,
+}
+
+impl<LR> Behaviour<LR> {
+    pub fn new(
+        buy_xmr: Option<BuyXmr>,
+        env_config: env::Config,
+        bitcoin_wallet: Arc<bitcoin::Wallet>,
+        latest_rate: LR,
+        resume_only: bool,
+    ) -> Self {
+        Self {
+            events: Default::default(),
+            buy_xmr,
+            env_config,
+            bitcoin_wallet,
+            latest_rate,
+            resume_only,
+        }
+    }
+}
+
+impl<LR> NetworkBehaviour for Behaviour<LR>
+where
+    LR: LatestRate + Send + 'static + Clone,
+{
+    type ProtocolsHandler = Handler<LR>;
+    type OutEvent = OutEvent;
+
+    fn new_handler(&mut self) -> Self::ProtocolsHandler {
+        Handler::new(
+            self.buy_xmr,
+            self.env_config,
+            self.bitcoin_wallet.clone(),
+            self.latest_rate.clone(),
+            self.resume_only,
+        )
+    }
+
+    fn addresses_of_peer(&mut self, _: &PeerId) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+
+    fn inject_connected(&mut self, _: &PeerId) {}
+
+    fn inject_disconnected(&mut self, _: &PeerId) {}
+
+    fn inject_event(&mut self, peer_id: PeerId, _: ConnectionId, event: HandlerOutEvent) {
+        match event {
+            HandlerOutEvent::Initiated {
+                setup_id,
+                reserve_btc,
+            } => self.events.push_back(OutEvent::Initiated {
+                peer_id,
+                setup_id,
+                reserve_btc,
+            }),
+            HandlerOutEvent::Completed {
+                setup_id,
+                result: Ok((swap_id, state2)),
+            } => self.events.push_back(OutEvent::Completed {
+                peer_id,
+                setup_id,
+                swap_id,
+                state2,
+            }),
+            HandlerOutEvent::Completed {
+                setup_id,
+                result: Err(error),
+            } => self.events.push_back(OutEvent::Error {
+                peer_id,
+                setup_id,
+                error,
+            }),
+        }
+    }
+
+    fn poll(
+        &mut self,
+        _cx: &mut std::task::Context<'_>,
+        _params: &mut impl PollParameters,
+    ) -> Poll<NetworkBehaviourAction<Self::OutEvent, Self::ProtocolsHandler>> {
+        if let Some(event) = self.events.pop_front() {
+            return Poll::Ready(NetworkBehaviourAction::GenerateEvent(event));
+        }
+
+        Poll::Pending
+    }
+}
+
+/// Resolves to the id of the setup together with its outcome.
+type InboundStream = BoxFuture<'static, (Uuid, Result<(Uuid, State2)>)>;
+
+pub struct Handler<LR> {
+    inbound_stream: OptionFuture<InboundStream>,
+    events: VecDeque<HandlerOutEvent>,
+
+    buy_xmr: Option<BuyXmr>,
+    env_config: env::Config,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+
+    latest_rate: LR,
+    resume_only: bool,
+
+    timeout: Duration,
+    keep_alive: KeepAlive,
+}
+
+impl<LR> Handler<LR> {
+    fn new(
+        buy_xmr: Option<BuyXmr>,
+        env_config: env::Config,
+        bitcoin_wallet: Arc<bitcoin::Wallet>,
+        latest_rate: LR,
+        resume_only: bool,
+    ) -> Self {
+        Self {
+            inbound_stream: OptionFuture::from(None),
+            events: Default::default(),
+            buy_xmr,
+            env_config,
+            bitcoin_wallet,
+            latest_rate,
+            resume_only,
+            timeout: Duration::from_secs(120),
+            keep_alive: KeepAlive::Until(Instant::now() + Duration::from_secs(10)),
+        }
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum HandlerOutEvent {
+    Initiated {
+        setup_id: Uuid,
+        reserve_btc: bmrng::RequestReceiver<(Uuid, bitcoin::Amount), BtcReservation>,
+    },
+    Completed {
+        setup_id: Uuid,
+        result: Result<(Uuid, State2)>,
+    },
+}
+
+impl<LR> ProtocolsHandler for Handler<LR>
+where
+    LR: LatestRate + Send + 'static,
+{
+    type InEvent = ();
+    type OutEvent = HandlerOutEvent;
+    type Error = Void;
+    type InboundProtocol = protocol::SwapSetup;
+    type OutboundProtocol = upgrade::DeniedUpgrade;
+    type InboundOpenInfo = ();
+    type OutboundOpenInfo = ();
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        SubstreamProtocol::new(protocol::reverse(), ())
+    }
+
+    fn inject_fully_negotiated_inbound(
+        &mut self,
+        // the reverse protocol has no legacy version
+        (mut substream, _): (NegotiatedSubstream, protocol::Version),
+        _: Self::InboundOpenInfo,
+    ) {
+        self.keep_alive = KeepAlive::Yes;
+
+        let (sender, receiver) = bmrng::channel_with_timeout::<
+            (Uuid, bitcoin::Amount),
+            BtcReservation,
+        >(1, Duration::from_secs(5));
+        let resume_only = self.resume_only;
+        let buy_xmr = self.buy_xmr;
+        let latest_rate = self.latest_rate.latest_rate();
+        let env_config = self.env_config;
+        let bitcoin_wallet = self.bitcoin_wallet.clone();
+
+        let protocol = tokio::time::timeout(self.timeout, async move {
+            let request = swap_setup::read_cbor_message::<ReverseSpotPriceRequest>(&mut substream)
+                .await
+                .context("Failed to read spot price request")?;
+
+            // wrap all of these into another future so we can `return` from all the
+            // different blocks
+            let validate = async {
+                if resume_only {
+                    return Err(Error::ResumeOnlyMode);
+                };
+
+                let buy_xmr = buy_xmr.ok_or(Error::NotBuying)?;
+
+                let blockchain_network = BlockchainNetwork {
+                    bitcoin: env_config.bitcoin_network,
+                    monero: env_config.monero_network,
+                };
+
+                if request.blockchain_network != blockchain_network {
+                    return Err(Error::BlockchainNetworkMismatch {
+                        cli: request.blockchain_network,
+                        asb: blockchain_network,
+                    });
+                }
+
+                let xmr = request.xmr;
+
+                if xmr < buy_xmr.min_buy_xmr {
+                    return Err(Error::AmountBelowMinimum {
+                        min: buy_xmr.min_buy_xmr,
+                        sell: xmr,
+                    });
+                }
+
+                if xmr > buy_xmr.max_buy_xmr {
+                    return Err(Error::AmountAboveMaximum {
+                        max: buy_xmr.max_buy_xmr,
+                        sell: xmr,
+                    });
+                }
+
+                let rate = latest_rate.map_err(|e| Error::LatestRateFetchFailed(Box::new(e)))?;
+                let btc = rate
+                    .buy_quote(xmr, buy_xmr.bid_spread)
+                    .map_err(Error::BuyQuoteCalculationFailed)?;
+
+                // The event loop holds the Bitcoin back until the setup is done, so
+                // concurrent setups are not accepted against the same Bitcoin
+                let reservation = sender
+                    .send_receive((request.swap_id, btc))
+                    .await
+                    .context("Failed to reserve Bitcoin")
+                    .map_err(Error::BalanceCheckFailed)?;
+
+                match reservation {
+                    BtcReservation::Reserved => Ok(btc),
+                    BtcReservation::BalanceTooLow { available } => Err(Error::BalanceTooLow {
+                        balance: available,
+                        sell: xmr,
+                    }),
+                    BtcReservation::SwapIdTaken => Err(Error::SwapIdTaken(request.swap_id)),
+                }
+            };
+
+            let result = validate.await;
+
+            swap_setup::write_cbor_message(
+                &mut substream,
+                ReverseSpotPriceResponse::from_result_ref(&result),
+            )
+            .await
+            .context("Failed to write spot price response")?;
+
+            let btc = result?;
+
+            let refund_address = bitcoin_wallet.new_address().await?;
+            let tx_refund_fee = bitcoin_wallet
+                .estimate_fee(bitcoin::TxRefund::weight(), btc)
+                .await?;
+            let tx_cancel_fee = bitcoin_wallet
+                .estimate_fee(bitcoin::TxCancel::weight(), btc)
+                .await?;
+
+            let state0 = State0::new(
+                request.swap_id,
+                &mut rand::thread_rng(),
+                btc,
+                request.xmr,
+                env_config.bitcoin_cancel_timelock,
+                env_config.bitcoin_punish_timelock,
+                refund_address,
+                env_config.monero_finality_confirmations,
+                tx_refund_fee,
+                tx_cancel_fee,
+            );
+
+            swap_setup::write_cbor_message(&mut substream, state0.next_message())
+                .await
+                .context("Failed to send message0")?;
+
+            let message1 = swap_setup::read_cbor_message::<Message1>(&mut substream)
+                .await
+                .context("Failed to read message1")?;
+            let state1 = state0
+                .receive(bitcoin_wallet.as_ref(), message1)
+                .await
+                .context("Failed to transition state0 -> state1 using message1")?;
+
+            swap_setup::write_cbor_message(&mut substream, state1.next_message())
+                .await
+                .context("Failed to send message2")?;
+
+            let message3 = swap_setup::read_cbor_message::<Message3>(&mut substream)
+                .await
+                .context("Failed to read message3")?;
+            let state2 = state1
+                .receive(message3)
+                .context("Failed to transition state1 -> state2 using message3")?;
+
+            swap_setup::write_cbor_message(&mut substream, state2.next_message())
+                .await
+                .context("Failed to send message4")?;
+
+            substream
+                .flush()
+                .await
+                .context("Failed to flush substream after all messages were sent")?;
+            substream
+                .close()
+                .await
+                .context("Failed to close substream after all messages were sent")?;
+
+            Ok((request.swap_id, state2))
+        });
+
+        // Identifies the setup towards the event loop, a peer may run several at once
+        let setup_id = Uuid::new_v4();
+        let max_seconds = self.timeout.as_secs();
+        self.inbound_stream = OptionFuture::from(Some(
+            async move {
+                let result = protocol.await.with_context(|| {
+                    format!("Failed to complete execution setup within {}s", max_seconds)
+                });
+
+                (setup_id, result.and_then(|outcome| outcome))
+            }
+            .boxed(),
+        ));
+
+        self.events.push_back(HandlerOutEvent::Initiated {
+            setup_id,
+            reserve_btc: receiver,
+        });
+    }
+
+    fn inject_fully_negotiated_outbound(&mut self, _: Void, _: Self::OutboundOpenInfo) {
+        unreachable!("The ASB does not support outbound in the handler")
+    }
+
+    fn inject_event(&mut self, _: Self::InEvent) {
+        unreachable!("The ASB does not receive events from the Behaviour in the handler")
+    }
+
+    fn inject_dial_upgrade_error(
+        &mut self,
+        _: Self::OutboundOpenInfo,
+        _: ProtocolsHandlerUpgrErr<Void>,
+    ) {
+        unreachable!("The ASB does not dial")
+    }
+
+    fn connection_keep_alive(&self) -> KeepAlive {
+        self.keep_alive
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn poll(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<
+        ProtocolsHandlerEvent<
+            Self::OutboundProtocol,
+            Self::OutboundOpenInfo,
+            Self::OutEvent,
+            Self::Error,
+        >,
+    > {
+        if let Some(event) = self.events.pop_front() {
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(event));
+        }
+
+        if let Some((setup_id, result)) = futures::ready!(self.inbound_stream.poll_unpin(cx)) {
+            self.inbound_stream = OptionFuture::from(None);
+            return Poll::Ready(ProtocolsHandlerEvent::Custom(HandlerOutEvent::Completed {
+                setup_id,
+                result,
+            }));
+        }
+
+        Poll::Pending
+    }
+}
+
+impl ReverseSpotPriceResponse {
+    pub fn from_result_ref(result: &Result<bitcoin::Amount, Error>) -> Self {
+        match result {
+            Ok(amount) => ReverseSpotPriceResponse::Btc(*amount),
+            Err(error) => ReverseSpotPriceResponse::Error(error.to_error_response()),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("ASB is running in resume-only mode")]
+    ResumeOnlyMode,
+    #[error("ASB is not configured to buy XMR")]
+    NotBuying,
+    #[error("Amount {sell} below minimum {min}")]
+    AmountBelowMinimum {
+        min: monero::Amount,
+        sell: monero::Amount,
+    },
+    #[error("Amount {sell} above maximum {max}")]
+    AmountAboveMaximum {
+        max: monero::Amount,
+        sell: monero::Amount,
+    },
+    #[error("Balance {balance} too low to fulfill swapping {sell}")]
+    BalanceTooLow {
+        balance: bitcoin::Amount,
+        sell: monero::Amount,
+    },
+    #[error("Swap id {0} is already taken by another swap")]
+    SwapIdTaken(Uuid),
+    #[error("Failed to fetch latest rate")]
+    LatestRateFetchFailed(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
+    #[error("Failed to calculate quote")]
+    BuyQuoteCalculationFailed(#[source] anyhow::Error),
+    #[error("Failed to check Bitcoin balance")]
+    BalanceCheckFailed(#[source] anyhow::Error),
+    #[error("Blockchain networks did not match, we are on {asb:?}, but request from {cli:?}")]
+    BlockchainNetworkMismatch {
+        cli: BlockchainNetwork,
+        asb: BlockchainNetwork,
+    },
+}
+
+impl Error {
+    pub fn to_error_response(&self) -> ReverseSpotPriceError {
+        match self {
+            Error::ResumeOnlyMode | Error::NotBuying => ReverseSpotPriceError::NoSwapsAccepted,
+            Error::AmountBelowMinimum { min, sell } => ReverseSpotPriceError::AmountBelowMinimum {
+                min: *min,
+                sell: *sell,
+            },
+            Error::AmountAboveMaximum { max, sell } => ReverseSpotPriceError::AmountAboveMaximum {
+                max: *max,
+                sell: *sell,
+            },
+            Error::BalanceTooLow { sell, .. } => {
+                ReverseSpotPriceError::BalanceTooLow { sell: *sell }
+            }
+            Error::BlockchainNetworkMismatch { cli, asb } => {
+                ReverseSpotPriceError::BlockchainNetworkMismatch {
+                    cli: *cli,
+                    asb: *asb,
+                }
+            }
+            Error::SwapIdTaken(_) => ReverseSpotPriceError::SwapIdTaken,
+            Error::LatestRateFetchFailed(_)
+            | Error::BuyQuoteCalculationFailed(_)
+            | Error::BalanceCheckFailed(_) => ReverseSpotPriceError::Other,
+        }
+    }
+}
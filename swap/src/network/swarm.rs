@@ -1,3 +1,4 @@
+use crate::asb::config::BuyXmr;
 use crate::asb::LatestRate;
 use crate::libp2p_ext::MultiAddrExt;
 use crate::network::rendezvous::XmrBtcNamespace;
@@ -7,42 +8,54 @@ use anyhow::{Context, Result};
 use libp2p::swarm::{NetworkBehaviour, SwarmBuilder};
 use libp2p::{identity, Multiaddr, Swarm};
 use std::fmt::Debug;
+use std::sync::Arc;
 
 #[allow(clippy::too_many_arguments)]
 pub fn asb<LR>(
     seed: &Seed,
     min_buy: bitcoin::Amount,
     max_buy: bitcoin::Amount,
+    buy_xmr: Option<BuyXmr>,
     latest_rate: LR,
     resume_only: bool,
     env_config: env::Config,
-    rendezvous_params: Option<(Multiaddr, XmrBtcNamespace)>,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    rendezvous_points: Vec<Multiaddr>,
+    namespace: XmrBtcNamespace,
+    tor_socks5_port: Option<u16>,
 ) -> Result<Swarm<asb::Behaviour<LR>>>
 where
     LR: LatestRate + Send + 'static + Debug + Clone,
 {
     let identity = seed.derive_libp2p_identity();
 
-    let rendezvous_params = if let Some((address, namespace)) = rendezvous_params {
-        let peer_id = address
-            .extract_peer_id()
-            .context("Rendezvous node address must contain peer ID")?;
+    let rendezvous_nodes = rendezvous_points
+        .into_iter()
+        .map(|address| {
+            let peer_id = address
+                .extract_peer_id()
+                .context("Rendezvous node address must contain peer ID")?;
 
-        Some((identity.clone(), peer_id, address, namespace))
-    } else {
-        None
-    };
+            // use default ttl on rendezvous point
+            Ok(asb::rendezous::RendezvousNode::new(
+                address, peer_id, namespace, None,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
 
     let behaviour = asb::Behaviour::new(
         min_buy,
         max_buy,
+        buy_xmr,
         latest_rate,
         resume_only,
         env_config,
-        rendezvous_params,
+        bitcoin_wallet,
+        identity.clone(),
+        rendezvous_nodes,
     );
 
-    let transport = asb::transport::new(&identity)?;
+    let transport = asb::transport::new(&identity, tor_socks5_port)?;
     let peer_id = identity.public().into();
 
     let swarm = SwarmBuilder::new(transport, behaviour, peer_id)
@@ -54,20 +67,29 @@ where
     Ok(swarm)
 }
 
+/// Creates the swarm of the CLI, which falls back to connecting directly if
+/// Tor is not running unless `tor_only` is set.
 pub async fn cli<T>(
     identity: identity::Keypair,
     tor_socks5_port: u16,
+    tor_only: bool,
     behaviour: T,
 ) -> Result<Swarm<T>>
 where
     T: NetworkBehaviour,
 {
-    let maybe_tor_socks5_port = match tor::Client::new(tor_socks5_port).assert_tor_running().await {
-        Ok(()) => Some(tor_socks5_port),
-        Err(_) => None,
-    };
+    let tor_running = tor::Client::new(tor_socks5_port).assert_tor_running().await;
 
-    let transport = cli::transport::new(&identity, maybe_tor_socks5_port)?;
+    let transport = match (tor_running, tor_only) {
+        (Ok(()), true) => cli::transport::new_tor_only(&identity, tor_socks5_port)?,
+        (Ok(()), false) => cli::transport::new(&identity, Some(tor_socks5_port))?,
+        (Err(e), true) => {
+            return Err(
+                e.context("Tor is not running, refusing to connect to peers with --tor-only")
+            )
+        }
+        (Err(_), false) => cli::transport::new(&identity, None)?,
+    };
     let peer_id = identity.public().into();
 
     let swarm = SwarmBuilder::new(transport, behaviour, peer_id)
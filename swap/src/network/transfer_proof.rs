@@ -30,18 +30,14 @@ pub struct Request {
     pub tx_lock_proof: monero::TransferProof,
 }
 
-pub fn alice() -> Behaviour {
+/// Constructs a new instance of the `transfer_proof` behaviour.
+///
+/// The ASB and the CLI can both be in the role of Alice or Bob, hence they both
+/// have to send and receive these messages.
+pub fn new() -> Behaviour {
     Behaviour::new(
         CborCodec::default(),
-        vec![(TransferProofProtocol, ProtocolSupport::Outbound)],
-        RequestResponseConfig::default(),
-    )
-}
-
-pub fn bob() -> Behaviour {
-    Behaviour::new(
-        CborCodec::default(),
-        vec![(TransferProofProtocol, ProtocolSupport::Inbound)],
+        vec![(TransferProofProtocol, ProtocolSupport::Full)],
         RequestResponseConfig::default(),
     )
 }
@@ -49,7 +45,13 @@ pub fn bob() -> Behaviour {
 impl From<(PeerId, Message)> for asb::OutEvent {
     fn from((peer, message): (PeerId, Message)) -> Self {
         match message {
-            Message::Request { .. } => Self::unexpected_request(peer),
+            Message::Request {
+                request, channel, ..
+            } => Self::TransferProofReceived {
+                msg: Box::new(request),
+                channel,
+                peer,
+            },
             Message::Response { request_id, .. } => Self::TransferProofAcknowledged {
                 peer,
                 id: request_id,
@@ -69,7 +71,9 @@ impl From<(PeerId, Message)> for cli::OutEvent {
                 channel,
                 peer,
             },
-            Message::Response { .. } => Self::unexpected_response(peer),
+            Message::Response { request_id, .. } => {
+                Self::TransferProofAcknowledged { id: request_id }
+            }
         }
     }
 }
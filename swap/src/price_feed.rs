@@ -0,0 +1,471 @@
+//! Sources for the market price of XMR in BTC.
+//!
+//! Every source produces a [`PriceUpdates`] handle which always holds the
+//! latest price reported by the source. Sources that depend on a remote
+//! server are re-established with an exponential backoff if the connection
+//! fails.
+
+mod aggregate;
+mod bitfinex;
+mod file;
+mod rest;
+
+pub use aggregate::{AggregationMethod, PriceAggregation};
+
+use crate::{kraken, tor};
+use anyhow::{anyhow, Context, Result};
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+use tokio_socks::tcp::Socks5Stream;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use url::Url;
+
+pub const DEFAULT_POLL_INTERVAL_SECS: u64 = 10;
+
+/// Configures where the ASB takes its market price from.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum PriceSource {
+    /// A websocket server that follows the Kraken price ticker protocol.
+    Kraken { ws_url: Url },
+    /// A websocket server that follows the Bitfinex v2 ticker protocol.
+    Bitfinex { ws_url: Url },
+    /// A REST endpoint that is polled for a JSON document containing the ask
+    /// price in BTC.
+    ///
+    /// `ask_pointer` is a JSON pointer (RFC 6901) to the ask price within the
+    /// document, e.g. `/result/XXMRXXBT/a/0`.
+    Rest {
+        url: Url,
+        ask_pointer: String,
+        /// A JSON pointer to the traded volume in XMR, which is only used to
+        /// weigh sources against each other.
+        #[serde(default)]
+        volume_pointer: Option<String>,
+        #[serde(default = "default_poll_interval_secs")]
+        poll_interval_secs: u64,
+    },
+    /// A fixed ask price, intended for testing.
+    Static {
+        #[serde(with = "::bitcoin::util::amount::serde::as_btc")]
+        ask_btc: bitcoin::Amount,
+    },
+    /// A file that contains nothing but the ask price in BTC. The file is
+    /// re-read periodically, so the price can be changed without restarting.
+    File {
+        path: PathBuf,
+        #[serde(default = "default_poll_interval_secs")]
+        poll_interval_secs: u64,
+    },
+}
+
+fn default_poll_interval_secs() -> u64 {
+    DEFAULT_POLL_INTERVAL_SECS
+}
+
+impl PriceSource {
+    /// Start retrieving price updates from this source, through the socks5
+    /// port of Tor if one is given.
+    pub fn connect(&self, tor_socks5_port: Option<u16>) -> Result<PriceUpdates> {
+        let price_updates = match self.clone() {
+            PriceSource::Kraken { ws_url } => kraken::connect(ws_url, tor_socks5_port)?,
+            PriceSource::Bitfinex { ws_url } => bitfinex::connect(ws_url, tor_socks5_port),
+            PriceSource::Rest {
+                url,
+                ask_pointer,
+                volume_pointer,
+                poll_interval_secs,
+            } => rest::connect(
+                url,
+                ask_pointer,
+                volume_pointer,
+                Duration::from_secs(poll_interval_secs),
+                tor_socks5_port,
+            )?,
+            PriceSource::Static { ask_btc } => fixed(ask_btc),
+            PriceSource::File {
+                path,
+                poll_interval_secs,
+            } => file::connect(path, Duration::from_secs(poll_interval_secs)),
+        };
+
+        Ok(price_updates)
+    }
+
+    /// A short name of the source, used in logs.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PriceSource::Kraken { .. } => "kraken",
+            PriceSource::Bitfinex { .. } => "bitfinex",
+            PriceSource::Rest { .. } => "rest",
+            PriceSource::Static { .. } => "static",
+            PriceSource::File { .. } => "file",
+        }
+    }
+}
+
+/// A price source that never changes.
+///
+/// The price is re-reported every second so it is never considered stale.
+pub fn fixed(ask: bitcoin::Amount) -> PriceUpdates {
+    spawn("static", move || {
+        let stream: TickerStream = futures::stream::unfold(true, move |first| async move {
+            if !first {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+
+            Some((Ok(FeedEvent::Ticker(Ticker::new(ask))), false))
+        })
+        .boxed();
+
+        async move { Ok(stream) }.boxed()
+    })
+}
+
+/// A single price reported by a source.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ticker {
+    pub ask: bitcoin::Amount,
+    /// The traded volume in XMR over the last 24 hours, if the source reports
+    /// it.
+    pub volume: Option<Decimal>,
+    /// When the price was received from the source.
+    pub timestamp: Instant,
+    /// When the source last confirmed that this price still holds, either by
+    /// reporting it or by a heartbeat.
+    pub last_seen: Instant,
+}
+
+impl Ticker {
+    pub fn new(ask: bitcoin::Amount) -> Self {
+        let now = Instant::now();
+
+        Self {
+            ask,
+            volume: None,
+            timestamp: now,
+            last_seen: now,
+        }
+    }
+
+    pub fn with_volume(self, volume: Option<Decimal>) -> Self {
+        Self { volume, ..self }
+    }
+
+    /// Mark the price as still valid at `now`.
+    pub fn seen_at(self, now: Instant) -> Self {
+        Self {
+            last_seen: now,
+            ..self
+        }
+    }
+
+    /// How long ago the source last confirmed the price.
+    ///
+    /// Sources that only report a price when it changes keep the price fresh
+    /// with their heartbeats, so a quiet market is not mistaken for a dead
+    /// feed.
+    pub fn age(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.last_seen)
+    }
+}
+
+pub type PriceUpdate = Result<Ticker, Error>;
+
+#[derive(Clone, Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Rate is not yet available")]
+    NotYetAvailable,
+    #[error("Permanently failed to retrieve rate from price source")]
+    PermanentFailure,
+    #[error("Only {healthy} price sources are healthy but at least {required} are required")]
+    InsufficientSources { healthy: usize, required: usize },
+    #[error("Rate is outdated, the last update was received {}s ago", .age.as_secs())]
+    Outdated { age: Duration },
+}
+
+#[derive(Clone, Debug)]
+pub struct PriceUpdates {
+    inner: watch::Receiver<PriceUpdate>,
+}
+
+impl PriceUpdates {
+    pub async fn wait_for_next_update(&mut self) -> Result<PriceUpdate> {
+        self.inner.changed().await?;
+
+        Ok(self.inner.borrow().clone())
+    }
+
+    pub fn latest_update(&mut self) -> PriceUpdate {
+        self.inner.borrow().clone()
+    }
+}
+
+/// Something a price source reported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum FeedEvent {
+    /// A new price.
+    Ticker(Ticker),
+    /// The source is alive and the last price it reported still holds.
+    Heartbeat,
+}
+
+/// A stream of feed events, where every error is already classified as
+/// transient or permanent.
+pub(crate) type TickerStream = BoxStream<'static, Result<FeedEvent, backoff::Error<anyhow::Error>>>;
+
+/// Connect to the websocket at `ws_url`, through the socks5 port of Tor if one
+/// is given and the server does not run on this machine.
+pub(crate) async fn connect_websocket(
+    ws_url: Url,
+    tor_socks5_port: Option<u16>,
+) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
+    let host = ws_url
+        .host_str()
+        .with_context(|| format!("Websocket URL {} has no host", ws_url))?
+        .to_owned();
+
+    let (stream, _) = match tor::socks5_port_for(&host, tor_socks5_port) {
+        Some(socks5_port) => {
+            let port = ws_url
+                .port_or_known_default()
+                .with_context(|| format!("Websocket URL {} has no port", ws_url))?;
+            let stream =
+                Socks5Stream::connect((Ipv4Addr::LOCALHOST, socks5_port), (host.as_str(), port))
+                    .await
+                    .context("Failed to connect through Tor")?;
+
+            tokio_tungstenite::client_async_tls(ws_url, stream.into_inner()).await?
+        }
+        None => tokio_tungstenite::connect_async(ws_url).await?,
+    };
+
+    Ok(stream)
+}
+
+/// Spawns a task that forwards the tickers of the stream returned by `connect`
+/// to the returned [`PriceUpdates`].
+///
+/// A heartbeat re-sends the last ticker of the current connection as seen
+/// now. Heartbeats that arrive before the first ticker of a connection are
+/// ignored, so a reconnect never vouches for a price received earlier.
+///
+/// If establishing the stream fails, the stream returns a transient error or
+/// the stream ends, `connect` is invoked again after an exponential backoff.
+/// A permanent error stops the task and is reported as
+/// [`Error::PermanentFailure`] to all subscribers.
+pub(crate) fn spawn<C>(source: &'static str, mut connect: C) -> PriceUpdates
+where
+    C: FnMut() -> BoxFuture<'static, Result<TickerStream, backoff::Error<anyhow::Error>>>
+        + Send
+        + 'static,
+{
+    let (price_update, price_update_receiver) = watch::channel(Err(Error::NotYetAvailable));
+    let price_update = Arc::new(price_update);
+
+    tokio::spawn(async move {
+        // The default backoff config is fine for us apart from one thing:
+        // `max_elapsed_time`. If we don't get an error within this timeframe,
+        // backoff won't actually retry the operation.
+        let backoff = backoff::ExponentialBackoff {
+            max_elapsed_time: None,
+            ..backoff::ExponentialBackoff::default()
+        };
+
+        let result = backoff::future::retry_notify::<Infallible, _, _, _, _, _>(
+            backoff,
+            || {
+                let price_update = price_update.clone();
+                let stream = connect();
+
+                async move {
+                    let mut stream = stream.await?;
+                    let mut latest = None;
+
+                    while let Some(event) = stream.try_next().await? {
+                        let ticker = match (event, latest) {
+                            (FeedEvent::Ticker(ticker), _) => ticker,
+                            (FeedEvent::Heartbeat, Some(ticker)) => ticker.seen_at(Instant::now()),
+                            (FeedEvent::Heartbeat, None) => continue,
+                        };
+                        latest = Some(ticker);
+
+                        let send_result = price_update.send(Ok(ticker));
+
+                        if send_result.is_err() {
+                            return Err(backoff::Error::Permanent(anyhow!(
+                                "receiver disconnected"
+                            )));
+                        }
+                    }
+
+                    Err(backoff::Error::transient(anyhow!("stream ended")))
+                }
+            },
+            |error, next: Duration| {
+                tracing::info!(
+                    %source,
+                    "Price source failed, retrying in {}ms. Error {:#}",
+                    next.as_millis(),
+                    error
+                );
+            },
+        )
+        .await;
+
+        match result {
+            Err(e) => {
+                tracing::warn!(%source, "Rate updates incurred an unrecoverable error: {:#}", e);
+
+                // in case the retries fail permanently, let the subscribers know
+                price_update.send(Err(Error::PermanentFailure))
+            }
+            Ok(never) => match never {},
+        }
+    });
+
+    PriceUpdates {
+        inner: price_update_receiver,
+    }
+}
+
+/// Parses an ask price in BTC from a JSON value, which may either be a number
+/// or a string containing a number.
+pub(crate) fn parse_ask(value: &serde_json::Value) -> Result<bitcoin::Amount> {
+    let ask = match value {
+        serde_json::Value::String(ask) => {
+            bitcoin::Amount::from_str_in(ask, bitcoin::Denomination::Bitcoin)?
+        }
+        serde_json::Value::Number(ask) => {
+            let ask = ask
+                .as_f64()
+                .ok_or_else(|| anyhow!("Ask price {} is not a valid number", ask))?;
+
+            bitcoin::Amount::from_btc(ask)?
+        }
+        value => anyhow::bail!("Ask price {} is neither a string nor a number", value),
+    };
+
+    Ok(ask)
+}
+
+/// Parses a volume from a JSON value, which may either be a number or a
+/// string containing a number.
+pub(crate) fn parse_volume(value: &serde_json::Value) -> Result<Decimal> {
+    let volume = match value {
+        serde_json::Value::String(volume) => Decimal::from_str(volume)?,
+        serde_json::Value::Number(volume) => volume
+            .as_f64()
+            .and_then(Decimal::from_f64)
+            .ok_or_else(|| anyhow!("Volume {} is not a valid number", volume))?,
+        value => anyhow::bail!("Volume {} is neither a string nor a number", value),
+    };
+
+    Ok(volume)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ask_from_string_and_number() {
+        let from_string = parse_ask(&serde_json::json!("0.00631")).unwrap();
+        let from_number = parse_ask(&serde_json::json!(0.00631)).unwrap();
+
+        assert_eq!(from_string, bitcoin::Amount::from_sat(631_000));
+        assert_eq!(from_number, bitcoin::Amount::from_sat(631_000));
+    }
+
+    #[test]
+    fn rejects_ask_of_unexpected_type() {
+        assert!(parse_ask(&serde_json::json!(["0.00631"])).is_err());
+    }
+
+    #[tokio::test]
+    async fn fixed_source_reports_configured_price() {
+        let ask = bitcoin::Amount::from_sat(631_000);
+        let mut price_updates = fixed(ask);
+
+        let ticker = price_updates.wait_for_next_update().await.unwrap().unwrap();
+
+        assert_eq!(ticker.ask, ask);
+    }
+
+    #[tokio::test]
+    async fn heartbeat_refreshes_age_but_not_timestamp() {
+        let ask = bitcoin::Amount::from_sat(631_000);
+        let events = vec![
+            FeedEvent::Heartbeat,
+            FeedEvent::Ticker(Ticker::new(ask)),
+            FeedEvent::Heartbeat,
+        ];
+        let mut price_updates = spawn("test", move || {
+            let stream: TickerStream = futures::stream::iter(events.clone())
+                .then(|event| async move {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    Ok(event)
+                })
+                .chain(futures::stream::pending())
+                .boxed();
+
+            async move { Ok(stream) }.boxed()
+        });
+
+        let ticker = price_updates.wait_for_next_update().await.unwrap().unwrap();
+        let confirmed = price_updates.wait_for_next_update().await.unwrap().unwrap();
+
+        assert_eq!(ticker.timestamp, ticker.last_seen);
+        assert_eq!(confirmed.ask, ask);
+        assert_eq!(confirmed.timestamp, ticker.timestamp);
+        assert!(confirmed.last_seen > ticker.last_seen);
+    }
+
+    #[test]
+    fn deserializes_price_sources() {
+        let kraken = r#"
+            type = "kraken"
+            ws_url = "wss://ws.kraken.com"
+        "#;
+        let rest = r#"
+            type = "rest"
+            url = "https://api.kraken.com/0/public/Ticker?pair=XMRXBT"
+            ask_pointer = "/result/XXMRXXBT/a/0"
+        "#;
+        let fixed = r#"
+            type = "static"
+            ask_btc = 0.00631
+        "#;
+
+        let kraken = toml::from_str::<PriceSource>(kraken).unwrap();
+        let rest = toml::from_str::<PriceSource>(rest).unwrap();
+        let fixed = toml::from_str::<PriceSource>(fixed).unwrap();
+
+        assert_eq!(kraken, PriceSource::Kraken {
+            ws_url: "wss://ws.kraken.com".parse().unwrap()
+        });
+        assert_eq!(rest, PriceSource::Rest {
+            url: "https://api.kraken.com/0/public/Ticker?pair=XMRXBT"
+                .parse()
+                .unwrap(),
+            ask_pointer: "/result/XXMRXXBT/a/0".to_owned(),
+            volume_pointer: None,
+            poll_interval_secs: DEFAULT_POLL_INTERVAL_SECS
+        });
+        assert_eq!(fixed, PriceSource::Static {
+            ask_btc: bitcoin::Amount::from_sat(631_000)
+        });
+    }
+}
@@ -0,0 +1,390 @@
+use crate::price_feed::{Error, PriceSource, PriceUpdate, PriceUpdates, Ticker};
+use anyhow::Result;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// How often the prices of all sources are re-aggregated.
+///
+/// This also bounds how long it takes to notice that a source went stale.
+const AGGREGATION_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Configures the ASB to take its market price from several sources at once.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PriceAggregation {
+    #[serde(default)]
+    pub method: AggregationMethod,
+    /// The maximum relative deviation of a source from the median of all
+    /// sources, e.g. `0.02` for 2%. Sources deviating further are ignored.
+    pub max_deviation: Decimal,
+    /// Sources that did not report or confirm a price within this many
+    /// seconds are ignored.
+    pub max_age_secs: u64,
+    /// The minimum number of healthy sources needed to report a price.
+    pub min_sources: usize,
+    pub sources: Vec<PriceSource>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AggregationMethod {
+    /// The median of the asking prices of all healthy sources.
+    Median,
+    /// The mean of the asking prices of all healthy sources, weighted by the
+    /// volume each source reports. Sources that do not report a volume are
+    /// not taken into account. Falls back to the median if no healthy source
+    /// reports a volume.
+    VolumeWeightedMean,
+}
+
+impl Default for AggregationMethod {
+    fn default() -> Self {
+        AggregationMethod::Median
+    }
+}
+
+impl PriceAggregation {
+    /// Connect to all configured sources and aggregate their prices.
+    pub fn connect(&self, tor_socks5_port: Option<u16>) -> Result<PriceUpdates> {
+        let feeds = self
+            .sources
+            .iter()
+            .map(|source| Ok((source.name(), source.connect(tor_socks5_port)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(spawn(feeds, self.clone()))
+    }
+
+    fn min_sources(&self) -> usize {
+        self.min_sources.max(1)
+    }
+
+    fn max_age(&self) -> Duration {
+        Duration::from_secs(self.max_age_secs)
+    }
+}
+
+fn spawn(mut feeds: Vec<(&'static str, PriceUpdates)>, config: PriceAggregation) -> PriceUpdates {
+    let (price_update, price_update_receiver) = watch::channel(Err(Error::NotYetAvailable));
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(AGGREGATION_INTERVAL);
+        let mut healthy = false;
+
+        loop {
+            interval.tick().await;
+
+            let updates = feeds
+                .iter_mut()
+                .map(|(source, feed)| (*source, feed.latest_update()))
+                .collect::<Vec<_>>();
+            let update = aggregate(&updates, &config, Instant::now());
+
+            match (&update, healthy) {
+                (Ok(_), false) => {
+                    tracing::info!("Enough price sources are healthy, resuming quoting");
+                    healthy = true;
+                }
+                (Err(error), true) => {
+                    tracing::warn!("Stopped quoting: {}", error);
+                    healthy = false;
+                }
+                _ => {}
+            }
+
+            if price_update.send(update).is_err() {
+                tracing::debug!("Price aggregation stopped because all receivers are gone");
+                return;
+            }
+        }
+    });
+
+    PriceUpdates {
+        inner: price_update_receiver,
+    }
+}
+
+/// Aggregates the latest updates of all sources into a single price.
+///
+/// Sources that failed, are older than the configured maximum age or deviate
+/// too far from the median of all remaining sources are dropped. If fewer than
+/// the configured minimum of sources remain, no price is reported.
+///
+/// The timestamp and the last confirmation of the aggregated price are the ones
+/// of the oldest source that contributed to it.
+pub fn aggregate(
+    updates: &[(&'static str, PriceUpdate)],
+    config: &PriceAggregation,
+    now: Instant,
+) -> PriceUpdate {
+    let required = config.min_sources();
+
+    let fresh = updates
+        .iter()
+        .filter_map(|(source, update)| match update {
+            Ok(ticker) if ticker.age(now) <= config.max_age() => Some((*source, *ticker)),
+            Ok(ticker) => {
+                tracing::debug!(%source, age_secs = %ticker.age(now).as_secs(), "Ignoring stale price");
+                None
+            }
+            Err(error) => {
+                tracing::debug!(%source, "Ignoring price source: {}", error);
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if fresh.len() < required {
+        return Err(Error::InsufficientSources {
+            healthy: fresh.len(),
+            required,
+        });
+    }
+
+    let median_ask = median(
+        fresh
+            .iter()
+            .map(|(_, ticker)| ticker.ask.as_sat())
+            .collect(),
+    );
+    let max_deviation = Decimal::from(median_ask) * config.max_deviation;
+
+    let healthy = fresh
+        .into_iter()
+        .filter(|(source, ticker)| {
+            let deviation = Decimal::from(ticker.ask.as_sat()) - Decimal::from(median_ask);
+
+            if deviation.abs() > max_deviation {
+                tracing::debug!(%source, ask = %ticker.ask, median = %bitcoin::Amount::from_sat(median_ask), "Ignoring outlier price");
+                return false;
+            }
+
+            true
+        })
+        .map(|(_, ticker)| ticker)
+        .collect::<Vec<_>>();
+
+    if healthy.len() < required {
+        return Err(Error::InsufficientSources {
+            healthy: healthy.len(),
+            required,
+        });
+    }
+
+    let ask = match config.method {
+        AggregationMethod::Median => median(healthy.iter().map(|t| t.ask.as_sat()).collect()),
+        AggregationMethod::VolumeWeightedMean => volume_weighted_mean(&healthy)
+            .unwrap_or_else(|| median(healthy.iter().map(|t| t.ask.as_sat()).collect())),
+    };
+    let volume = healthy
+        .iter()
+        .filter_map(|ticker| ticker.volume)
+        .reduce(|total, volume| total + volume);
+    let timestamp = healthy
+        .iter()
+        .map(|ticker| ticker.timestamp)
+        .min()
+        .expect("at least one healthy price source");
+    let last_seen = healthy
+        .iter()
+        .map(|ticker| ticker.last_seen)
+        .min()
+        .expect("at least one healthy price source");
+
+    Ok(Ticker {
+        ask: bitcoin::Amount::from_sat(ask),
+        volume,
+        timestamp,
+        last_seen,
+    })
+}
+
+/// The median of the given amounts, rounded down to the next satoshi.
+fn median(mut sats: Vec<u64>) -> u64 {
+    sats.sort_unstable();
+
+    let middle = sats.len() / 2;
+
+    if sats.len() % 2 == 0 {
+        let lower = sats[middle - 1];
+        let upper = sats[middle];
+
+        lower + (upper - lower) / 2
+    } else {
+        sats[middle]
+    }
+}
+
+fn volume_weighted_mean(tickers: &[Ticker]) -> Option<u64> {
+    let (weighted_sum, total_volume) = tickers
+        .iter()
+        .filter_map(|ticker| Some((Decimal::from(ticker.ask.as_sat()), ticker.volume?)))
+        .fold(
+            (Decimal::ZERO, Decimal::ZERO),
+            |(sum, total), (ask, volume)| (sum + ask * volume, total + volume),
+        );
+
+    if total_volume <= Decimal::ZERO {
+        return None;
+    }
+
+    (weighted_sum / total_volume).floor().to_u64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONE_PERCENT: Decimal = Decimal::from_parts(1, 0, 0, false, 2);
+
+    fn config(method: AggregationMethod, min_sources: usize) -> PriceAggregation {
+        PriceAggregation {
+            method,
+            max_deviation: ONE_PERCENT,
+            max_age_secs: 60,
+            min_sources,
+            sources: vec![],
+        }
+    }
+
+    fn ticker(sats: u64, volume: Option<i64>, timestamp: Instant) -> PriceUpdate {
+        Ok(Ticker {
+            ask: bitcoin::Amount::from_sat(sats),
+            volume: volume.map(Decimal::from),
+            timestamp,
+            last_seen: timestamp,
+        })
+    }
+
+    #[test]
+    fn median_of_odd_and_even_number_of_prices() {
+        assert_eq!(median(vec![3, 1, 2]), 2);
+        assert_eq!(median(vec![4, 1, 3, 2]), 2);
+        assert_eq!(median(vec![5]), 5);
+    }
+
+    #[test]
+    fn aggregates_median_of_all_sources() {
+        let now = Instant::now();
+        let updates = [
+            ("a", ticker(630_000, None, now)),
+            ("b", ticker(631_000, None, now)),
+            ("c", ticker(632_000, None, now)),
+        ];
+
+        let ticker = aggregate(&updates, &config(AggregationMethod::Median, 2), now).unwrap();
+
+        assert_eq!(ticker.ask, bitcoin::Amount::from_sat(631_000));
+    }
+
+    #[test]
+    fn drops_outliers() {
+        let now = Instant::now();
+        let updates = [
+            ("a", ticker(630_000, Some(1), now)),
+            ("b", ticker(632_000, Some(1), now)),
+            ("c", ticker(631_000, Some(1), now)),
+            ("bad tick", ticker(1_000_000, Some(1_000), now)),
+        ];
+
+        let ticker = aggregate(
+            &updates,
+            &config(AggregationMethod::VolumeWeightedMean, 3),
+            now,
+        )
+        .unwrap();
+
+        assert_eq!(ticker.ask, bitcoin::Amount::from_sat(631_000));
+    }
+
+    #[test]
+    fn weighs_prices_by_volume() {
+        let now = Instant::now();
+        let updates = [
+            ("a", ticker(630_000, Some(3), now)),
+            ("b", ticker(634_000, Some(1), now)),
+            ("no volume", ticker(632_000, None, now)),
+        ];
+
+        let ticker = aggregate(
+            &updates,
+            &config(AggregationMethod::VolumeWeightedMean, 2),
+            now,
+        )
+        .unwrap();
+
+        assert_eq!(ticker.ask, bitcoin::Amount::from_sat(631_000));
+        assert_eq!(ticker.volume, Some(Decimal::from(4)));
+    }
+
+    #[test]
+    fn drops_stale_and_failed_sources() {
+        let now = Instant::now();
+        let stale = now - Duration::from_secs(61);
+        let updates = [
+            ("a", ticker(630_000, None, now)),
+            ("stale", ticker(631_000, None, stale)),
+            ("failed", Err(Error::PermanentFailure)),
+        ];
+
+        let error = aggregate(&updates, &config(AggregationMethod::Median, 2), now).unwrap_err();
+
+        assert!(matches!(error, Error::InsufficientSources {
+            healthy: 1,
+            required: 2
+        }));
+    }
+
+    #[test]
+    fn stops_quoting_if_too_few_sources_agree() {
+        let now = Instant::now();
+        let updates = [
+            ("a", ticker(600_000, None, now)),
+            ("b", ticker(630_000, None, now)),
+            ("c", ticker(660_000, None, now)),
+        ];
+
+        let error = aggregate(&updates, &config(AggregationMethod::Median, 2), now).unwrap_err();
+
+        assert!(matches!(error, Error::InsufficientSources {
+            healthy: 1,
+            required: 2
+        }));
+    }
+
+    #[test]
+    fn reports_timestamp_of_oldest_contributing_source() {
+        let now = Instant::now();
+        let older = now - Duration::from_secs(30);
+        let updates = [
+            ("a", ticker(630_000, None, now)),
+            ("b", ticker(631_000, None, older)),
+        ];
+
+        let ticker = aggregate(&updates, &config(AggregationMethod::Median, 2), now).unwrap();
+
+        assert_eq!(ticker.timestamp, older);
+        assert_eq!(ticker.last_seen, older);
+    }
+
+    #[test]
+    fn keeps_sources_that_sent_a_heartbeat() {
+        let now = Instant::now();
+        let long_ago = now - Duration::from_secs(600);
+        let updates = [
+            ("a", ticker(630_000, None, now)),
+            (
+                "quiet",
+                ticker(631_000, None, long_ago).map(|ticker| ticker.seen_at(now)),
+            ),
+        ];
+
+        let ticker = aggregate(&updates, &config(AggregationMethod::Median, 2), now).unwrap();
+
+        assert_eq!(ticker.timestamp, long_ago);
+        assert_eq!(ticker.last_seen, now);
+    }
+}
@@ -0,0 +1,272 @@
+use crate::price_feed::{connect_websocket, spawn, FeedEvent, PriceUpdates, Ticker, TickerStream};
+use anyhow::Context;
+use futures::{FutureExt, SinkExt, StreamExt, TryStreamExt};
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use url::Url;
+
+/// Connect to a websocket server that follows the Bitfinex v2 ticker protocol.
+///
+/// If the connection fails, it will automatically be re-established.
+/// See: https://docs.bitfinex.com/reference#ws-public-ticker
+pub fn connect(ws_url: Url, tor_socks5_port: Option<u16>) -> PriceUpdates {
+    spawn("bitfinex", move || {
+        let ws_url = ws_url.clone();
+
+        async move {
+            let stream = connection::new(ws_url, tor_socks5_port)
+                .await
+                .map_err(backoff::Error::transient)?;
+
+            let stream: TickerStream = stream
+                .map_err(|e| match e {
+                    // Failures while parsing a message are permanent because they most likely
+                    // present a programmer error
+                    connection::Error::Parse(_) => backoff::Error::Permanent(e.into()),
+                    e => backoff::Error::transient(e.into()),
+                })
+                .boxed();
+
+            Ok::<_, backoff::Error<anyhow::Error>>(stream)
+        }
+        .boxed()
+    })
+}
+
+/// Bitfinex websocket connection module.
+///
+/// Responsible for subscribing to the XMR/BTC ticker and transforming the
+/// received websocket frames into a stream of tickers and heartbeats.
+mod connection {
+    use super::*;
+    use futures::stream::BoxStream;
+    use tokio_tungstenite::tungstenite;
+
+    pub async fn new(
+        ws_url: Url,
+        tor_socks5_port: Option<u16>,
+    ) -> anyhow::Result<BoxStream<'static, Result<FeedEvent, Error>>> {
+        let mut rate_stream = connect_websocket(ws_url, tor_socks5_port)
+            .await
+            .context("Failed to connect to Bitfinex websocket API")?;
+
+        rate_stream
+            .send(SUBSCRIBE_XMR_BTC_TICKER_PAYLOAD.into())
+            .await?;
+
+        let stream = rate_stream.err_into().try_filter_map(parse_message).boxed();
+
+        Ok(stream)
+    }
+
+    async fn parse_message(msg: tungstenite::Message) -> Result<Option<FeedEvent>, Error> {
+        let msg = match msg {
+            tungstenite::Message::Text(msg) => msg,
+            tungstenite::Message::Close(_) => {
+                tracing::debug!("Bitfinex rate stream was closed");
+
+                return Err(Error::ConnectionClosed);
+            }
+            msg => {
+                tracing::trace!(
+                    "Bitfinex rate stream returned non text message that will be ignored: {}",
+                    msg
+                );
+
+                return Ok(None);
+            }
+        };
+
+        let message = match serde_json::from_str::<wire::Message>(&msg) {
+            Ok(message) => message,
+            Err(error) => {
+                tracing::warn!(%msg, "Failed to deserialize message as ticker update. Error {:#}", error);
+                return Ok(None);
+            }
+        };
+
+        match message {
+            wire::Message::Event(wire::Event::Info) => {
+                tracing::debug!("Connected to Bitfinex websocket API");
+
+                Ok(None)
+            }
+            wire::Message::Event(wire::Event::Subscribed) => {
+                tracing::debug!("Subscribed to updates for ticker");
+
+                Ok(None)
+            }
+            wire::Message::Event(wire::Event::Error { msg, code }) => {
+                Err(Error::Rejected { msg, code })
+            }
+            wire::Message::Heartbeat(..) => {
+                tracing::trace!("Received heartbeat message");
+
+                Ok(Some(FeedEvent::Heartbeat))
+            }
+            wire::Message::Ticker(_, ticker) => {
+                let ask = ticker.ask().ok_or(wire::Error::MissingAsk)?;
+                let ask = bitcoin::Amount::from_btc(ask).map_err(wire::Error::from)?;
+                let volume = ticker.volume().and_then(Decimal::from_f64);
+
+                Ok(Some(FeedEvent::Ticker(
+                    Ticker::new(ask).with_volume(volume),
+                )))
+            }
+        }
+    }
+
+    #[derive(Debug, thiserror::Error)]
+    pub enum Error {
+        #[error("The Bitfinex server closed the websocket connection")]
+        ConnectionClosed,
+        #[error("Bitfinex rejected the subscription with code {code}: {msg}")]
+        Rejected { msg: String, code: u64 },
+        #[error("Failed to read message from websocket stream")]
+        WebSocket(#[from] tungstenite::Error),
+        #[error("Failed to parse rate from websocket message")]
+        Parse(#[from] wire::Error),
+    }
+
+    const SUBSCRIBE_XMR_BTC_TICKER_PAYLOAD: &str = r#"
+    { "event": "subscribe",
+      "channel": "ticker",
+      "symbol": "tXMRBTC"
+    }"#;
+}
+
+/// Bitfinex websocket API wire module.
+///
+/// Responsible for parsing websocket text messages to events and tickers.
+mod wire {
+    use bitcoin::util::amount::ParseAmountError;
+    use serde::Deserialize;
+
+    #[derive(Debug, thiserror::Error)]
+    pub enum Error {
+        #[error("Ask price is missing in ticker")]
+        MissingAsk,
+        #[error("Failed to parse Bitcoin amount")]
+        BitcoinParseAmount(#[from] ParseAmountError),
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(untagged)]
+    pub enum Message {
+        Event(Event),
+        Heartbeat(u64, Heartbeat),
+        Ticker(u64, TickerData),
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(tag = "event", rename_all = "lowercase")]
+    pub enum Event {
+        Info,
+        Subscribed,
+        Error { msg: String, code: u64 },
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    pub enum Heartbeat {
+        #[serde(rename = "hb")]
+        Beat,
+    }
+
+    /// `[BID, BID_SIZE, ASK, ASK_SIZE, DAILY_CHANGE, DAILY_CHANGE_RELATIVE,
+    /// LAST_PRICE, VOLUME, HIGH, LOW]`
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(transparent)]
+    pub struct TickerData(Vec<f64>);
+
+    impl TickerData {
+        pub fn ask(&self) -> Option<f64> {
+            self.0.get(2).copied()
+        }
+
+        pub fn volume(&self) -> Option<f64> {
+            self.0.get(7).copied()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn can_deserialize_info_event() {
+            let event = r#"{"event":"info","version":2,"serverId":"6f0c0d6c-2a37-4a5c-9b4a-7a3c8a9e1f0d","platform":{"status":1}}"#;
+
+            let event = serde_json::from_str::<Message>(event).unwrap();
+
+            assert_eq!(event, Message::Event(Event::Info))
+        }
+
+        #[test]
+        fn can_deserialize_subscribed_event() {
+            let event = r#"{"event":"subscribed","channel":"ticker","chanId":224555,"symbol":"tXMRBTC","pair":"XMRBTC"}"#;
+
+            let event = serde_json::from_str::<Message>(event).unwrap();
+
+            assert_eq!(event, Message::Event(Event::Subscribed))
+        }
+
+        #[test]
+        fn can_deserialize_heartbeat() {
+            let message = r#"[224555,"hb"]"#;
+
+            let message = serde_json::from_str::<Message>(message).unwrap();
+
+            assert_eq!(message, Message::Heartbeat(224555, Heartbeat::Beat))
+        }
+
+        #[test]
+        fn deserialize_ticker_update() {
+            let message = r#"[224555,[0.0063,512.1,0.00631,498.7,-0.00002,-0.0032,0.00631,1320.5,0.0065,0.0062]]"#;
+
+            let message = serde_json::from_str::<Message>(message).unwrap();
+
+            match message {
+                Message::Ticker(_, ticker) => {
+                    assert_eq!(ticker.ask(), Some(0.00631));
+                    assert_eq!(ticker.volume(), Some(1320.5));
+                }
+                message => panic!("expected ticker but got {:?}", message),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server;
+    use tokio_tungstenite::tungstenite::Message;
+
+    #[tokio::test]
+    async fn receives_ticker_from_mock_server() {
+        let address = mock_server::websocket(|mut ws| async move {
+            ws.send(Message::Text(r#"{"event":"info","version":2}"#.to_owned()))
+                .await
+                .unwrap();
+            let _subscribe = ws.next().await.unwrap().unwrap();
+            ws.send(Message::Text(r#"{"event":"subscribed","channel":"ticker","chanId":1,"symbol":"tXMRBTC","pair":"XMRBTC"}"#.to_owned()))
+                .await
+                .unwrap();
+            ws.send(Message::Text(
+                r#"[1,[0.0063,512.1,0.00631,498.7,-0.00002,-0.0032,0.00631,1320.5,0.0065,0.0062]]"#
+                    .to_owned(),
+            ))
+            .await
+            .unwrap();
+
+            futures::future::pending::<()>().await;
+        })
+        .await;
+
+        let mut price_updates = connect(format!("ws://{}", address).parse().unwrap(), None);
+
+        let ticker = price_updates.wait_for_next_update().await.unwrap().unwrap();
+
+        assert_eq!(ticker.ask, bitcoin::Amount::from_sat(631_000));
+    }
+}
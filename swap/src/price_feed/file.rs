@@ -0,0 +1,78 @@
+use crate::price_feed::{spawn, FeedEvent, PriceUpdates, Ticker, TickerStream};
+use anyhow::{Context, Result};
+use futures::{FutureExt, StreamExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Periodically read the ask price from a file.
+///
+/// The file must contain nothing but the ask price for 1 XMR in BTC, e.g.
+/// `0.00631`. Surrounding whitespace is ignored.
+pub fn connect(path: PathBuf, poll_interval: Duration) -> PriceUpdates {
+    spawn("file", move || {
+        let path = path.clone();
+
+        async move {
+            let stream: TickerStream = futures::stream::unfold(true, move |first| {
+                let path = path.clone();
+
+                async move {
+                    if !first {
+                        tokio::time::sleep(poll_interval).await;
+                    }
+
+                    let ticker = read(&path)
+                        .await
+                        .map(FeedEvent::Ticker)
+                        .map_err(backoff::Error::transient);
+
+                    Some((ticker, false))
+                }
+            })
+            .boxed();
+
+            Ok::<_, backoff::Error<anyhow::Error>>(stream)
+        }
+        .boxed()
+    })
+}
+
+async fn read(path: &Path) -> Result<Ticker> {
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("Failed to read price from {}", path.display()))?;
+    let ask = bitcoin::Amount::from_str_in(content.trim(), bitcoin::Denomination::Bitcoin)
+        .with_context(|| format!("Failed to parse price in {}", path.display()))?;
+
+    Ok(Ticker::new(ask))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn reads_price_from_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("price");
+        tokio::fs::write(&path, "0.00631\n").await.unwrap();
+
+        let mut price_updates = connect(path, Duration::from_secs(60));
+
+        let ticker = price_updates.wait_for_next_update().await.unwrap().unwrap();
+
+        assert_eq!(ticker.ask, bitcoin::Amount::from_sat(631_000));
+    }
+
+    #[tokio::test]
+    async fn fails_to_read_malformed_price() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("price");
+        tokio::fs::write(&path, "not a price").await.unwrap();
+
+        let result = read(&path).await;
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use crate::price_feed::{
+    parse_ask, parse_volume, spawn, FeedEvent, PriceUpdates, Ticker, TickerStream,
+};
+use crate::tor;
+use anyhow::{Context, Result};
+use futures::{FutureExt, StreamExt};
+use std::time::Duration;
+use url::Url;
+
+/// Poll a REST endpoint for the current ask price.
+///
+/// The endpoint has to respond with a JSON document; the ask price is looked
+/// up within the document using `ask_pointer`, the traded volume using the
+/// optional `volume_pointer`.
+pub fn connect(
+    url: Url,
+    ask_pointer: String,
+    volume_pointer: Option<String>,
+    poll_interval: Duration,
+    tor_socks5_port: Option<u16>,
+) -> Result<PriceUpdates> {
+    let client = match tor_socks5_port {
+        Some(port) => reqwest::Client::builder()
+            .proxy(tor::reqwest_proxy(port))
+            .build()?,
+        None => reqwest::Client::new(),
+    };
+
+    let price_updates = spawn("rest", move || {
+        let client = client.clone();
+        let url = url.clone();
+        let ask_pointer = ask_pointer.clone();
+        let volume_pointer = volume_pointer.clone();
+
+        async move {
+            let stream: TickerStream = futures::stream::unfold(true, move |first| {
+                let client = client.clone();
+                let url = url.clone();
+                let ask_pointer = ask_pointer.clone();
+                let volume_pointer = volume_pointer.clone();
+
+                async move {
+                    if !first {
+                        tokio::time::sleep(poll_interval).await;
+                    }
+
+                    let ticker = fetch(&client, url, &ask_pointer, volume_pointer.as_deref())
+                        .await
+                        .map(FeedEvent::Ticker)
+                        .map_err(backoff::Error::transient);
+
+                    Some((ticker, false))
+                }
+            })
+            .boxed();
+
+            Ok::<_, backoff::Error<anyhow::Error>>(stream)
+        }
+        .boxed()
+    });
+
+    Ok(price_updates)
+}
+
+async fn fetch(
+    client: &reqwest::Client,
+    url: Url,
+    ask_pointer: &str,
+    volume_pointer: Option<&str>,
+) -> Result<Ticker> {
+    let body = client
+        .get(url.clone())
+        .send()
+        .await
+        .with_context(|| format!("Failed to request price from {}", url))?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    let document = serde_json::from_str::<serde_json::Value>(&body)
+        .context("Price endpoint did not respond with JSON")?;
+    let ask = document
+        .pointer(ask_pointer)
+        .with_context(|| format!("No value at {} in price response", ask_pointer))?;
+    let ask = parse_ask(ask)?;
+    let volume = match volume_pointer {
+        Some(volume_pointer) => {
+            let volume = document
+                .pointer(volume_pointer)
+                .with_context(|| format!("No value at {} in price response", volume_pointer))?;
+
+            Some(parse_volume(volume)?)
+        }
+        None => None,
+    };
+
+    Ok(Ticker::new(ask).with_volume(volume))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{self, Response};
+
+    /// Serves `body` as JSON to every request.
+    async fn serve_json(body: &str) -> Url {
+        let (address, _) = mock_server::http(std::iter::repeat(Response::json(body))).await;
+
+        format!("http://{}/ticker", address).parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn reads_ask_price_from_json_response() {
+        let url =
+            serve_json(r#"{"error":[],"result":{"XXMRXXBT":{"a":["0.00631000","7","7.000"]}}}"#)
+                .await;
+        let mut price_updates = connect(
+            url,
+            "/result/XXMRXXBT/a/0".to_owned(),
+            None,
+            Duration::from_secs(60),
+            None,
+        )
+        .unwrap();
+
+        let ticker = price_updates.wait_for_next_update().await.unwrap().unwrap();
+
+        assert_eq!(ticker.ask, bitcoin::Amount::from_sat(631_000));
+    }
+
+    #[tokio::test]
+    async fn fails_to_fetch_if_pointer_does_not_match() {
+        let url = serve_json(r#"{"price":0.00631}"#).await;
+
+        let result = fetch(&reqwest::Client::new(), url, "/ask", None).await;
+
+        assert!(result.is_err());
+    }
+}
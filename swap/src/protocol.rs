@@ -1,3 +1,4 @@
+use crate::network::quote::BidQuote;
 use crate::protocol::alice::swap::is_complete as alice_is_complete;
 use crate::protocol::alice::AliceState;
 use crate::protocol::bob::swap::is_complete as bob_is_complete;
@@ -13,10 +14,13 @@ use sha2::Sha256;
 use sigma_fun::ext::dl_secp256k1_ed25519_eq::{CrossCurveDLEQ, CrossCurveDLEQProof};
 use sigma_fun::HashTranscript;
 use std::convert::TryInto;
+use std::fmt;
 use uuid::Uuid;
 
 pub mod alice;
 pub mod bob;
+pub mod event;
+pub mod recovery;
 
 pub static CROSS_CURVE_PROOF_SYSTEM: Lazy<
     CrossCurveDLEQ<HashTranscript<Sha256, rand_chacha::ChaCha20Rng>>,
@@ -66,12 +70,20 @@ pub struct Message2 {
 pub struct Message3 {
     tx_cancel_sig: bitcoin::Signature,
     tx_refund_encsig: bitcoin::EncryptedSignature,
+    /// The signatures for the cancel and refund transactions at every fee in
+    /// [`bitcoin::TxCancel::fee_bumps`].
+    #[serde(default)]
+    tx_cancel_fee_bumps: Vec<(bitcoin::Signature, bitcoin::EncryptedSignature)>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Message4 {
     tx_punish_sig: bitcoin::Signature,
     tx_cancel_sig: bitcoin::Signature,
+    /// The signatures for the cancel and punish transactions at every fee in
+    /// [`bitcoin::TxCancel::fee_bumps`] Alice signed at.
+    #[serde(default)]
+    tx_cancel_fee_bumps: Vec<(bitcoin::Signature, bitcoin::Signature)>,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -90,6 +102,15 @@ impl State {
     }
 }
 
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            State::Alice(state) => write!(f, "{}", state),
+            State::Bob(state) => write!(f, "{}", state),
+        }
+    }
+}
+
 impl From<AliceState> for State {
     fn from(alice: AliceState) -> Self {
         Self::Alice(alice)
@@ -143,4 +164,14 @@ pub trait Database {
     async fn insert_latest_state(&self, swap_id: Uuid, state: State) -> Result<()>;
     async fn get_state(&self, swap_id: Uuid) -> Result<State>;
     async fn all(&self) -> Result<Vec<(Uuid, State)>>;
+    async fn insert_monero_reservation(&self, swap_id: Uuid, amount: monero::Amount) -> Result<()>;
+    async fn remove_monero_reservation(&self, swap_id: Uuid) -> Result<()>;
+    async fn all_monero_reservations(&self) -> Result<Vec<(Uuid, monero::Amount)>>;
+    async fn insert_seller_observation(
+        &self,
+        peer_id: PeerId,
+        address: Multiaddr,
+        quote: Option<BidQuote>,
+    ) -> Result<()>;
+    async fn all_seller_observations(&self) -> Result<Vec<(PeerId, Multiaddr, Option<BidQuote>)>>;
 }
@@ -1,6 +1,7 @@
 //! Run an XMR/BTC swap in the role of Alice.
 //! Alice holds XMR and wishes receive BTC.
 use crate::env::Config;
+use crate::protocol::event::SwapEvents;
 use crate::protocol::Database;
 use crate::{asb, bitcoin, monero};
 use std::sync::Arc;
@@ -20,4 +21,5 @@ pub struct Swap {
     pub env_config: Config,
     pub swap_id: Uuid,
     pub db: Arc<dyn Database + Send + Sync>,
+    pub events: SwapEvents,
 }
@@ -1,3 +1,4 @@
+use crate::bitcoin::wallet::ScriptStatus;
 use crate::bitcoin::{
     current_epoch, CancelTimelock, ExpiredTimelocks, PunishTimelock, Transaction, TxCancel,
     TxPunish, TxRedeem, TxRefund, Txid,
@@ -13,7 +14,8 @@ use monero_rpc::wallet::BlockHeight;
 use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
 use sigma_fun::ext::dl_secp256k1_ed25519_eq::CrossCurveDLEQProof;
-use std::fmt;
+use std::time::Duration;
+use std::{fmt, iter};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -253,6 +255,7 @@ impl State1 {
     pub fn receive(self, msg: Message2) -> Result<State2> {
         let tx_lock = bitcoin::TxLock::from_psbt(msg.psbt, self.a.public(), self.B, self.btc)
             .context("Failed to re-construct TxLock from received PSBT")?;
+        let tx_cancel_fee_bumps = TxCancel::fee_bumps(self.tx_cancel_fee, tx_lock.lock_amount());
 
         Ok(State2 {
             a: self.a,
@@ -273,6 +276,7 @@ impl State1 {
             tx_punish_fee: self.tx_punish_fee,
             tx_refund_fee: self.tx_refund_fee,
             tx_cancel_fee: self.tx_cancel_fee,
+            tx_cancel_fee_bumps,
         })
     }
 }
@@ -297,9 +301,21 @@ pub struct State2 {
     tx_punish_fee: bitcoin::Amount,
     tx_refund_fee: bitcoin::Amount,
     tx_cancel_fee: bitcoin::Amount,
+    /// The fees the cancel transaction is signed at in addition to
+    /// `tx_cancel_fee`.
+    tx_cancel_fee_bumps: Vec<bitcoin::Amount>,
 }
 
 impl State2 {
+    /// Only sign the cancel transaction at the negotiated fee, for a peer
+    /// that does not support fee bumps.
+    pub fn without_fee_bumps(self) -> Self {
+        Self {
+            tx_cancel_fee_bumps: Vec::new(),
+            ..self
+        }
+    }
+
     pub fn next_message(&self) -> Message3 {
         let tx_cancel = bitcoin::TxCancel::new(
             &self.tx_lock,
@@ -319,12 +335,39 @@ impl State2 {
         let tx_refund_encsig = self.a.encsign(self.S_b_bitcoin, tx_refund.digest());
 
         let tx_cancel_sig = self.a.sign(tx_cancel.digest());
+
+        let tx_cancel_fee_bumps = self
+            .tx_cancel_fee_bumps
+            .iter()
+            .map(|tx_cancel_fee| {
+                let tx_cancel = self.tx_cancel_with_fee(*tx_cancel_fee);
+                let tx_refund =
+                    bitcoin::TxRefund::new(&tx_cancel, &self.refund_address, self.tx_refund_fee);
+
+                (
+                    self.a.sign(tx_cancel.digest()),
+                    self.a.encsign(self.S_b_bitcoin, tx_refund.digest()),
+                )
+            })
+            .collect();
+
         Message3 {
             tx_cancel_sig,
             tx_refund_encsig,
+            tx_cancel_fee_bumps,
         }
     }
 
+    fn tx_cancel_with_fee(&self, tx_cancel_fee: bitcoin::Amount) -> TxCancel {
+        TxCancel::new(
+            &self.tx_lock,
+            self.cancel_timelock,
+            self.a.public(),
+            self.B,
+            tx_cancel_fee,
+        )
+    }
+
     pub fn receive(self, msg: Message4) -> Result<State3> {
         let tx_cancel = bitcoin::TxCancel::new(
             &self.tx_lock,
@@ -344,6 +387,40 @@ impl State2 {
         bitcoin::verify_sig(&self.B, &tx_punish.digest(), &msg.tx_punish_sig)
             .context("Failed to verify punish transaction")?;
 
+        if msg.tx_cancel_fee_bumps.len() != self.tx_cancel_fee_bumps.len() {
+            bail!(
+                "Bob signed the cancel transaction at {} bumped fees instead of {}",
+                msg.tx_cancel_fee_bumps.len(),
+                self.tx_cancel_fee_bumps.len()
+            )
+        }
+
+        let tx_cancel_fee_bumps = self
+            .tx_cancel_fee_bumps
+            .iter()
+            .copied()
+            .zip(msg.tx_cancel_fee_bumps)
+            .map(|(tx_cancel_fee, (tx_cancel_sig_bob, tx_punish_sig_bob))| {
+                let tx_cancel = self.tx_cancel_with_fee(tx_cancel_fee);
+                bitcoin::verify_sig(&self.B, &tx_cancel.digest(), &tx_cancel_sig_bob)
+                    .context("Failed to verify cancel transaction")?;
+                let tx_punish = bitcoin::TxPunish::new(
+                    &tx_cancel,
+                    &self.punish_address,
+                    self.punish_timelock,
+                    self.tx_punish_fee,
+                );
+                bitcoin::verify_sig(&self.B, &tx_punish.digest(), &tx_punish_sig_bob)
+                    .context("Failed to verify punish transaction")?;
+
+                Ok(CancelFeeBump {
+                    tx_cancel_fee,
+                    tx_cancel_sig_bob,
+                    tx_punish_sig_bob,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
         Ok(State3 {
             a: self.a,
             B: self.B,
@@ -361,6 +438,7 @@ impl State2 {
             tx_lock: self.tx_lock,
             tx_punish_sig_bob: msg.tx_punish_sig,
             tx_cancel_sig_bob: msg.tx_cancel_sig,
+            tx_cancel_fee_bumps,
             tx_redeem_fee: self.tx_redeem_fee,
             tx_punish_fee: self.tx_punish_fee,
             tx_refund_fee: self.tx_refund_fee,
@@ -388,6 +466,8 @@ pub struct State3 {
     pub tx_lock: bitcoin::TxLock,
     tx_punish_sig_bob: bitcoin::Signature,
     tx_cancel_sig_bob: bitcoin::Signature,
+    #[serde(default)]
+    tx_cancel_fee_bumps: Vec<CancelFeeBump>,
     #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
     tx_redeem_fee: bitcoin::Amount,
     #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
@@ -403,10 +483,11 @@ impl State3 {
         &self,
         bitcoin_wallet: &bitcoin::Wallet,
     ) -> Result<ExpiredTimelocks> {
-        let tx_cancel = self.tx_cancel();
-
         let tx_lock_status = bitcoin_wallet.status_of_script(&self.tx_lock).await?;
-        let tx_cancel_status = bitcoin_wallet.status_of_script(&tx_cancel).await?;
+        let tx_cancel_status = bitcoin_wallet
+            .status_of_conflicting(&self.tx_cancels())
+            .await?
+            .map_or(ScriptStatus::Unseen, |(_, status)| status);
 
         Ok(current_epoch(
             self.cancel_timelock,
@@ -416,6 +497,11 @@ impl State3 {
         ))
     }
 
+    /// The amount of Monero we lock in this swap.
+    pub fn xmr(&self) -> monero::Amount {
+        self.xmr
+    }
+
     pub fn lock_xmr_transfer_request(&self) -> TransferRequest {
         let S_a = monero::PublicKey::from_private_key(&monero::PrivateKey { scalar: self.s_a });
 
@@ -447,18 +533,59 @@ impl State3 {
         }
     }
 
+    /// The cancel transaction at the negotiated fee.
     pub fn tx_cancel(&self) -> TxCancel {
+        self.tx_cancel_with_fee(self.tx_cancel_fee)
+    }
+
+    fn tx_cancel_with_fee(&self, tx_cancel_fee: bitcoin::Amount) -> TxCancel {
         TxCancel::new(
             &self.tx_lock,
             self.cancel_timelock,
             self.a.public(),
             self.B,
-            self.tx_cancel_fee,
+            tx_cancel_fee,
         )
     }
 
-    pub fn tx_refund(&self) -> TxRefund {
-        bitcoin::TxRefund::new(&self.tx_cancel(), &self.refund_address, self.tx_refund_fee)
+    /// The cancel transaction at the negotiated fee, followed by the ones at
+    /// bumped fees.
+    fn tx_cancels(&self) -> Vec<TxCancel> {
+        self.cancel_variants()
+            .iter()
+            .map(|variant| self.tx_cancel_with_fee(variant.tx_cancel_fee))
+            .collect()
+    }
+
+    /// Bob's signatures for every cancel transaction in the order of
+    /// [`State3::tx_cancels`].
+    fn cancel_variants(&self) -> Vec<CancelFeeBump> {
+        let negotiated = CancelFeeBump {
+            tx_cancel_fee: self.tx_cancel_fee,
+            tx_cancel_sig_bob: self.tx_cancel_sig_bob.clone(),
+            tx_punish_sig_bob: self.tx_punish_sig_bob.clone(),
+        };
+
+        iter::once(negotiated)
+            .chain(self.tx_cancel_fee_bumps.iter().cloned())
+            .collect()
+    }
+
+    /// Wait until one of the cancel transactions has been published.
+    pub async fn watch_for_tx_cancel(&self, bitcoin_wallet: &bitcoin::Wallet) -> Result<TxCancel> {
+        loop {
+            let mut tx_cancels = self.tx_cancels();
+
+            if let Some((index, _)) = bitcoin_wallet.status_of_conflicting(&tx_cancels).await? {
+                return Ok(tx_cancels.swap_remove(index));
+            }
+
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        }
+    }
+
+    pub fn tx_refund_spending(&self, tx_cancel: &TxCancel) -> TxRefund {
+        bitcoin::TxRefund::new(tx_cancel, &self.refund_address, self.tx_refund_fee)
     }
 
     pub fn tx_redeem(&self) -> TxRedeem {
@@ -469,7 +596,14 @@ impl State3 {
         &self,
         published_refund_tx: bitcoin::Transaction,
     ) -> Result<monero::PrivateKey> {
-        self.tx_refund().extract_monero_private_key(
+        let tx_refund = self
+            .tx_cancels()
+            .iter()
+            .map(|tx_cancel| self.tx_refund_spending(tx_cancel))
+            .find(|tx_refund| tx_refund.txid() == published_refund_tx.txid())
+            .context("Published transaction is not a refund transaction of this swap")?;
+
+        tx_refund.extract_monero_private_key(
             published_refund_tx,
             self.s_a,
             self.a.clone(),
@@ -481,23 +615,45 @@ impl State3 {
         &self,
         bitcoin_wallet: &bitcoin::Wallet,
     ) -> Result<Transaction> {
-        let tx_cancel = self.tx_cancel();
-        let tx = bitcoin_wallet.get_raw_transaction(tx_cancel.txid()).await?;
-        Ok(tx)
+        for tx_cancel in self.tx_cancels() {
+            if let Ok(Some(tx)) = bitcoin_wallet.get_tx(tx_cancel.txid()).await {
+                return Ok(tx);
+            }
+        }
+
+        bail!("Cancel transaction has not been published")
     }
 
     pub async fn fetch_tx_refund(&self, bitcoin_wallet: &bitcoin::Wallet) -> Result<Transaction> {
-        let tx_refund = self.tx_refund();
-        let tx = bitcoin_wallet.get_raw_transaction(tx_refund.txid()).await?;
-        Ok(tx)
+        for tx_cancel in self.tx_cancels() {
+            let tx_refund = self.tx_refund_spending(&tx_cancel);
+
+            if let Ok(Some(tx)) = bitcoin_wallet.get_tx(tx_refund.txid()).await {
+                return Ok(tx);
+            }
+        }
+
+        bail!("Refund transaction has not been published")
     }
 
+    /// Publish the cancel transaction at the negotiated fee.
     pub async fn submit_tx_cancel(&self, bitcoin_wallet: &bitcoin::Wallet) -> Result<Txid> {
         let transaction = self.signed_cancel_transaction()?;
+
         let (tx_id, _) = bitcoin_wallet.broadcast(transaction, "cancel").await?;
+
         Ok(tx_id)
     }
 
+    /// The cancel transaction at the negotiated fee followed by the ones at
+    /// bumped fees, ready to be published.
+    pub fn signed_cancel_transactions(&self) -> Result<Vec<Transaction>> {
+        self.cancel_variants()
+            .into_iter()
+            .map(|variant| self.signed_cancel_transaction_for(variant))
+            .collect()
+    }
+
     pub async fn refund_xmr(
         &self,
         monero_wallet: &monero::Wallet,
@@ -527,9 +683,17 @@ impl State3 {
     }
 
     pub async fn punish_btc(&self, bitcoin_wallet: &bitcoin::Wallet) -> Result<Txid> {
-        let signed_tx_punish = self.signed_punish_transaction()?;
+        let (index, _) = bitcoin_wallet
+            .status_of_conflicting(&self.tx_cancels())
+            .await?
+            .context("Cancel transaction has not been published")?;
+        let variant = self.cancel_variants().swap_remove(index);
+        let signed_tx_punish = self.signed_punish_transaction_for(variant)?;
 
         let (txid, subscription) = bitcoin_wallet.broadcast(signed_tx_punish, "punish").await?;
+        bitcoin_wallet
+            .wait_until_confirmed_or_bump(&subscription, "punish")
+            .await?;
         subscription.wait_until_final().await?;
 
         Ok(txid)
@@ -545,23 +709,134 @@ impl State3 {
     }
 
     pub fn signed_cancel_transaction(&self) -> Result<bitcoin::Transaction> {
-        self.tx_cancel()
-            .complete_as_alice(self.a.clone(), self.B, self.tx_cancel_sig_bob.clone())
+        let negotiated = self.cancel_variants().swap_remove(0);
+
+        self.signed_cancel_transaction_for(negotiated)
+    }
+
+    fn signed_cancel_transaction_for(
+        &self,
+        variant: CancelFeeBump,
+    ) -> Result<bitcoin::Transaction> {
+        self.tx_cancel_with_fee(variant.tx_cancel_fee)
+            .complete_as_alice(self.a.clone(), self.B, variant.tx_cancel_sig_bob)
             .context("Failed to complete Bitcoin cancel transaction")
     }
 
     pub fn signed_punish_transaction(&self) -> Result<bitcoin::Transaction> {
-        self.tx_punish()
-            .complete(self.tx_punish_sig_bob.clone(), self.a.clone(), self.B)
+        let negotiated = self.cancel_variants().swap_remove(0);
+
+        self.signed_punish_transaction_for(negotiated)
+    }
+
+    fn signed_punish_transaction_for(
+        &self,
+        variant: CancelFeeBump,
+    ) -> Result<bitcoin::Transaction> {
+        self.tx_punish_spending(&self.tx_cancel_with_fee(variant.tx_cancel_fee))
+            .complete(variant.tx_punish_sig_bob, self.a.clone(), self.B)
             .context("Failed to complete Bitcoin punish transaction")
     }
 
-    fn tx_punish(&self) -> TxPunish {
+    fn tx_punish_spending(&self, tx_cancel: &TxCancel) -> TxPunish {
         bitcoin::TxPunish::new(
-            &self.tx_cancel(),
+            tx_cancel,
             &self.punish_address,
             self.punish_timelock,
             self.tx_punish_fee,
         )
     }
 }
+
+/// Bob's signatures for the cancel transaction at one of the fees of
+/// [`TxCancel::fee_bumps`] and for the punish transaction spending it.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct CancelFeeBump {
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+    tx_cancel_fee: bitcoin::Amount,
+    tx_cancel_sig_bob: bitcoin::Signature,
+    tx_punish_sig_bob: bitcoin::Signature,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::WalletBuilder;
+    use crate::env::{GetConfig, Regtest};
+    use crate::protocol::bob;
+    use rand::rngs::OsRng;
+
+    /// Alice and Bob right before Alice sends `Message3`.
+    async fn set_up_until_message3() -> (State2, bob::State1) {
+        let alice_wallet = WalletBuilder::new(bitcoin::Amount::ONE_BTC.as_sat()).build();
+        let bob_wallet = WalletBuilder::new(bitcoin::Amount::ONE_BTC.as_sat()).build();
+        let spending_fee = bitcoin::Amount::from_sat(1_000);
+        let btc_amount = bitcoin::Amount::from_sat(500_000);
+        let xmr_amount = monero::Amount::from_piconero(10000);
+        let config = Regtest::get_config();
+
+        let alice_state0 = State0::new(
+            btc_amount,
+            xmr_amount,
+            config,
+            alice_wallet.new_address().await.unwrap(),
+            alice_wallet.new_address().await.unwrap(),
+            spending_fee,
+            spending_fee,
+            &mut OsRng,
+        );
+        let bob_state0 = bob::State0::new(
+            Uuid::new_v4(),
+            &mut OsRng,
+            btc_amount,
+            xmr_amount,
+            config.bitcoin_cancel_timelock,
+            config.bitcoin_punish_timelock,
+            bob_wallet.new_address().await.unwrap(),
+            config.monero_finality_confirmations,
+            spending_fee,
+            spending_fee,
+        );
+
+        let (_, alice_state1) = alice_state0.receive(bob_state0.next_message()).unwrap();
+        let bob_state1 = bob_state0
+            .receive(&bob_wallet, alice_state1.next_message())
+            .await
+            .unwrap();
+        let alice_state2 = alice_state1.receive(bob_state1.next_message()).unwrap();
+
+        (alice_state2, bob_state1)
+    }
+
+    #[tokio::test]
+    async fn given_message4_without_all_fee_bumps_alice_rejects_it() {
+        let (alice_state2, bob_state1) = set_up_until_message3().await;
+        let bob_state2 = bob_state1.receive(alice_state2.next_message()).unwrap();
+
+        let mut message4 = bob_state2.next_message();
+        assert_eq!(message4.tx_cancel_fee_bumps.len(), 3);
+        message4.tx_cancel_fee_bumps.pop();
+        assert!(alice_state2.clone().receive(message4).is_err());
+
+        let mut message4 = bob_state2.next_message();
+        message4.tx_cancel_fee_bumps.clear();
+        assert!(alice_state2.clone().receive(message4).is_err());
+
+        assert!(alice_state2.receive(bob_state2.next_message()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn given_legacy_peer_no_fee_bumps_are_exchanged() {
+        let (alice_state2, bob_state1) = set_up_until_message3().await;
+        let alice_state2 = alice_state2.without_fee_bumps();
+        let bob_state1 = bob_state1.without_fee_bumps();
+
+        let message3 = alice_state2.next_message();
+        assert!(message3.tx_cancel_fee_bumps.is_empty());
+        let bob_state2 = bob_state1.receive(message3).unwrap();
+
+        let message4 = bob_state2.next_message();
+        assert!(message4.tx_cancel_fee_bumps.is_empty());
+        assert!(alice_state2.receive(message4).is_ok());
+    }
+}
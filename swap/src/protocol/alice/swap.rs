@@ -4,6 +4,7 @@ use crate::asb::{EventLoopHandle, LatestRate};
 use crate::bitcoin::ExpiredTimelocks;
 use crate::env::Config;
 use crate::protocol::alice::{AliceState, Swap};
+use crate::protocol::event::{SwapEvent, SwapEvents};
 use crate::{bitcoin, monero};
 use anyhow::{bail, Context, Result};
 use tokio::select;
@@ -19,6 +20,28 @@ where
 
 #[tracing::instrument(name = "swap", skip(swap,exit_early,rate_service), fields(id = %swap.swap_id), err)]
 pub async fn run_until<LR>(
+    swap: Swap,
+    exit_early: fn(&AliceState) -> bool,
+    rate_service: LR,
+) -> Result<AliceState>
+where
+    LR: LatestRate + Clone,
+{
+    let swap_id = swap.swap_id;
+    let events = swap.events.clone();
+
+    advance_until(swap, exit_early, rate_service)
+        .await
+        .map_err(|error| {
+            events.send(SwapEvent::Failed {
+                swap_id,
+                error: format!("{:#}", error),
+            });
+            error
+        })
+}
+
+async fn advance_until<LR>(
     mut swap: Swap,
     exit_early: fn(&AliceState) -> bool,
     rate_service: LR,
@@ -37,12 +60,18 @@ where
             swap.monero_wallet.as_ref(),
             &swap.env_config,
             rate_service.clone(),
+            &swap.events,
         )
         .await?;
 
         swap.db
             .insert_latest_state(swap.swap_id, current_state.clone().into())
             .await?;
+
+        swap.events.send(SwapEvent::StateChanged {
+            swap_id: swap.swap_id,
+            state: current_state.to_string(),
+        });
     }
 
     Ok(current_state)
@@ -56,15 +85,15 @@ async fn next_state<LR>(
     monero_wallet: &monero::Wallet,
     env_config: &Config,
     mut rate_service: LR,
+    events: &SwapEvents,
 ) -> Result<AliceState>
 where
     LR: LatestRate,
 {
-    let rate = rate_service
-        .latest_rate()
-        .map_or("NaN".to_string(), |rate| format!("{}", rate));
-
-    tracing::info!(%state, %rate, "Advancing state");
+    match rate_service.latest_rate() {
+        Ok(rate) => tracing::info!(%state, %rate, "Advancing state"),
+        Err(_) => tracing::info!(%state, "Advancing state"),
+    }
 
     Ok(match state {
         AliceState::Started { state3 } => {
@@ -92,7 +121,7 @@ where
             let tx_lock_status = bitcoin_wallet.subscribe_to(state3.tx_lock.clone()).await;
             match timeout(
                 env_config.bitcoin_lock_confirmed_timeout,
-                tx_lock_status.wait_until_final(),
+                events.wait_until_final(swap_id, &tx_lock_status),
             )
             .await
             {
@@ -168,6 +197,10 @@ where
             tokio::select! {
                 result = event_loop_handle.send_transfer_proof(transfer_proof.clone()) => {
                    result?;
+                   events.send(SwapEvent::TransferProof {
+                       swap_id,
+                       transfer_proof: transfer_proof.clone(),
+                   });
 
                    AliceState::XmrLockTransferProofSent {
                        monero_wallet_restore_blockheight,
@@ -175,7 +208,7 @@ where
                        state3,
                    }
                 },
-                result = tx_lock_status.wait_until_confirmed_with(state3.cancel_timelock) => {
+                result = events.wait_until_cancel_timelock_expired(swap_id, &tx_lock_status, state3.cancel_timelock) => {
                     let _ = result?;
                     AliceState::CancelTimelockExpired {
                         monero_wallet_restore_blockheight,
@@ -195,7 +228,7 @@ where
             select! {
                 biased; // make sure the cancel timelock expiry future is polled first
 
-                result = tx_lock_status.wait_until_confirmed_with(state3.cancel_timelock) => {
+                result = events.wait_until_cancel_timelock_expired(swap_id, &tx_lock_status, state3.cancel_timelock) => {
                     let _ = result?;
                     AliceState::CancelTimelockExpired {
                         monero_wallet_restore_blockheight,
@@ -225,16 +258,27 @@ where
                 let tx_lock_status = bitcoin_wallet.subscribe_to(state3.tx_lock.clone()).await;
                 match state3.signed_redeem_transaction(*encrypted_signature) {
                     Ok(tx) => match bitcoin_wallet.broadcast(tx, "redeem").await {
-                        Ok((_, subscription)) => match subscription.wait_until_seen().await {
-                            Ok(_) => AliceState::BtcRedeemTransactionPublished { state3 },
+                        Ok((txid, subscription)) => match subscription.wait_until_seen().await {
+                            Ok(_) => {
+                                events.send(SwapEvent::BitcoinTransactionPublished {
+                                    swap_id,
+                                    kind: "redeem".to_owned(),
+                                    txid,
+                                });
+                                AliceState::BtcRedeemTransactionPublished { state3 }
+                            }
                             Err(e) => {
                                 bail!("Waiting for Bitcoin redeem transaction to be in mempool failed with {}! The redeem transaction was published, but it is not ensured that the transaction was included! You're screwed.", e)
                             }
                         },
                         Err(error) => {
                             tracing::error!("Failed to publish redeem transaction: {:#}", error);
-                            tx_lock_status
-                                .wait_until_confirmed_with(state3.cancel_timelock)
+                            events
+                                .wait_until_cancel_timelock_expired(
+                                    swap_id,
+                                    &tx_lock_status,
+                                    state3.cancel_timelock,
+                                )
                                 .await?;
 
                             AliceState::CancelTimelockExpired {
@@ -251,8 +295,12 @@ where
                             "Waiting for cancellation timelock to expire",
                         );
 
-                        tx_lock_status
-                            .wait_until_confirmed_with(state3.cancel_timelock)
+                        events
+                            .wait_until_cancel_timelock_expired(
+                                swap_id,
+                                &tx_lock_status,
+                                state3.cancel_timelock,
+                            )
                             .await?;
 
                         AliceState::CancelTimelockExpired {
@@ -272,7 +320,14 @@ where
         AliceState::BtcRedeemTransactionPublished { state3 } => {
             let subscription = bitcoin_wallet.subscribe_to(state3.tx_redeem()).await;
 
-            match subscription.wait_until_final().await {
+            let confirmed = async {
+                bitcoin_wallet
+                    .wait_until_confirmed_or_bump(&subscription, "redeem")
+                    .await?;
+                subscription.wait_until_final().await
+            };
+
+            match confirmed.await {
                 Ok(_) => AliceState::BtcRedeemed,
                 Err(e) => {
                     bail!("The Bitcoin redeem transaction was seen in mempool, but waiting for finality timed out with {}. Manual investigation might be needed to ensure that the transaction was included.", e)
@@ -289,11 +344,16 @@ where
                 // to be able to eventually punish. Since the punish timelock is
                 // relative to the publication of the cancel transaction we have to ensure it
                 // gets published once the cancel timelock expires.
-                if let Err(e) = state3.submit_tx_cancel(bitcoin_wallet).await {
-                    tracing::debug!(
+                match state3.submit_tx_cancel(bitcoin_wallet).await {
+                    Ok(txid) => events.send(SwapEvent::BitcoinTransactionPublished {
+                        swap_id,
+                        kind: "cancel".to_owned(),
+                        txid,
+                    }),
+                    Err(e) => tracing::debug!(
                         "Assuming cancel transaction is already broadcasted because: {:#}",
                         e
-                    )
+                    ),
                 }
             }
 
@@ -308,14 +368,39 @@ where
             transfer_proof,
             state3,
         } => {
-            let tx_refund_status = bitcoin_wallet.subscribe_to(state3.tx_refund()).await;
-            let tx_cancel_status = bitcoin_wallet.subscribe_to(state3.tx_cancel()).await;
+            let tx_cancel = state3.watch_for_tx_cancel(bitcoin_wallet).await?;
+            let tx_cancel_txid = tx_cancel.txid();
+            let tx_refund = state3.tx_refund_spending(&tx_cancel);
+            let tx_refund_txid = tx_refund.txid();
+
+            let tx_refund_status = bitcoin_wallet.subscribe_to(tx_refund).await;
+            let tx_cancel_status = bitcoin_wallet.subscribe_to(tx_cancel).await;
+
+            // The punish timelock only starts once the cancel transaction is confirmed, so
+            // we replace it with one paying a higher fee if it gets stuck. A replacement
+            // is watched for instead by starting over.
+            let signed_tx_cancels = state3.signed_cancel_transactions()?;
+            let punish_timelock = state3.punish_timelock;
+            let punish_timelock_expired = async {
+                let confirmed = bitcoin_wallet
+                    .replace_until_confirmed(signed_tx_cancels, "cancel")
+                    .await?;
+                if confirmed != tx_cancel_txid {
+                    return Ok(false);
+                }
+
+                events
+                    .wait_until_punish_timelock_expired(swap_id, &tx_cancel_status, punish_timelock)
+                    .await?;
+
+                Result::<_, anyhow::Error>::Ok(true)
+            };
 
             select! {
                 seen_refund = tx_refund_status.wait_until_seen() => {
                     seen_refund.context("Failed to monitor refund transaction")?;
 
-                    let published_refund_tx = bitcoin_wallet.get_raw_transaction(state3.tx_refund().txid()).await?;
+                    let published_refund_tx = bitcoin_wallet.get_raw_transaction(tx_refund_txid).await?;
                     let spend_key = state3.extract_monero_private_key(published_refund_tx)?;
 
                     AliceState::BtcRefunded {
@@ -325,13 +410,19 @@ where
                         state3,
                     }
                 }
-                result = tx_cancel_status.wait_until_confirmed_with(state3.punish_timelock) => {
-                    let _ = result?;
-
-                    AliceState::BtcPunishable {
-                        monero_wallet_restore_blockheight,
-                        transfer_proof,
-                        state3,
+                expired = punish_timelock_expired => {
+                    if expired? {
+                        AliceState::BtcPunishable {
+                            monero_wallet_restore_blockheight,
+                            transfer_proof,
+                            state3,
+                        }
+                    } else {
+                        AliceState::BtcCancelled {
+                            monero_wallet_restore_blockheight,
+                            transfer_proof,
+                            state3,
+                        }
                     }
                 }
             }
@@ -362,7 +453,14 @@ where
             let punish = state3.punish_btc(bitcoin_wallet).await;
 
             match punish {
-                Ok(_) => AliceState::BtcPunished,
+                Ok(txid) => {
+                    events.send(SwapEvent::BitcoinTransactionPublished {
+                        swap_id,
+                        kind: "punish".to_owned(),
+                        txid,
+                    });
+                    AliceState::BtcPunished
+                }
                 Err(error) => {
                     tracing::warn!("Failed to publish punish transaction: {:#}", error);
 
@@ -375,9 +473,7 @@ where
 
                     tracing::info!("Falling back to refund");
 
-                    let published_refund_tx = bitcoin_wallet
-                        .get_raw_transaction(state3.tx_refund().txid())
-                        .await?;
+                    let published_refund_tx = state3.fetch_tx_refund(bitcoin_wallet).await?;
 
                     let spend_key = state3.extract_monero_private_key(published_refund_tx)?;
 
@@ -3,6 +3,7 @@ use std::sync::Arc;
 use anyhow::Result;
 use uuid::Uuid;
 
+use crate::protocol::event::SwapEvents;
 use crate::protocol::Database;
 use crate::{bitcoin, cli, env, monero};
 
@@ -21,7 +22,19 @@ pub struct Swap {
     pub monero_wallet: Arc<monero::Wallet>,
     pub env_config: env::Config,
     pub id: Uuid,
-    pub monero_receive_address: monero::Address,
+    pub role: Role,
+    pub events: SwapEvents,
+}
+
+/// Who runs the swap as Bob, which decides where the redeemed XMR go.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    /// The CLI buying XMR, which sweeps them to the given address.
+    Cli {
+        monero_receive_address: monero::Address,
+    },
+    /// The ASB buying XMR, which sweeps them into the wallet it trades with.
+    Asb,
 }
 
 impl Swap {
@@ -48,7 +61,10 @@ impl Swap {
             monero_wallet,
             env_config,
             id,
-            monero_receive_address,
+            role: Role::Cli {
+                monero_receive_address,
+            },
+            events: SwapEvents::default(),
         }
     }
 
@@ -72,7 +88,16 @@ impl Swap {
             monero_wallet,
             env_config,
             id,
-            monero_receive_address,
+            role: Role::Cli {
+                monero_receive_address,
+            },
+            events: SwapEvents::default(),
         })
     }
+
+    /// Publish the progress of the swap on the given channel.
+    pub fn with_events(mut self, events: SwapEvents) -> Self {
+        self.events = events;
+        self
+    }
 }
@@ -1,4 +1,4 @@
-use crate::bitcoin::wallet::EstimateFeeRate;
+use crate::bitcoin::wallet::{EstimateFeeRate, ScriptStatus};
 use crate::bitcoin::{
     self, current_epoch, CancelTimelock, ExpiredTimelocks, PunishTimelock, Transaction, TxCancel,
     TxLock, Txid,
@@ -18,7 +18,7 @@ use rand::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
 use sha2::Sha256;
 use sigma_fun::ext::dl_secp256k1_ed25519_eq::CrossCurveDLEQProof;
-use std::fmt;
+use std::{fmt, iter};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, PartialEq)]
@@ -182,6 +182,7 @@ impl State0 {
         )
         .await?;
         let v = msg.v_a + self.v_b;
+        let tx_cancel_fee_bumps = TxCancel::fee_bumps(self.tx_cancel_fee, tx_lock.lock_amount());
 
         Ok(State1 {
             A: msg.A,
@@ -202,6 +203,7 @@ impl State0 {
             tx_refund_fee: self.tx_refund_fee,
             tx_punish_fee: msg.tx_punish_fee,
             tx_cancel_fee: self.tx_cancel_fee,
+            tx_cancel_fee_bumps,
         })
     }
 }
@@ -226,9 +228,21 @@ pub struct State1 {
     tx_refund_fee: bitcoin::Amount,
     tx_punish_fee: bitcoin::Amount,
     tx_cancel_fee: bitcoin::Amount,
+    /// The fees Alice has to sign the cancel transaction at in addition to
+    /// `tx_cancel_fee`.
+    tx_cancel_fee_bumps: Vec<bitcoin::Amount>,
 }
 
 impl State1 {
+    /// Only expect the cancel transaction to be signed at the negotiated fee,
+    /// for a peer that does not support fee bumps.
+    pub fn without_fee_bumps(self) -> Self {
+        Self {
+            tx_cancel_fee_bumps: Vec::new(),
+            ..self
+        }
+    }
+
     pub fn next_message(&self) -> Message2 {
         Message2 {
             psbt: self.tx_lock.clone().into(),
@@ -254,6 +268,46 @@ impl State1 {
             &msg.tx_refund_encsig,
         )?;
 
+        if msg.tx_cancel_fee_bumps.len() != self.tx_cancel_fee_bumps.len() {
+            bail!(
+                "Alice signed the cancel transaction at {} bumped fees instead of {}",
+                msg.tx_cancel_fee_bumps.len(),
+                self.tx_cancel_fee_bumps.len()
+            )
+        }
+
+        let tx_cancel_fee_bumps = self
+            .tx_cancel_fee_bumps
+            .iter()
+            .copied()
+            .zip(msg.tx_cancel_fee_bumps)
+            .map(|(tx_cancel_fee, (tx_cancel_sig_a, tx_refund_encsig))| {
+                let tx_cancel = TxCancel::new(
+                    &self.tx_lock,
+                    self.cancel_timelock,
+                    self.A,
+                    self.b.public(),
+                    tx_cancel_fee,
+                );
+                let tx_refund =
+                    bitcoin::TxRefund::new(&tx_cancel, &self.refund_address, self.tx_refund_fee);
+
+                bitcoin::verify_sig(&self.A, &tx_cancel.digest(), &tx_cancel_sig_a)?;
+                bitcoin::verify_encsig(
+                    self.A,
+                    bitcoin::PublicKey::from(self.s_b.to_secpfun_scalar()),
+                    &tx_refund.digest(),
+                    &tx_refund_encsig,
+                )?;
+
+                Ok(CancelFeeBump {
+                    tx_cancel_fee,
+                    tx_cancel_sig_a,
+                    tx_refund_encsig,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
         Ok(State2 {
             A: self.A,
             b: self.b,
@@ -270,6 +324,7 @@ impl State1 {
             tx_lock: self.tx_lock,
             tx_cancel_sig_a: msg.tx_cancel_sig,
             tx_refund_encsig: msg.tx_refund_encsig,
+            tx_cancel_fee_bumps,
             min_monero_confirmations: self.min_monero_confirmations,
             tx_redeem_fee: self.tx_redeem_fee,
             tx_refund_fee: self.tx_refund_fee,
@@ -296,6 +351,8 @@ pub struct State2 {
     tx_lock: bitcoin::TxLock,
     tx_cancel_sig_a: Signature,
     tx_refund_encsig: bitcoin::EncryptedSignature,
+    #[serde(default)]
+    tx_cancel_fee_bumps: Vec<CancelFeeBump>,
     min_monero_confirmations: u64,
     #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
     tx_redeem_fee: bitcoin::Amount,
@@ -308,6 +365,11 @@ pub struct State2 {
 }
 
 impl State2 {
+    /// The amount of Bitcoin we lock in the swap.
+    pub fn lock_amount(&self) -> bitcoin::Amount {
+        self.tx_lock.lock_amount()
+    }
+
     pub fn next_message(&self) -> Message4 {
         let tx_cancel = TxCancel::new(
             &self.tx_lock,
@@ -325,9 +387,35 @@ impl State2 {
         );
         let tx_punish_sig = self.b.sign(tx_punish.digest());
 
+        let tx_cancel_fee_bumps = self
+            .tx_cancel_fee_bumps
+            .iter()
+            .map(|fee_bump| {
+                let tx_cancel = TxCancel::new(
+                    &self.tx_lock,
+                    self.cancel_timelock,
+                    self.A,
+                    self.b.public(),
+                    fee_bump.tx_cancel_fee,
+                );
+                let tx_punish = bitcoin::TxPunish::new(
+                    &tx_cancel,
+                    &self.punish_address,
+                    self.punish_timelock,
+                    self.tx_punish_fee,
+                );
+
+                (
+                    self.b.sign(tx_cancel.digest()),
+                    self.b.sign(tx_punish.digest()),
+                )
+            })
+            .collect();
+
         Message4 {
             tx_punish_sig,
             tx_cancel_sig,
+            tx_cancel_fee_bumps,
         }
     }
 
@@ -348,6 +436,7 @@ impl State2 {
                 tx_lock: self.tx_lock.clone(),
                 tx_cancel_sig_a: self.tx_cancel_sig_a,
                 tx_refund_encsig: self.tx_refund_encsig,
+                tx_cancel_fee_bumps: self.tx_cancel_fee_bumps,
                 min_monero_confirmations: self.min_monero_confirmations,
                 tx_redeem_fee: self.tx_redeem_fee,
                 tx_refund_fee: self.tx_refund_fee,
@@ -374,6 +463,8 @@ pub struct State3 {
     pub tx_lock: bitcoin::TxLock,
     tx_cancel_sig_a: Signature,
     tx_refund_encsig: bitcoin::EncryptedSignature,
+    #[serde(default)]
+    tx_cancel_fee_bumps: Vec<CancelFeeBump>,
     min_monero_confirmations: u64,
     #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
     tx_redeem_fee: bitcoin::Amount,
@@ -412,6 +503,7 @@ impl State3 {
             tx_lock: self.tx_lock,
             tx_cancel_sig_a: self.tx_cancel_sig_a,
             tx_refund_encsig: self.tx_refund_encsig,
+            tx_cancel_fee_bumps: self.tx_cancel_fee_bumps,
             monero_wallet_restore_blockheight,
             tx_redeem_fee: self.tx_redeem_fee,
             tx_refund_fee: self.tx_refund_fee,
@@ -430,6 +522,7 @@ impl State3 {
             tx_lock: self.tx_lock.clone(),
             tx_cancel_sig_a: self.tx_cancel_sig_a.clone(),
             tx_refund_encsig: self.tx_refund_encsig.clone(),
+            tx_cancel_fee_bumps: self.tx_cancel_fee_bumps.clone(),
             tx_refund_fee: self.tx_refund_fee,
             tx_cancel_fee: self.tx_cancel_fee,
         }
@@ -443,23 +536,7 @@ impl State3 {
         &self,
         bitcoin_wallet: &bitcoin::Wallet,
     ) -> Result<ExpiredTimelocks> {
-        let tx_cancel = TxCancel::new(
-            &self.tx_lock,
-            self.cancel_timelock,
-            self.A,
-            self.b.public(),
-            self.tx_cancel_fee,
-        );
-
-        let tx_lock_status = bitcoin_wallet.status_of_script(&self.tx_lock).await?;
-        let tx_cancel_status = bitcoin_wallet.status_of_script(&tx_cancel).await?;
-
-        Ok(current_epoch(
-            self.cancel_timelock,
-            self.punish_timelock,
-            tx_lock_status,
-            tx_cancel_status,
-        ))
+        self.cancel().expired_timelock(bitcoin_wallet).await
     }
 }
 
@@ -477,6 +554,8 @@ pub struct State4 {
     pub tx_lock: bitcoin::TxLock,
     tx_cancel_sig_a: Signature,
     tx_refund_encsig: bitcoin::EncryptedSignature,
+    #[serde(default)]
+    tx_cancel_fee_bumps: Vec<CancelFeeBump>,
     monero_wallet_restore_blockheight: BlockHeight,
     #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
     tx_redeem_fee: bitcoin::Amount,
@@ -524,23 +603,7 @@ impl State4 {
         &self,
         bitcoin_wallet: &bitcoin::Wallet,
     ) -> Result<ExpiredTimelocks> {
-        let tx_cancel = TxCancel::new(
-            &self.tx_lock,
-            self.cancel_timelock,
-            self.A,
-            self.b.public(),
-            self.tx_cancel_fee,
-        );
-
-        let tx_lock_status = bitcoin_wallet.status_of_script(&self.tx_lock).await?;
-        let tx_cancel_status = bitcoin_wallet.status_of_script(&tx_cancel).await?;
-
-        Ok(current_epoch(
-            self.cancel_timelock,
-            self.punish_timelock,
-            tx_lock_status,
-            tx_cancel_status,
-        ))
+        self.clone().cancel().expired_timelock(bitcoin_wallet).await
     }
 
     pub fn cancel(self) -> State6 {
@@ -554,6 +617,7 @@ impl State4 {
             tx_lock: self.tx_lock,
             tx_cancel_sig_a: self.tx_cancel_sig_a,
             tx_refund_encsig: self.tx_refund_encsig,
+            tx_cancel_fee_bumps: self.tx_cancel_fee_bumps,
             tx_refund_fee: self.tx_refund_fee,
             tx_cancel_fee: self.tx_cancel_fee,
         }
@@ -594,6 +658,8 @@ pub struct State6 {
     tx_lock: bitcoin::TxLock,
     tx_cancel_sig_a: Signature,
     tx_refund_encsig: bitcoin::EncryptedSignature,
+    #[serde(default)]
+    tx_cancel_fee_bumps: Vec<CancelFeeBump>,
     #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
     pub tx_refund_fee: bitcoin::Amount,
     #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
@@ -605,16 +671,11 @@ impl State6 {
         &self,
         bitcoin_wallet: &bitcoin::Wallet,
     ) -> Result<ExpiredTimelocks> {
-        let tx_cancel = TxCancel::new(
-            &self.tx_lock,
-            self.cancel_timelock,
-            self.A,
-            self.b.public(),
-            self.tx_cancel_fee,
-        );
-
         let tx_lock_status = bitcoin_wallet.status_of_script(&self.tx_lock).await?;
-        let tx_cancel_status = bitcoin_wallet.status_of_script(&tx_cancel).await?;
+        let tx_cancel_status = bitcoin_wallet
+            .status_of_conflicting(&self.tx_cancels())
+            .await?
+            .map_or(ScriptStatus::Unseen, |(_, status)| status);
 
         Ok(current_epoch(
             self.cancel_timelock,
@@ -628,51 +689,66 @@ impl State6 {
         &self,
         bitcoin_wallet: &bitcoin::Wallet,
     ) -> Result<Transaction> {
-        let tx_cancel = bitcoin::TxCancel::new(
-            &self.tx_lock,
-            self.cancel_timelock,
-            self.A,
-            self.b.public(),
-            self.tx_cancel_fee,
-        );
-
-        let tx = bitcoin_wallet.get_raw_transaction(tx_cancel.txid()).await?;
+        for tx_cancel in self.tx_cancels() {
+            if let Ok(Some(tx)) = bitcoin_wallet.get_tx(tx_cancel.txid()).await {
+                return Ok(tx);
+            }
+        }
 
-        Ok(tx)
+        bail!("Cancel transaction has not been published")
     }
 
+    /// Publish the cancel transaction at the negotiated fee.
     pub async fn submit_tx_cancel(&self, bitcoin_wallet: &bitcoin::Wallet) -> Result<Txid> {
-        let transaction = bitcoin::TxCancel::new(
-            &self.tx_lock,
-            self.cancel_timelock,
-            self.A,
-            self.b.public(),
-            self.tx_cancel_fee,
-        )
-        .complete_as_bob(self.A, self.b.clone(), self.tx_cancel_sig_a.clone())
-        .context("Failed to complete Bitcoin cancel transaction")?;
+        let transaction = self
+            .tx_cancel()
+            .complete_as_bob(self.A, self.b.clone(), self.tx_cancel_sig_a.clone())
+            .context("Failed to complete Bitcoin cancel transaction")?;
 
         let (tx_id, _) = bitcoin_wallet.broadcast(transaction, "cancel").await?;
 
         Ok(tx_id)
     }
 
-    pub async fn publish_refund_btc(&self, bitcoin_wallet: &bitcoin::Wallet) -> Result<()> {
-        let signed_tx_refund = self.signed_refund_transaction()?;
-        let (_, subscription) = bitcoin_wallet.broadcast(signed_tx_refund, "refund").await?;
+    /// The cancel transaction at the negotiated fee followed by the ones at
+    /// bumped fees, ready to be published.
+    pub fn signed_cancel_transactions(&self) -> Result<Vec<Transaction>> {
+        self.cancel_variants()
+            .into_iter()
+            .map(|variant| {
+                self.tx_cancel_with_fee(variant.tx_cancel_fee)
+                    .complete_as_bob(self.A, self.b.clone(), variant.tx_cancel_sig_a)
+            })
+            .collect::<Result<Vec<_>>>()
+            .context("Failed to complete Bitcoin cancel transaction")
+    }
+
+    pub async fn publish_refund_btc(&self, bitcoin_wallet: &bitcoin::Wallet) -> Result<Txid> {
+        let tx_cancels = self.tx_cancels();
+        let (index, _) = bitcoin_wallet
+            .status_of_conflicting(&tx_cancels)
+            .await?
+            .context("Cancel transaction has not been published")?;
+        let variant = self.cancel_variants().swap_remove(index);
+
+        let signed_tx_refund = self.signed_refund_transaction_spending(variant)?;
+        let (txid, subscription) = bitcoin_wallet.broadcast(signed_tx_refund, "refund").await?;
+        bitcoin_wallet
+            .wait_until_confirmed_or_bump(&subscription, "refund")
+            .await?;
         subscription.wait_until_final().await?;
 
-        Ok(())
+        Ok(txid)
     }
 
     pub fn signed_refund_transaction(&self) -> Result<Transaction> {
-        let tx_cancel = bitcoin::TxCancel::new(
-            &self.tx_lock,
-            self.cancel_timelock,
-            self.A,
-            self.b.public(),
-            self.tx_cancel_fee,
-        );
+        let negotiated = self.cancel_variants().swap_remove(0);
+
+        self.signed_refund_transaction_spending(negotiated)
+    }
+
+    fn signed_refund_transaction_spending(&self, variant: CancelFeeBump) -> Result<Transaction> {
+        let tx_cancel = self.tx_cancel_with_fee(variant.tx_cancel_fee);
         let tx_refund =
             bitcoin::TxRefund::new(&tx_cancel, &self.refund_address, self.tx_refund_fee);
 
@@ -680,7 +756,7 @@ impl State6 {
 
         let sig_b = self.b.sign(tx_refund.digest());
         let sig_a =
-            adaptor.decrypt_signature(&self.s_b.to_secpfun_scalar(), self.tx_refund_encsig.clone());
+            adaptor.decrypt_signature(&self.s_b.to_secpfun_scalar(), variant.tx_refund_encsig);
 
         let signed_tx_refund =
             tx_refund.add_signatures((self.A, sig_a), (self.b.public(), sig_b))?;
@@ -690,4 +766,52 @@ impl State6 {
     pub fn tx_lock_id(&self) -> bitcoin::Txid {
         self.tx_lock.txid()
     }
+
+    /// The cancel transaction at the negotiated fee.
+    pub fn tx_cancel(&self) -> TxCancel {
+        self.tx_cancel_with_fee(self.tx_cancel_fee)
+    }
+
+    fn tx_cancel_with_fee(&self, tx_cancel_fee: bitcoin::Amount) -> TxCancel {
+        TxCancel::new(
+            &self.tx_lock,
+            self.cancel_timelock,
+            self.A,
+            self.b.public(),
+            tx_cancel_fee,
+        )
+    }
+
+    /// The cancel transaction at the negotiated fee, followed by the ones at
+    /// bumped fees.
+    fn tx_cancels(&self) -> Vec<TxCancel> {
+        self.cancel_variants()
+            .iter()
+            .map(|variant| self.tx_cancel_with_fee(variant.tx_cancel_fee))
+            .collect()
+    }
+
+    /// Alice's signatures for every cancel transaction in the order of
+    /// [`State6::tx_cancels`].
+    fn cancel_variants(&self) -> Vec<CancelFeeBump> {
+        let negotiated = CancelFeeBump {
+            tx_cancel_fee: self.tx_cancel_fee,
+            tx_cancel_sig_a: self.tx_cancel_sig_a.clone(),
+            tx_refund_encsig: self.tx_refund_encsig.clone(),
+        };
+
+        iter::once(negotiated)
+            .chain(self.tx_cancel_fee_bumps.iter().cloned())
+            .collect()
+    }
+}
+
+/// Alice's signatures for the cancel transaction at one of the fees of
+/// [`TxCancel::fee_bumps`] and for the refund transaction spending it.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct CancelFeeBump {
+    #[serde(with = "::bitcoin::util::amount::serde::as_sat")]
+    tx_cancel_fee: bitcoin::Amount,
+    tx_cancel_sig_a: Signature,
+    tx_refund_encsig: bitcoin::EncryptedSignature,
 }
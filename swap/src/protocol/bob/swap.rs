@@ -3,6 +3,7 @@ use crate::cli::EventLoopHandle;
 use crate::network::swap_setup::bob::NewSwap;
 use crate::protocol::bob;
 use crate::protocol::bob::state::*;
+use crate::protocol::event::{SwapEvent, SwapEvents};
 use crate::{bitcoin, monero};
 use anyhow::{bail, Context, Result};
 use tokio::select;
@@ -24,6 +25,22 @@ pub async fn run(swap: bob::Swap) -> Result<BobState> {
 }
 
 pub async fn run_until(
+    swap: bob::Swap,
+    is_target_state: fn(&BobState) -> bool,
+) -> Result<BobState> {
+    let swap_id = swap.id;
+    let events = swap.events.clone();
+
+    advance_until(swap, is_target_state).await.map_err(|error| {
+        events.send(SwapEvent::Failed {
+            swap_id,
+            error: format!("{:#}", error),
+        });
+        error
+    })
+}
+
+async fn advance_until(
     mut swap: bob::Swap,
     is_target_state: fn(&BobState) -> bool,
 ) -> Result<BobState> {
@@ -36,13 +53,19 @@ pub async fn run_until(
             &mut swap.event_loop_handle,
             swap.bitcoin_wallet.as_ref(),
             swap.monero_wallet.as_ref(),
-            swap.monero_receive_address,
+            swap.role,
+            &swap.events,
         )
         .await?;
 
         swap.db
             .insert_latest_state(swap.id, current_state.clone().into())
             .await?;
+
+        swap.events.send(SwapEvent::StateChanged {
+            swap_id: swap.id,
+            state: current_state.to_string(),
+        });
     }
 
     Ok(current_state)
@@ -54,7 +77,8 @@ async fn next_state(
     event_loop_handle: &mut EventLoopHandle,
     bitcoin_wallet: &bitcoin::Wallet,
     monero_wallet: &monero::Wallet,
-    monero_receive_address: monero::Address,
+    role: bob::Role,
+    events: &SwapEvents,
 ) -> Result<BobState> {
     tracing::debug!(%state, "Advancing state");
 
@@ -102,7 +126,12 @@ async fn next_state(
                 .sign_and_finalize(tx_lock.clone().into())
                 .await
                 .context("Failed to sign Bitcoin lock transaction")?;
-            let (..) = bitcoin_wallet.broadcast(signed_tx, "lock").await?;
+            let (txid, _) = bitcoin_wallet.broadcast(signed_tx, "lock").await?;
+            events.send(SwapEvent::BitcoinTransactionPublished {
+                swap_id,
+                kind: "lock".to_owned(),
+                txid,
+            });
 
             BobState::BtcLocked {
                 state3,
@@ -119,8 +148,21 @@ async fn next_state(
 
             if let ExpiredTimelocks::None = state3.current_epoch(bitcoin_wallet).await? {
                 let transfer_proof_watcher = event_loop_handle.recv_transfer_proof();
-                let cancel_timelock_expires =
-                    tx_lock_status.wait_until_confirmed_with(state3.cancel_timelock);
+                // The cancel timelock only starts once the lock transaction is confirmed, so
+                // we bump its fee if it gets stuck in the meantime.
+                let cancel_timelock_expires = async {
+                    bitcoin_wallet
+                        .wait_until_confirmed_or_bump(&tx_lock_status, "lock")
+                        .await?;
+
+                    events
+                        .wait_until_cancel_timelock_expired(
+                            swap_id,
+                            &tx_lock_status,
+                            state3.cancel_timelock,
+                        )
+                        .await
+                };
 
                 tracing::info!("Waiting for Alice to lock Monero");
 
@@ -129,6 +171,10 @@ async fn next_state(
                         let transfer_proof = transfer_proof?;
 
                         tracing::info!(txid = %transfer_proof.tx_hash(), "Alice locked Monero");
+                        events.send(SwapEvent::TransferProof {
+                            swap_id,
+                            transfer_proof: transfer_proof.clone(),
+                        });
 
                         BobState::XmrLockProofReceived {
                             state: state3,
@@ -167,13 +213,13 @@ async fn next_state(
                                 tracing::warn!(%expected, %actual, "Insufficient Monero have been locked!");
                                 tracing::info!(timelock = %state.cancel_timelock, "Waiting for cancel timelock to expire");
 
-                                tx_lock_status.wait_until_confirmed_with(state.cancel_timelock).await?;
+                                events.wait_until_cancel_timelock_expired(swap_id, &tx_lock_status, state.cancel_timelock).await?;
 
                                 BobState::CancelTimelockExpired(state.cancel())
                             },
                         }
                     }
-                    result = tx_lock_status.wait_until_confirmed_with(state.cancel_timelock) => {
+                    result = events.wait_until_cancel_timelock_expired(swap_id, &tx_lock_status, state.cancel_timelock) => {
                         let _ = result?;
                         BobState::CancelTimelockExpired(state.cancel())
                     }
@@ -197,7 +243,7 @@ async fn next_state(
                             Err(bmrng::error::RequestError::RecvTimeoutError) => unreachable!("We construct the channel with no timeout"),
                         }
                     },
-                    result = tx_lock_status.wait_until_confirmed_with(state.cancel_timelock) => {
+                    result = events.wait_until_cancel_timelock_expired(swap_id, &tx_lock_status, state.cancel_timelock) => {
                         let _ = result?;
                         BobState::CancelTimelockExpired(state.cancel())
                     }
@@ -214,7 +260,7 @@ async fn next_state(
                     state5 = state.watch_for_redeem_btc(bitcoin_wallet) => {
                         BobState::BtcRedeemed(state5?)
                     },
-                    result = tx_lock_status.wait_until_confirmed_with(state.cancel_timelock) => {
+                    result = events.wait_until_cancel_timelock_expired(swap_id, &tx_lock_status, state.cancel_timelock) => {
                         let _ = result?;
                         BobState::CancelTimelockExpired(state.cancel())
                     }
@@ -227,6 +273,35 @@ async fn next_state(
             let (spend_key, view_key) = state.xmr_keys();
 
             let wallet_file_name = swap_id.to_string();
+
+            let monero_receive_address = match role {
+                bob::Role::Cli {
+                    monero_receive_address,
+                } => monero_receive_address,
+                // The wallet is shared with other swaps, so instead of leaving the generated
+                // wallet loaded, we sweep and re-open the main wallet while holding the lock
+                // on it. If sweeping fails the swap stays in this state, so resuming it tries
+                // again.
+                bob::Role::Asb => {
+                    let tx_hashes = monero_wallet
+                        .sweep_from(
+                            wallet_file_name,
+                            spend_key,
+                            view_key,
+                            state.monero_wallet_restore_blockheight,
+                        )
+                        .await?;
+
+                    for tx_hash in tx_hashes {
+                        tracing::info!(txid=%tx_hash.0, "Successfully transferred XMR to wallet");
+                    }
+
+                    return Ok(BobState::XmrRedeemed {
+                        tx_lock_id: state.tx_lock_id(),
+                    });
+                }
+            };
+
             if let Err(e) = monero_wallet
                 .create_from_and_load(
                     wallet_file_name.clone(),
@@ -261,13 +336,23 @@ async fn next_state(
         }
         BobState::CancelTimelockExpired(state4) => {
             if state4.check_for_tx_cancel(bitcoin_wallet).await.is_err() {
-                state4.submit_tx_cancel(bitcoin_wallet).await?;
+                let txid = state4.submit_tx_cancel(bitcoin_wallet).await?;
+                events.send(SwapEvent::BitcoinTransactionPublished {
+                    swap_id,
+                    kind: "cancel".to_owned(),
+                    txid,
+                });
             }
 
             BobState::BtcCancelled(state4)
         }
         BobState::BtcCancelled(state) => {
-            // Bob has cancelled the swap
+            // Bob has cancelled the swap, the cancel transaction has to be confirmed before
+            // he can refund, so we replace it with one paying a higher fee if it gets stuck
+            bitcoin_wallet
+                .replace_until_confirmed(state.signed_cancel_transactions()?, "cancel")
+                .await?;
+
             match state.expired_timelock(bitcoin_wallet).await? {
                 ExpiredTimelocks::None => {
                     bail!(
@@ -275,7 +360,12 @@ async fn next_state(
                     );
                 }
                 ExpiredTimelocks::Cancel => {
-                    state.publish_refund_btc(bitcoin_wallet).await?;
+                    let txid = state.publish_refund_btc(bitcoin_wallet).await?;
+                    events.send(SwapEvent::BitcoinTransactionPublished {
+                        swap_id,
+                        kind: "refund".to_owned(),
+                        txid,
+                    });
                     BobState::BtcRefunded(state)
                 }
                 ExpiredTimelocks::Punish => BobState::BtcPunished {
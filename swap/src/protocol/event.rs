@@ -0,0 +1,262 @@
+//! Typed progress events of running swaps.
+//!
+//! Swaps publish [`SwapEvent`]s on a [`SwapEvents`] broadcast channel.
+//! Consumers like a UI, a notification sink or tests subscribe to it instead of
+//! scraping the logs.
+
+use crate::bitcoin::wallet::{ScriptStatus, Subscription};
+use crate::bitcoin::{CancelTimelock, PunishTimelock, Txid};
+use crate::monero::TransferProof;
+use anyhow::Result;
+use futures::Stream;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+/// How many events are buffered for slow subscribers.
+const CHANNEL_CAPACITY: usize = 100;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SwapEvent {
+    /// The swap advanced to the given state, which has been persisted.
+    StateChanged { swap_id: Uuid, state: String },
+    /// We published one of the Bitcoin transactions of the swap.
+    BitcoinTransactionPublished {
+        swap_id: Uuid,
+        kind: String,
+        txid: Txid,
+    },
+    /// The status of a Bitcoin transaction we are waiting for changed.
+    BitcoinTransactionStatus {
+        swap_id: Uuid,
+        txid: Txid,
+        status: TransactionStatus,
+    },
+    /// Alice sent, or Bob received, the proof that the Monero were locked.
+    TransferProof {
+        swap_id: Uuid,
+        transfer_proof: TransferProof,
+    },
+    /// The number of blocks left until one of the timelocks expires.
+    TimelockCountdown {
+        swap_id: Uuid,
+        timelock: Timelock,
+        blocks_left: u32,
+    },
+    /// The swap stopped because of an error, it can be resumed.
+    Failed { swap_id: Uuid, error: String },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum TransactionStatus {
+    Unseen,
+    InMempool,
+    Confirmed { confirmations: u32 },
+    Retrying,
+}
+
+impl From<ScriptStatus> for TransactionStatus {
+    fn from(status: ScriptStatus) -> Self {
+        match status {
+            ScriptStatus::Unseen => TransactionStatus::Unseen,
+            ScriptStatus::InMempool => TransactionStatus::InMempool,
+            ScriptStatus::Confirmed(inner) => TransactionStatus::Confirmed {
+                confirmations: inner.confirmations(),
+            },
+            ScriptStatus::Retrying => TransactionStatus::Retrying,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Timelock {
+    /// Relative to the lock transaction, after it the swap can be cancelled.
+    Cancel,
+    /// Relative to the cancel transaction, after it Alice can punish Bob.
+    Punish,
+}
+
+/// The sending side of the channel swaps publish their [`SwapEvent`]s on.
+///
+/// Cloning it gives another sender to the same channel. Events sent while
+/// nobody is subscribed are dropped.
+#[derive(Clone, Debug)]
+pub struct SwapEvents {
+    sender: broadcast::Sender<SwapEvent>,
+}
+
+impl Default for SwapEvents {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+
+        Self { sender }
+    }
+}
+
+impl SwapEvents {
+    pub fn subscribe(&self) -> broadcast::Receiver<SwapEvent> {
+        self.sender.subscribe()
+    }
+
+    /// All events sent from now on, skipping the ones a slow consumer
+    /// missed.
+    pub fn stream(&self) -> impl Stream<Item = SwapEvent> + Send + Unpin + 'static {
+        let stream = futures::stream::unfold(self.subscribe(), |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, receiver)),
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::warn!(%skipped, "Consumer missed swap events")
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+
+        Box::pin(stream)
+    }
+
+    pub fn send(&self, event: SwapEvent) {
+        // Nobody might be subscribed, which is fine
+        let _ = self.sender.send(event);
+    }
+
+    /// Wait until the lock transaction is final, reporting its confirmations.
+    pub async fn wait_until_final(&self, swap_id: Uuid, tx_lock: &Subscription) -> Result<()> {
+        let finality_confirmations = tx_lock.finality_confirmations();
+
+        self.wait_reporting_status(swap_id, tx_lock, None, |status| {
+            status.is_confirmed_with(finality_confirmations)
+        })
+        .await
+    }
+
+    /// Wait until the cancel timelock expired, reporting the confirmations of
+    /// the lock transaction and the blocks left.
+    pub async fn wait_until_cancel_timelock_expired(
+        &self,
+        swap_id: Uuid,
+        tx_lock: &Subscription,
+        cancel_timelock: CancelTimelock,
+    ) -> Result<()> {
+        self.wait_reporting_status(
+            swap_id,
+            tx_lock,
+            Some((Timelock::Cancel, cancel_timelock.into())),
+            |status| status.is_confirmed_with(cancel_timelock),
+        )
+        .await
+    }
+
+    /// Wait until the punish timelock expired, reporting the confirmations of
+    /// the cancel transaction and the blocks left.
+    pub async fn wait_until_punish_timelock_expired(
+        &self,
+        swap_id: Uuid,
+        tx_cancel: &Subscription,
+        punish_timelock: PunishTimelock,
+    ) -> Result<()> {
+        self.wait_reporting_status(
+            swap_id,
+            tx_cancel,
+            Some((Timelock::Punish, punish_timelock.into())),
+            |status| status.is_confirmed_with(punish_timelock),
+        )
+        .await
+    }
+
+    async fn wait_reporting_status(
+        &self,
+        swap_id: Uuid,
+        subscription: &Subscription,
+        timelock: Option<(Timelock, u32)>,
+        mut predicate: impl FnMut(&ScriptStatus) -> bool,
+    ) -> Result<()> {
+        let txid = subscription.txid();
+        let mut last_status = None;
+
+        subscription
+            .wait_until(|status| {
+                if last_status != Some(*status) {
+                    last_status = Some(*status);
+
+                    self.send(SwapEvent::BitcoinTransactionStatus {
+                        swap_id,
+                        txid,
+                        status: (*status).into(),
+                    });
+
+                    if let (Some((timelock, blocks)), ScriptStatus::Confirmed(inner)) =
+                        (timelock, status)
+                    {
+                        self.send(SwapEvent::TimelockCountdown {
+                            swap_id,
+                            timelock,
+                            blocks_left: blocks.saturating_sub(inner.confirmations()),
+                        });
+                    }
+                }
+
+                predicate(status)
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitcoin::wallet::Confirmed;
+
+    #[test]
+    fn serializes_confirmations_instead_of_depth() {
+        let status = TransactionStatus::from(ScriptStatus::Confirmed(Confirmed::new(2)));
+
+        assert_eq!(
+            serde_json::to_value(status).unwrap(),
+            serde_json::json!({ "status": "confirmed", "confirmations": 3 })
+        );
+    }
+
+    #[test]
+    fn events_are_tagged_with_their_type() {
+        let event = SwapEvent::TimelockCountdown {
+            swap_id: Uuid::nil(),
+            timelock: Timelock::Cancel,
+            blocks_left: 5,
+        };
+
+        assert_eq!(
+            serde_json::to_value(event).unwrap(),
+            serde_json::json!({
+                "type": "timelock_countdown",
+                "swap_id": Uuid::nil(),
+                "timelock": "cancel",
+                "blocks_left": 5
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn events_without_subscribers_are_dropped() {
+        let events = SwapEvents::default();
+
+        events.send(SwapEvent::StateChanged {
+            swap_id: Uuid::nil(),
+            state: "btc is locked".to_owned(),
+        });
+
+        let mut receiver = events.subscribe();
+        let event = SwapEvent::Failed {
+            swap_id: Uuid::nil(),
+            error: "boom".to_owned(),
+        };
+        events.send(event.clone());
+
+        assert_eq!(receiver.recv().await.unwrap(), event);
+    }
+}
@@ -0,0 +1,87 @@
+//! Manual recovery of swaps in either role.
+//!
+//! The ASB is Bob in swaps in which it buys XMR and the CLI is Alice in swaps
+//! in which it sells XMR, so the recovery commands of both look up the role
+//! we had in a swap before acting on it.
+
+use crate::bitcoin::{self, Txid};
+use crate::protocol::bob::BobState;
+use crate::protocol::{Database, State};
+use crate::{asb, cli, monero};
+use anyhow::{bail, Context, Result};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Publish the cancel transaction of the swap.
+pub async fn cancel(
+    swap_id: Uuid,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    db: Arc<dyn Database + Send + Sync>,
+) -> Result<(Txid, State)> {
+    match db.get_state(swap_id).await? {
+        State::Alice(_) => {
+            let (txid, state) = asb::cancel(swap_id, bitcoin_wallet, db).await?;
+            Ok((txid, state.into()))
+        }
+        State::Bob(_) => {
+            let (txid, state) = cli::cancel(swap_id, bitcoin_wallet, db).await?;
+            Ok((txid, state.into()))
+        }
+    }
+}
+
+/// Refund what we locked in the swap: the BTC if we bought XMR, the XMR if we
+/// sold them.
+///
+/// Refunding XMR needs a Monero wallet to sweep them into.
+pub async fn refund(
+    swap_id: Uuid,
+    bitcoin_wallet: Arc<bitcoin::Wallet>,
+    monero_wallet: Option<Arc<monero::Wallet>>,
+    db: Arc<dyn Database + Send + Sync>,
+) -> Result<State> {
+    match db.get_state(swap_id).await? {
+        State::Alice(_) => {
+            let monero_wallet =
+                monero_wallet.context("Refunding the XMR of the swap requires a Monero wallet")?;
+            let state = asb::refund(swap_id, bitcoin_wallet, monero_wallet, db).await?;
+
+            Ok(state.into())
+        }
+        State::Bob(_) => Ok(cli::refund(swap_id, bitcoin_wallet, db).await?.into()),
+    }
+}
+
+/// Mark a swap in which nothing was locked yet as safely aborted.
+pub async fn safely_abort(swap_id: Uuid, db: Arc<dyn Database + Send + Sync>) -> Result<State> {
+    let state = match db.get_state(swap_id).await? {
+        State::Alice(_) => return Ok(asb::safely_abort(swap_id, db).await?.into()),
+        State::Bob(state) => state,
+    };
+
+    match state {
+        BobState::Started { .. } | BobState::SwapSetupCompleted(_) => {
+            let state = State::Bob(BobState::SafelyAborted);
+
+            db.insert_latest_state(swap_id, state.clone()).await?;
+
+            Ok(state)
+        }
+
+        BobState::BtcLocked { .. }
+        | BobState::XmrLockProofReceived { .. }
+        | BobState::XmrLocked(_)
+        | BobState::EncSigSent(_)
+        | BobState::BtcRedeemed(_)
+        | BobState::CancelTimelockExpired(_)
+        | BobState::BtcCancelled(_)
+        | BobState::BtcRefunded(_)
+        | BobState::XmrRedeemed { .. }
+        | BobState::BtcPunished { .. }
+        | BobState::SafelyAborted => bail!(
+            "Cannot safely abort swap {} because it is in state {} which cannot be safely aborted",
+            swap_id,
+            state
+        ),
+    }
+}
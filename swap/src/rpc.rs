@@ -0,0 +1,181 @@
+//! The parts of the JSON-RPC servers of the ASB and the CLI that both share.
+//!
+//! Both servers require every request to carry the token stored in the
+//! `rpc-token` file of the data directory as `Authorization: Bearer <token>`
+//! header.
+
+use crate::protocol::State;
+use ::bitcoin::Txid;
+use anyhow::{Context as _, Result};
+use jsonrpsee::core::Error as RpcError;
+use jsonrpsee::server::{RpcModule, ServerBuilder, ServerHandle};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+use tower_http::auth::RequireAuthorizationLayer;
+use uuid::Uuid;
+
+const TOKEN_FILE_NAME: &str = "rpc-token";
+
+/// Start a server for the given methods that only accepts requests carrying
+/// the token.
+pub(crate) async fn serve<C>(
+    listen: SocketAddr,
+    token: &str,
+    methods: RpcModule<C>,
+) -> Result<(SocketAddr, ServerHandle)>
+where
+    C: Send + Sync + 'static,
+{
+    let middleware = tower::ServiceBuilder::new().layer(RequireAuthorizationLayer::bearer(token));
+
+    let server = ServerBuilder::default()
+        .set_middleware(middleware)
+        .build(listen)
+        .await
+        .with_context(|| format!("Failed to bind RPC server to {}", listen))?;
+    let address = server.local_addr()?;
+
+    let handle = server.start(methods)?;
+
+    Ok((address, handle))
+}
+
+/// Read the token clients have to authenticate with, generating a new one on
+/// first use.
+///
+/// A generated token is only readable by the current user.
+pub fn read_or_generate_token(data_dir: &Path) -> Result<String> {
+    let path = data_dir.join(TOKEN_FILE_NAME);
+
+    if path.exists() {
+        let token = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read RPC token from {}", path.display()))?;
+
+        return Ok(token.trim().to_owned());
+    }
+
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+
+    write_token(&path, &token)
+        .with_context(|| format!("Failed to write RPC token to {}", path.display()))?;
+
+    Ok(token)
+}
+
+fn write_token(path: &Path, token: &str) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)?.write_all(token.as_bytes())?;
+
+    Ok(())
+}
+
+pub(crate) fn to_rpc_error(error: anyhow::Error) -> RpcError {
+    RpcError::Custom(format!("{:#}", error))
+}
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct SwapIdParams {
+    pub swap_id: Uuid,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Swap {
+    swap_id: Uuid,
+    state: String,
+    finished: bool,
+}
+
+impl Swap {
+    pub(crate) fn new(swap_id: Uuid, state: State) -> Self {
+        Self {
+            swap_id,
+            state: state.to_string(),
+            finished: state.swap_finished(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct Recovered {
+    txid: Option<Txid>,
+    state: String,
+}
+
+impl Recovered {
+    pub(crate) fn new(txid: Option<Txid>, state: impl ToString) -> Self {
+        Self {
+            txid,
+            state: state.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn generates_token_only_once() {
+        let data_dir = tempdir().unwrap();
+
+        let token = read_or_generate_token(data_dir.path()).unwrap();
+        let reread = read_or_generate_token(data_dir.path()).unwrap();
+
+        assert_eq!(token.len(), 64);
+        assert_eq!(token, reread);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn generated_token_is_only_readable_by_the_user() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let data_dir = tempdir().unwrap();
+
+        read_or_generate_token(data_dir.path()).unwrap();
+        let metadata = fs::metadata(data_dir.path().join(TOKEN_FILE_NAME)).unwrap();
+
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+    }
+
+    #[tokio::test]
+    async fn rejects_requests_without_the_right_token() {
+        let mut module = RpcModule::new(());
+        module.register_method("ping", |_, _| Ok("pong")).unwrap();
+        let (address, _handle) = serve("127.0.0.1:0".parse().unwrap(), "secret", module)
+            .await
+            .unwrap();
+
+        assert_eq!(ping(address, None).await, 401);
+        assert_eq!(ping(address, Some("Bearer wrong")).await, 401);
+        assert_eq!(ping(address, Some("secret")).await, 401);
+        assert_eq!(ping(address, Some("Bearer secret")).await, 200);
+    }
+
+    async fn ping(address: SocketAddr, authorization: Option<&str>) -> u16 {
+        let mut request = reqwest::Client::new()
+            .post(format!("http://{}", address))
+            .header("Content-Type", "application/json")
+            .body(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#);
+        if let Some(authorization) = authorization {
+            request = request.header("Authorization", authorization);
+        }
+
+        request.send().await.unwrap().status().as_u16()
+    }
+}
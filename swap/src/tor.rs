@@ -1,6 +1,12 @@
+#[cfg(feature = "embedded-tor")]
+mod embedded;
+
+#[cfg(feature = "embedded-tor")]
+pub use embedded::start_embedded;
+
 use anyhow::{bail, Context, Result};
 use std::future::Future;
-use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
 use tokio::net::TcpStream;
 use torut::control::{AsyncEvent, AuthenticatedConn, ConnError, UnauthenticatedConn};
 use torut::onion::TorSecretKeyV3;
@@ -103,6 +109,67 @@ impl Client {
     }
 }
 
+/// Start Tor inside this process, which needs the `embedded-tor` feature.
+#[cfg(not(feature = "embedded-tor"))]
+pub async fn start_embedded(
+    _data_dir: &std::path::Path,
+    _socks5_port: u16,
+    _control_port: Option<u16>,
+) -> Result<()> {
+    bail!("Cannot start embedded Tor because this binary was built without the `embedded-tor` feature")
+}
+
+/// Fails if something, e.g. a system tor, already listens on `port` of
+/// localhost.
+pub fn ensure_port_available(port: u16) -> Result<()> {
+    std::net::TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port)).with_context(
+        || {
+            format!(
+                "Port {} is already in use, stop the system tor or choose another port",
+                port
+            )
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Whether `host` is this machine.
+///
+/// Tor can't connect to it and connecting to it does not reveal anything, so
+/// connections to this machine never go through Tor.
+pub fn is_local_host(host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    host == "localhost"
+        || host
+            .parse::<IpAddr>()
+            .map(|ip| ip.is_loopback())
+            .unwrap_or(false)
+}
+
+/// The socks5 port to connect to `host` through, none if the connection has
+/// to be made directly.
+pub fn socks5_port_for(host: &str, tor_socks5_port: Option<u16>) -> Option<u16> {
+    tor_socks5_port.filter(|_| !is_local_host(host))
+}
+
+/// The address of the socks5 proxy of Tor, as expected by most clients.
+pub fn socks5_address(socks5_port: u16) -> String {
+    SocketAddrV4::new(Ipv4Addr::LOCALHOST, socks5_port).to_string()
+}
+
+/// A proxy for `reqwest` that sends all requests but those to this machine
+/// through the socks5 port of Tor, which also resolves the host names.
+pub fn reqwest_proxy(socks5_port: u16) -> reqwest::Proxy {
+    let proxy_url = format!("socks5h://{}", socks5_address(socks5_port));
+
+    reqwest::Proxy::custom(move |url| match url.host_str() {
+        Some(host) if is_local_host(host) => None,
+        _ => Some(proxy_url.clone()),
+    })
+}
+
 type Handler = fn(AsyncEvent<'_>) -> Box<dyn Future<Output = Result<(), ConnError>> + Unpin>;
 
 #[allow(missing_debug_implementations)]
@@ -126,3 +193,41 @@ impl AuthenticatedClient {
             .context("Failed to add onion service")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn taken_port_is_not_available() {
+        let listener =
+            std::net::TcpListener::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        assert!(ensure_port_available(port).is_err());
+
+        drop(listener);
+        assert!(ensure_port_available(port).is_ok());
+    }
+
+    #[test]
+    fn connections_to_this_machine_bypass_tor() {
+        assert_eq!(socks5_port_for("localhost", Some(9050)), None);
+        assert_eq!(socks5_port_for("127.0.0.1", Some(9050)), None);
+        assert_eq!(socks5_port_for("[::1]", Some(9050)), None);
+    }
+
+    #[test]
+    fn remote_connections_go_through_tor() {
+        assert_eq!(socks5_port_for("blockstream.info", Some(9050)), Some(9050));
+        assert_eq!(socks5_port_for("192.168.1.2", Some(9050)), Some(9050));
+        assert_eq!(
+            socks5_port_for(
+                "oarchy4tamydxcitaki6bc2v4leza6v35iezmu2chg2bap63sv6f2did.onion",
+                Some(9050)
+            ),
+            Some(9050)
+        );
+        assert_eq!(socks5_port_for("blockstream.info", None), None);
+    }
+}
@@ -0,0 +1,89 @@
+use crate::tor::{ensure_port_available, Client};
+use anyhow::{anyhow, Context, Result};
+use libtor::{Tor, TorBool, TorFlag};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::time::{sleep, timeout};
+
+/// How long we give the embedded Tor to bootstrap before giving up.
+const BOOTSTRAP_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Start Tor inside this process, listening on the given ports on localhost
+/// like a system tor configured with them would, and wait until it has
+/// bootstrapped.
+///
+/// The control port uses cookie authentication with the cookie in
+/// `data_dir`. Tor keeps running until the process exits and can only be
+/// started once.
+///
+/// Fails if one of the ports is already taken, most likely by a system tor
+/// we would otherwise mistake for the embedded one, or if Tor exits before
+/// it has bootstrapped.
+pub async fn start_embedded(
+    data_dir: &Path,
+    socks5_port: u16,
+    control_port: Option<u16>,
+) -> Result<()> {
+    ensure_port_available(socks5_port)?;
+    if let Some(control_port) = control_port {
+        ensure_port_available(control_port)?;
+    }
+
+    std::fs::create_dir_all(data_dir).with_context(|| {
+        format!(
+            "Failed to create data directory {} for Tor",
+            data_dir.display()
+        )
+    })?;
+
+    let mut tor = Tor::new();
+    tor.flag(TorFlag::DataDirectory(data_dir.display().to_string()))
+        .flag(TorFlag::SocksPort(socks5_port));
+    if let Some(control_port) = control_port {
+        tor.flag(TorFlag::ControlPort(control_port))
+            .flag(TorFlag::CookieAuthentication(TorBool::True));
+    }
+
+    tracing::info!(data_dir = %data_dir.display(), %socks5_port, "Starting embedded Tor");
+
+    // Runs on a thread of its own, there is no way to stop it from the outside
+    let tor_thread = tor.start_background();
+
+    // Joining blocks, so we wait for Tor to exit on another thread to hear about
+    // it while bootstrapping and afterwards
+    let (exited_tx, exited_rx) = oneshot::channel();
+    thread::spawn(move || {
+        let exit = match tor_thread.join() {
+            Ok(Ok(code)) => anyhow!("Embedded Tor exited with code {}", code),
+            Ok(Err(e)) => anyhow!("Embedded Tor failed: {:?}", e),
+            Err(_) => anyhow!("Embedded Tor panicked"),
+        };
+
+        if let Err(exit) = exited_tx.send(exit) {
+            tracing::error!("{:#}", exit);
+        }
+    });
+
+    let client = Client::new(socks5_port);
+    let bootstrapped = timeout(BOOTSTRAP_TIMEOUT, async {
+        while client.assert_tor_running().await.is_err() {
+            sleep(Duration::from_secs(1)).await;
+        }
+    });
+
+    tokio::select! {
+        bootstrapped = bootstrapped => {
+            bootstrapped.context("Embedded Tor did not bootstrap in time")?;
+        }
+        exit = exited_rx => {
+            let exit = exit.unwrap_or_else(|_| anyhow!("Embedded Tor exited"));
+            return Err(exit.context("Embedded Tor exited while bootstrapping"));
+        }
+    }
+
+    tracing::info!("Embedded Tor bootstrapped");
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+pub mod harness;
+
+use harness::SlowCancelConfig;
+use swap::bitcoin::{parse_rpc_error_code, Amount, RpcErrorCode, TxRedeem};
+
+#[tokio::test]
+async fn wallet_works_with_every_bitcoin_backend() {
+    harness::setup_bitcoin_backends_test(SlowCancelConfig, |ctx| async move {
+        for backend in ctx.backends.clone() {
+            let wallet = ctx
+                .funded_wallet(backend.clone(), Amount::from_sat(1_000_000))
+                .await;
+            let amount = Amount::from_sat(100_000);
+
+            let fee = wallet.estimate_fee(TxRedeem::weight(), amount).await?;
+            assert!(fee > Amount::ZERO, "{} estimated no fee", backend);
+
+            let address = wallet.new_address().await?;
+            let psbt = wallet.send_to_address(address, amount, None).await?;
+            let transaction = wallet.sign_and_finalize(psbt).await?;
+
+            let (txid, subscription) = wallet.broadcast(transaction.clone(), "test").await?;
+            subscription.wait_until_confirmed_with(1).await?;
+
+            assert_eq!(wallet.get_raw_transaction(txid).await?, transaction);
+
+            let error = wallet
+                .broadcast(transaction, "test")
+                .await
+                .expect_err("transaction to be in the chain already");
+            assert_eq!(
+                parse_rpc_error_code(&error)?,
+                i64::from(RpcErrorCode::RpcVerifyAlreadyInChain),
+                "{} did not report the rejection",
+                backend
+            );
+        }
+
+        Ok(())
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn wallet_bumps_fee_of_stuck_transaction_on_every_bitcoin_backend() {
+    harness::setup_bitcoin_backends_test(SlowCancelConfig, |ctx| async move {
+        for backend in ctx.backends.clone() {
+            let wallet = ctx
+                .funded_wallet(backend.clone(), Amount::from_sat(1_000_000))
+                .await;
+
+            let address = wallet.new_address().await?;
+            let psbt = wallet
+                .send_to_address(address, Amount::from_sat(100_000), None)
+                .await?;
+            let transaction = wallet.sign_and_finalize(psbt).await?;
+
+            let mut spent = Amount::ZERO;
+            for input in &transaction.input {
+                let previous = wallet
+                    .get_raw_transaction(input.previous_output.txid)
+                    .await?;
+                spent +=
+                    Amount::from_sat(previous.output[input.previous_output.vout as usize].value);
+            }
+            let sent = Amount::from_sat(transaction.output.iter().map(|output| output.value).sum());
+            ctx.deprioritise_transaction(transaction.txid(), spent - sent)?;
+
+            let (_, subscription) = wallet.broadcast(transaction, "test").await?;
+            wallet
+                .wait_until_confirmed_or_bump(&subscription, "test")
+                .await?;
+        }
+
+        Ok(())
+    })
+    .await;
+}
@@ -0,0 +1,69 @@
+pub mod harness;
+
+use harness::alice_run_until::is_xmr_lock_transaction_sent;
+use harness::bob_run_until::is_btc_locked;
+use harness::FastCancelConfig;
+use swap::asb::FixedRate;
+use swap::protocol::alice::AliceState;
+use swap::protocol::bob::BobState;
+use swap::protocol::{alice, bob};
+
+/// Bob locks Btc and Alice locks Xmr. Alice does not act so Bob cancels, but
+/// the cancel transaction at the negotiated fee never gets mined. Bob replaces
+/// it with one paying a higher fee and refunds. Eventually Alice comes back
+/// online and refunds by spotting the refund of the replacement.
+#[tokio::test]
+async fn given_stuck_cancel_transaction_bob_replaces_it_and_both_refund() {
+    harness::setup_test(FastCancelConfig, |mut ctx| async move {
+        let (bob_swap, bob_join_handle) = ctx.bob_swap().await;
+        let bob_swap_id = bob_swap.id;
+        let bob_swap = tokio::spawn(bob::run_until(bob_swap, is_btc_locked));
+
+        let alice_swap = ctx.alice_next_swap().await;
+        let alice_swap = tokio::spawn(alice::run_until(
+            alice_swap,
+            is_xmr_lock_transaction_sent,
+            FixedRate::default(),
+        ));
+
+        let bob_state = bob_swap.await??;
+        let tx_cancel = if let BobState::BtcLocked { state3, .. } = &bob_state {
+            let tx_cancel = state3.cancel().tx_cancel();
+            let fee = state3.tx_lock.lock_amount() - tx_cancel.amount();
+            ctx.deprioritise_bitcoin_transaction(tx_cancel.txid(), fee)?;
+
+            tx_cancel
+        } else {
+            panic!("Bob in unexpected state {}", bob_state);
+        };
+
+        let (bob_swap, _) = ctx
+            .stop_and_resume_bob_from_db(bob_join_handle, bob_swap_id)
+            .await;
+        let bob_bitcoin_wallet = bob_swap.bitcoin_wallet.clone();
+        let bob_state = bob::run(bob_swap).await?;
+        assert!(matches!(bob_state, BobState::BtcRefunded(..)));
+
+        let tx_cancel_status = bob_bitcoin_wallet.status_of_script(&tx_cancel).await?;
+        assert!(
+            !tx_cancel_status.is_confirmed(),
+            "cancel transaction at the negotiated fee was confirmed"
+        );
+
+        let alice_state = alice_swap.await??;
+        assert!(matches!(
+            alice_state,
+            AliceState::XmrLockTransactionSent { .. }
+        ));
+
+        ctx.restart_alice().await;
+        let alice_swap = ctx.alice_next_swap().await;
+        let alice_swap = tokio::spawn(alice::run(alice_swap, FixedRate::default()));
+
+        let alice_state = alice_swap.await??;
+        ctx.assert_alice_refunded(alice_state).await;
+
+        Ok(())
+    })
+    .await;
+}
@@ -0,0 +1,65 @@
+pub mod harness;
+
+use harness::SlowCancelConfig;
+use serde_json::json;
+use std::time::Duration;
+use swap::asb::FixedRate;
+use swap::protocol::alice;
+use swap::protocol::alice::AliceState;
+use tokio::time::{sleep, timeout};
+
+#[tokio::test]
+async fn given_daemon_buys_xmr_then_swap_completes_without_being_recovered_meanwhile() {
+    harness::setup_test(SlowCancelConfig, |mut ctx| async move {
+        let daemon = ctx.bob_daemon().await;
+
+        let swap = daemon
+            .call(
+                "buy_xmr",
+                json!({
+                    "seller": daemon.seller.to_string(),
+                    "bitcoin_change_address": daemon.bitcoin_change_address.to_string(),
+                    "monero_receive_address": daemon.monero_receive_address.to_string(),
+                }),
+            )
+            .await
+            .unwrap();
+        let swap_id = swap["swap_id"].clone();
+
+        // the swap shares the Monero wallet and must not be recovered from under it
+        for method in ["resume", "cancel", "refund"] {
+            let error = daemon
+                .call(method, json!({ "swap_id": swap_id }))
+                .await
+                .unwrap_err();
+            assert_eq!(error, "Another swap is already running", "{}", method);
+        }
+
+        let alice_swap = ctx.alice_next_swap().await;
+        let alice_state = alice::run(alice_swap, FixedRate::default()).await?;
+        assert!(matches!(alice_state, AliceState::BtcRedeemed));
+
+        let finished = timeout(Duration::from_secs(120), async {
+            loop {
+                let history = daemon.call("history", json!({})).await.unwrap();
+                let swap = history
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .find(|swap| swap["swap_id"] == swap_id)
+                    .cloned()
+                    .unwrap();
+
+                if swap["finished"] == true {
+                    return swap;
+                }
+                sleep(Duration::from_secs(1)).await;
+            }
+        })
+        .await?;
+        assert_eq!(finished["state"], "xmr is redeemed");
+
+        Ok(())
+    })
+    .await
+}
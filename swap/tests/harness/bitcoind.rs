@@ -104,6 +104,7 @@ impl IntoIterator for BitcoindArgs {
             "-regtest".to_string(),
             "-listen=1".to_string(),
             "-prune=0".to_string(),
+            "-txindex=1".to_string(),
             "-rpcallowip=0.0.0.0/0".to_string(),
             "-rpcbind=0.0.0.0".to_string(),
             format!("-rpcuser={}", RPC_USER),
@@ -4,25 +4,35 @@ mod electrs;
 use anyhow::{bail, Context, Result};
 use async_trait::async_trait;
 use bitcoin_harness::{BitcoindRpcApi, Client};
+use bitcoincore_rpc::{Auth, RpcApi};
 use futures::Future;
 use get_port::get_port;
+use jsonrpsee::server::ServerHandle;
+use libp2p::core::multiaddr::Protocol;
 use libp2p::core::Multiaddr;
 use libp2p::PeerId;
 use monero_harness::{image, Monero};
+use rust_decimal::Decimal;
 use std::cmp::Ordering;
+use std::convert::TryFrom;
 use std::fmt;
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
+use swap::asb::config::BuyXmr;
 use swap::asb::FixedRate;
-use swap::bitcoin::{CancelTimelock, PunishTimelock, TxCancel, TxPunish, TxRedeem, TxRefund};
+use swap::bitcoin::{CancelTimelock, PunishTimelock, TxCancel, TxPunish, TxRedeem, TxRefund, Txid};
 use swap::database::SqliteDatabase;
 use swap::env::{Config, GetConfig};
 use swap::fs::ensure_directory_exists;
+use swap::network::rendezvous::XmrBtcNamespace;
+use swap::network::swap_setup::reverse::alice::{min_btc, NewSwap as NewReverseSwap};
 use swap::network::swarm;
 use swap::protocol::alice::{AliceState, Swap};
 use swap::protocol::bob::BobState;
-use swap::protocol::{alice, bob};
+use swap::protocol::event::SwapEvents;
+use swap::protocol::{alice, bob, Database, State};
 use swap::seed::Seed;
 use swap::{asb, bitcoin, cli, env, monero};
 use tempfile::{tempdir, NamedTempFile};
@@ -36,7 +46,121 @@ use tracing_subscriber::util::SubscriberInitExt;
 use url::Url;
 use uuid::Uuid;
 
-pub async fn setup_test<T, F, C>(_config: C, testfn: T)
+pub async fn setup_test<T, F, C>(config: C, testfn: T)
+where
+    T: Fn(TestContext) -> F,
+    F: Future<Output = Result<()>>,
+    C: GetConfig,
+{
+    setup(config, Direction::AliceSellsXmr, testfn).await
+}
+
+/// Set up a test in which the roles are reversed: the ASB buys XMR from the
+/// CLI.
+///
+/// The ASB is still referred to as Alice and the CLI as Bob, but the ASB
+/// starts out with BTC and the CLI with XMR.
+pub async fn setup_reverse_test<T, F, C>(config: C, testfn: T)
+where
+    T: Fn(TestContext) -> F,
+    F: Future<Output = Result<()>>,
+    C: GetConfig,
+{
+    setup(config, Direction::AliceBuysXmr, testfn).await
+}
+
+/// Set up a test against the Bitcoin backends only, without Monero or any
+/// swap.
+///
+/// Every backend is served by the same regtest bitcoind, either directly or
+/// through electrs.
+pub async fn setup_bitcoin_backends_test<T, F, C>(_config: C, testfn: T)
+where
+    T: Fn(BitcoinBackends) -> F,
+    F: Future<Output = Result<()>>,
+    C: GetConfig,
+{
+    let cli = Cli::default();
+
+    let _guard = tracing_subscriber::fmt()
+        .with_env_filter("warn,swap=debug,bitcoin_harness=info,testcontainers=info")
+        .with_test_writer()
+        .set_default();
+
+    let prefix = random_prefix();
+    let bitcoind_name = format!("{}_{}", prefix, "bitcoind");
+    let (_bitcoind, bitcoind_url) =
+        init_bitcoind_container(&cli, prefix.clone(), bitcoind_name.clone(), prefix.clone())
+            .await
+            .expect("could not init bitcoind");
+    let electrs = init_electrs_container(&cli, prefix.clone(), bitcoind_name, prefix)
+        .await
+        .expect("could not init electrs");
+
+    let electrum_url = format!(
+        "tcp://@localhost:{}",
+        electrs.get_host_port(electrs::RPC_PORT).unwrap()
+    );
+    let esplora_url = format!(
+        "http://localhost:{}",
+        electrs.get_host_port(electrs::HTTP_PORT).unwrap()
+    );
+
+    let backends = BitcoinBackends {
+        env_config: C::get_config(),
+        bitcoind_url: bitcoind_url.clone(),
+        backends: vec![
+            bitcoin::BackendConfig::Electrum {
+                url: Url::parse(&electrum_url).unwrap(),
+            },
+            bitcoin::BackendConfig::Bitcoind { url: bitcoind_url },
+            bitcoin::BackendConfig::Esplora {
+                url: Url::parse(&esplora_url).unwrap(),
+            },
+        ],
+    };
+
+    testfn(backends).await.unwrap()
+}
+
+pub struct BitcoinBackends {
+    env_config: Config,
+    bitcoind_url: Url,
+    pub backends: Vec<bitcoin::BackendConfig>,
+}
+
+impl BitcoinBackends {
+    /// A new wallet on the given backend, funded by the regtest node.
+    pub async fn funded_wallet(
+        &self,
+        backend: bitcoin::BackendConfig,
+        amount: bitcoin::Amount,
+    ) -> bitcoin::Wallet {
+        init_bitcoin_wallet(
+            backend,
+            self.bitcoind_url.clone(),
+            amount,
+            tempdir().unwrap().path(),
+            &Seed::random().unwrap(),
+            self.env_config,
+        )
+        .await
+    }
+
+    /// Make the regtest node leave the transaction out of the blocks it
+    /// mines, see [`deprioritise_transaction`].
+    pub fn deprioritise_transaction(&self, txid: Txid, fee: bitcoin::Amount) -> Result<()> {
+        deprioritise_transaction(&self.bitcoind_url, txid, fee)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Direction {
+    AliceSellsXmr,
+    AliceBuysXmr,
+}
+
+async fn setup<T, F, C>(_config: C, direction: Direction, testfn: T)
 where
     T: Fn(TestContext) -> F,
     F: Future<Output = Result<()>>,
@@ -57,8 +181,16 @@ where
     let btc_amount = bitcoin::Amount::from_sat(1_000_000);
     let xmr_amount = monero::Amount::from_monero(btc_amount.as_btc() / FixedRate::RATE).unwrap();
 
-    let alice_starting_balances =
-        StartingBalances::new(bitcoin::Amount::ZERO, xmr_amount, Some(10));
+    let (alice_starting_balances, bob_starting_balances) = match direction {
+        Direction::AliceSellsXmr => (
+            StartingBalances::new(bitcoin::Amount::ZERO, xmr_amount, Some(10)),
+            StartingBalances::new(btc_amount * 10, monero::Amount::ZERO, None),
+        ),
+        Direction::AliceBuysXmr => (
+            StartingBalances::new(btc_amount * 10, monero::Amount::ZERO, None),
+            StartingBalances::new(bitcoin::Amount::ZERO, xmr_amount, Some(10)),
+        ),
+    };
 
     let electrs_rpc_port = containers
         .electrs
@@ -75,6 +207,7 @@ where
         electrs_rpc_port,
         &alice_seed,
         env_config,
+        None,
     )
     .await;
 
@@ -84,7 +217,7 @@ where
         .expect("failed to parse Alice's address");
 
     let alice_db_path = NamedTempFile::new().unwrap().path().to_path_buf();
-    let (alice_handle, alice_swap_handle) = start_alice(
+    let (alice_handle, alice_swap_handle, alice_reverse_swap_handle) = start_alice(
         &alice_seed,
         alice_db_path.clone(),
         alice_listen_address.clone(),
@@ -95,17 +228,18 @@ where
     .await;
 
     let bob_seed = Seed::random().unwrap();
-    let bob_starting_balances = StartingBalances::new(btc_amount * 10, monero::Amount::ZERO, None);
 
     let (bob_bitcoin_wallet, bob_monero_wallet) = init_test_wallets(
         MONERO_WALLET_NAME_BOB,
-        containers.bitcoind_url,
+        containers.bitcoind_url.clone(),
         &monero,
         bob_starting_balances.clone(),
         tempdir().unwrap().path(),
         electrs_rpc_port,
         &bob_seed,
         env_config,
+        // like the CLI, Bob verifies transfers with the transactions of monerod
+        Some(monero.monerod().client().clone()),
     )
     .await;
 
@@ -132,11 +266,13 @@ where
         alice_bitcoin_wallet,
         alice_monero_wallet,
         alice_swap_handle,
+        alice_reverse_swap_handle,
         alice_handle,
         bob_params,
         bob_starting_balances,
         bob_bitcoin_wallet,
         bob_monero_wallet,
+        bitcoind_url: containers.bitcoind_url.clone(),
     };
 
     testfn(test).await.unwrap()
@@ -222,7 +358,11 @@ async fn start_alice(
     env_config: Config,
     bitcoin_wallet: Arc<bitcoin::Wallet>,
     monero_wallet: Arc<monero::Wallet>,
-) -> (AliceApplicationHandle, Receiver<alice::Swap>) {
+) -> (
+    AliceApplicationHandle,
+    Receiver<alice::Swap>,
+    Receiver<bob::Swap>,
+) {
     if let Some(parent_dir) = db_path.parent() {
         ensure_directory_exists(parent_dir).unwrap();
     }
@@ -233,6 +373,11 @@ async fn start_alice(
 
     let min_buy = bitcoin::Amount::from_sat(u64::MIN);
     let max_buy = bitcoin::Amount::from_sat(u64::MAX);
+    let buy_xmr = Some(BuyXmr {
+        min_buy_xmr: monero::Amount::ZERO,
+        max_buy_xmr: monero::Amount::from_piconero(u64::MAX),
+        bid_spread: Decimal::ZERO,
+    });
     let latest_rate = FixedRate::default();
     let resume_only = false;
 
@@ -240,15 +385,19 @@ async fn start_alice(
         seed,
         min_buy,
         max_buy,
+        buy_xmr,
         latest_rate,
         resume_only,
         env_config,
+        bitcoin_wallet.clone(),
+        vec![],
+        XmrBtcNamespace::Testnet,
         None,
     )
     .unwrap();
     swarm.listen_on(listen_address).unwrap();
 
-    let (event_loop, swap_handle) = asb::EventLoop::new(
+    let (event_loop, swap_handle, reverse_swap_handle) = asb::EventLoop::new(
         swarm,
         env_config,
         bitcoin_wallet,
@@ -257,13 +406,18 @@ async fn start_alice(
         FixedRate::default(),
         min_buy,
         max_buy,
+        buy_xmr,
     )
     .unwrap();
 
     let peer_id = event_loop.peer_id();
     let handle = tokio::spawn(event_loop.run());
 
-    (AliceApplicationHandle { handle, peer_id }, swap_handle)
+    (
+        AliceApplicationHandle { handle, peer_id },
+        swap_handle,
+        reverse_swap_handle,
+    )
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -276,6 +430,7 @@ async fn init_test_wallets(
     electrum_rpc_port: u16,
     seed: &Seed,
     env_config: Config,
+    monerod_client: Option<monero_rpc::monerod::Client>,
 ) -> (Arc<bitcoin::Wallet>, Arc<monero::Wallet>) {
     monero
         .init_wallet(
@@ -296,28 +451,58 @@ async fn init_test_wallets(
     )
     .await
     .unwrap();
+    let xmr_wallet = match monerod_client {
+        Some(client) => xmr_wallet
+            .with_monerod(client.clone())
+            .with_fee_estimation(client),
+        None => xmr_wallet,
+    };
 
     let electrum_rpc_url = {
         let input = format!("tcp://@localhost:{}", electrum_rpc_port);
         Url::parse(&input).unwrap()
     };
 
+    let btc_wallet = init_bitcoin_wallet(
+        bitcoin::BackendConfig::Electrum {
+            url: electrum_rpc_url,
+        },
+        bitcoind_url,
+        starting_balances.btc,
+        datadir,
+        seed,
+        env_config,
+    )
+    .await;
+
+    (Arc::new(btc_wallet), Arc::new(xmr_wallet))
+}
+
+async fn init_bitcoin_wallet(
+    backend: bitcoin::BackendConfig,
+    bitcoind_url: Url,
+    starting_balance: bitcoin::Amount,
+    datadir: &Path,
+    seed: &Seed,
+    env_config: Config,
+) -> bitcoin::Wallet {
     let btc_wallet = swap::bitcoin::Wallet::new(
-        electrum_rpc_url,
+        backend,
         datadir,
         seed.derive_extended_private_key(env_config.bitcoin_network)
             .expect("Could not create extended private key from seed"),
         env_config,
         1,
+        None,
     )
     .await
     .expect("could not init btc wallet");
 
-    if starting_balances.btc != bitcoin::Amount::ZERO {
+    if starting_balance != bitcoin::Amount::ZERO {
         mint(
             bitcoind_url,
             btc_wallet.new_address().await.unwrap(),
-            starting_balances.btc,
+            starting_balance,
         )
         .await
         .expect("could not mint btc starting balance");
@@ -331,7 +516,7 @@ async fn init_test_wallets(
 
             let btc_balance = btc_wallet.balance().await.unwrap();
 
-            if btc_balance == starting_balances.btc {
+            if btc_balance == starting_balance {
                 break;
             } else if retries == max_retries {
                 panic!(
@@ -343,7 +528,7 @@ async fn init_test_wallets(
         }
     }
 
-    (Arc::new(btc_wallet), Arc::new(xmr_wallet))
+    btc_wallet
 }
 
 const MONERO_WALLET_NAME_BOB: &str = "bob";
@@ -463,6 +648,85 @@ impl BobParams {
         Ok((swap, event_loop))
     }
 
+    pub async fn new_reverse_swap(
+        &self,
+        xmr: monero::Amount,
+        min_btc: bitcoin::Amount,
+    ) -> Result<(alice::Swap, BobApplicationHandle)> {
+        let swap_id = Uuid::new_v4();
+
+        let (event_loop, mut handle) = self.new_eventloop(swap_id).await?;
+        let join_handle = tokio::spawn(event_loop.run());
+
+        let db = self.open_db().await?;
+        db.insert_peer_id(swap_id, self.alice_peer_id).await?;
+
+        let state3 = handle
+            .setup_reverse_swap(NewReverseSwap {
+                swap_id,
+                xmr,
+                min_btc,
+                bitcoin_redeem_address: self.bitcoin_wallet.new_address().await?,
+            })
+            .await?;
+        let state = AliceState::Started {
+            state3: Box::new(state3),
+        };
+        db.insert_latest_state(swap_id, state.clone().into())
+            .await?;
+
+        let swap = alice::Swap {
+            state,
+            event_loop_handle: handle.alice_handle()?,
+            bitcoin_wallet: self.bitcoin_wallet.clone(),
+            monero_wallet: self.monero_wallet.clone(),
+            env_config: self.env_config,
+            swap_id,
+            db,
+            events: SwapEvents::default(),
+        };
+
+        Ok((swap, BobApplicationHandle(join_handle)))
+    }
+
+    pub async fn new_reverse_swap_from_db(
+        &self,
+        swap_id: Uuid,
+    ) -> Result<(alice::Swap, BobApplicationHandle)> {
+        let (event_loop, mut handle) = self.new_eventloop(swap_id).await?;
+        let join_handle = tokio::spawn(event_loop.run());
+
+        let db = self.open_db().await?;
+        let state = match db.get_state(swap_id).await? {
+            State::Alice(state) => state,
+            State::Bob(state) => bail!("Expected a swap selling XMR but got {}", state),
+        };
+
+        let swap = alice::Swap {
+            state,
+            event_loop_handle: handle.alice_handle()?,
+            bitcoin_wallet: self.bitcoin_wallet.clone(),
+            monero_wallet: self.monero_wallet.clone(),
+            env_config: self.env_config,
+            swap_id,
+            db,
+            events: SwapEvents::default(),
+        };
+
+        Ok((swap, BobApplicationHandle(join_handle)))
+    }
+
+    async fn open_db(&self) -> Result<Arc<SqliteDatabase>> {
+        if let Some(parent_dir) = self.db_path.parent() {
+            ensure_directory_exists(parent_dir)?;
+        }
+        if !self.db_path.exists() {
+            tokio::fs::File::create(&self.db_path).await?;
+        }
+
+        Ok(Arc::new(SqliteDatabase::open(&self.db_path).await?))
+    }
+
     pub async fn new_eventloop(
         &self,
         swap_id: Uuid,
@@ -478,6 +742,7 @@ impl BobParams {
         let mut swarm = swarm::cli(
             self.seed.derive_libp2p_identity(),
             tor_socks5_port,
+            false,
             behaviour,
         )
         .await?;
@@ -497,6 +762,51 @@ impl BobApplicationHandle {
     }
 }
 
+/// The CLI in daemon mode, running with Bob's wallets.
+pub struct BobDaemon {
+    address: SocketAddr,
+    token: String,
+    pub seller: Multiaddr,
+    pub bitcoin_change_address: bitcoin::Address,
+    pub monero_receive_address: monero::Address,
+    _server: ServerHandle,
+}
+
+impl BobDaemon {
+    /// Call a method of the daemon, returning its result or the message of
+    /// the error it failed with.
+    pub async fn call(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, String> {
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+
+        let response = reqwest::Client::new()
+            .post(format!("http://{}", self.address))
+            .header("Authorization", format!("Bearer {}", self.token))
+            .header("Content-Type", "application/json")
+            .body(request.to_string())
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        let mut response: serde_json::Value = serde_json::from_str(&response).unwrap();
+
+        match response.get("error") {
+            Some(error) => Err(error["message"].as_str().unwrap().to_owned()),
+            None => Ok(response["result"].take()),
+        }
+    }
+}
+
 pub struct AliceApplicationHandle {
     handle: JoinHandle<()>,
     peer_id: PeerId,
@@ -522,19 +832,28 @@ pub struct TestContext {
     alice_bitcoin_wallet: Arc<bitcoin::Wallet>,
     alice_monero_wallet: Arc<monero::Wallet>,
     alice_swap_handle: mpsc::Receiver<Swap>,
+    alice_reverse_swap_handle: mpsc::Receiver<bob::Swap>,
     alice_handle: AliceApplicationHandle,
 
     bob_params: BobParams,
     bob_starting_balances: StartingBalances,
     bob_bitcoin_wallet: Arc<bitcoin::Wallet>,
     bob_monero_wallet: Arc<monero::Wallet>,
+
+    bitcoind_url: Url,
 }
 
 impl TestContext {
+    /// Make the regtest node leave the transaction out of the blocks it
+    /// mines, see [`deprioritise_transaction`].
+    pub fn deprioritise_bitcoin_transaction(&self, txid: Txid, fee: bitcoin::Amount) -> Result<()> {
+        deprioritise_transaction(&self.bitcoind_url, txid, fee)
+    }
+
     pub async fn restart_alice(&mut self) {
         self.alice_handle.abort();
 
-        let (alice_handle, alice_swap_handle) = start_alice(
+        let (alice_handle, alice_swap_handle, alice_reverse_swap_handle) = start_alice(
             &self.alice_seed,
             self.alice_db_path.clone(),
             self.alice_listen_address.clone(),
@@ -546,6 +865,7 @@ impl TestContext {
 
         self.alice_handle = alice_handle;
         self.alice_swap_handle = alice_swap_handle;
+        self.alice_reverse_swap_handle = alice_reverse_swap_handle;
     }
 
     pub async fn alice_next_swap(&mut self) -> alice::Swap {
@@ -555,6 +875,77 @@ impl TestContext {
             .unwrap()
     }
 
+    pub async fn alice_next_reverse_swap(&mut self) -> bob::Swap {
+        timeout(Duration::from_secs(20), self.alice_reverse_swap_handle.recv())
+            .await
+            .expect("No Alice reverse swap within 20 seconds, aborting because this test is likely waiting for a swap forever...")
+            .unwrap()
+    }
+
+    pub async fn bob_reverse_swap(&mut self) -> (alice::Swap, BobApplicationHandle) {
+        let price = bitcoin::Amount::from_btc(FixedRate::RATE).unwrap();
+
+        self.try_bob_reverse_swap(price).await.unwrap()
+    }
+
+    /// Starts a reverse swap in which Bob holds Alice to having quoted `price`
+    /// per XMR.
+    pub async fn try_bob_reverse_swap(
+        &mut self,
+        price: bitcoin::Amount,
+    ) -> Result<(alice::Swap, BobApplicationHandle)> {
+        let min_btc = min_btc(price, self.xmr_amount)?;
+
+        self.bob_params
+            .new_reverse_swap(self.xmr_amount, min_btc)
+            .await
+    }
+
+    pub async fn stop_and_resume_bob_reverse_swap_from_db(
+        &mut self,
+        join_handle: BobApplicationHandle,
+        swap_id: Uuid,
+    ) -> (alice::Swap, BobApplicationHandle) {
+        join_handle.abort();
+
+        self.bob_params
+            .new_reverse_swap_from_db(swap_id)
+            .await
+            .unwrap()
+    }
+
+    /// Start the CLI in daemon mode with Bob's wallets and database.
+    pub async fn bob_daemon(&self) -> BobDaemon {
+        let context = cli::rpc::Context::new(
+            self.bob_params.open_db().await.unwrap(),
+            self.bob_bitcoin_wallet.clone(),
+            self.bob_monero_wallet.clone(),
+            self.env_config,
+            self.bob_params.seed.derive_libp2p_identity(),
+            XmrBtcNamespace::Testnet,
+            get_port().expect("Failed to find a free port"),
+            false,
+        );
+        let token = "token".to_owned();
+        let (address, server) =
+            cli::rpc::run_server("127.0.0.1:0".parse().unwrap(), &token, context)
+                .await
+                .unwrap();
+
+        BobDaemon {
+            address,
+            token,
+            seller: self
+                .bob_params
+                .alice_address
+                .clone()
+                .with(Protocol::P2p(self.bob_params.alice_peer_id.into())),
+            bitcoin_change_address: self.bob_bitcoin_wallet.new_address().await.unwrap(),
+            monero_receive_address: self.bob_monero_wallet.get_main_address(),
+            _server: server,
+        }
+    }
+
     pub async fn bob_swap(&mut self) -> (bob::Swap, BobApplicationHandle) {
         let (swap, event_loop) = self.bob_params.new_swap(self.btc_amount).await.unwrap();
 
@@ -721,6 +1112,81 @@ impl TestContext {
         .unwrap();
     }
 
+    /// Assert that Bob sold his XMR to Alice in a reverse swap.
+    pub async fn assert_reverse_redeemed(&self, bob_state: AliceState, alice_state: BobState) {
+        assert!(matches!(bob_state, AliceState::BtcRedeemed));
+        assert!(matches!(alice_state, BobState::XmrRedeemed { .. }));
+
+        assert_eventual_balance(
+            self.bob_bitcoin_wallet.as_ref(),
+            Ordering::Greater,
+            self.bob_starting_balances.btc,
+        )
+        .await
+        .unwrap();
+
+        assert_eventual_balance(
+            self.alice_bitcoin_wallet.as_ref(),
+            Ordering::Less,
+            self.alice_starting_balances.btc,
+        )
+        .await
+        .unwrap();
+
+        assert_eventual_balance(
+            self.alice_monero_wallet.as_ref(),
+            Ordering::Greater,
+            self.alice_starting_balances.xmr,
+        )
+        .await
+        .unwrap();
+    }
+
+    /// Assert that Bob got his XMR back in a reverse swap.
+    pub async fn assert_reverse_refunded(&self, bob_state: AliceState, alice_state: BobState) {
+        assert!(matches!(bob_state, AliceState::XmrRefunded));
+        assert!(matches!(alice_state, BobState::BtcRefunded(_)));
+
+        assert_eventual_balance(
+            self.bob_bitcoin_wallet.as_ref(),
+            Ordering::Equal,
+            self.bob_starting_balances.btc,
+        )
+        .await
+        .unwrap();
+
+        // Bob pays the fees for locking and sweeping the XMR
+        assert_eventual_balance(
+            self.bob_monero_wallet.as_ref(),
+            Ordering::Less,
+            self.bob_starting_balances.xmr,
+        )
+        .await
+        .unwrap();
+    }
+
+    /// Assert that Bob punished Alice in a reverse swap.
+    pub async fn assert_reverse_punished(&self, bob_state: AliceState, alice_state: BobState) {
+        assert!(matches!(bob_state, AliceState::BtcPunished));
+        assert!(matches!(alice_state, BobState::BtcPunished { .. }));
+
+        assert_eventual_balance(
+            self.bob_bitcoin_wallet.as_ref(),
+            Ordering::Greater,
+            self.bob_starting_balances.btc,
+        )
+        .await
+        .unwrap();
+
+        assert_eventual_balance(
+            self.alice_monero_wallet.as_ref(),
+            Ordering::Equal,
+            self.alice_starting_balances.xmr,
+        )
+        .await
+        .unwrap();
+    }
+
     fn alice_redeemed_xmr_balance(&self) -> monero::Amount {
         self.alice_starting_balances.xmr - self.xmr_amount
     }
@@ -948,6 +1414,33 @@ pub async fn mint(node_url: Url, address: bitcoin::Address, amount: bitcoin::Amo
     Ok(())
 }
 
+/// Make the regtest node treat the transaction as if it paid `fee` less,
+/// which leaves it out of the blocks it mines if that is all it pays.
+///
+/// This works before the transaction is even broadcast and doesn't affect
+/// transactions replacing it or spending its outputs.
+pub fn deprioritise_transaction(node_url: &Url, txid: Txid, fee: bitcoin::Amount) -> Result<()> {
+    let url = format!(
+        "http://{}:{}",
+        node_url.host_str().context("bitcoind url has no host")?,
+        node_url.port().context("bitcoind url has no port")?
+    );
+    let auth = Auth::UserPass(
+        bitcoind::RPC_USER.to_string(),
+        bitcoind::RPC_PASSWORD.to_string(),
+    );
+    let client = bitcoincore_rpc::Client::new(&url, auth)?;
+
+    let fee_delta = -i64::try_from(fee.as_sat())?;
+    client.call::<bool>("prioritisetransaction", &[
+        serde_json::json!(txid),
+        serde_json::json!(0),
+        serde_json::json!(fee_delta),
+    ])?;
+
+    Ok(())
+}
+
 // This is just to keep the containers alive
 #[allow(dead_code)]
 struct Containers<'a> {
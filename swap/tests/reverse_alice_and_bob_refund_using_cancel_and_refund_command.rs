@@ -0,0 +1,91 @@
+pub mod harness;
+
+use harness::alice_run_until::is_xmr_lock_transaction_sent;
+use harness::bob_run_until::is_btc_locked;
+use harness::FastCancelConfig;
+use std::convert::TryInto;
+use swap::asb::FixedRate;
+use swap::protocol::alice::AliceState;
+use swap::protocol::bob::BobState;
+use swap::protocol::{alice, bob, recovery, State};
+
+/// Alice buys XMR from Bob. Once both locked their funds, Bob manually cancels
+/// the swap and both manually refund.
+#[tokio::test]
+async fn given_alice_and_bob_manually_refund_after_funds_locked_in_reverse_swap_both_refund() {
+    harness::setup_reverse_test(FastCancelConfig, |mut ctx| async move {
+        let (bob_swap, bob_join_handle) = ctx.bob_reverse_swap().await;
+        let bob_swap_id = bob_swap.swap_id;
+        let bob_swap = tokio::spawn(alice::run_until(
+            bob_swap,
+            is_xmr_lock_transaction_sent,
+            FixedRate::default(),
+        ));
+
+        let alice_swap = ctx.alice_next_reverse_swap().await;
+        let alice_state = bob::run_until(alice_swap, is_btc_locked).await?;
+        assert!(matches!(alice_state, BobState::BtcLocked { .. }));
+
+        let bob_state = bob_swap.await??;
+        assert!(matches!(
+            bob_state,
+            AliceState::XmrLockTransactionSent { .. }
+        ));
+
+        let (bob_swap, bob_join_handle) = ctx
+            .stop_and_resume_bob_reverse_swap_from_db(bob_join_handle, bob_swap_id)
+            .await;
+
+        // Ensure cancel timelock is expired
+        if let AliceState::XmrLockTransactionSent { state3, .. } = bob_swap.state.clone() {
+            bob_swap
+                .bitcoin_wallet
+                .subscribe_to(state3.tx_lock)
+                .await
+                .wait_until_confirmed_with(state3.cancel_timelock)
+                .await?;
+        } else {
+            panic!("Bob in unexpected state {}", bob_swap.state);
+        }
+
+        // Bob manually cancels
+        bob_join_handle.abort();
+        let (_, state) = recovery::cancel(
+            bob_swap.swap_id,
+            bob_swap.bitcoin_wallet.clone(),
+            bob_swap.db.clone(),
+        )
+        .await?;
+        assert!(matches!(
+            state,
+            State::Alice(AliceState::BtcCancelled { .. })
+        ));
+
+        // Alice manually refunds her BTC
+        ctx.restart_alice().await;
+        let alice_swap = ctx.alice_next_reverse_swap().await;
+        let alice_state: BobState = recovery::refund(
+            alice_swap.id,
+            alice_swap.bitcoin_wallet,
+            None,
+            alice_swap.db,
+        )
+        .await?
+        .try_into()?;
+
+        // Bob manually refunds his XMR
+        let bob_state: AliceState = recovery::refund(
+            bob_swap.swap_id,
+            bob_swap.bitcoin_wallet,
+            Some(bob_swap.monero_wallet),
+            bob_swap.db,
+        )
+        .await?
+        .try_into()?;
+
+        ctx.assert_reverse_refunded(bob_state, alice_state).await;
+
+        Ok(())
+    })
+    .await
+}
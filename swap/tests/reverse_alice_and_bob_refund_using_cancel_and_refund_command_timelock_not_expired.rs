@@ -0,0 +1,109 @@
+pub mod harness;
+
+use harness::alice_run_until::is_xmr_lock_transaction_sent;
+use harness::bob_run_until::is_btc_locked;
+use harness::SlowCancelConfig;
+use swap::asb::FixedRate;
+use swap::bitcoin::{parse_rpc_error_code, RpcErrorCode};
+use swap::protocol::alice::AliceState;
+use swap::protocol::bob::BobState;
+use swap::protocol::{alice, bob, recovery};
+
+#[tokio::test]
+async fn given_alice_and_bob_manually_cancel_reverse_swap_when_timelock_not_expired_errors() {
+    harness::setup_reverse_test(SlowCancelConfig, |mut ctx| async move {
+        let (bob_swap, bob_join_handle) = ctx.bob_reverse_swap().await;
+        let swap_id = bob_swap.swap_id;
+        let bob_swap = tokio::spawn(alice::run_until(
+            bob_swap,
+            is_xmr_lock_transaction_sent,
+            FixedRate::default(),
+        ));
+
+        let alice_swap = ctx.alice_next_reverse_swap().await;
+        let alice_state = bob::run_until(alice_swap, is_btc_locked).await?;
+        assert!(matches!(alice_state, BobState::BtcLocked { .. }));
+
+        let bob_state = bob_swap.await??;
+        assert!(matches!(
+            bob_state,
+            AliceState::XmrLockTransactionSent { .. }
+        ));
+
+        let (bob_swap, bob_join_handle) = ctx
+            .stop_and_resume_bob_reverse_swap_from_db(bob_join_handle, swap_id)
+            .await;
+        assert!(matches!(
+            bob_swap.state,
+            AliceState::XmrLockTransactionSent { .. }
+        ));
+
+        // Bob tries but fails to manually cancel
+        let error = recovery::cancel(swap_id, bob_swap.bitcoin_wallet, bob_swap.db)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            parse_rpc_error_code(&error).unwrap(),
+            i64::from(RpcErrorCode::RpcVerifyRejected)
+        );
+
+        ctx.restart_alice().await;
+        let alice_swap = ctx.alice_next_reverse_swap().await;
+        assert!(matches!(alice_swap.state, BobState::BtcLocked { .. }));
+
+        // Alice tries but fails to manually cancel
+        let error = recovery::cancel(swap_id, alice_swap.bitcoin_wallet, alice_swap.db)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            parse_rpc_error_code(&error).unwrap(),
+            i64::from(RpcErrorCode::RpcVerifyRejected)
+        );
+
+        ctx.restart_alice().await;
+        let alice_swap = ctx.alice_next_reverse_swap().await;
+        assert!(matches!(alice_swap.state, BobState::BtcLocked { .. }));
+
+        // Alice tries but fails to manually refund
+        let error = recovery::refund(swap_id, alice_swap.bitcoin_wallet, None, alice_swap.db)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            parse_rpc_error_code(&error).unwrap(),
+            i64::from(RpcErrorCode::RpcVerifyError)
+        );
+
+        let (bob_swap, bob_join_handle) = ctx
+            .stop_and_resume_bob_reverse_swap_from_db(bob_join_handle, swap_id)
+            .await;
+        assert!(matches!(
+            bob_swap.state,
+            AliceState::XmrLockTransactionSent { .. }
+        ));
+
+        // Bob tries but fails to manually refund
+        let result = recovery::refund(
+            swap_id,
+            bob_swap.bitcoin_wallet,
+            Some(bob_swap.monero_wallet),
+            bob_swap.db,
+        )
+        .await;
+        assert!(result.is_err());
+
+        let (bob_swap, _) = ctx
+            .stop_and_resume_bob_reverse_swap_from_db(bob_join_handle, swap_id)
+            .await;
+        assert!(matches!(
+            bob_swap.state,
+            AliceState::XmrLockTransactionSent { .. }
+        ));
+
+        ctx.restart_alice().await;
+        let alice_swap = ctx.alice_next_reverse_swap().await;
+        assert!(matches!(alice_swap.state, BobState::BtcLocked { .. }));
+
+        Ok(())
+    })
+    .await;
+}
@@ -0,0 +1,61 @@
+pub mod harness;
+
+use harness::alice_run_until::is_xmr_lock_transaction_sent;
+use harness::bob_run_until::is_btc_locked;
+use harness::FastPunishConfig;
+use swap::asb::FixedRate;
+use swap::protocol::alice::AliceState;
+use swap::protocol::bob::BobState;
+use swap::protocol::{alice, bob};
+
+/// Alice buys XMR from Bob. Alice locks Btc and Bob locks Xmr, then Alice does
+/// not act anymore. Bob restarts, cancels and punishes.
+#[tokio::test]
+async fn bob_punishes_after_restart_if_alice_dead() {
+    harness::setup_reverse_test(FastPunishConfig, |mut ctx| async move {
+        let (bob_swap, bob_join_handle) = ctx.bob_reverse_swap().await;
+        let bob_swap_id = bob_swap.swap_id;
+        let bob_bitcoin_wallet = bob_swap.bitcoin_wallet.clone();
+        let bob_swap = tokio::spawn(alice::run_until(
+            bob_swap,
+            is_xmr_lock_transaction_sent,
+            FixedRate::default(),
+        ));
+
+        let alice_swap = ctx.alice_next_reverse_swap().await;
+        let alice_state = bob::run_until(alice_swap, is_btc_locked).await?;
+        assert!(matches!(alice_state, BobState::BtcLocked { .. }));
+
+        let bob_state = bob_swap.await??;
+
+        // Ensure cancel timelock is expired (we can only ensure that, because the
+        // cancel transaction is not published at this point)
+        if let AliceState::XmrLockTransactionSent { state3, .. } = bob_state {
+            bob_bitcoin_wallet
+                .subscribe_to(state3.tx_lock)
+                .await
+                .wait_until_confirmed_with(state3.cancel_timelock)
+                .await?;
+        } else {
+            panic!("Bob in unexpected state {}", bob_state);
+        }
+
+        let (bob_swap, _) = ctx
+            .stop_and_resume_bob_reverse_swap_from_db(bob_join_handle, bob_swap_id)
+            .await;
+        let bob_state = alice::run(bob_swap, FixedRate::default()).await?;
+
+        // Restart Alice after Bob punished to ensure Alice transitions to
+        // punished and does not run indefinitely
+        ctx.restart_alice().await;
+        let alice_swap = ctx.alice_next_reverse_swap().await;
+        assert!(matches!(alice_swap.state, BobState::BtcLocked { .. }));
+
+        let alice_state = bob::run(alice_swap).await?;
+
+        ctx.assert_reverse_punished(bob_state, alice_state).await;
+
+        Ok(())
+    })
+    .await;
+}
@@ -0,0 +1,25 @@
+pub mod harness;
+
+use harness::SlowCancelConfig;
+use swap::asb::FixedRate;
+use swap::bitcoin;
+use swap::network::swap_setup::reverse::alice::Error;
+
+/// Alice offers less for the XMR than the price Bob was quoted, Bob refuses to
+/// set up the swap.
+#[tokio::test]
+async fn reverse_cli_refuses_seller_undercutting_quote() {
+    harness::setup_reverse_test(SlowCancelConfig, |mut ctx| async move {
+        let quoted_price = bitcoin::Amount::from_btc(FixedRate::RATE * 1.1).unwrap();
+
+        let error = ctx.try_bob_reverse_swap(quoted_price).await.unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::OfferBelowQuote { .. })
+        ));
+
+        Ok(())
+    })
+    .await;
+}
@@ -0,0 +1,26 @@
+pub mod harness;
+
+use harness::SlowCancelConfig;
+use swap::asb::FixedRate;
+use swap::protocol::{alice, bob};
+use tokio::join;
+
+/// Bob sells XMR to Alice, i.e. the ASB buys XMR from the CLI.
+#[tokio::test]
+async fn reverse_happy_path() {
+    harness::setup_reverse_test(SlowCancelConfig, |mut ctx| async move {
+        let (bob_swap, _) = ctx.bob_reverse_swap().await;
+        let bob_swap = tokio::spawn(alice::run(bob_swap, FixedRate::default()));
+
+        let alice_swap = ctx.alice_next_reverse_swap().await;
+        let alice_swap = tokio::spawn(bob::run(alice_swap));
+
+        let (bob_state, alice_state) = join!(bob_swap, alice_swap);
+
+        ctx.assert_reverse_redeemed(bob_state??, alice_state??)
+            .await;
+
+        Ok(())
+    })
+    .await;
+}
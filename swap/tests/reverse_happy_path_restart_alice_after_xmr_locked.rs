@@ -0,0 +1,32 @@
+pub mod harness;
+
+use harness::bob_run_until::is_xmr_locked;
+use harness::SlowCancelConfig;
+use swap::asb::FixedRate;
+use swap::protocol::bob::BobState;
+use swap::protocol::{alice, bob};
+
+/// Alice buys XMR from Bob and restarts after the XMR are locked.
+#[tokio::test]
+async fn given_alice_restarts_after_bought_xmr_is_locked_resume_swap() {
+    harness::setup_reverse_test(SlowCancelConfig, |mut ctx| async move {
+        let (bob_swap, _) = ctx.bob_reverse_swap().await;
+        let bob_swap = tokio::spawn(alice::run(bob_swap, FixedRate::default()));
+
+        let alice_swap = ctx.alice_next_reverse_swap().await;
+        let alice_state = bob::run_until(alice_swap, is_xmr_locked).await?;
+        assert!(matches!(alice_state, BobState::XmrLocked { .. }));
+
+        ctx.restart_alice().await;
+        let alice_swap = ctx.alice_next_reverse_swap().await;
+        assert!(matches!(alice_swap.state, BobState::XmrLocked { .. }));
+
+        let alice_state = bob::run(alice_swap).await?;
+        let bob_state = bob_swap.await??;
+
+        ctx.assert_reverse_redeemed(bob_state, alice_state).await;
+
+        Ok(())
+    })
+    .await;
+}
@@ -0,0 +1,49 @@
+pub mod harness;
+
+use harness::alice_run_until::is_xmr_lock_transaction_sent;
+use harness::SlowCancelConfig;
+use swap::asb::FixedRate;
+use swap::protocol::alice::AliceState;
+use swap::protocol::{alice, bob};
+use tokio::join;
+
+/// Bob sells XMR to Alice and restarts after sending the XMR lock transaction.
+#[tokio::test]
+async fn given_bob_restarts_after_selling_xmr_is_locked_resume_swap() {
+    harness::setup_reverse_test(SlowCancelConfig, |mut ctx| async move {
+        let (bob_swap, bob_join_handle) = ctx.bob_reverse_swap().await;
+        let bob_swap_id = bob_swap.swap_id;
+        let bob_swap = tokio::spawn(alice::run_until(
+            bob_swap,
+            is_xmr_lock_transaction_sent,
+            FixedRate::default(),
+        ));
+
+        let alice_swap = ctx.alice_next_reverse_swap().await;
+        let alice_swap = tokio::spawn(bob::run(alice_swap));
+
+        let bob_state = bob_swap.await??;
+        assert!(matches!(
+            bob_state,
+            AliceState::XmrLockTransactionSent { .. }
+        ));
+
+        let (bob_swap, _) = ctx
+            .stop_and_resume_bob_reverse_swap_from_db(bob_join_handle, bob_swap_id)
+            .await;
+        assert!(matches!(
+            bob_swap.state,
+            AliceState::XmrLockTransactionSent { .. }
+        ));
+
+        let bob_swap = tokio::spawn(alice::run(bob_swap, FixedRate::default()));
+
+        let (bob_state, alice_state) = join!(bob_swap, alice_swap);
+
+        ctx.assert_reverse_redeemed(bob_state??, alice_state??)
+            .await;
+
+        Ok(())
+    })
+    .await;
+}
@@ -0,0 +1,36 @@
+pub mod harness;
+
+use harness::bob_run_until::is_btc_locked;
+use harness::FastPunishConfig;
+use swap::asb::FixedRate;
+use swap::protocol::bob::BobState;
+use swap::protocol::{alice, bob};
+
+/// Alice buys XMR from Bob. Alice locks Btc and Bob locks Xmr, then Alice does
+/// not act anymore. Bob punishes.
+#[tokio::test]
+async fn bob_punishes_if_alice_never_acts_after_buying_xmr() {
+    harness::setup_reverse_test(FastPunishConfig, |mut ctx| async move {
+        let (bob_swap, _) = ctx.bob_reverse_swap().await;
+        let bob_swap = tokio::spawn(alice::run(bob_swap, FixedRate::default()));
+
+        let alice_swap = ctx.alice_next_reverse_swap().await;
+        let alice_state = bob::run_until(alice_swap, is_btc_locked).await?;
+        assert!(matches!(alice_state, BobState::BtcLocked { .. }));
+
+        let bob_state = bob_swap.await??;
+
+        // Restart Alice after Bob punished to ensure Alice transitions to
+        // punished and does not run indefinitely
+        ctx.restart_alice().await;
+        let alice_swap = ctx.alice_next_reverse_swap().await;
+        assert!(matches!(alice_swap.state, BobState::BtcLocked { .. }));
+
+        let alice_state = bob::run(alice_swap).await?;
+
+        ctx.assert_reverse_punished(bob_state, alice_state).await;
+
+        Ok(())
+    })
+    .await;
+}
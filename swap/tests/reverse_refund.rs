@@ -0,0 +1,44 @@
+pub mod harness;
+
+use harness::alice_run_until::is_xmr_lock_transaction_sent;
+use harness::FastCancelConfig;
+use swap::asb::FixedRate;
+use swap::protocol::alice::AliceState;
+use swap::protocol::{alice, bob};
+
+/// Alice buys XMR from Bob. Alice locks Btc and Bob locks Xmr, but Bob does
+/// not act so Alice refunds. Eventually Bob comes back online and refunds as
+/// well.
+#[tokio::test]
+async fn bob_refunds_after_restart_if_alice_already_refunded() {
+    harness::setup_reverse_test(FastCancelConfig, |mut ctx| async move {
+        let (bob_swap, bob_join_handle) = ctx.bob_reverse_swap().await;
+        let bob_swap_id = bob_swap.swap_id;
+        let bob_swap = tokio::spawn(alice::run_until(
+            bob_swap,
+            is_xmr_lock_transaction_sent,
+            FixedRate::default(),
+        ));
+
+        let alice_swap = ctx.alice_next_reverse_swap().await;
+        let alice_swap = tokio::spawn(bob::run(alice_swap));
+
+        let bob_state = bob_swap.await??;
+        assert!(matches!(
+            bob_state,
+            AliceState::XmrLockTransactionSent { .. }
+        ));
+
+        let alice_state = alice_swap.await??;
+
+        let (bob_swap, _) = ctx
+            .stop_and_resume_bob_reverse_swap_from_db(bob_join_handle, bob_swap_id)
+            .await;
+        let bob_state = alice::run(bob_swap, FixedRate::default()).await?;
+
+        ctx.assert_reverse_refunded(bob_state, alice_state).await;
+
+        Ok(())
+    })
+    .await;
+}